@@ -8,6 +8,19 @@ fn main() {
     // shared library.
     println!("cargo:rustc-link-lib=gfapi");
 
+    // Build against the stub library from gfapi-stub instead of the
+    // installed gluster when GFAPI_STUB_DIR points at where it was built
+    println!("cargo:rerun-if-env-changed=GFAPI_STUB_DIR");
+    println!("cargo:rustc-check-cfg=cfg(gfapi_stub)");
+    let mut include_dir = "/usr/include/glusterfs/api".to_string();
+    if let Some(stub_dir) = env::var_os("GFAPI_STUB_DIR") {
+        let stub_dir = PathBuf::from(stub_dir);
+        println!("cargo:rustc-link-search=native={}", stub_dir.display());
+        println!("cargo:rustc-link-arg=-Wl,-rpath,{}", stub_dir.display());
+        println!("cargo:rustc-cfg=gfapi_stub");
+        include_dir = format!("{}/gfapi-stub/include", env!("CARGO_MANIFEST_DIR"));
+    }
+
     // Tell cargo to invalidate the built crate whenever the wrapper changes
     println!("cargo:rerun-if-changed=wrapper.h");
 
@@ -18,7 +31,7 @@ fn main() {
         // The input header we would like to generate
         // bindings for.
         .header("wrapper.h")
-        .clang_arg("-I/usr/include/glusterfs/api")
+        .clang_arg(format!("-I{}", include_dir))
         // Tell cargo to invalidate the built crate whenever any of the
         // included header files changed.
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
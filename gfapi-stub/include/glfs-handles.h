@@ -0,0 +1,25 @@
+/*
+ * The subset of <glfs-handles.h> declared by gfapi-stub.  Handles come
+ * from glfs_xreaddirplus_r, the stub has no GFIDs to create them from.
+ */
+#ifndef _GLFS_HANDLES_H
+#define _GLFS_HANDLES_H
+
+#include "glfs.h"
+
+#define GFAPI_HANDLE_LENGTH 16
+
+struct glfs_object;
+
+struct glfs_object *glfs_h_create_from_handle(struct glfs *fs,
+                                              unsigned char *handle, int len,
+                                              struct stat *stat);
+int glfs_h_close(struct glfs_object *object);
+struct glfs_fd *glfs_h_open(struct glfs *fs, struct glfs_object *object,
+                            int flags);
+struct glfs_fd *glfs_h_opendir(struct glfs *fs, struct glfs_object *object);
+int glfs_h_stat(struct glfs *fs, struct glfs_object *object, struct stat *stat);
+int glfs_h_getxattrs(struct glfs *fs, struct glfs_object *object,
+                     const char *name, void *value, size_t size);
+
+#endif /* _GLFS_HANDLES_H */
@@ -0,0 +1,155 @@
+/*
+ * The subset of <glfs.h> implemented by gfapi-stub.  Signatures match
+ * glusterfs 6 and later.  Only used to generate the gfapi-sys bindings
+ * when building against the stub instead of an installed gluster.
+ */
+#ifndef _GLFS_H
+#define _GLFS_H
+
+#include <dirent.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <sys/stat.h>
+#include <sys/statvfs.h>
+#include <sys/types.h>
+#include <sys/uio.h>
+#include <time.h>
+
+struct glfs;
+typedef struct glfs glfs_t;
+
+struct glfs_fd;
+typedef struct glfs_fd glfs_fd_t;
+
+/* Only ever passed as NULL by gfapi-sys */
+struct glfs_stat;
+
+glfs_t *glfs_new(const char *volname);
+int glfs_set_volfile_server(glfs_t *fs, const char *transport,
+                            const char *host, int port);
+int glfs_set_logging(glfs_t *fs, const char *logfile, int loglevel);
+int glfs_init(glfs_t *fs);
+int glfs_fini(glfs_t *fs);
+ssize_t glfs_get_volfile(glfs_t *fs, void *buf, size_t len);
+int glfs_get_volumeid(glfs_t *fs, char *volid, size_t size);
+
+#define GLFS_SYSRQ_HELP 'h'
+#define GLFS_SYSRQ_STATEDUMP 's'
+int glfs_sysrq(glfs_t *fs, char sysrq);
+int glfs_set_statedump_path(glfs_t *fs, const char *path);
+
+int glfs_setfsuid(uid_t fsuid);
+int glfs_setfsgid(gid_t fsgid);
+int glfs_setfsgroups(size_t size, const gid_t *list);
+
+glfs_fd_t *glfs_open(glfs_t *fs, const char *path, int flags);
+glfs_fd_t *glfs_creat(glfs_t *fs, const char *path, int flags, mode_t mode);
+int glfs_close(glfs_fd_t *fd);
+glfs_fd_t *glfs_dup(glfs_fd_t *fd);
+
+ssize_t glfs_read(glfs_fd_t *fd, void *buf, size_t count, int flags);
+ssize_t glfs_write(glfs_fd_t *fd, const void *buf, size_t count, int flags);
+ssize_t glfs_readv(glfs_fd_t *fd, const struct iovec *iov, int iovcnt,
+                   int flags);
+ssize_t glfs_writev(glfs_fd_t *fd, const struct iovec *iov, int iovcnt,
+                    int flags);
+ssize_t glfs_pread(glfs_fd_t *fd, void *buf, size_t count, off_t offset,
+                   int flags, struct glfs_stat *poststat);
+ssize_t glfs_pwrite(glfs_fd_t *fd, const void *buf, size_t count,
+                    off_t offset, int flags, struct glfs_stat *prestat,
+                    struct glfs_stat *poststat);
+ssize_t glfs_preadv(glfs_fd_t *fd, const struct iovec *iov, int iovcnt,
+                    off_t offset, int flags);
+ssize_t glfs_pwritev(glfs_fd_t *fd, const struct iovec *iov, int iovcnt,
+                     off_t offset, int flags);
+off_t glfs_lseek(glfs_fd_t *fd, off_t offset, int whence);
+ssize_t glfs_copy_file_range(glfs_fd_t *glfd_in, off_t *off_in,
+                             glfs_fd_t *glfd_out, off_t *off_out, size_t len,
+                             unsigned int flags, struct glfs_stat *statbuf,
+                             struct glfs_stat *prestat,
+                             struct glfs_stat *poststat);
+int glfs_truncate(glfs_t *fs, const char *path, off_t length);
+int glfs_ftruncate(glfs_fd_t *fd, off_t length, struct glfs_stat *prestat,
+                   struct glfs_stat *poststat);
+int glfs_fsync(glfs_fd_t *fd, struct glfs_stat *prestat,
+               struct glfs_stat *poststat);
+int glfs_fdatasync(glfs_fd_t *fd, struct glfs_stat *prestat,
+                   struct glfs_stat *poststat);
+int glfs_fallocate(glfs_fd_t *fd, int keep_size, off_t offset, size_t len);
+int glfs_discard(glfs_fd_t *fd, off_t offset, size_t len);
+int glfs_zerofill(glfs_fd_t *fd, off_t offset, off_t len);
+int glfs_posix_lock(glfs_fd_t *fd, int cmd, struct flock *flock);
+
+int glfs_stat(glfs_t *fs, const char *path, struct stat *buf);
+int glfs_lstat(glfs_t *fs, const char *path, struct stat *buf);
+int glfs_fstat(glfs_fd_t *fd, struct stat *buf);
+int glfs_statvfs(glfs_t *fs, const char *path, struct statvfs *buf);
+int glfs_access(glfs_t *fs, const char *path, int mode);
+
+int glfs_symlink(glfs_t *fs, const char *oldpath, const char *newpath);
+int glfs_readlink(glfs_t *fs, const char *path, char *buf, size_t bufsiz);
+int glfs_mknod(glfs_t *fs, const char *path, mode_t mode, dev_t dev);
+int glfs_mkdir(glfs_t *fs, const char *path, mode_t mode);
+int glfs_unlink(glfs_t *fs, const char *path);
+int glfs_rmdir(glfs_t *fs, const char *path);
+int glfs_rename(glfs_t *fs, const char *oldpath, const char *newpath);
+int glfs_link(glfs_t *fs, const char *oldpath, const char *newpath);
+
+glfs_fd_t *glfs_opendir(glfs_t *fs, const char *path);
+int glfs_readdir_r(glfs_fd_t *fd, struct dirent *dirent,
+                   struct dirent **result);
+int glfs_readdirplus_r(glfs_fd_t *fd, struct stat *stat, struct dirent *ext,
+                       struct dirent **result);
+int glfs_closedir(glfs_fd_t *fd);
+long glfs_telldir(glfs_fd_t *fd);
+void glfs_seekdir(glfs_fd_t *fd, long offset);
+
+#define GFAPI_XREADDIRP_NULL 0x00000000
+#define GFAPI_XREADDIRP_STAT 0x00000001
+#define GFAPI_XREADDIRP_HANDLE 0x00000002
+struct glfs_xreaddirp_stat;
+struct glfs_object;
+int glfs_xreaddirplus_r(glfs_fd_t *fd, uint32_t flags,
+                        struct glfs_xreaddirp_stat **xstat_p,
+                        struct dirent *ext, struct dirent **res);
+struct stat *glfs_xreaddirplus_get_stat(struct glfs_xreaddirp_stat *xstat);
+struct glfs_object *
+glfs_xreaddirplus_get_object(struct glfs_xreaddirp_stat *xstat);
+void glfs_free(void *ptr);
+
+ssize_t glfs_getxattr(glfs_t *fs, const char *path, const char *name,
+                      void *value, size_t size);
+ssize_t glfs_lgetxattr(glfs_t *fs, const char *path, const char *name,
+                       void *value, size_t size);
+ssize_t glfs_fgetxattr(glfs_fd_t *fd, const char *name, void *value,
+                       size_t size);
+ssize_t glfs_listxattr(glfs_t *fs, const char *path, void *value,
+                       size_t size);
+ssize_t glfs_llistxattr(glfs_t *fs, const char *path, void *value,
+                        size_t size);
+ssize_t glfs_flistxattr(glfs_fd_t *fd, void *value, size_t size);
+int glfs_setxattr(glfs_t *fs, const char *path, const char *name,
+                  const void *value, size_t size, int flags);
+int glfs_lsetxattr(glfs_t *fs, const char *path, const char *name,
+                   const void *value, size_t size, int flags);
+int glfs_fsetxattr(glfs_fd_t *fd, const char *name, const void *value,
+                   size_t size, int flags);
+int glfs_removexattr(glfs_t *fs, const char *path, const char *name);
+int glfs_lremovexattr(glfs_t *fs, const char *path, const char *name);
+int glfs_fremovexattr(glfs_fd_t *fd, const char *name);
+
+char *glfs_getcwd(glfs_t *fs, char *buf, size_t size);
+int glfs_chdir(glfs_t *fs, const char *path);
+int glfs_fchdir(glfs_fd_t *fd);
+
+int glfs_chmod(glfs_t *fs, const char *path, mode_t mode);
+int glfs_fchmod(glfs_fd_t *fd, mode_t mode);
+int glfs_chown(glfs_t *fs, const char *path, uid_t uid, gid_t gid);
+int glfs_lchown(glfs_t *fs, const char *path, uid_t uid, gid_t gid);
+int glfs_fchown(glfs_fd_t *fd, uid_t uid, gid_t gid);
+int glfs_utimens(glfs_t *fs, const char *path, const struct timespec times[2]);
+int glfs_lutimens(glfs_t *fs, const char *path,
+                  const struct timespec times[2]);
+int glfs_futimens(glfs_fd_t *fd, const struct timespec times[2]);
+
+#endif /* _GLFS_H */
@@ -0,0 +1,1468 @@
+//! A stand-in for libgfapi used to test gfapi-sys without gluster
+//! Builds a libgfapi.so implementing the glfs_* functions gfapi-sys calls
+//! on top of a directory of the host, so the safe wrappers can be tested
+//! on any machine.  Every volume gets a fresh temporary directory that is
+//! removed by glfs_fini, unless GFAPI_STUB_ROOT names a directory to use
+//! instead.
+//!
+//! Tests steer the stub through a few extra functions:
+//!  * gfapi_stub_inject(name, errno, count) makes the next count calls of
+//!    the glfs function called name fail with errno, or every call if count
+//!    is negative.
+//!  * gfapi_stub_clear() drops every injected failure.
+//!  * gfapi_stub_set_volfile(buf, len) sets the volfile returned by
+//!    glfs_get_volfile for volumes initialized afterwards.
+//!  * gfapi_stub_fs_ids(uid, gid, groups, len) reports the ids the thread
+//!    last set with glfs_setfsuid, glfs_setfsgid and glfs_setfsgroups,
+//!    -1 until it has, and returns how many groups were set.
+//!  * gfapi_stub_unknown_d_type(on) has directory reads report every
+//!    entry as DT_UNKNOWN, like bricks on filesystems without d_type.
+//!
+//! Injected failures, volfiles, fs ids and d_types are per thread so tests running in
+//! parallel don't see each other's.  Handles from glfs_xreaddirplus_r can
+//! be opened and statted, but there are no GFIDs so glfs_h_* functions
+//! taking one fail with ENOSYS.  GFAPI_STUB_RUN_DIR stands in for
+//! /var/run/gluster, where io-stats dumps are written.
+#![allow(non_camel_case_types, clippy::missing_safety_doc)]
+
+use libc::{
+    c_char, c_int, c_uchar, c_uint, c_void, dev_t, dirent, flock, gid_t, iovec, mode_t, off_t,
+    size_t, ssize_t, stat, statvfs, timespec, uid_t, AT_FDCWD, AT_SYMLINK_NOFOLLOW, DIR, EINVAL,
+    ENOSYS, ENOTDIR, ENOTSUP, ERANGE, FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE,
+    FALLOC_FL_ZERO_RANGE, F_GETLK, F_SETLK, F_SETLKW, O_CLOEXEC, O_CREAT,
+};
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, OsStr};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+pub struct glfs {
+    volname: String,
+    servers: Vec<String>,
+    root: PathBuf,
+    /// Set when the root is a temporary directory glfs_fini should remove
+    remove_root: bool,
+    volfile: Vec<u8>,
+    volume_id: [u8; 16],
+    /// The working directory, relative to root
+    cwd: Mutex<PathBuf>,
+    /// Opened by glfs_set_logging and kept open until glfs_fini like
+    /// gluster's log, which only hears about unmounting
+    log: Mutex<Option<std::fs::File>>,
+    /// Where glfs_sysrq writes statedumps
+    statedump_path: Mutex<PathBuf>,
+}
+
+pub struct glfs_fd {
+    fs: *mut glfs,
+    fd: c_int,
+    /// Set for fds from glfs_opendir
+    dir: *mut DIR,
+    /// Where the fd was opened on the volume, for glfs_fchdir
+    path: PathBuf,
+}
+
+/// Objects are kept by volume path, so a handle follows renames only as
+/// far as the stub cares
+pub struct glfs_object {
+    path: PathBuf,
+}
+
+pub struct glfs_xreaddirp_stat {
+    /// GFAPI_XREADDIRP_* flags saying which of stat and object are set
+    valid: u32,
+    stat: stat,
+    object: *mut glfs_object,
+}
+
+const GFAPI_XREADDIRP_STAT: u32 = 0x1;
+const GFAPI_XREADDIRP_HANDLE: u32 = 0x2;
+pub enum glfs_stat {}
+
+struct Injection {
+    errno: c_int,
+    /// Negative to fail forever
+    remaining: c_int,
+}
+
+thread_local! {
+    static INJECTED: RefCell<HashMap<String, Injection>> = RefCell::new(HashMap::new());
+    static VOLFILE: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+    static FS_IDS: RefCell<(uid_t, gid_t, Vec<gid_t>)> =
+        const { RefCell::new((uid_t::MAX, gid_t::MAX, Vec::new())) };
+    static UNKNOWN_D_TYPE: Cell<bool> = const { Cell::new(false) };
+}
+
+static NEXT_ROOT: AtomicUsize = AtomicUsize::new(0);
+
+fn set_errno(code: c_int) {
+    unsafe { *libc::__errno_location() = code }
+}
+
+fn errno() -> c_int {
+    unsafe { *libc::__errno_location() }
+}
+
+/// Returns true, with errno set, if a failure was injected for function
+fn injected(function: &str) -> bool {
+    INJECTED.with(|injected| {
+        let mut injected = injected.borrow_mut();
+        let injection = match injected.get_mut(function) {
+            Some(injection) => injection,
+            None => return false,
+        };
+        set_errno(injection.errno);
+        if injection.remaining > 0 {
+            injection.remaining -= 1;
+            if injection.remaining == 0 {
+                injected.remove(function);
+            }
+        }
+        true
+    })
+}
+
+/// Return $fail with errno set if a failure was injected for $name
+macro_rules! inject {
+    ($name:expr, $fail:expr) => {
+        if injected($name) {
+            return $fail;
+        }
+    };
+}
+
+/// Unwrap a Result<T, errno>, returning $fail with errno set on Err
+macro_rules! try_errno {
+    ($result:expr, $fail:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(code) => {
+                set_errno(code);
+                return $fail;
+            }
+        }
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gfapi_stub_inject(function: *const c_char, errno: c_int, count: c_int) {
+    let function = CStr::from_ptr(function).to_string_lossy().into_owned();
+    INJECTED.with(|injected| {
+        injected.borrow_mut().insert(
+            function,
+            Injection {
+                errno,
+                remaining: count,
+            },
+        )
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn gfapi_stub_clear() {
+    INJECTED.with(|injected| injected.borrow_mut().clear());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gfapi_stub_set_volfile(volfile: *const c_void, len: size_t) {
+    let volfile = std::slice::from_raw_parts(volfile as *const u8, len).to_vec();
+    VOLFILE.with(|current| *current.borrow_mut() = Some(volfile));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gfapi_stub_fs_ids(
+    uid: *mut uid_t,
+    gid: *mut gid_t,
+    groups: *mut gid_t,
+    len: size_t,
+) -> size_t {
+    FS_IDS.with(|ids| {
+        let ids = ids.borrow();
+        *uid = ids.0;
+        *gid = ids.1;
+        let count = ids.2.len().min(len);
+        ptr::copy_nonoverlapping(ids.2.as_ptr(), groups, count);
+        ids.2.len()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn gfapi_stub_unknown_d_type(on: c_int) {
+    UNKNOWN_D_TYPE.with(|unknown| unknown.set(on != 0));
+}
+
+/// The current time as gluster puts it at the start of log lines
+fn log_time() -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let mut buf = [0u8; 32];
+    let len = unsafe {
+        libc::gmtime_r(&secs, &mut tm);
+        libc::strftime(
+            buf.as_mut_ptr() as *mut c_char,
+            buf.len(),
+            b"%Y-%m-%d %H:%M:%S\0".as_ptr() as *const c_char,
+            &tm,
+        )
+    };
+    format!(
+        "{}.{:06} +0000",
+        String::from_utf8_lossy(&buf[..len]),
+        now.subsec_micros()
+    )
+}
+
+fn default_volfile(volname: &str, host: &str) -> Vec<u8> {
+    format!(
+        "volume {vol}-client-0\n    type protocol/client\n    option remote-host {host}\n    \
+         option remote-subvolume /bricks/{vol}\nend-volume\n\nvolume {vol}\n    \
+         type debug/io-stats\n    subvolumes {vol}-client-0\nend-volume\n",
+        vol = volname,
+        host = host
+    )
+    .into_bytes()
+}
+
+/// The same id every time for a volume name
+fn volume_id(volname: &str) -> [u8; 16] {
+    let mut id = [0; 16];
+    for (seed, half) in id.chunks_mut(8).enumerate() {
+        // FNV-1a, seeded differently for each half
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325 ^ seed as u64;
+        for byte in volname.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        half.copy_from_slice(&hash.to_be_bytes());
+    }
+    id
+}
+
+impl glfs {
+    /// Where a path on the volume lives on the host.  .. is resolved
+    /// lexically and never leaves the root.
+    fn volume_path(&self, path: &Path) -> PathBuf {
+        let mut resolved = if path.has_root() {
+            PathBuf::from("/")
+        } else {
+            self.cwd.lock().unwrap().clone()
+        };
+        for component in path.components() {
+            match component {
+                Component::RootDir => resolved = PathBuf::from("/"),
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::Normal(name) => resolved.push(name),
+                Component::CurDir | Component::Prefix(_) => {}
+            }
+        }
+        resolved
+    }
+
+    fn host_path(&self, volume_path: &Path) -> CString {
+        let host = self
+            .root
+            .join(volume_path.strip_prefix("/").unwrap_or(volume_path));
+        CString::new(host.into_os_string().into_vec()).unwrap()
+    }
+}
+
+unsafe fn fs_ref<'a>(fs: *mut glfs) -> Result<&'a glfs, c_int> {
+    fs.as_ref().ok_or(EINVAL)
+}
+
+unsafe fn fd_ref<'a>(fd: *mut glfs_fd) -> Result<&'a glfs_fd, c_int> {
+    fd.as_ref().ok_or(EINVAL)
+}
+
+unsafe fn volume_path(
+    fs: *mut glfs,
+    path: *const c_char,
+) -> Result<(&'static glfs, PathBuf), c_int> {
+    let fs = fs_ref(fs)?;
+    if path.is_null() {
+        return Err(EINVAL);
+    }
+    let path = Path::new(OsStr::from_bytes(CStr::from_ptr(path).to_bytes()));
+    if path.as_os_str().is_empty() {
+        return Err(libc::ENOENT);
+    }
+    Ok((fs, fs.volume_path(path)))
+}
+
+unsafe fn host_path(fs: *mut glfs, path: *const c_char) -> Result<CString, c_int> {
+    let (fs, path) = volume_path(fs, path)?;
+    Ok(fs.host_path(&path))
+}
+
+unsafe fn new_fd(fs: *mut glfs, fd: c_int, dir: *mut DIR, path: PathBuf) -> *mut glfs_fd {
+    Box::into_raw(Box::new(glfs_fd { fs, fd, dir, path }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_new(volname: *const c_char) -> *mut glfs {
+    inject!("glfs_new", ptr::null_mut());
+    if volname.is_null() {
+        set_errno(EINVAL);
+        return ptr::null_mut();
+    }
+    let volname = CStr::from_ptr(volname).to_string_lossy().into_owned();
+    Box::into_raw(Box::new(glfs {
+        volume_id: volume_id(&volname),
+        volname,
+        servers: Vec::new(),
+        root: PathBuf::new(),
+        remove_root: false,
+        volfile: Vec::new(),
+        cwd: Mutex::new(PathBuf::from("/")),
+        log: Mutex::new(None),
+        statedump_path: Mutex::new(PathBuf::from("/var/run/gluster")),
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_set_volfile_server(
+    fs: *mut glfs,
+    _transport: *const c_char,
+    host: *const c_char,
+    _port: c_int,
+) -> c_int {
+    inject!("glfs_set_volfile_server", -1);
+    let fs = try_errno!(fs.as_mut().ok_or(EINVAL), -1);
+    if host.is_null() {
+        set_errno(EINVAL);
+        return -1;
+    }
+    fs.servers
+        .push(CStr::from_ptr(host).to_string_lossy().into_owned());
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_set_logging(
+    fs: *mut glfs,
+    logfile: *const c_char,
+    _loglevel: c_int,
+) -> c_int {
+    inject!("glfs_set_logging", -1);
+    let fs = try_errno!(fs_ref(fs), -1);
+    if logfile.is_null() {
+        set_errno(EINVAL);
+        return -1;
+    }
+    let logfile = OsStr::from_bytes(CStr::from_ptr(logfile).to_bytes());
+    // gluster takes - as stderr, which the stub doesn't bother writing to
+    let log = match logfile.as_bytes() {
+        b"-" => None,
+        _ => Some(try_errno!(
+            std::fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(logfile)
+                .map_err(|e| e.raw_os_error().unwrap_or(libc::EIO)),
+            -1
+        )),
+    };
+    *fs.log.lock().unwrap() = log;
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_set_statedump_path(fs: *mut glfs, path: *const c_char) -> c_int {
+    inject!("glfs_set_statedump_path", -1);
+    let fs = try_errno!(fs_ref(fs), -1);
+    if path.is_null() {
+        set_errno(EINVAL);
+        return -1;
+    }
+    let path = PathBuf::from(OsStr::from_bytes(CStr::from_ptr(path).to_bytes()));
+    match std::fs::metadata(&path) {
+        Ok(meta) if meta.is_dir() => {}
+        Ok(_) => {
+            set_errno(ENOTDIR);
+            return -1;
+        }
+        Err(e) => {
+            set_errno(e.raw_os_error().unwrap_or(libc::EIO));
+            return -1;
+        }
+    }
+    *fs.statedump_path.lock().unwrap() = path;
+    0
+}
+
+/// Only GLFS_SYSRQ_STATEDUMP is understood, which writes a small but
+/// well-formed dump naming the volume's one client xlator
+#[no_mangle]
+pub unsafe extern "C" fn glfs_sysrq(fs: *mut glfs, sysrq: c_char) -> c_int {
+    inject!("glfs_sysrq", -1);
+    let fs = try_errno!(fs_ref(fs), -1);
+    if sysrq as u8 != b's' {
+        set_errno(ENOTSUP);
+        return -1;
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let path = fs.statedump_path.lock().unwrap().join(format!(
+        "glusterdump.{}.dump.{}",
+        std::process::id(),
+        now.as_secs()
+    ));
+    let vol = &fs.volname;
+    let dump = format!(
+        "DUMP-START-TIME: {time}
+
+[mallinfo]
+mallinfo_arena=1052672
+mallinfo_uordblks=851968
+mallinfo_fordblks=200704
+
+[global.glusterfs - Memory usage]
+num_types=2
+
+[global.glusterfs - usage-type gf_common_mt_inode_ctx memusage]
+size=4096
+num_allocs=16
+max_size=8192
+max_num_allocs=32
+total_allocs=48
+
+[global.glusterfs - usage-type gf_common_mt_fdentry_t memusage]
+size=640
+num_allocs=1
+max_size=640
+max_num_allocs=1
+total_allocs=1
+
+[mempool]
+-----=-----
+pool-name=glusterfs:dict_t
+active-count=3
+sizeof-type=160
+padded-sizeof=256
+size=768
+shared-pool=0x0
+-----=-----
+pool-name=glusterfs:data_t
+active-count=7
+sizeof-type=48
+padded-sizeof=128
+size=896
+shared-pool=0x0
+
+[protocol/client.{vol}-client-0 - Memory usage]
+num_types=1
+
+[protocol/client.{vol}-client-0 - usage-type gf_client_mt_clnt_conf_t memusage]
+size=1024
+num_allocs=1
+max_size=1024
+max_num_allocs=1
+total_allocs=1
+
+[xlator.protocol.client.{vol}-client-0.priv]
+xlator.protocol.client.{vol}-client-0.priv.connected=1
+xlator.protocol.client.{vol}-client-0.priv.total_bytes_read=4096
+xlator.protocol.client.{vol}-client-0.priv.total_bytes_written=1024
+
+[xlator.api.{vol}.itable]
+xlator.api.{vol}.itable.hashsize=14057
+xlator.api.{vol}.itable.name={vol}/inode
+xlator.api.{vol}.itable.lru_limit=131072
+xlator.api.{vol}.itable.active_size=2
+xlator.api.{vol}.itable.lru_size=1
+xlator.api.{vol}.itable.purge_size=0
+
+[xlator.api.{vol}.itable.active.1]
+gfid=00000000-0000-0000-0000-000000000001
+nlookup=0
+fd-count=0
+active-fd-count=0
+ref=1
+ia_type=2
+
+[xlator.api.{vol}.itable.active.2]
+gfid=5e6f3c1a-8d2b-4f5e-9a7c-1b2c3d4e5f60
+nlookup=1
+fd-count=1
+active-fd-count=1
+ref=2
+ia_type=1
+
+[xlator.api.{vol}.itable.lru.1]
+gfid=0a1b2c3d-4e5f-6071-8293-a4b5c6d7e8f9
+nlookup=1
+fd-count=0
+active-fd-count=0
+ref=0
+ia_type=1
+
+[xlator.api.{vol}.fdtable]
+xlator.api.{vol}.fdtable.refcount=1
+xlator.api.{vol}.fdtable.maxfds=128
+xlator.api.{vol}.fdtable.first_free=1
+
+[xlator.api.{vol}.fdtable.fdentry[0]]
+pid={pid}
+refcount=1
+flags=2
+
+[global.callpool]
+callpool_address=0x7f0000001000
+callpool.cnt=1
+
+[global.callpool.stack.1]
+stack=0x7f0000002000
+uid=0
+gid=0
+pid={pid}
+unique=42
+lk-owner=0000000000000000
+op=LOOKUP
+type=1
+cnt=2
+
+[global.callpool.stack.1.frame.1]
+frame=0x7f0000003000
+ref_count=0
+translator={vol}-client-0
+complete=0
+parent={vol}
+wind_from=syncop_lookup
+wind_to=subvol->fops->lookup
+unwind_to=syncop_lookup_cbk
+
+[global.callpool.stack.1.frame.2]
+frame=0x7f0000004000
+ref_count=1
+translator={vol}
+complete=0
+
+DUMP-END-TIME: {time}
+",
+        time = log_time(),
+        vol = vol,
+        pid = std::process::id(),
+    );
+    try_errno!(
+        std::fs::write(&path, dump).map_err(|e| e.raw_os_error().unwrap_or(libc::EIO)),
+        -1
+    );
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_init(fs: *mut glfs) -> c_int {
+    inject!("glfs_init", -1);
+    let fs = try_errno!(fs.as_mut().ok_or(EINVAL), -1);
+    if fs.servers.is_empty() {
+        // Real gfapi has nowhere to fetch the volfile from either
+        set_errno(EINVAL);
+        return -1;
+    }
+    match std::env::var_os("GFAPI_STUB_ROOT") {
+        Some(root) => fs.root = PathBuf::from(root),
+        None => {
+            fs.root = std::env::temp_dir().join(format!(
+                "gfapi-stub-{}-{}",
+                std::process::id(),
+                NEXT_ROOT.fetch_add(1, Ordering::SeqCst)
+            ));
+            fs.remove_root = true;
+        }
+    }
+    if let Err(e) = std::fs::create_dir_all(&fs.root) {
+        set_errno(e.raw_os_error().unwrap_or(libc::EIO));
+        return -1;
+    }
+    fs.volfile = VOLFILE
+        .with(|volfile| volfile.borrow().clone())
+        .unwrap_or_else(|| default_volfile(&fs.volname, &fs.servers[0]));
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_fini(fs: *mut glfs) -> c_int {
+    inject!("glfs_fini", -1);
+    if fs.is_null() {
+        set_errno(EINVAL);
+        return -1;
+    }
+    let fs = Box::from_raw(fs);
+    if let Some(log) = fs.log.lock().unwrap().as_mut() {
+        use std::io::Write;
+        let _ = writeln!(
+            log,
+            "[{}] I [MSGID: 101190] [glfs.c:1:glfs_fini] 0-{}-gfapi: unmounting {}",
+            log_time(),
+            fs.volname,
+            fs.volname
+        );
+    }
+    if fs.remove_root {
+        let _ = std::fs::remove_dir_all(&fs.root);
+    }
+    0
+}
+
+/// Returns the volfile length when it fits in len, otherwise how many
+/// bytes too small len is as a negative number
+#[no_mangle]
+pub unsafe extern "C" fn glfs_get_volfile(fs: *mut glfs, buf: *mut c_void, len: size_t) -> ssize_t {
+    inject!("glfs_get_volfile", -1);
+    let fs = try_errno!(fs_ref(fs), -1);
+    if fs.volfile.is_empty() {
+        return 0;
+    }
+    if len < fs.volfile.len() {
+        return -((fs.volfile.len() - len) as ssize_t);
+    }
+    ptr::copy_nonoverlapping(fs.volfile.as_ptr(), buf as *mut u8, fs.volfile.len());
+    fs.volfile.len() as ssize_t
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_get_volumeid(
+    fs: *mut glfs,
+    volid: *mut c_char,
+    size: size_t,
+) -> c_int {
+    inject!("glfs_get_volumeid", -1);
+    let fs = try_errno!(fs_ref(fs), -1);
+    if volid.is_null() || size == 0 {
+        return fs.volume_id.len() as c_int;
+    }
+    if size < fs.volume_id.len() {
+        set_errno(ERANGE);
+        return -1;
+    }
+    ptr::copy_nonoverlapping(fs.volume_id.as_ptr(), volid as *mut u8, fs.volume_id.len());
+    fs.volume_id.len() as c_int
+}
+
+#[no_mangle]
+pub extern "C" fn glfs_setfsuid(fsuid: uid_t) -> c_int {
+    inject!("glfs_setfsuid", -1);
+    FS_IDS.with(|ids| ids.borrow_mut().0 = fsuid);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn glfs_setfsgid(fsgid: gid_t) -> c_int {
+    inject!("glfs_setfsgid", -1);
+    FS_IDS.with(|ids| ids.borrow_mut().1 = fsgid);
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_setfsgroups(size: size_t, list: *const gid_t) -> c_int {
+    inject!("glfs_setfsgroups", -1);
+    let groups = match size {
+        0 => Vec::new(),
+        _ => std::slice::from_raw_parts(list, size).to_vec(),
+    };
+    FS_IDS.with(|ids| ids.borrow_mut().2 = groups);
+    0
+}
+
+unsafe fn open_fd(fs: *mut glfs, path: *const c_char, flags: c_int, mode: mode_t) -> *mut glfs_fd {
+    let (_, path) = try_errno!(volume_path(fs, path), ptr::null_mut());
+    open_volume_path(fs, path, flags, mode)
+}
+
+unsafe fn open_volume_path(
+    fs: *mut glfs,
+    path: PathBuf,
+    flags: c_int,
+    mode: mode_t,
+) -> *mut glfs_fd {
+    let volume = try_errno!(fs_ref(fs), ptr::null_mut());
+    let host = volume.host_path(&path);
+    let fd = libc::open(host.as_ptr(), flags | O_CLOEXEC, mode as libc::c_uint);
+    if fd < 0 {
+        return ptr::null_mut();
+    }
+    new_fd(fs, fd, ptr::null_mut(), path)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_open(
+    fs: *mut glfs,
+    path: *const c_char,
+    flags: c_int,
+) -> *mut glfs_fd {
+    inject!("glfs_open", ptr::null_mut());
+    open_fd(fs, path, flags, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_creat(
+    fs: *mut glfs,
+    path: *const c_char,
+    flags: c_int,
+    mode: mode_t,
+) -> *mut glfs_fd {
+    inject!("glfs_creat", ptr::null_mut());
+    open_fd(fs, path, flags | O_CREAT, mode)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_close(fd: *mut glfs_fd) -> c_int {
+    inject!("glfs_close", -1);
+    if fd.is_null() {
+        set_errno(EINVAL);
+        return -1;
+    }
+    let fd = Box::from_raw(fd);
+    libc::close(fd.fd)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_dup(fd: *mut glfs_fd) -> *mut glfs_fd {
+    inject!("glfs_dup", ptr::null_mut());
+    let fd = try_errno!(fd_ref(fd), ptr::null_mut());
+    let new = libc::fcntl(fd.fd, libc::F_DUPFD_CLOEXEC, 0);
+    if new < 0 {
+        return ptr::null_mut();
+    }
+    new_fd(fd.fs, new, ptr::null_mut(), fd.path.clone())
+}
+
+/// Define glfs functions that forward to the libc function of the same
+/// name on the fd of their first argument
+macro_rules! fd_functions {
+    ($(fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty = $libc:ident;)*) => {
+        $(
+            #[no_mangle]
+            pub unsafe extern "C" fn $name(fd: *mut glfs_fd, $($arg: $ty),*) -> $ret {
+                inject!(stringify!($name), -1);
+                let fd = try_errno!(fd_ref(fd), -1);
+                libc::$libc(fd.fd, $($arg),*) as $ret
+            }
+        )*
+    };
+}
+
+fd_functions! {
+    fn glfs_readv(iov: *const iovec, iovcnt: c_int) -> ssize_t = readv;
+    fn glfs_writev(iov: *const iovec, iovcnt: c_int) -> ssize_t = writev;
+    fn glfs_preadv(iov: *const iovec, iovcnt: c_int, offset: off_t) -> ssize_t = preadv;
+    fn glfs_pwritev(iov: *const iovec, iovcnt: c_int, offset: off_t) -> ssize_t = pwritev;
+    fn glfs_lseek(offset: off_t, whence: c_int) -> off_t = lseek;
+    fn glfs_fstat(buf: *mut stat) -> c_int = fstat;
+    fn glfs_fchmod(mode: mode_t) -> c_int = fchmod;
+    fn glfs_fchown(uid: uid_t, gid: gid_t) -> c_int = fchown;
+    fn glfs_futimens(times: *const timespec) -> c_int = futimens;
+    fn glfs_fgetxattr(name: *const c_char, value: *mut c_void, size: size_t) -> ssize_t = fgetxattr;
+    fn glfs_fsetxattr(
+        name: *const c_char,
+        value: *const c_void,
+        size: size_t,
+        flags: c_int
+    ) -> c_int = fsetxattr;
+    fn glfs_fremovexattr(name: *const c_char) -> c_int = fremovexattr;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_read(
+    fd: *mut glfs_fd,
+    buf: *mut c_void,
+    count: size_t,
+    _flags: c_int,
+) -> ssize_t {
+    inject!("glfs_read", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    libc::read(fd.fd, buf, count)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_write(
+    fd: *mut glfs_fd,
+    buf: *const c_void,
+    count: size_t,
+    _flags: c_int,
+) -> ssize_t {
+    inject!("glfs_write", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    libc::write(fd.fd, buf, count)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_pread(
+    fd: *mut glfs_fd,
+    buf: *mut c_void,
+    count: size_t,
+    offset: off_t,
+    _flags: c_int,
+    _poststat: *mut glfs_stat,
+) -> ssize_t {
+    inject!("glfs_pread", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    libc::pread(fd.fd, buf, count, offset)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_pwrite(
+    fd: *mut glfs_fd,
+    buf: *const c_void,
+    count: size_t,
+    offset: off_t,
+    _flags: c_int,
+    _prestat: *mut glfs_stat,
+    _poststat: *mut glfs_stat,
+) -> ssize_t {
+    inject!("glfs_pwrite", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    libc::pwrite(fd.fd, buf, count, offset)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_copy_file_range(
+    fd_in: *mut glfs_fd,
+    off_in: *mut off_t,
+    fd_out: *mut glfs_fd,
+    off_out: *mut off_t,
+    len: size_t,
+    flags: c_uint,
+    _statbuf: *mut glfs_stat,
+    _prestat: *mut glfs_stat,
+    _poststat: *mut glfs_stat,
+) -> ssize_t {
+    inject!("glfs_copy_file_range", -1);
+    let fd_in = try_errno!(fd_ref(fd_in), -1);
+    let fd_out = try_errno!(fd_ref(fd_out), -1);
+    libc::copy_file_range(fd_in.fd, off_in, fd_out.fd, off_out, len, flags)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_ftruncate(
+    fd: *mut glfs_fd,
+    length: off_t,
+    _prestat: *mut glfs_stat,
+    _poststat: *mut glfs_stat,
+) -> c_int {
+    inject!("glfs_ftruncate", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    libc::ftruncate(fd.fd, length)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_fsync(
+    fd: *mut glfs_fd,
+    _prestat: *mut glfs_stat,
+    _poststat: *mut glfs_stat,
+) -> c_int {
+    inject!("glfs_fsync", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    libc::fsync(fd.fd)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_fdatasync(
+    fd: *mut glfs_fd,
+    _prestat: *mut glfs_stat,
+    _poststat: *mut glfs_stat,
+) -> c_int {
+    inject!("glfs_fdatasync", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    libc::fdatasync(fd.fd)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_fallocate(
+    fd: *mut glfs_fd,
+    keep_size: c_int,
+    offset: off_t,
+    len: size_t,
+) -> c_int {
+    inject!("glfs_fallocate", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    let mode = if keep_size != 0 {
+        FALLOC_FL_KEEP_SIZE
+    } else {
+        0
+    };
+    libc::fallocate(fd.fd, mode, offset, len as off_t)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_discard(fd: *mut glfs_fd, offset: off_t, len: size_t) -> c_int {
+    inject!("glfs_discard", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    libc::fallocate(
+        fd.fd,
+        FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE,
+        offset,
+        len as off_t,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_zerofill(fd: *mut glfs_fd, offset: off_t, len: off_t) -> c_int {
+    inject!("glfs_zerofill", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    libc::fallocate(fd.fd, FALLOC_FL_ZERO_RANGE, offset, len)
+}
+
+/// Like gluster only the fcntl lock commands are accepted
+#[no_mangle]
+pub unsafe extern "C" fn glfs_posix_lock(fd: *mut glfs_fd, cmd: c_int, flock: *mut flock) -> c_int {
+    inject!("glfs_posix_lock", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    if cmd != F_GETLK && cmd != F_SETLK && cmd != F_SETLKW {
+        set_errno(EINVAL);
+        return -1;
+    }
+    libc::fcntl(fd.fd, cmd, flock)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_flistxattr(
+    fd: *mut glfs_fd,
+    value: *mut c_void,
+    size: size_t,
+) -> ssize_t {
+    inject!("glfs_flistxattr", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    libc::flistxattr(fd.fd, value as *mut c_char, size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_fchdir(fd: *mut glfs_fd) -> c_int {
+    inject!("glfs_fchdir", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    let fs = try_errno!(fs_ref(fd.fs), -1);
+    let mut stat_buf: stat = std::mem::zeroed();
+    if libc::fstat(fd.fd, &mut stat_buf) < 0 {
+        return -1;
+    }
+    if stat_buf.st_mode & libc::S_IFMT != libc::S_IFDIR {
+        set_errno(ENOTDIR);
+        return -1;
+    }
+    *fs.cwd.lock().unwrap() = fd.path.clone();
+    0
+}
+
+/// Define glfs functions that forward to the libc function of the same
+/// name on the host path of their first argument
+macro_rules! path_functions {
+    ($(fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty = $libc:ident;)*) => {
+        $(
+            #[no_mangle]
+            pub unsafe extern "C" fn $name(fs: *mut glfs, path: *const c_char, $($arg: $ty),*) -> $ret {
+                inject!(stringify!($name), -1);
+                let path = try_errno!(host_path(fs, path), -1);
+                libc::$libc(path.as_ptr(), $($arg),*) as $ret
+            }
+        )*
+    };
+}
+
+path_functions! {
+    fn glfs_truncate(length: off_t) -> c_int = truncate;
+    fn glfs_stat(buf: *mut stat) -> c_int = stat;
+    fn glfs_lstat(buf: *mut stat) -> c_int = lstat;
+    fn glfs_statvfs(buf: *mut statvfs) -> c_int = statvfs;
+    fn glfs_access(mode: c_int) -> c_int = access;
+    fn glfs_mknod(mode: mode_t, dev: dev_t) -> c_int = mknod;
+    fn glfs_mkdir(mode: mode_t) -> c_int = mkdir;
+    fn glfs_unlink() -> c_int = unlink;
+    fn glfs_rmdir() -> c_int = rmdir;
+    fn glfs_chmod(mode: mode_t) -> c_int = chmod;
+    fn glfs_chown(uid: uid_t, gid: gid_t) -> c_int = chown;
+    fn glfs_lchown(uid: uid_t, gid: gid_t) -> c_int = lchown;
+    fn glfs_getxattr(name: *const c_char, value: *mut c_void, size: size_t) -> ssize_t = getxattr;
+    fn glfs_lgetxattr(name: *const c_char, value: *mut c_void, size: size_t) -> ssize_t = lgetxattr;
+    fn glfs_lsetxattr(
+        name: *const c_char,
+        value: *const c_void,
+        size: size_t,
+        flags: c_int
+    ) -> c_int = lsetxattr;
+    fn glfs_removexattr(name: *const c_char) -> c_int = removexattr;
+    fn glfs_lremovexattr(name: *const c_char) -> c_int = lremovexattr;
+}
+
+/// Like path_functions! but, like the io-stats translator, takes
+/// trusted.io-stats-dump as a request to dump its counters to the named
+/// file under $GFAPI_STUB_RUN_DIR, or /var/run/gluster
+#[no_mangle]
+pub unsafe extern "C" fn glfs_setxattr(
+    fs: *mut glfs,
+    path: *const c_char,
+    name: *const c_char,
+    value: *const c_void,
+    size: size_t,
+    flags: c_int,
+) -> c_int {
+    inject!("glfs_setxattr", -1);
+    let host = try_errno!(host_path(fs, path), -1);
+    if name.is_null() || CStr::from_ptr(name).to_bytes() != b"trusted.io-stats-dump" {
+        return libc::setxattr(host.as_ptr(), name, value, size, flags);
+    }
+    let file = std::slice::from_raw_parts(value as *const u8, size);
+    // io-stats refuses paths but still reports success
+    if file.is_empty() || file.contains(&b'/') {
+        return 0;
+    }
+    let dir = std::env::var_os("GFAPI_STUB_RUN_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/var/run/gluster"));
+    let _ = std::fs::write(dir.join(OsStr::from_bytes(file)), io_stats_dump());
+    0
+}
+
+fn io_stats_dump() -> String {
+    format!(
+        "
+=== Cumulative stats ===
+      Duration : 120 secs
+     BytesRead : 8192
+  BytesWritten : 4608
+
+Block Size   :               1B+             512B+            4096B+
+Read Count   :                 0                 0                 2
+Write Count  :                 1                 8                 0
+
+Block Size   :           131072B+
+Read Count   :                 1
+Write Count  :                 0
+
+Fop           Call Count    Avg-Latency    Min-Latency    Max-Latency
+---           ----------    -----------    -----------    -----------
+WRITE                  9     120.50 us      90.00 us     200.00 us
+READ                   3      80.00 us      70.00 us      90.00 us
+FLUSH                  1           0 us           0 us           0 us
+LOOKUP                12      45.25 us      10.00 us     300.00 us
+------ ----- ----- ----- ----- ----- ----- ----- ----- ----- -----
+
+Current open fd's: 1 Max open fd's: 2 time {time}
+
+==========Open File Stats========
+
+COUNT:  \t  FILE NAME
+
+=== Interval 3 stats ===
+      Duration : 10 secs
+     BytesRead : 0
+  BytesWritten : 512
+
+Block Size   :             512B+
+Read Count   :                 0
+Write Count  :                 1
+
+Fop           Call Count    Avg-Latency    Min-Latency    Max-Latency
+---           ----------    -----------    -----------    -----------
+WRITE                  1      95.00 us      95.00 us      95.00 us
+------ ----- ----- ----- ----- ----- ----- ----- ----- ----- -----
+",
+        time = log_time()
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_listxattr(
+    fs: *mut glfs,
+    path: *const c_char,
+    value: *mut c_void,
+    size: size_t,
+) -> ssize_t {
+    inject!("glfs_listxattr", -1);
+    let path = try_errno!(host_path(fs, path), -1);
+    libc::listxattr(path.as_ptr(), value as *mut c_char, size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_llistxattr(
+    fs: *mut glfs,
+    path: *const c_char,
+    value: *mut c_void,
+    size: size_t,
+) -> ssize_t {
+    inject!("glfs_llistxattr", -1);
+    let path = try_errno!(host_path(fs, path), -1);
+    libc::llistxattr(path.as_ptr(), value as *mut c_char, size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_readlink(
+    fs: *mut glfs,
+    path: *const c_char,
+    buf: *mut c_char,
+    bufsiz: size_t,
+) -> c_int {
+    inject!("glfs_readlink", -1);
+    let path = try_errno!(host_path(fs, path), -1);
+    libc::readlink(path.as_ptr(), buf, bufsiz) as c_int
+}
+
+/// The target is stored as given, relative targets resolve on the host
+/// the same way they would on the volume
+#[no_mangle]
+pub unsafe extern "C" fn glfs_symlink(
+    fs: *mut glfs,
+    oldpath: *const c_char,
+    newpath: *const c_char,
+) -> c_int {
+    inject!("glfs_symlink", -1);
+    let newpath = try_errno!(host_path(fs, newpath), -1);
+    if oldpath.is_null() {
+        set_errno(EINVAL);
+        return -1;
+    }
+    libc::symlink(oldpath, newpath.as_ptr())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_rename(
+    fs: *mut glfs,
+    oldpath: *const c_char,
+    newpath: *const c_char,
+) -> c_int {
+    inject!("glfs_rename", -1);
+    let oldpath = try_errno!(host_path(fs, oldpath), -1);
+    let newpath = try_errno!(host_path(fs, newpath), -1);
+    libc::rename(oldpath.as_ptr(), newpath.as_ptr())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_link(
+    fs: *mut glfs,
+    oldpath: *const c_char,
+    newpath: *const c_char,
+) -> c_int {
+    inject!("glfs_link", -1);
+    let oldpath = try_errno!(host_path(fs, oldpath), -1);
+    let newpath = try_errno!(host_path(fs, newpath), -1);
+    libc::link(oldpath.as_ptr(), newpath.as_ptr())
+}
+
+unsafe fn utimens_at(
+    fs: *mut glfs,
+    path: *const c_char,
+    times: *const timespec,
+    flags: c_int,
+) -> c_int {
+    let path = try_errno!(host_path(fs, path), -1);
+    libc::utimensat(AT_FDCWD, path.as_ptr(), times, flags)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_utimens(
+    fs: *mut glfs,
+    path: *const c_char,
+    times: *const timespec,
+) -> c_int {
+    inject!("glfs_utimens", -1);
+    utimens_at(fs, path, times, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_lutimens(
+    fs: *mut glfs,
+    path: *const c_char,
+    times: *const timespec,
+) -> c_int {
+    inject!("glfs_lutimens", -1);
+    utimens_at(fs, path, times, AT_SYMLINK_NOFOLLOW)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_getcwd(fs: *mut glfs, buf: *mut c_char, size: size_t) -> *mut c_char {
+    inject!("glfs_getcwd", ptr::null_mut());
+    let fs = try_errno!(fs_ref(fs), ptr::null_mut());
+    let cwd = fs.cwd.lock().unwrap();
+    let cwd = cwd.as_os_str().as_bytes();
+    if buf.is_null() || size < cwd.len() + 1 {
+        set_errno(ERANGE);
+        return ptr::null_mut();
+    }
+    ptr::copy_nonoverlapping(cwd.as_ptr(), buf as *mut u8, cwd.len());
+    *buf.add(cwd.len()) = 0;
+    buf
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_chdir(fs: *mut glfs, path: *const c_char) -> c_int {
+    inject!("glfs_chdir", -1);
+    let (volume, path) = try_errno!(volume_path(fs, path), -1);
+    let mut stat_buf: stat = std::mem::zeroed();
+    if libc::stat(volume.host_path(&path).as_ptr(), &mut stat_buf) < 0 {
+        return -1;
+    }
+    if stat_buf.st_mode & libc::S_IFMT != libc::S_IFDIR {
+        set_errno(ENOTDIR);
+        return -1;
+    }
+    *volume.cwd.lock().unwrap() = path;
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_opendir(fs: *mut glfs, path: *const c_char) -> *mut glfs_fd {
+    inject!("glfs_opendir", ptr::null_mut());
+    let (volume, path) = try_errno!(volume_path(fs, path), ptr::null_mut());
+    let dir = libc::opendir(volume.host_path(&path).as_ptr());
+    if dir.is_null() {
+        return ptr::null_mut();
+    }
+    new_fd(fs, libc::dirfd(dir), dir, path)
+}
+
+/// Read the next entry into dirent.  At the end of the directory result
+/// is set to NULL and dirent is left alone, like readdir_r(3).
+unsafe fn next_entry(fd: &glfs_fd, entry: *mut dirent, result: *mut *mut dirent) -> c_int {
+    if fd.dir.is_null() {
+        set_errno(ENOTDIR);
+        return -1;
+    }
+    set_errno(0);
+    let next = libc::readdir(fd.dir);
+    if next.is_null() {
+        if errno() != 0 {
+            return -1;
+        }
+        *result = ptr::null_mut();
+        return 0;
+    }
+    ptr::copy_nonoverlapping(next, entry, 1);
+    if UNKNOWN_D_TYPE.with(Cell::get) {
+        (*entry).d_type = libc::DT_UNKNOWN;
+    }
+    *result = entry;
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_readdir_r(
+    fd: *mut glfs_fd,
+    entry: *mut dirent,
+    result: *mut *mut dirent,
+) -> c_int {
+    inject!("glfs_readdir_r", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    next_entry(fd, entry, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_readdirplus_r(
+    fd: *mut glfs_fd,
+    stat_buf: *mut stat,
+    entry: *mut dirent,
+    result: *mut *mut dirent,
+) -> c_int {
+    inject!("glfs_readdirplus_r", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    if next_entry(fd, entry, result) < 0 {
+        return -1;
+    }
+    if !(*result).is_null() && !stat_buf.is_null() {
+        let name = (*entry).d_name.as_ptr();
+        if libc::fstatat(fd.fd, name, stat_buf, AT_SYMLINK_NOFOLLOW) < 0 {
+            return -1;
+        }
+    }
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_closedir(fd: *mut glfs_fd) -> c_int {
+    inject!("glfs_closedir", -1);
+    if fd.is_null() {
+        set_errno(EINVAL);
+        return -1;
+    }
+    let fd = Box::from_raw(fd);
+    if fd.dir.is_null() {
+        return libc::close(fd.fd);
+    }
+    libc::closedir(fd.dir)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_telldir(fd: *mut glfs_fd) -> libc::c_long {
+    inject!("glfs_telldir", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    if fd.dir.is_null() {
+        set_errno(ENOTDIR);
+        return -1;
+    }
+    libc::telldir(fd.dir)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_seekdir(fd: *mut glfs_fd, offset: libc::c_long) {
+    if injected("glfs_seekdir") {
+        return;
+    }
+    let fd = try_errno!(fd_ref(fd), ());
+    if fd.dir.is_null() {
+        set_errno(ENOTDIR);
+        return;
+    }
+    libc::seekdir(fd.dir, offset)
+}
+
+/// Like glfs_readdirplus_r, but the stat and a handle come back in an
+/// xstat the caller frees with glfs_free.  . and .. get no handle.
+#[no_mangle]
+pub unsafe extern "C" fn glfs_xreaddirplus_r(
+    fd: *mut glfs_fd,
+    flags: u32,
+    xstat_p: *mut *mut glfs_xreaddirp_stat,
+    entry: *mut dirent,
+    result: *mut *mut dirent,
+) -> c_int {
+    inject!("glfs_xreaddirplus_r", -1);
+    let fd = try_errno!(fd_ref(fd), -1);
+    if xstat_p.is_null() {
+        set_errno(EINVAL);
+        return -1;
+    }
+    *xstat_p = ptr::null_mut();
+    if next_entry(fd, entry, result) < 0 {
+        return -1;
+    }
+    if (*result).is_null() {
+        return 0;
+    }
+    let name = CStr::from_ptr((*entry).d_name.as_ptr());
+    let mut xstat = Box::new(glfs_xreaddirp_stat {
+        valid: 0,
+        stat: std::mem::zeroed(),
+        object: ptr::null_mut(),
+    });
+    if flags & GFAPI_XREADDIRP_STAT != 0 {
+        if libc::fstatat(fd.fd, name.as_ptr(), &mut xstat.stat, AT_SYMLINK_NOFOLLOW) < 0 {
+            return -1;
+        }
+        xstat.valid |= GFAPI_XREADDIRP_STAT;
+    }
+    let dots = name.to_bytes() == b"." || name.to_bytes() == b"..";
+    if flags & GFAPI_XREADDIRP_HANDLE != 0 && !dots {
+        xstat.object = Box::into_raw(Box::new(glfs_object {
+            path: fd.path.join(OsStr::from_bytes(name.to_bytes())),
+        }));
+        xstat.valid |= GFAPI_XREADDIRP_HANDLE;
+    }
+    let valid = xstat.valid as c_int;
+    *xstat_p = Box::into_raw(xstat);
+    valid
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_xreaddirplus_get_stat(xstat: *mut glfs_xreaddirp_stat) -> *mut stat {
+    match xstat.as_mut() {
+        Some(xstat) if xstat.valid & GFAPI_XREADDIRP_STAT != 0 => &mut xstat.stat,
+        _ => {
+            set_errno(EINVAL);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_xreaddirplus_get_object(
+    xstat: *mut glfs_xreaddirp_stat,
+) -> *mut glfs_object {
+    match xstat.as_ref() {
+        Some(xstat) if xstat.valid & GFAPI_XREADDIRP_HANDLE != 0 => xstat.object,
+        _ => {
+            set_errno(EINVAL);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// gfapi-sys only frees xstats, so that's all this understands
+#[no_mangle]
+pub unsafe extern "C" fn glfs_free(ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    let xstat = Box::from_raw(ptr as *mut glfs_xreaddirp_stat);
+    if !xstat.object.is_null() {
+        drop(Box::from_raw(xstat.object));
+    }
+}
+
+/// GFIDs aren't emulated, so there's nothing to look a handle up by
+#[no_mangle]
+pub extern "C" fn glfs_h_create_from_handle(
+    _fs: *mut glfs,
+    _handle: *mut c_uchar,
+    _len: c_int,
+    _stat: *mut stat,
+) -> *mut glfs_object {
+    set_errno(ENOSYS);
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_h_close(object: *mut glfs_object) -> c_int {
+    inject!("glfs_h_close", -1);
+    if object.is_null() {
+        set_errno(EINVAL);
+        return -1;
+    }
+    drop(Box::from_raw(object));
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_h_open(
+    fs: *mut glfs,
+    object: *mut glfs_object,
+    flags: c_int,
+) -> *mut glfs_fd {
+    inject!("glfs_h_open", ptr::null_mut());
+    let object = try_errno!(object.as_ref().ok_or(EINVAL), ptr::null_mut());
+    open_volume_path(fs, object.path.clone(), flags, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_h_opendir(fs: *mut glfs, object: *mut glfs_object) -> *mut glfs_fd {
+    inject!("glfs_h_opendir", ptr::null_mut());
+    let volume = try_errno!(fs_ref(fs), ptr::null_mut());
+    let object = try_errno!(object.as_ref().ok_or(EINVAL), ptr::null_mut());
+    let dir = libc::opendir(volume.host_path(&object.path).as_ptr());
+    if dir.is_null() {
+        return ptr::null_mut();
+    }
+    new_fd(fs, libc::dirfd(dir), dir, object.path.clone())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glfs_h_stat(
+    fs: *mut glfs,
+    object: *mut glfs_object,
+    stat_buf: *mut stat,
+) -> c_int {
+    inject!("glfs_h_stat", -1);
+    let volume = try_errno!(fs_ref(fs), -1);
+    let object = try_errno!(object.as_ref().ok_or(EINVAL), -1);
+    libc::lstat(volume.host_path(&object.path).as_ptr(), stat_buf)
+}
+
+#[no_mangle]
+pub extern "C" fn glfs_h_getxattrs(
+    _fs: *mut glfs,
+    _object: *mut glfs_object,
+    _name: *const c_char,
+    _value: *mut c_void,
+    _size: size_t,
+) -> c_int {
+    set_errno(ENOSYS);
+    -1
+}
@@ -0,0 +1,284 @@
+//! POSIX access control lists
+//! Gluster stores ACLs in the same system.posix_acl_* xattrs as the Linux
+//! kernel, so this module encodes and decodes that binary format.
+use crate::gluster::{Gluster, GlusterError};
+use errno::{errno, Errno};
+use libc::{mode_t, ENODATA};
+
+use std::convert::TryInto;
+use std::fmt;
+use std::path::Path;
+
+pub const ACL_ACCESS_KEY: &str = "system.posix_acl_access";
+pub const ACL_DEFAULT_KEY: &str = "system.posix_acl_default";
+
+const ACL_XATTR_VERSION: u32 = 0x0002;
+const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+
+/// Which of the two ACLs on a path to operate on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AclType {
+    /// The ACL checked when accessing the path
+    Access,
+    /// The ACL new entries of a directory inherit
+    Default,
+}
+
+impl AclType {
+    fn xattr_key(self) -> &'static str {
+        match self {
+            AclType::Access => ACL_ACCESS_KEY,
+            AclType::Default => ACL_DEFAULT_KEY,
+        }
+    }
+}
+
+/// Who an ACL entry applies to
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum AclTag {
+    /// The owner of the file
+    UserObj,
+    /// A user named by uid
+    User(u32),
+    /// The owning group of the file
+    GroupObj,
+    /// A group named by gid
+    Group(u32),
+    /// The maximum permissions granted to named users and all groups
+    Mask,
+    /// Everyone else
+    Other,
+}
+
+/// Read, write and execute permission bits
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AclPerms {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl AclPerms {
+    /// Decode the low three bits of a mode, eg 0o5 is r-x
+    pub fn from_bits(bits: u16) -> AclPerms {
+        AclPerms {
+            read: bits & 0o4 != 0,
+            write: bits & 0o2 != 0,
+            execute: bits & 0o1 != 0,
+        }
+    }
+
+    pub fn bits(self) -> u16 {
+        (self.read as u16) << 2 | (self.write as u16) << 1 | self.execute as u16
+    }
+}
+
+impl fmt::Display for AclPerms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            if self.read { 'r' } else { '-' },
+            if self.write { 'w' } else { '-' },
+            if self.execute { 'x' } else { '-' }
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AclEntry {
+    pub tag: AclTag,
+    pub perms: AclPerms,
+}
+
+/// A POSIX ACL.  Entries are kept in the canonical order the kernel and
+/// gluster expect: owner, named users, owning group, named groups, mask
+/// and other.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Acl {
+    entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    /// Build an ACL out of entries in any order.  Returns an error if the
+    /// entries don't form a valid ACL.
+    pub fn new(mut entries: Vec<AclEntry>) -> Result<Acl, GlusterError> {
+        entries.sort_by_key(|e| e.tag);
+        let acl = Acl { entries };
+        acl.validate()?;
+        Ok(acl)
+    }
+
+    /// The minimal ACL equivalent to the permission bits of a mode
+    pub fn from_mode(mode: mode_t) -> Acl {
+        let perms = |shift: u32| AclPerms::from_bits((mode >> shift) as u16 & 0o7);
+        Acl {
+            entries: vec![
+                AclEntry {
+                    tag: AclTag::UserObj,
+                    perms: perms(6),
+                },
+                AclEntry {
+                    tag: AclTag::GroupObj,
+                    perms: perms(3),
+                },
+                AclEntry {
+                    tag: AclTag::Other,
+                    perms: perms(0),
+                },
+            ],
+        }
+    }
+
+    pub fn entries(&self) -> &[AclEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Check the rules from acl(5): exactly one owner, owning group and
+    /// other entry, no duplicate named entries, and a mask whenever there
+    /// are named entries.  An empty ACL is valid and means "no ACL".
+    pub fn validate(&self) -> Result<(), GlusterError> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+        let count = |f: fn(&AclTag) -> bool| self.entries.iter().filter(|e| f(&e.tag)).count();
+        if count(|t| *t == AclTag::UserObj) != 1
+            || count(|t| *t == AclTag::GroupObj) != 1
+            || count(|t| *t == AclTag::Other) != 1
+        {
+            return Err(GlusterError::Error(
+                "acl needs exactly one user::, group:: and other:: entry".into(),
+            ));
+        }
+        if self.entries.windows(2).any(|w| w[0].tag == w[1].tag) {
+            return Err(GlusterError::Error("acl has duplicate entries".into()));
+        }
+        let named = count(|t| matches!(t, AclTag::User(_) | AclTag::Group(_)));
+        if named > 0 && count(|t| *t == AclTag::Mask) != 1 {
+            return Err(GlusterError::Error(
+                "acl with named entries needs a mask:: entry".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decode the value of a system.posix_acl_* xattr
+    pub fn from_xattr(buf: &[u8]) -> Result<Acl, GlusterError> {
+        if buf.len() < 4 || buf.len() % 8 != 4 {
+            return Err(GlusterError::Error(format!(
+                "acl xattr has unexpected length {}",
+                buf.len()
+            )));
+        }
+        let version = u32::from_le_bytes(buf[0..4].try_into().unwrap());
+        if version != ACL_XATTR_VERSION {
+            return Err(GlusterError::Error(format!(
+                "unsupported acl xattr version {}",
+                version
+            )));
+        }
+        let mut entries = Vec::with_capacity((buf.len() - 4) / 8);
+        for chunk in buf[4..].chunks(8) {
+            let tag = u16::from_le_bytes(chunk[0..2].try_into().unwrap());
+            let perm = u16::from_le_bytes(chunk[2..4].try_into().unwrap());
+            let id = u32::from_le_bytes(chunk[4..8].try_into().unwrap());
+            let tag = match tag {
+                ACL_USER_OBJ => AclTag::UserObj,
+                ACL_USER => AclTag::User(id),
+                ACL_GROUP_OBJ => AclTag::GroupObj,
+                ACL_GROUP => AclTag::Group(id),
+                ACL_MASK => AclTag::Mask,
+                ACL_OTHER => AclTag::Other,
+                _ => {
+                    return Err(GlusterError::Error(format!(
+                        "unknown acl entry tag {:#x}",
+                        tag
+                    )))
+                }
+            };
+            entries.push(AclEntry {
+                tag,
+                perms: AclPerms::from_bits(perm),
+            });
+        }
+        Acl::new(entries)
+    }
+
+    /// Encode into the value of a system.posix_acl_* xattr
+    pub fn to_xattr(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(4 + self.entries.len() * 8);
+        buf.extend_from_slice(&ACL_XATTR_VERSION.to_le_bytes());
+        for entry in &self.entries {
+            let (tag, id) = match entry.tag {
+                AclTag::UserObj => (ACL_USER_OBJ, ACL_UNDEFINED_ID),
+                AclTag::User(uid) => (ACL_USER, uid),
+                AclTag::GroupObj => (ACL_GROUP_OBJ, ACL_UNDEFINED_ID),
+                AclTag::Group(gid) => (ACL_GROUP, gid),
+                AclTag::Mask => (ACL_MASK, ACL_UNDEFINED_ID),
+                AclTag::Other => (ACL_OTHER, ACL_UNDEFINED_ID),
+            };
+            buf.extend_from_slice(&tag.to_le_bytes());
+            buf.extend_from_slice(&entry.perms.bits().to_le_bytes());
+            buf.extend_from_slice(&id.to_le_bytes());
+        }
+        buf
+    }
+}
+
+/// Formats the ACL the same way getfacl does, one entry per line
+impl fmt::Display for Acl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            match entry.tag {
+                AclTag::UserObj => writeln!(f, "user::{}", entry.perms)?,
+                AclTag::User(uid) => writeln!(f, "user:{}:{}", uid, entry.perms)?,
+                AclTag::GroupObj => writeln!(f, "group::{}", entry.perms)?,
+                AclTag::Group(gid) => writeln!(f, "group:{}:{}", gid, entry.perms)?,
+                AclTag::Mask => writeln!(f, "mask::{}", entry.perms)?,
+                AclTag::Other => writeln!(f, "other::{}", entry.perms)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Gluster {
+    /// Read the access or default ACL of a path.  A path without an access
+    /// ACL reports the minimal ACL matching its mode, and a directory without
+    /// a default ACL reports an empty one.  The volume must be mounted with
+    /// the acl option for gluster to enforce these.
+    pub fn get_acl(&self, path: &Path, acl_type: AclType) -> Result<Acl, GlusterError> {
+        match self.getxattr_bytes(path, acl_type.xattr_key()) {
+            Ok(buf) => Acl::from_xattr(&buf),
+            Err(_) if errno() == Errno(ENODATA) => match acl_type {
+                AclType::Access => Ok(Acl::from_mode(self.stat(path)?.st_mode)),
+                AclType::Default => Ok(Acl::default()),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replace the access or default ACL of a path.  Setting an empty
+    /// default ACL removes it.
+    pub fn set_acl(&self, path: &Path, acl_type: AclType, acl: &Acl) -> Result<(), GlusterError> {
+        acl.validate()?;
+        if acl.is_empty() {
+            return match acl_type {
+                AclType::Access => Err(GlusterError::Error("an access acl can't be empty".into())),
+                AclType::Default => self.removexattr(path, ACL_DEFAULT_KEY),
+            };
+        }
+        self.setxattr(path, acl_type.xattr_key(), &acl.to_xattr(), 0)
+    }
+}
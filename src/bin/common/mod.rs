@@ -0,0 +1,326 @@
+//! What gfcli and gfsh share: listing, copying and the ls/stat formats
+// Each binary uses only some of this
+#![allow(dead_code)]
+
+use errno::errno;
+use gfapi_sys::gluster::{Gluster, GlusterError, GlusterFile};
+use libc::{
+    c_char, stat, statvfs, EEXIST, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFREG,
+    S_IFSOCK,
+};
+
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+pub const CHUNK: usize = 1 << 20;
+
+pub type CliResult<T> = Result<T, String>;
+
+/// Name the path a failure happened on
+pub trait At<T> {
+    fn at(self, path: &Path) -> CliResult<T>;
+}
+
+impl<T> At<T> for Result<T, GlusterError> {
+    fn at(self, path: &Path) -> CliResult<T> {
+        self.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+impl<T> At<T> for io::Result<T> {
+    fn at(self, path: &Path) -> CliResult<T> {
+        self.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+pub fn file_name(path: &Path) -> CliResult<&std::ffi::OsStr> {
+    path.file_name()
+        .ok_or_else(|| format!("{}: has no file name", path.display()))
+}
+
+pub fn is_dir(st: &stat) -> bool {
+    st.st_mode & S_IFMT == S_IFDIR
+}
+
+pub fn file_type(mode: u32) -> &'static str {
+    match mode & S_IFMT {
+        S_IFDIR => "directory",
+        S_IFREG => "file",
+        S_IFLNK => "symlink",
+        S_IFCHR => "char-device",
+        S_IFBLK => "block-device",
+        S_IFIFO => "fifo",
+        S_IFSOCK => "socket",
+        _ => "unknown",
+    }
+}
+
+/// The mode the way ls -l shows it, like drwxr-xr-x
+pub fn mode_string(mode: u32) -> String {
+    let kind = match mode & S_IFMT {
+        S_IFDIR => 'd',
+        S_IFLNK => 'l',
+        S_IFCHR => 'c',
+        S_IFBLK => 'b',
+        S_IFIFO => 'p',
+        S_IFSOCK => 's',
+        _ => '-',
+    };
+    let mut text = kind.to_string();
+    for (shift, special, set, unset) in &[
+        (6, 0o4000, 's', 'S'),
+        (3, 0o2000, 's', 'S'),
+        (0, 0o1000, 't', 'T'),
+    ] {
+        let bits = (mode >> shift) & 7;
+        text.push(if bits & 4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 2 != 0 { 'w' } else { '-' });
+        text.push(match (mode & special != 0, bits & 1 != 0) {
+            (true, true) => *set,
+            (true, false) => *unset,
+            (false, true) => 'x',
+            (false, false) => '-',
+        });
+    }
+    text
+}
+
+pub fn local_time(secs: i64, format: &str) -> String {
+    let format = std::ffi::CString::new(format).unwrap();
+    let mut buf = [0u8; 64];
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        let time = secs as libc::time_t;
+        libc::localtime_r(&time, &mut tm);
+        let len = libc::strftime(
+            buf.as_mut_ptr() as *mut c_char,
+            buf.len(),
+            format.as_ptr(),
+            &tm,
+        );
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    }
+}
+
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["", "K", "M", "G", "T", "P"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}", bytes)
+    } else if size < 10.0 {
+        format!("{:.1}{}", size, UNITS[unit])
+    } else {
+        format!("{:.0}{}", size, UNITS[unit])
+    }
+}
+
+/// Size, used and available bytes of a filesystem
+// The block counts are u32 on some platforms
+#[allow(clippy::unnecessary_cast)]
+pub fn space(vfs: &statvfs) -> (u64, u64, u64) {
+    let block = vfs.f_frsize as u64;
+    (
+        vfs.f_blocks as u64 * block,
+        (vfs.f_blocks - vfs.f_bfree) as u64 * block,
+        vfs.f_bavail as u64 * block,
+    )
+}
+
+pub fn read_link(cluster: &Gluster, path: &Path) -> Option<String> {
+    // readlink doesn't say how much it filled, so start zeroed
+    let mut buf = vec![0u8; 4096];
+    cluster.readlink(path, &mut buf).ok()?;
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// Stream a whole file to out, returning how much was copied
+pub fn copy_out(file: &GlusterFile, out: &mut dyn Write) -> Result<u64, GlusterError> {
+    let mut offset = 0;
+    loop {
+        let mut chunk = Vec::with_capacity(CHUNK);
+        let read = file.pread(&mut chunk, CHUNK, offset, 0)?;
+        if read == 0 {
+            return Ok(offset as u64);
+        }
+        out.write_all(&chunk)?;
+        offset += read as i64;
+    }
+}
+
+/// Write everything from input to file, returning how much was copied
+pub fn copy_in(input: &mut dyn Read, file: &GlusterFile) -> Result<u64, GlusterError> {
+    let mut buf = vec![0; CHUNK];
+    let mut offset = 0;
+    loop {
+        let count = match input.read(&mut buf) {
+            Ok(0) => return Ok(offset as u64),
+            Ok(count) => count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let mut written = 0;
+        while written < count {
+            let wrote = file.pwrite(&buf[written..count], count - written, offset, 0)?;
+            written += wrote as usize;
+            offset += wrote as i64;
+        }
+    }
+}
+
+pub fn parse_mode(mode: &str) -> CliResult<u32> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("{} isn't an octal mode", mode))
+}
+
+/// uid[:gid] or :gid as numbers, with what's left out unchanged
+pub fn parse_owner(owner: &str) -> CliResult<(u32, u32)> {
+    let id = |id: &str| -> CliResult<u32> {
+        if id.is_empty() {
+            return Ok(u32::MAX);
+        }
+        id.parse().map_err(|_| {
+            format!(
+                "{} isn't a numeric id, names can't be looked up remotely",
+                id
+            )
+        })
+    };
+    match owner.find(':') {
+        Some(i) => Ok((id(&owner[..i])?, id(&owner[i + 1..])?)),
+        None => Ok((id(owner)?, u32::MAX)),
+    }
+}
+
+/// A directory entry as ls shows it
+pub struct Entry {
+    pub name: String,
+    pub stat: stat,
+    /// Where a symlink points, when asked for
+    pub target: Option<String>,
+}
+
+/// The entries of the directory at path sorted by name, or just path
+/// itself if it isn't a directory.  Hidden names are left out unless all
+/// is set and symlinks are only read when links is.
+pub fn list(cluster: &Gluster, path: &Path, all: bool, links: bool) -> CliResult<Vec<Entry>> {
+    let st = cluster.lsstat(path).at(path)?;
+    if !is_dir(&st) {
+        let target = match st.st_mode & S_IFMT {
+            S_IFLNK if links => read_link(cluster, path),
+            _ => None,
+        };
+        return Ok(vec![Entry {
+            name: path.display().to_string(),
+            stat: st,
+            target,
+        }]);
+    }
+    let mut entries = Vec::new();
+    for entry in cluster.opendir_plus(path).at(path)? {
+        let entry = entry.at(path)?;
+        let name = entry.path.to_string_lossy().into_owned();
+        if name.starts_with('.') && !all {
+            continue;
+        }
+        let target = match entry.stat.st_mode & S_IFMT {
+            S_IFLNK if links => read_link(cluster, &path.join(&entry.path)),
+            _ => None,
+        };
+        entries.push(Entry {
+            name,
+            stat: entry.stat,
+            target,
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// Print entries the way ls -l does, with the columns lined up
+pub fn print_long(entries: &[Entry]) {
+    let width = |column: &dyn Fn(&stat) -> String| {
+        entries
+            .iter()
+            .map(|entry| column(&entry.stat).len())
+            .max()
+            .unwrap_or(0)
+    };
+    let nlink = width(&|st| st.st_nlink.to_string());
+    let uid = width(&|st| st.st_uid.to_string());
+    let gid = width(&|st| st.st_gid.to_string());
+    let size = width(&|st| st.st_size.to_string());
+    for entry in entries {
+        let st = &entry.stat;
+        let name = match entry.target {
+            Some(ref target) => format!("{} -> {}", entry.name, target),
+            None => entry.name.clone(),
+        };
+        println!(
+            "{} {:>nlink$} {:>uid$} {:>gid$} {:>size$} {} {}",
+            mode_string(st.st_mode),
+            st.st_nlink,
+            st.st_uid,
+            st.st_gid,
+            st.st_size,
+            local_time(st.st_mtime, "%Y-%m-%d %H:%M"),
+            name,
+            nlink = nlink,
+            uid = uid,
+            gid = gid,
+            size = size
+        );
+    }
+}
+
+/// Print what's known about path the way stat does
+pub fn print_status(path: &Path, st: &stat, target: Option<&str>) {
+    match target {
+        Some(target) => println!("  File: {} -> {}", path.display(), target),
+        None => println!("  File: {}", path.display()),
+    }
+    println!(
+        "  Size: {:<12} Blocks: {:<10} {}",
+        st.st_size,
+        st.st_blocks,
+        file_type(st.st_mode)
+    );
+    println!(" Inode: {:<12} Links: {}", st.st_ino, st.st_nlink);
+    println!(
+        "Access: ({:04o}/{})  Uid: {}  Gid: {}",
+        st.st_mode & 0o7777,
+        mode_string(st.st_mode),
+        st.st_uid,
+        st.st_gid
+    );
+    let format = "%Y-%m-%d %H:%M:%S %z";
+    println!("Access: {}", local_time(st.st_atime, format));
+    println!("Modify: {}", local_time(st.st_mtime, format));
+    println!("Change: {}", local_time(st.st_ctime, format));
+}
+
+/// mkdir -p: create path and whatever leads to it, accepting directories
+/// that already exist
+pub fn make_dirs(cluster: &Gluster, path: &Path, mode: u32) -> CliResult<()> {
+    let mut dir = PathBuf::new();
+    for component in path.components() {
+        dir.push(component);
+        if let Component::RootDir | Component::CurDir | Component::ParentDir = component {
+            continue;
+        }
+        if let Err(e) = cluster.mkdir(&dir, mode) {
+            let exists = errno().0 == EEXIST;
+            if !exists || !is_dir(&cluster.stat(&dir).at(&dir)?) {
+                return Err(e).at(&dir);
+            }
+        }
+    }
+    Ok(())
+}
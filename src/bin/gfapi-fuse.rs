@@ -0,0 +1,138 @@
+//! Mount a gluster volume, or a directory of one, through gfapi and FUSE
+//! gfapi-fuse [OPTIONS] <SERVER> <VOLUME> <MOUNTPOINT>
+use clap::{App, Arg, ArgMatches};
+use gfapi_sys::fuse::{FuseOptions, GlusterFuse};
+use gfapi_sys::gluster::{Gluster, GlusterLogLevel};
+
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::Duration;
+
+fn seconds(matches: &ArgMatches<'_>, name: &str) -> Duration {
+    let value = matches.value_of(name).unwrap();
+    match value.parse::<f64>() {
+        Ok(secs) if secs >= 0.0 => Duration::from_secs_f64(secs),
+        _ => {
+            eprintln!("--{} must be a number of seconds, not {}", name, value);
+            exit(2);
+        }
+    }
+}
+
+fn main() {
+    let matches = App::new("gfapi-fuse")
+        .about("Mount a gluster volume through gfapi and FUSE")
+        .arg(
+            Arg::with_name("server")
+                .help("Server to fetch the volfile from")
+                .required(true),
+        )
+        .arg(Arg::with_name("volume").required(true))
+        .arg(Arg::with_name("mountpoint").required(true))
+        .arg(
+            Arg::with_name("port")
+                .long("port")
+                .takes_value(true)
+                .default_value("24007"),
+        )
+        .arg(
+            Arg::with_name("subdir")
+                .long("subdir")
+                .takes_value(true)
+                .default_value("/")
+                .help("Directory of the volume to mount instead of its root"),
+        )
+        .arg(
+            Arg::with_name("attr-timeout")
+                .long("attr-timeout")
+                .takes_value(true)
+                .default_value("1")
+                .help("Seconds file attributes are cached for"),
+        )
+        .arg(
+            Arg::with_name("entry-timeout")
+                .long("entry-timeout")
+                .takes_value(true)
+                .default_value("1")
+                .help("Seconds name lookups are cached for"),
+        )
+        .arg(
+            Arg::with_name("read-only")
+                .long("read-only")
+                .help("Refuse all modifications"),
+        )
+        .arg(
+            Arg::with_name("impersonate")
+                .long("impersonate")
+                .help("Access the volume as the user making each request"),
+        )
+        .arg(
+            Arg::with_name("root-squash")
+                .long("root-squash")
+                .requires("impersonate")
+                .help("Access the volume as nobody on behalf of root"),
+        )
+        .arg(
+            Arg::with_name("allow-other")
+                .long("allow-other")
+                .help("Let other users access the mount"),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .takes_value(true)
+                .help("Where gfapi writes its own log"),
+        )
+        .get_matches();
+
+    let server = matches.value_of("server").unwrap();
+    let volume = matches.value_of("volume").unwrap();
+    let port = match matches.value_of("port").unwrap().parse::<u16>() {
+        Ok(port) => port,
+        Err(_) => {
+            eprintln!("--port must be a port number");
+            exit(2);
+        }
+    };
+    let subdir = PathBuf::from(matches.value_of("subdir").unwrap());
+    let options = FuseOptions {
+        fsname: format!(
+            "{}:{}{}",
+            server,
+            volume,
+            Path::new("/").join(&subdir).display()
+        ),
+        subdir,
+        attr_timeout: seconds(&matches, "attr-timeout"),
+        entry_timeout: seconds(&matches, "entry-timeout"),
+        read_only: matches.is_present("read-only"),
+        impersonate: matches.is_present("impersonate"),
+        root_squash: matches.is_present("root-squash"),
+        allow_other: matches.is_present("allow-other"),
+    };
+
+    let cluster = match Gluster::connect(volume, server, port) {
+        Ok(cluster) => cluster,
+        Err(e) => {
+            eprintln!("connecting to {} on {} failed: {}", volume, server, e);
+            exit(1);
+        }
+    };
+    if let Some(log_file) = matches.value_of("log-file") {
+        if let Err(e) = cluster.set_logging(Path::new(log_file), GlusterLogLevel::Info) {
+            eprintln!("setting the gfapi log file failed: {}", e);
+            exit(1);
+        }
+    }
+    let fs = match GlusterFuse::new(cluster, options) {
+        Ok(fs) => fs,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+    if let Err(e) = fs.mount(matches.value_of("mountpoint").unwrap()) {
+        eprintln!("mount failed: {}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,157 @@
+//! Serve the directories of a gluster volume as S3 buckets
+//! gfapi-s3 [OPTIONS] <SERVER> <VOLUME>
+use clap::{App, Arg};
+use gfapi_sys::gluster::{Gluster, GlusterLogLevel};
+use gfapi_sys::s3::{S3Gateway, S3Request};
+use tiny_http::{Header, Response, Server, StatusCode};
+
+use std::path::Path;
+use std::process::exit;
+use std::sync::Arc;
+use std::thread;
+
+fn serve(gateway: &S3Gateway<Gluster>, mut request: tiny_http::Request) {
+    let method = request.method().as_str().to_string();
+    let url = request.url().to_string();
+    let (path, query) = match url.find('?') {
+        Some(i) => (&url[..i], &url[i + 1..]),
+        None => (url.as_str(), ""),
+    };
+    let headers: Vec<(String, String)> = request
+        .headers()
+        .iter()
+        .map(|header| {
+            (
+                header.field.as_str().as_str().to_string(),
+                header.value.as_str().to_string(),
+            )
+        })
+        .collect();
+    let response = gateway.handle(S3Request {
+        method: &method,
+        path,
+        query,
+        headers: &headers,
+        body: request.as_reader(),
+    });
+    let headers = response
+        .headers
+        .iter()
+        .filter_map(|(name, value)| Header::from_bytes(name.as_bytes(), value.as_bytes()).ok())
+        .collect();
+    let reply = Response::new(
+        StatusCode(response.status),
+        headers,
+        response.body,
+        Some(response.content_length as usize),
+        None,
+    )
+    // Clients need the length of what HEAD describes, and it's known anyway
+    .with_chunked_threshold(usize::MAX);
+    if let Err(e) = request.respond(reply) {
+        eprintln!("replying to {} {} failed: {}", method, url, e);
+    }
+}
+
+fn main() {
+    let matches = App::new("gfapi-s3")
+        .about("Serve the directories of a gluster volume as S3 buckets")
+        .arg(
+            Arg::with_name("server")
+                .help("Server to fetch the volfile from")
+                .required(true),
+        )
+        .arg(Arg::with_name("volume").required(true))
+        .arg(
+            Arg::with_name("port")
+                .long("port")
+                .takes_value(true)
+                .default_value("24007"),
+        )
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .takes_value(true)
+                .default_value("127.0.0.1:9000")
+                .help("Address to serve S3 requests on"),
+        )
+        .arg(
+            Arg::with_name("root")
+                .long("root")
+                .takes_value(true)
+                .default_value("/")
+                .help("Directory of the volume whose subdirectories are the buckets"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .takes_value(true)
+                .default_value("8")
+                .help("Requests served at once"),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .takes_value(true)
+                .help("Where gfapi writes its own log"),
+        )
+        .get_matches();
+
+    let server = matches.value_of("server").unwrap();
+    let volume = matches.value_of("volume").unwrap();
+    let port = match matches.value_of("port").unwrap().parse::<u16>() {
+        Ok(port) => port,
+        Err(_) => {
+            eprintln!("--port must be a port number");
+            exit(2);
+        }
+    };
+    let threads = match matches.value_of("threads").unwrap().parse::<usize>() {
+        Ok(threads) if threads > 0 => threads,
+        _ => {
+            eprintln!("--threads must be a positive number");
+            exit(2);
+        }
+    };
+
+    let cluster = match Gluster::connect(volume, server, port) {
+        Ok(cluster) => cluster,
+        Err(e) => {
+            eprintln!("connecting to {} on {} failed: {}", volume, server, e);
+            exit(1);
+        }
+    };
+    if let Some(log_file) = matches.value_of("log-file") {
+        if let Err(e) = cluster.set_logging(Path::new(log_file), GlusterLogLevel::Info) {
+            eprintln!("setting the gfapi log file failed: {}", e);
+            exit(1);
+        }
+    }
+    let listen = matches.value_of("listen").unwrap();
+    let http = match Server::http(listen) {
+        Ok(http) => Arc::new(http),
+        Err(e) => {
+            eprintln!("listening on {} failed: {}", listen, e);
+            exit(1);
+        }
+    };
+    let gateway = Arc::new(S3Gateway::new(
+        cluster,
+        Path::new(matches.value_of("root").unwrap()),
+    ));
+
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let http = Arc::clone(&http);
+            let gateway = Arc::clone(&gateway);
+            thread::spawn(move || {
+                while let Ok(request) = http.recv() {
+                    serve(&gateway, request);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
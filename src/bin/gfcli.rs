@@ -0,0 +1,627 @@
+//! Command line access to gluster volumes where FUSE mounts aren't allowed
+//! gfcli [--json] <COMMAND> gluster://host[:port]/volume/path ...
+mod common;
+
+use crate::common::*;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use errno::errno;
+use gfapi_sys::gluster::Gluster;
+use gfapi_sys::url::GlusterUrl;
+use libc::{stat, ENOENT, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY, S_IFLNK, S_IFMT};
+use serde_json::{json, Value};
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/// Commands given several URLs usually name one volume, so keep the last
+/// connection around rather than reconnecting for each
+#[derive(Default)]
+struct Connections {
+    current: Option<(GlusterUrl, Gluster)>,
+}
+
+impl Connections {
+    fn get(&mut self, url: &GlusterUrl) -> CliResult<&Gluster> {
+        let reuse = match self.current {
+            Some((ref connected, _)) => connected.same_volume(url),
+            None => false,
+        };
+        if !reuse {
+            let cluster = url
+                .connect()
+                .map_err(|e| format!("connecting to {} on {}: {}", url.volume, url.host, e))?;
+            self.current = Some((url.clone(), cluster));
+        }
+        Ok(&self.current.as_ref().unwrap().1)
+    }
+}
+
+fn urls(matches: &ArgMatches<'_>, name: &str) -> CliResult<Vec<GlusterUrl>> {
+    matches
+        .values_of(name)
+        .unwrap()
+        .map(|url| GlusterUrl::parse(url).map_err(|e| e.to_string()))
+        .collect()
+}
+
+fn url(matches: &ArgMatches<'_>, name: &str) -> CliResult<GlusterUrl> {
+    GlusterUrl::parse(matches.value_of(name).unwrap()).map_err(|e| e.to_string())
+}
+
+fn stat_json(name: &str, st: &stat, target: Option<&str>) -> Value {
+    let mut value = json!({
+        "name": name,
+        "type": file_type(st.st_mode),
+        "mode": format!("{:04o}", st.st_mode & 0o7777),
+        "size": st.st_size,
+        "uid": st.st_uid,
+        "gid": st.st_gid,
+        "nlink": st.st_nlink,
+        "inode": st.st_ino,
+        "blocks": st.st_blocks,
+        "atime": st.st_atime,
+        "mtime": st.st_mtime,
+        "ctime": st.st_ctime,
+    });
+    if let Some(target) = target {
+        value["target"] = json!(target);
+    }
+    value
+}
+
+fn print_json(value: &Value) {
+    println!("{}", value);
+}
+
+fn ls(matches: &ArgMatches<'_>, json: bool) -> CliResult<()> {
+    let long = matches.is_present("long");
+    let all = matches.is_present("all");
+    let mut connections = Connections::default();
+    let mut listings = Vec::new();
+    for url in urls(matches, "url")? {
+        let entries = list(connections.get(&url)?, &url.path, all, long || json)?;
+        listings.push((url, entries));
+    }
+
+    if json {
+        let listings: Vec<Value> = listings
+            .iter()
+            .map(|(url, entries)| {
+                json!({
+                    "url": url.to_string(),
+                    "entries": entries
+                        .iter()
+                        .map(|entry| stat_json(&entry.name, &entry.stat, entry.target.as_deref()))
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        print_json(&Value::Array(listings));
+        return Ok(());
+    }
+    let headings = listings.len() > 1;
+    for (i, (url, entries)) in listings.iter().enumerate() {
+        if headings {
+            if i > 0 {
+                println!();
+            }
+            println!("{}:", url);
+        }
+        if long {
+            print_long(entries);
+        } else {
+            for entry in entries {
+                println!("{}", entry.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cat(matches: &ArgMatches<'_>) -> CliResult<()> {
+    let mut connections = Connections::default();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for url in urls(matches, "url")? {
+        let file = connections
+            .get(&url)?
+            .open(&url.path, O_RDONLY)
+            .at(&url.path)?;
+        copy_out(&file, &mut out).at(&url.path)?;
+    }
+    out.flush().map_err(|e| e.to_string())
+}
+
+fn put(matches: &ArgMatches<'_>, json: bool) -> CliResult<()> {
+    let local = Path::new(matches.value_of("local").unwrap());
+    let mut url = url(matches, "url")?;
+    let cluster = url.connect().map_err(|e| e.to_string())?;
+    let stdin = local == Path::new("-");
+    // Putting into a directory keeps the local name
+    if let Ok(st) = cluster.stat(&url.path) {
+        if is_dir(&st) {
+            if stdin {
+                return Err(format!("{}: is a directory", url.path.display()));
+            }
+            url.path = url.path.join(file_name(local)?);
+        }
+    }
+    let (mut input, mode): (Box<dyn Read>, u32) = if stdin {
+        (Box::new(io::stdin()), 0o644)
+    } else {
+        let file = File::open(local).at(local)?;
+        let mode = file.metadata().at(local)?.permissions().mode() & 0o777;
+        (Box::new(file), mode)
+    };
+    let file = cluster
+        .create(&url.path, O_WRONLY | O_CREAT | O_TRUNC, mode)
+        .at(&url.path)?;
+    let bytes = copy_in(&mut input, &file).at(&url.path)?;
+    file.fsync().at(&url.path)?;
+    if json {
+        print_json(&json!({ "url": url.to_string(), "bytes": bytes }));
+    }
+    Ok(())
+}
+
+fn get(matches: &ArgMatches<'_>, json: bool) -> CliResult<()> {
+    let url = url(matches, "url")?;
+    let cluster = url.connect().map_err(|e| e.to_string())?;
+    let file = cluster.open(&url.path, O_RDONLY).at(&url.path)?;
+    let name = file_name(&url.path)?;
+    let mut local = PathBuf::from(matches.value_of("local").unwrap_or(&name.to_string_lossy()));
+    let bytes = if local == Path::new("-") {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        let bytes = copy_out(&file, &mut out).at(&url.path)?;
+        out.flush().map_err(|e| e.to_string())?;
+        bytes
+    } else {
+        if local.is_dir() {
+            local.push(name);
+        }
+        let mut out = File::create(&local).at(&local)?;
+        copy_out(&file, &mut out).at(&url.path)?
+    };
+    if json {
+        print_json(&json!({ "path": local.display().to_string(), "bytes": bytes }));
+    }
+    Ok(())
+}
+
+fn status(matches: &ArgMatches<'_>, json: bool) -> CliResult<()> {
+    let mut connections = Connections::default();
+    let mut stats = Vec::new();
+    for url in urls(matches, "url")? {
+        let cluster = connections.get(&url)?;
+        let st = cluster.lsstat(&url.path).at(&url.path)?;
+        let target = match st.st_mode & S_IFMT {
+            S_IFLNK => read_link(cluster, &url.path),
+            _ => None,
+        };
+        if json {
+            stats.push(stat_json(
+                &url.path.display().to_string(),
+                &st,
+                target.as_deref(),
+            ));
+            continue;
+        }
+        print_status(&url.path, &st, target.as_deref());
+    }
+    if json {
+        print_json(&Value::Array(stats));
+    }
+    Ok(())
+}
+
+fn mkdir(matches: &ArgMatches<'_>) -> CliResult<()> {
+    let mode = parse_mode(matches.value_of("mode").unwrap())?;
+    let parents = matches.is_present("parents");
+    let mut connections = Connections::default();
+    for url in urls(matches, "url")? {
+        let cluster = connections.get(&url)?;
+        if parents {
+            make_dirs(cluster, &url.path, mode)?;
+        } else {
+            cluster.mkdir(&url.path, mode).at(&url.path)?;
+        }
+    }
+    Ok(())
+}
+
+fn rm(matches: &ArgMatches<'_>) -> CliResult<()> {
+    let recursive = matches.is_present("recursive");
+    let force = matches.is_present("force");
+    let mut connections = Connections::default();
+    for url in urls(matches, "url")? {
+        let cluster = connections.get(&url)?;
+        let st = match cluster.lsstat(&url.path) {
+            Ok(st) => st,
+            Err(_) if force && errno().0 == ENOENT => continue,
+            Err(e) => return Err(e).at(&url.path),
+        };
+        if !is_dir(&st) {
+            cluster.unlink(&url.path).at(&url.path)?;
+        } else if recursive {
+            cluster.remove_dir_all(&url.path).at(&url.path)?;
+        } else {
+            return Err(format!("{}: is a directory, use -r", url.path.display()));
+        }
+    }
+    Ok(())
+}
+
+fn mv(matches: &ArgMatches<'_>) -> CliResult<()> {
+    let url = url(matches, "url")?;
+    let mut dest = url
+        .resolve(matches.value_of("dest").unwrap())
+        .map_err(|e| e.to_string())?;
+    let cluster = url.connect().map_err(|e| e.to_string())?;
+    if let Ok(st) = cluster.stat(&dest) {
+        if is_dir(&st) {
+            dest.push(file_name(&url.path)?);
+        }
+    }
+    cluster.rename(&url.path, &dest).at(&url.path)
+}
+
+fn ln(matches: &ArgMatches<'_>) -> CliResult<()> {
+    let url = url(matches, "url")?;
+    let target = matches.value_of("target").unwrap();
+    let cluster = url.connect().map_err(|e| e.to_string())?;
+    if matches.is_present("symbolic") {
+        // Symlinks keep their target as written, relative or not
+        cluster.symlink(Path::new(target), &url.path).at(&url.path)
+    } else {
+        let target = url.resolve(target).map_err(|e| e.to_string())?;
+        cluster.link(&target, &url.path).at(&url.path)
+    }
+}
+
+fn chmod(matches: &ArgMatches<'_>) -> CliResult<()> {
+    let mode = parse_mode(matches.value_of("mode").unwrap())?;
+    let mut connections = Connections::default();
+    for url in urls(matches, "url")? {
+        connections
+            .get(&url)?
+            .chmod(&url.path, mode)
+            .at(&url.path)?;
+    }
+    Ok(())
+}
+
+fn chown(matches: &ArgMatches<'_>) -> CliResult<()> {
+    let (uid, gid) = parse_owner(matches.value_of("owner").unwrap())?;
+    let mut connections = Connections::default();
+    for url in urls(matches, "url")? {
+        let cluster = connections.get(&url)?;
+        if matches.is_present("no-dereference") {
+            cluster.lchown(&url.path, uid, gid).at(&url.path)?;
+        } else {
+            cluster.chown(&url.path, uid, gid).at(&url.path)?;
+        }
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn xattr(matches: &ArgMatches<'_>, json: bool) -> CliResult<()> {
+    let (command, matches) = match matches.subcommand() {
+        (command, Some(matches)) => (command, matches),
+        _ => unreachable!(),
+    };
+    let url = url(matches, "url")?;
+    let cluster = url.connect().map_err(|e| e.to_string())?;
+    match command {
+        "get" => {
+            let name = matches.value_of("name").unwrap();
+            let value = cluster.getxattr_bytes(&url.path, name).at(&url.path)?;
+            // Gluster's own attributes are mostly binary
+            let text = match (matches.is_present("hex"), String::from_utf8(value.clone())) {
+                (false, Ok(text)) => text.trim_end_matches('\0').to_string(),
+                _ => format!("0x{}", hex(&value)),
+            };
+            if json {
+                print_json(&json!({ "name": name, "value": text }));
+            } else {
+                println!("{}", text);
+            }
+        }
+        "set" => {
+            let name = matches.value_of("name").unwrap();
+            let value = matches.value_of("value").unwrap();
+            cluster
+                .setxattr(&url.path, name, value.as_bytes(), 0)
+                .at(&url.path)?;
+        }
+        "list" => {
+            let names = cluster.listxattr(&url.path).at(&url.path)?;
+            let names: Vec<&str> = names.split('\0').filter(|name| !name.is_empty()).collect();
+            if json {
+                print_json(&json!(names));
+            } else {
+                for name in names {
+                    println!("{}", name);
+                }
+            }
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn df(matches: &ArgMatches<'_>, json: bool) -> CliResult<()> {
+    let url = url(matches, "url")?;
+    let cluster = url.connect().map_err(|e| e.to_string())?;
+    let vfs = cluster.statvfs(&url.path).at(&url.path)?;
+    let (size, used, available) = space(&vfs);
+    if json {
+        print_json(&json!({
+            "url": url.to_string(),
+            "size": size,
+            "used": used,
+            "available": available,
+            "inodes": vfs.f_files,
+            "inodes_free": vfs.f_ffree,
+        }));
+        return Ok(());
+    }
+    let show = |bytes: u64| {
+        if matches.is_present("human") {
+            human_size(bytes)
+        } else {
+            (bytes / 1024).to_string()
+        }
+    };
+    // Rounded up like df does
+    let percent = (used * 100).div_ceil(size.max(1));
+    println!(
+        "{:<30} {:>12} {:>12} {:>12} {:>5}",
+        "Volume",
+        if matches.is_present("human") {
+            "Size"
+        } else {
+            "1K-blocks"
+        },
+        "Used",
+        "Available",
+        "Use%"
+    );
+    println!(
+        "{:<30} {:>12} {:>12} {:>12} {:>4}%",
+        format!("{}:{}", url.host, url.volume),
+        show(size),
+        show(used),
+        show(available),
+        percent
+    );
+    Ok(())
+}
+
+fn volfile(matches: &ArgMatches<'_>, json: bool) -> CliResult<()> {
+    let url = url(matches, "url")?;
+    let cluster = url.connect().map_err(|e| e.to_string())?;
+    let volfile = cluster.get_volfile().map_err(|e| e.to_string())?;
+    if json {
+        print_json(&json!({ "volume": url.volume, "volfile": volfile }));
+    } else {
+        print!("{}", volfile);
+    }
+    Ok(())
+}
+
+fn volume_id(matches: &ArgMatches<'_>, json: bool) -> CliResult<()> {
+    let url = url(matches, "url")?;
+    let cluster = url.connect().map_err(|e| e.to_string())?;
+    let id = cluster.get_volume_id().map_err(|e| e.to_string())?;
+    if json {
+        print_json(&json!({ "volume": url.volume, "volume_id": id.to_hyphenated().to_string() }));
+    } else {
+        println!("{}", id.to_hyphenated());
+    }
+    Ok(())
+}
+
+fn url_arg(multiple: bool) -> Arg<'static, 'static> {
+    Arg::with_name("url")
+        .help("gluster://host[:port]/volume/path")
+        .required(true)
+        .multiple(multiple)
+}
+
+fn main() {
+    let matches = App::new("gfcli")
+        .about("Work with gluster volumes without mounting them")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("Print results as JSON"),
+        )
+        .subcommand(
+            SubCommand::with_name("ls")
+                .about("List directories")
+                .arg(Arg::with_name("long").short("l").help("Show details"))
+                .arg(
+                    Arg::with_name("all")
+                        .short("a")
+                        .help("Include hidden files"),
+                )
+                .arg(url_arg(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("cat")
+                .about("Print files")
+                .arg(url_arg(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("put")
+                .about("Upload a local file, or stdin given -")
+                .arg(Arg::with_name("local").required(true))
+                .arg(url_arg(false)),
+        )
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Download a file, to stdout given -")
+                .arg(url_arg(false))
+                .arg(Arg::with_name("local")),
+        )
+        .subcommand(
+            SubCommand::with_name("stat")
+                .about("Show file status")
+                .arg(url_arg(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("mkdir")
+                .about("Create directories")
+                .arg(
+                    Arg::with_name("parents")
+                        .short("p")
+                        .help("Create missing parents and accept existing directories"),
+                )
+                .arg(
+                    Arg::with_name("mode")
+                        .short("m")
+                        .takes_value(true)
+                        .default_value("755"),
+                )
+                .arg(url_arg(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("rm")
+                .about("Remove files")
+                .arg(
+                    Arg::with_name("recursive")
+                        .short("r")
+                        .help("Remove directories and their contents"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .short("f")
+                        .help("Ignore files that don't exist"),
+                )
+                .arg(url_arg(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("mv")
+                .about("Rename a file within its volume")
+                .arg(url_arg(false))
+                .arg(
+                    Arg::with_name("dest")
+                        .required(true)
+                        .help("Path on the same volume, or its URL"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("ln")
+                .about("Create the link at URL to TARGET")
+                .arg(Arg::with_name("symbolic").short("s").help("Make a symlink"))
+                .arg(
+                    Arg::with_name("target")
+                        .required(true)
+                        .help("Path on the same volume, or any text for a symlink"),
+                )
+                .arg(url_arg(false)),
+        )
+        .subcommand(
+            SubCommand::with_name("chmod")
+                .about("Change permissions")
+                .arg(Arg::with_name("mode").required(true).help("Octal mode"))
+                .arg(url_arg(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("chown")
+                .about("Change ownership")
+                .arg(
+                    Arg::with_name("no-dereference")
+                        .short("h")
+                        .help("Change symlinks rather than what they point to"),
+                )
+                .arg(
+                    Arg::with_name("owner")
+                        .required(true)
+                        .help("uid[:gid] or :gid"),
+                )
+                .arg(url_arg(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("xattr")
+                .about("Work with extended attributes")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("get")
+                        .arg(url_arg(false))
+                        .arg(Arg::with_name("name").required(true))
+                        .arg(
+                            Arg::with_name("hex")
+                                .long("hex")
+                                .help("Print the value as hex even if it's text"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .arg(url_arg(false))
+                        .arg(Arg::with_name("name").required(true))
+                        .arg(Arg::with_name("value").required(true)),
+                )
+                .subcommand(SubCommand::with_name("list").arg(url_arg(false))),
+        )
+        .subcommand(
+            SubCommand::with_name("df")
+                .about("Show volume usage")
+                .arg(
+                    Arg::with_name("human")
+                        .short("h")
+                        .help("Print sizes like 1.5G"),
+                )
+                .arg(url_arg(false)),
+        )
+        .subcommand(
+            SubCommand::with_name("volfile")
+                .about("Print the volume's client volfile")
+                .arg(url_arg(false)),
+        )
+        .subcommand(
+            SubCommand::with_name("volume-id")
+                .about("Print the volume's id")
+                .arg(url_arg(false)),
+        )
+        .get_matches();
+
+    let json = matches.is_present("json");
+    let (command, sub) = match matches.subcommand() {
+        (command, Some(sub)) => (command, sub),
+        _ => unreachable!(),
+    };
+    let json = json || sub.is_present("json");
+    let result = match command {
+        "ls" => ls(sub, json),
+        "cat" => cat(sub),
+        "put" => put(sub, json),
+        "get" => get(sub, json),
+        "stat" => status(sub, json),
+        "mkdir" => mkdir(sub),
+        "rm" => rm(sub),
+        "mv" => mv(sub),
+        "ln" => ln(sub),
+        "chmod" => chmod(sub),
+        "chown" => chown(sub),
+        "xattr" => xattr(sub, json),
+        "df" => df(sub, json),
+        "volfile" => volfile(sub, json),
+        "volume-id" => volume_id(sub, json),
+        _ => unreachable!(),
+    };
+    if let Err(e) = result {
+        eprintln!("gfcli {}: {}", command, e);
+        exit(1);
+    }
+}
@@ -0,0 +1,615 @@
+//! An interactive shell on a gluster volume, like an FTP client
+//! gfsh [OPTIONS] gluster://host[:port]/volume[/dir]
+//! The connection stays open between commands, so glfs_init is paid once.
+mod common;
+
+use crate::common::*;
+use clap::{App, Arg};
+use gfapi_sys::gluster::{Gluster, GlusterLogLevel};
+use gfapi_sys::url::GlusterUrl;
+use libc::{ENOENT, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY, S_IFLNK, S_IFMT};
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::rc::Rc;
+
+const HELP: &str = "\
+Remote commands, with paths relative to the remote directory:
+  cd [DIR]                 pwd
+  ls [-la] [PATH...]       stat PATH...
+  cat PATH...              df [-h] [PATH]
+  mkdir [-p] DIR...        rmdir DIR...
+  rm [-rf] PATH...         mv FROM TO
+  ln [-s] TARGET LINK      chmod MODE PATH...
+  chown [-h] UID[:GID] PATH...
+  xattr get PATH NAME      xattr set PATH NAME VALUE
+  xattr list PATH
+Transfers and local commands:
+  put LOCAL [REMOTE]       get REMOTE [LOCAL]
+  lcd [DIR]                lpwd
+  lls [DIR]
+  help                     exit";
+
+const COMMANDS: &[&str] = &[
+    "cat", "cd", "chmod", "chown", "df", "exit", "get", "help", "lcd", "ln", "lls", "lpwd", "ls",
+    "mkdir", "mv", "put", "pwd", "quit", "rm", "rmdir", "stat", "xattr",
+];
+
+/// Split a line into words, honouring quotes and backslash escapes
+fn split_words(line: &str) -> CliResult<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\\') | (Some('"'), '\\') => {
+                let escaped = chars.next().ok_or("unfinished \\ escape")?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (Some(open), c) if c == open => quote = None,
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated quote".to_string());
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Escape a name so split_words gives it back as one word
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_whitespace() || "\\\"'".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Where the word being completed starts, skipping escaped whitespace
+fn word_start(line: &str) -> usize {
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c.is_whitespace() {
+            start = i + c.len_utf8();
+        }
+    }
+    start
+}
+
+/// Separate single letter flags like -rf from the other arguments,
+/// refusing letters not in allowed
+fn flags<'a>(args: &'a [String], allowed: &str) -> CliResult<(String, Vec<&'a str>)> {
+    let mut set = String::new();
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            rest.extend(args.map(String::as_str));
+            break;
+        }
+        match arg.strip_prefix('-') {
+            Some(letters) if !letters.is_empty() => {
+                for letter in letters.chars() {
+                    if !allowed.contains(letter) {
+                        return Err(format!("unknown option -{}", letter));
+                    }
+                    set.push(letter);
+                }
+            }
+            _ => rest.push(arg.as_str()),
+        }
+    }
+    Ok((set, rest))
+}
+
+fn arity(args: &[&str], min: usize, max: usize, usage: &str) -> CliResult<()> {
+    if args.len() < min || args.len() > max {
+        return Err(format!("usage: {}", usage));
+    }
+    Ok(())
+}
+
+/// Completes commands, then remote paths from directory listings, or
+/// local ones for the arguments that name local files
+struct ShellHelper {
+    cluster: Rc<Gluster>,
+    local: FilenameCompleter,
+}
+
+impl ShellHelper {
+    fn complete_remote(&self, word: &str) -> Vec<Pair> {
+        let (dir, prefix) = match word.rfind('/') {
+            Some(i) => (&word[..=i], &word[i + 1..]),
+            None => ("", word),
+        };
+        let unescaped = split_words(dir)
+            .ok()
+            .and_then(|words| words.into_iter().next())
+            .unwrap_or_default();
+        let listing = if unescaped.is_empty() {
+            "."
+        } else {
+            &unescaped
+        };
+        let prefix = split_words(prefix)
+            .ok()
+            .and_then(|words| words.into_iter().next())
+            .unwrap_or_default();
+        let entries = match self.cluster.opendir(Path::new(listing)) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        let mut pairs: Vec<Pair> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.path.to_string_lossy().into_owned();
+                if name == "." || name == ".." || !name.starts_with(&prefix) {
+                    return None;
+                }
+                let suffix = if entry.file_type.is_dir() { "/" } else { "" };
+                Some(Pair {
+                    display: format!("{}{}", name, suffix),
+                    replacement: format!("{}{}{}", dir, escape(&name), suffix),
+                })
+            })
+            .collect();
+        pairs.sort_by(|a, b| a.display.cmp(&b.display));
+        pairs
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = word_start(before);
+        let words = split_words(&before[..start]).unwrap_or_default();
+        let word = &before[start..];
+        let local = match words.first().map(String::as_str) {
+            None => {
+                let commands = COMMANDS
+                    .iter()
+                    .filter(|command| command.starts_with(word))
+                    .map(|command| Pair {
+                        display: command.to_string(),
+                        replacement: format!("{} ", command),
+                    })
+                    .collect();
+                return Ok((start, commands));
+            }
+            Some("lcd") | Some("lls") => true,
+            Some("put") => words.len() == 1,
+            Some("get") => words.len() == 2,
+            Some(_) => false,
+        };
+        if local {
+            return self.local.complete(line, pos, ctx);
+        }
+        Ok((start, self.complete_remote(word)))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+struct Shell {
+    cluster: Rc<Gluster>,
+    url: GlusterUrl,
+}
+
+impl Shell {
+    fn prompt(&self) -> String {
+        let cwd = self.cluster.getcwd().unwrap_or_else(|_| "?".to_string());
+        format!("{}:{}> ", self.url.volume, cwd)
+    }
+
+    /// Run one command line, returning false once the shell should exit
+    fn run(&self, line: &str) -> CliResult<bool> {
+        let words = split_words(line)?;
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (command.as_str(), args),
+            None => return Ok(true),
+        };
+        let cluster = &*self.cluster;
+        match command {
+            "exit" | "quit" => return Ok(false),
+            "help" => println!("{}", HELP),
+            "pwd" => println!("{}", cluster.getcwd().map_err(|e| e.to_string())?),
+            "cd" => {
+                let dir = args.first().map_or("/", String::as_str);
+                cluster.chdir(Path::new(dir)).at(Path::new(dir))?;
+            }
+            "ls" => {
+                let (set, paths) = flags(args, "la")?;
+                let paths = if paths.is_empty() { vec!["."] } else { paths };
+                for (i, path) in paths.iter().enumerate() {
+                    let long = set.contains('l');
+                    let entries = list(cluster, Path::new(path), set.contains('a'), long)?;
+                    if paths.len() > 1 {
+                        if i > 0 {
+                            println!();
+                        }
+                        println!("{}:", path);
+                    }
+                    if long {
+                        print_long(&entries);
+                    } else {
+                        for entry in entries {
+                            println!("{}", entry.name);
+                        }
+                    }
+                }
+            }
+            "cat" => {
+                let stdout = io::stdout();
+                let mut out = stdout.lock();
+                for path in args {
+                    let path = Path::new(path);
+                    let file = cluster.open(path, O_RDONLY).at(path)?;
+                    copy_out(&file, &mut out).at(path)?;
+                }
+                out.flush().map_err(|e| e.to_string())?;
+            }
+            "stat" => {
+                for path in args {
+                    let path = Path::new(path);
+                    let st = cluster.lsstat(path).at(path)?;
+                    let target = match st.st_mode & S_IFMT {
+                        S_IFLNK => read_link(cluster, path),
+                        _ => None,
+                    };
+                    print_status(path, &st, target.as_deref());
+                }
+            }
+            "mkdir" => {
+                let (set, dirs) = flags(args, "p")?;
+                for dir in dirs {
+                    if set.contains('p') {
+                        make_dirs(cluster, Path::new(dir), 0o755)?;
+                    } else {
+                        cluster.mkdir(Path::new(dir), 0o755).at(Path::new(dir))?;
+                    }
+                }
+            }
+            "rmdir" => {
+                for dir in args {
+                    cluster.rmdir(Path::new(dir)).at(Path::new(dir))?;
+                }
+            }
+            "rm" => {
+                let (set, paths) = flags(args, "rf")?;
+                for path in paths {
+                    let path = Path::new(path);
+                    let st = match cluster.lsstat(path) {
+                        Ok(st) => st,
+                        Err(_) if set.contains('f') && errno::errno().0 == ENOENT => continue,
+                        Err(e) => return Err(e).at(path),
+                    };
+                    if !is_dir(&st) {
+                        cluster.unlink(path).at(path)?;
+                    } else if set.contains('r') {
+                        cluster.remove_dir_all(path).at(path)?;
+                    } else {
+                        return Err(format!("{}: is a directory, use -r", path.display()));
+                    }
+                }
+            }
+            "mv" => {
+                let (_, paths) = flags(args, "")?;
+                arity(&paths, 2, 2, "mv FROM TO")?;
+                let from = Path::new(paths[0]);
+                let mut to = PathBuf::from(paths[1]);
+                if cluster.stat(&to).map(|st| is_dir(&st)).unwrap_or(false) {
+                    to.push(file_name(from)?);
+                }
+                cluster.rename(from, &to).at(from)?;
+            }
+            "ln" => {
+                let (set, paths) = flags(args, "s")?;
+                arity(&paths, 2, 2, "ln [-s] TARGET LINK")?;
+                let (target, link) = (Path::new(paths[0]), Path::new(paths[1]));
+                if set.contains('s') {
+                    cluster.symlink(target, link).at(link)?;
+                } else {
+                    cluster.link(target, link).at(link)?;
+                }
+            }
+            "chmod" => {
+                let (mode, paths) = args.split_first().ok_or("usage: chmod MODE PATH...")?;
+                let mode = parse_mode(mode)?;
+                for path in paths {
+                    cluster.chmod(Path::new(path), mode).at(Path::new(path))?;
+                }
+            }
+            "chown" => {
+                let (set, args) = flags(args, "h")?;
+                let (owner, paths) = args
+                    .split_first()
+                    .ok_or("usage: chown [-h] UID[:GID] PATH...")?;
+                let (uid, gid) = parse_owner(owner)?;
+                for path in paths {
+                    let path = Path::new(path);
+                    if set.contains('h') {
+                        cluster.lchown(path, uid, gid).at(path)?;
+                    } else {
+                        cluster.chown(path, uid, gid).at(path)?;
+                    }
+                }
+            }
+            "xattr" => self.xattr(args)?,
+            "df" => {
+                let (set, paths) = flags(args, "h")?;
+                arity(&paths, 0, 1, "df [-h] [PATH]")?;
+                let path = Path::new(paths.first().copied().unwrap_or("."));
+                let vfs = cluster.statvfs(path).at(path)?;
+                let (size, used, available) = space(&vfs);
+                let show = |bytes: u64| {
+                    if set.contains('h') {
+                        human_size(bytes)
+                    } else {
+                        (bytes / 1024).to_string()
+                    }
+                };
+                println!(
+                    "size {}  used {}  available {}  inodes {}  free inodes {}",
+                    show(size),
+                    show(used),
+                    show(available),
+                    vfs.f_files,
+                    vfs.f_ffree
+                );
+            }
+            "put" => self.put(args)?,
+            "get" => self.get(args)?,
+            "lcd" => {
+                let dir = match args.first() {
+                    Some(dir) => PathBuf::from(dir),
+                    None => PathBuf::from(std::env::var_os("HOME").ok_or("HOME isn't set")?),
+                };
+                std::env::set_current_dir(&dir).at(&dir)?;
+            }
+            "lpwd" => {
+                let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+                println!("{}", cwd.display());
+            }
+            "lls" => {
+                let dir = Path::new(args.first().map_or(".", String::as_str));
+                let mut names = Vec::new();
+                for entry in std::fs::read_dir(dir).at(dir)? {
+                    let entry = entry.at(dir)?;
+                    let mut name = entry.file_name().to_string_lossy().into_owned();
+                    if entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false) {
+                        name.push('/');
+                    }
+                    names.push(name);
+                }
+                names.sort();
+                for name in names {
+                    println!("{}", name);
+                }
+            }
+            _ => return Err(format!("unknown command {}, try help", command)),
+        }
+        Ok(true)
+    }
+
+    fn xattr(&self, args: &[String]) -> CliResult<()> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match args.split_first() {
+            Some((&"get", rest)) => {
+                arity(rest, 2, 2, "xattr get PATH NAME")?;
+                let path = Path::new(rest[0]);
+                let value = self.cluster.getxattr_bytes(path, rest[1]).at(path)?;
+                match String::from_utf8(value) {
+                    Ok(text) => println!("{}", text.trim_end_matches('\0')),
+                    Err(e) => {
+                        let hex: String = e
+                            .as_bytes()
+                            .iter()
+                            .map(|byte| format!("{:02x}", byte))
+                            .collect();
+                        println!("0x{}", hex);
+                    }
+                }
+            }
+            Some((&"set", rest)) => {
+                arity(rest, 3, 3, "xattr set PATH NAME VALUE")?;
+                let path = Path::new(rest[0]);
+                self.cluster
+                    .setxattr(path, rest[1], rest[2].as_bytes(), 0)
+                    .at(path)?;
+            }
+            Some((&"list", rest)) => {
+                arity(rest, 1, 1, "xattr list PATH")?;
+                let path = Path::new(rest[0]);
+                let names = self.cluster.listxattr(path).at(path)?;
+                for name in names.split('\0').filter(|name| !name.is_empty()) {
+                    println!("{}", name);
+                }
+            }
+            _ => return Err("usage: xattr get|set|list PATH ...".to_string()),
+        }
+        Ok(())
+    }
+
+    fn put(&self, args: &[String]) -> CliResult<()> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        arity(&args, 1, 2, "put LOCAL [REMOTE]")?;
+        let local = Path::new(args[0]);
+        let name = file_name(local)?;
+        let mut remote = PathBuf::from(args.get(1).copied().unwrap_or("."));
+        if self
+            .cluster
+            .stat(&remote)
+            .map(|st| is_dir(&st))
+            .unwrap_or(false)
+        {
+            remote.push(name);
+        }
+        let mut input = File::open(local).at(local)?;
+        let mode = input.metadata().at(local)?.permissions().mode() & 0o777;
+        let file = self
+            .cluster
+            .create(&remote, O_WRONLY | O_CREAT | O_TRUNC, mode)
+            .at(&remote)?;
+        let bytes = copy_in(&mut input, &file).at(&remote)?;
+        file.fsync().at(&remote)?;
+        println!("{} bytes to {}", bytes, remote.display());
+        Ok(())
+    }
+
+    fn get(&self, args: &[String]) -> CliResult<()> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        arity(&args, 1, 2, "get REMOTE [LOCAL]")?;
+        let remote = Path::new(args[0]);
+        let name = file_name(remote)?;
+        let mut local = PathBuf::from(args.get(1).copied().unwrap_or("."));
+        if local.is_dir() {
+            local.push(name);
+        }
+        let file = self.cluster.open(remote, O_RDONLY).at(remote)?;
+        let mut out = File::create(&local).at(&local)?;
+        let bytes = copy_out(&file, &mut out).at(remote)?;
+        println!("{} bytes to {}", bytes, local.display());
+        Ok(())
+    }
+}
+
+fn main() {
+    let matches = App::new("gfsh")
+        .about("An interactive shell on a gluster volume")
+        .arg(
+            Arg::with_name("url")
+                .help("gluster://host[:port]/volume[/dir]")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .takes_value(true)
+                .help("Where gfapi writes its own log"),
+        )
+        .arg(
+            Arg::with_name("history")
+                .long("history")
+                .takes_value(true)
+                .help("History file, ~/.gfsh_history by default"),
+        )
+        .get_matches();
+
+    let url = match GlusterUrl::parse(matches.value_of("url").unwrap()) {
+        Ok(url) => url,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
+    };
+    let cluster = match url.connect() {
+        Ok(cluster) => Rc::new(cluster),
+        Err(e) => {
+            eprintln!("connecting to {} on {} failed: {}", url.volume, url.host, e);
+            exit(1);
+        }
+    };
+    if let Some(log_file) = matches.value_of("log-file") {
+        if let Err(e) = cluster.set_logging(Path::new(log_file), GlusterLogLevel::Info) {
+            eprintln!("setting the gfapi log file failed: {}", e);
+            exit(1);
+        }
+    }
+    if let Err(e) = cluster.chdir(&url.path) {
+        eprintln!("{}: {}", url.path.display(), e);
+        exit(1);
+    }
+
+    let mut editor: Editor<ShellHelper, DefaultHistory> = match Editor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("starting the line editor failed: {}", e);
+            exit(1);
+        }
+    };
+    editor.set_helper(Some(ShellHelper {
+        cluster: Rc::clone(&cluster),
+        local: FilenameCompleter::new(),
+    }));
+    let history = match matches.value_of("history") {
+        Some(history) => Some(PathBuf::from(history)),
+        None => std::env::var_os("HOME").map(|home| Path::new(&home).join(".gfsh_history")),
+    };
+    if let Some(ref history) = history {
+        let _ = editor.load_history(history);
+    }
+
+    let shell = Shell { cluster, url };
+    let mut failed = false;
+    loop {
+        let line = match editor.readline(&shell.prompt()) {
+            Ok(line) => line,
+            // ^C abandons the line, ^D leaves
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+                break;
+            }
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+        match shell.run(&line) {
+            Ok(true) => failed = false,
+            Ok(false) => break,
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+    if let Some(ref history) = history {
+        let _ = editor.save_history(history);
+    }
+    // Scripts piped in can tell whether their last command worked
+    if failed {
+        exit(1);
+    }
+}
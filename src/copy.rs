@@ -0,0 +1,258 @@
+//! Copying files within a volume
+//! Gluster::copy_file has the bricks copy the data with copy_file_range
+//! where the volume can, so none of it crosses the network to this client,
+//! and reads and writes it through the client where it can't, for example
+//! when the two files hash to different bricks.
+//!
+//! The sparse copies, copy_file_sparse and upload_sparse, only copy the
+//! data of VM images and the like and leave their holes as holes, where
+//! a plain copy would fill them in with zeros.
+use crate::gluster::{
+    get_error, next_extent, ExtentKind, FallocMode, Gluster, GlusterError, GlusterFile,
+};
+use errno::{errno, set_errno, Errno};
+use libc::{mode_t, stat, EINVAL, ENOSYS, EOPNOTSUPP, EXDEV, O_CREAT, O_RDONLY, O_WRONLY};
+
+use std::fs::File;
+use std::os::unix::fs::{FileExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// The most copy_file asks the bricks to copy in one call
+const SERVER_CHUNK: usize = 1 << 30;
+/// What a buffered copy reads and writes at a time
+const BUFFERED_CHUNK: usize = 1 << 20;
+
+/// How copy_file moved the data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyMethod {
+    /// The bricks copied it with copy_file_range
+    ServerSide,
+    /// Read and written by this client, because the volume couldn't copy
+    /// it server side or it came from outside the volume
+    Buffered,
+}
+
+/// What a copy did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileCopy {
+    /// Of data copied
+    pub bytes: u64,
+    /// Of holes left as holes, only found by the sparse copies
+    pub holes: u64,
+    pub method: CopyMethod,
+}
+
+/// Whether copy_file_range failed because the volume can't copy between
+/// the files rather than because of something a buffered copy would also
+/// run into
+fn server_side_unsupported() -> bool {
+    let code = errno();
+    code == Errno(EXDEV) || code == Errno(EOPNOTSUPP) || code == Errno(ENOSYS)
+}
+
+/// Write all of buf to file at offset
+fn write_all_at(file: &GlusterFile, buf: &[u8], offset: i64) -> Result<(), GlusterError> {
+    let mut written = 0;
+    while written < buf.len() {
+        let wrote = file.pwrite(
+            &buf[written..],
+            buf.len() - written,
+            offset + written as i64,
+            0,
+        )?;
+        written += wrote as usize;
+    }
+    Ok(())
+}
+
+/// Copies ranges between two files of a volume, server side until the
+/// first copy_file_range shows the volume can't
+struct Copier<'a> {
+    src: &'a GlusterFile,
+    dst: &'a GlusterFile,
+    /// None until the first copy_file_range
+    method: Option<CopyMethod>,
+    bytes: u64,
+}
+
+impl<'a> Copier<'a> {
+    fn new(src: &'a GlusterFile, dst: &'a GlusterFile) -> Copier<'a> {
+        Copier {
+            src,
+            dst,
+            method: None,
+            bytes: 0,
+        }
+    }
+
+    /// Copy len bytes at offset to the same offset, or fewer at the end of
+    /// src
+    fn copy(&mut self, offset: i64, len: u64) -> Result<(), GlusterError> {
+        let mut offset = offset;
+        let mut remaining = len;
+        while remaining > 0 && self.method != Some(CopyMethod::Buffered) {
+            let chunk = remaining.min(SERVER_CHUNK as u64) as usize;
+            match self.src.copy_range_to(self.dst, offset, offset, chunk) {
+                Ok(0) => return Ok(()),
+                Ok(copied) => {
+                    self.method = Some(CopyMethod::ServerSide);
+                    offset += copied as i64;
+                    remaining -= copied as u64;
+                    self.bytes += copied as u64;
+                }
+                Err(_) if self.method.is_none() && server_side_unsupported() => {
+                    debug!("Copying through the client: {}", errno());
+                    self.method = Some(CopyMethod::Buffered);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let mut chunk = Vec::with_capacity(BUFFERED_CHUNK);
+        while remaining > 0 {
+            let count = remaining.min(BUFFERED_CHUNK as u64) as usize;
+            let read = self.src.pread(&mut chunk, count, offset, 0)? as usize;
+            if read == 0 {
+                break;
+            }
+            write_all_at(self.dst, &chunk, offset)?;
+            offset += read as i64;
+            remaining -= read as u64;
+            self.bytes += read as u64;
+        }
+        Ok(())
+    }
+
+    fn finish(self, holes: u64) -> FileCopy {
+        FileCopy {
+            bytes: self.bytes,
+            holes,
+            method: self.method.unwrap_or(CopyMethod::ServerSide),
+        }
+    }
+}
+
+/// Have dst read as zeros where the source has a hole, punching out what
+/// dst held there before.  Past the dst_size it had, the ftruncate that
+/// ends a sparse copy leaves a hole.
+fn punch_hole(dst: &GlusterFile, offset: i64, len: i64, dst_size: i64) -> Result<(), GlusterError> {
+    let len = len.min(dst_size - offset);
+    if len <= 0 {
+        return Ok(());
+    }
+    let range = offset as u64..(offset + len) as u64;
+    match dst.allocate(range.clone(), FallocMode::PunchHole) {
+        // Without hole punching zeros still read the same
+        Err(GlusterError::Unsupported(_)) => dst.allocate(range, FallocMode::ZeroRange),
+        result => result,
+    }
+}
+
+impl Gluster {
+    /// Open or create dst to copy src into, without changing it yet.  Like
+    /// cp, copying a file onto itself, under its own name or another link
+    /// to it, fails with EINVAL rather than destroying it.
+    fn open_copy_dst(
+        &self,
+        src: &stat,
+        dst: &Path,
+        mode: mode_t,
+    ) -> Result<(GlusterFile, stat), GlusterError> {
+        let dst_file = self.create(dst, O_WRONLY | O_CREAT, mode)?;
+        let dst_stat = dst_file.fstat()?;
+        if dst_stat.st_dev == src.st_dev && dst_stat.st_ino == src.st_ino {
+            set_errno(Errno(EINVAL));
+            return Err(GlusterError::new(get_error()));
+        }
+        Ok((dst_file, dst_stat))
+    }
+
+    /// Copy the file at src to dst, replacing dst if it exists and giving
+    /// it src's permissions.  The data is copied server side when the volume
+    /// supports it, which the first copy_file_range finds out, and through
+    /// this client otherwise.  src and dst being the same file fails with
+    /// EINVAL.
+    pub fn copy_file(&self, src: &Path, dst: &Path) -> Result<FileCopy, GlusterError> {
+        traced!(DEBUG, "copy_file", volume = self.volume, path = src);
+        let src_file = self.open(src, O_RDONLY)?;
+        let stat = src_file.fstat()?;
+        let (dst_file, _) = self.open_copy_dst(&stat, dst, stat.st_mode & 0o7777)?;
+        dst_file.ftruncate(0)?;
+
+        let mut copier = Copier::new(&src_file, &dst_file);
+        copier.copy(0, u64::MAX)?;
+        Ok(copier.finish(0))
+    }
+
+    /// Like copy_file, but only copies src's data and leaves its holes as
+    /// holes in dst.  An existing dst is overwritten in place, with the
+    /// data it had where src has holes punched out, and cut to src's size.
+    pub fn copy_file_sparse(&self, src: &Path, dst: &Path) -> Result<FileCopy, GlusterError> {
+        traced!(DEBUG, "copy_file_sparse", volume = self.volume, path = src);
+        let src_file = self.open(src, O_RDONLY)?;
+        let stat = src_file.fstat()?;
+        let (dst_file, dst_stat) = self.open_copy_dst(&stat, dst, stat.st_mode & 0o7777)?;
+        let dst_size = dst_stat.st_size;
+
+        let mut copier = Copier::new(&src_file, &dst_file);
+        let mut holes = 0;
+        for extent in src_file.extents()? {
+            let extent = extent?;
+            match extent.kind {
+                ExtentKind::Data => copier.copy(extent.offset, extent.len as u64)?,
+                ExtentKind::Hole => {
+                    punch_hole(&dst_file, extent.offset, extent.len, dst_size)?;
+                    holes += extent.len as u64;
+                }
+            }
+        }
+        dst_file.ftruncate(stat.st_size)?;
+        Ok(copier.finish(holes))
+    }
+
+    /// Copy a file from outside the volume to dst like copy_file_sparse,
+    /// finding its holes with lseek on the local file
+    pub fn upload_sparse(&self, local: &Path, dst: &Path) -> Result<FileCopy, GlusterError> {
+        traced!(DEBUG, "upload_sparse", volume = self.volume, path = dst);
+        let src = File::open(local)?;
+        let metadata = src.metadata()?;
+        let mode = metadata.permissions().mode() & 0o7777;
+        let dst_file = self.create(dst, O_WRONLY | O_CREAT, mode)?;
+        let dst_size = dst_file.fstat()?.st_size;
+        let size = metadata.len() as i64;
+
+        let fd = src.as_raw_fd();
+        let seek = |offset, whence| match unsafe { libc::lseek(fd, offset, whence) } {
+            offset if offset < 0 => Err(GlusterError::new(get_error())),
+            offset => Ok(offset),
+        };
+        let mut copy = FileCopy {
+            bytes: 0,
+            holes: 0,
+            method: CopyMethod::Buffered,
+        };
+        let mut chunk = vec![0; BUFFERED_CHUNK];
+        let mut offset = 0;
+        while let Some(extent) = next_extent(seek, offset, size)? {
+            offset = extent.offset + extent.len;
+            if extent.kind == ExtentKind::Hole {
+                punch_hole(&dst_file, extent.offset, extent.len, dst_size)?;
+                copy.holes += extent.len as u64;
+                continue;
+            }
+            let mut at = extent.offset;
+            while at < offset {
+                let count = ((offset - at) as usize).min(BUFFERED_CHUNK);
+                let read = src.read_at(&mut chunk[..count], at as u64)?;
+                if read == 0 {
+                    break;
+                }
+                write_all_at(&dst_file, &chunk[..read], at)?;
+                at += read as i64;
+                copy.bytes += read as u64;
+            }
+        }
+        dst_file.ftruncate(size)?;
+        Ok(copy)
+    }
+}
@@ -0,0 +1,164 @@
+//! Per-thread credential impersonation
+//! gfapi sends the uid, gid and supplementary groups set with
+//! glfs_setfsuid, glfs_setfsgid and glfs_setfsgroups along with every
+//! operation made from the calling thread.  Bricks enforce permissions
+//! against these, which lets one client act on behalf of many users.
+use crate::glfs::*;
+use crate::gluster::{get_error, Gluster, GlusterError};
+use libc::{gid_t, uid_t};
+
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::ptr;
+
+/// The identity gluster operations are performed as
+#[derive(Clone, Debug, PartialEq)]
+pub struct Credentials {
+    pub uid: uid_t,
+    pub gid: gid_t,
+    pub groups: Vec<gid_t>,
+}
+
+impl Credentials {
+    pub fn new(uid: uid_t, gid: gid_t, groups: &[gid_t]) -> Credentials {
+        Credentials {
+            uid,
+            gid,
+            groups: groups.to_vec(),
+        }
+    }
+
+    /// The effective identity of this process, which gfapi uses until a
+    /// thread sets its own.
+    pub fn process() -> Result<Credentials, GlusterError> {
+        unsafe {
+            let count = libc::getgroups(0, ptr::null_mut());
+            if count < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            let mut groups: Vec<gid_t> = vec![0; count as usize];
+            let count = libc::getgroups(count, groups.as_mut_ptr());
+            if count < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            groups.truncate(count as usize);
+            Ok(Credentials {
+                uid: libc::geteuid(),
+                gid: libc::getegid(),
+                groups,
+            })
+        }
+    }
+
+    /// Make this the identity of the calling thread
+    fn apply(&self) -> Result<(), GlusterError> {
+        unsafe {
+            if glfs_setfsgroups(self.groups.len(), self.groups.as_ptr()) < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            if glfs_setfsgid(self.gid) < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            if glfs_setfsuid(self.uid) < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    // gfapi has no getter for the fs credentials so track what this
+    // module set, one entry per live guard with the innermost last.
+    // Empty means the thread still uses the process identity.
+    static GUARDS: RefCell<Vec<(u64, Credentials)>> = const { RefCell::new(Vec::new()) };
+    static NEXT_GUARD: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The identity of the innermost live guard, None for the process's
+fn innermost() -> Option<Credentials> {
+    GUARDS.with(|guards| guards.borrow().last().map(|(_, creds)| creds.clone()))
+}
+
+fn restore() -> Result<(), GlusterError> {
+    match innermost() {
+        Some(creds) => creds.apply(),
+        None => Credentials::process()?.apply(),
+    }
+}
+
+/// Restores the previous identity of the thread when dropped.  Guards
+/// dropped out of order leave the thread as the newest guard still alive,
+/// or the process identity once none are.  Because the identity belongs
+/// to the thread the guard can't be sent to another one.
+#[derive(Debug)]
+pub struct ImpersonationGuard {
+    id: u64,
+    // Credentials are per thread so the guard must stay on this one
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ImpersonationGuard {
+    fn drop(&mut self) {
+        let innermost = GUARDS.with(|guards| {
+            let mut guards = guards.borrow_mut();
+            let innermost = guards.last().map(|(id, _)| *id) == Some(self.id);
+            guards.retain(|(id, _)| *id != self.id);
+            innermost
+        });
+        // An outer guard going first leaves the identity in force as is
+        if !innermost {
+            return;
+        }
+        if let Err(e) = restore() {
+            error!("failed to restore gluster credentials: {:?}", e);
+        }
+    }
+}
+
+impl Gluster {
+    /// Perform every following operation made from this thread as uid, gid
+    /// and groups until the returned guard is dropped.  This applies to all
+    /// Gluster handles used from the thread, not just this one.
+    pub fn impersonate(
+        &self,
+        uid: uid_t,
+        gid: gid_t,
+        groups: &[gid_t],
+    ) -> Result<ImpersonationGuard, GlusterError> {
+        let creds = Credentials::new(uid, gid, groups);
+        if let Err(e) = creds.apply() {
+            // Don't leave the thread half way between two identities
+            restore()?;
+            return Err(e);
+        }
+        let id = NEXT_GUARD.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            id
+        });
+        GUARDS.with(|guards| guards.borrow_mut().push((id, creds)));
+        Ok(ImpersonationGuard {
+            id,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Run a closure with this thread impersonating creds and restore
+    /// the previous identity afterwards, even if the closure fails.
+    pub fn with_credentials<F, T>(&self, creds: &Credentials, f: F) -> Result<T, GlusterError>
+    where
+        F: FnOnce(&Gluster) -> Result<T, GlusterError>,
+    {
+        let _guard = self.impersonate(creds.uid, creds.gid, &creds.groups)?;
+        f(self)
+    }
+
+    /// The identity operations from this thread are currently performed as
+    pub fn current_credentials(&self) -> Result<Credentials, GlusterError> {
+        match innermost() {
+            Some(creds) => Ok(creds),
+            None => Credentials::process(),
+        }
+    }
+}
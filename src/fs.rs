@@ -0,0 +1,365 @@
+//! Backend-agnostic filesystem traits
+//! GlusterFs and GlusterFileOps cover the operations of Gluster and
+//! GlusterFile with the same signatures, so code written against the traits
+//! can run on a live volume or on the in-memory filesystem in memfs.
+use crate::gluster::{
+    get_error, DirEntry, DirEntryPlus, FallocMode, Gluster, GlusterDirectory, GlusterDirectoryPlus,
+    GlusterError, GlusterFile, PosixLockCmd,
+};
+use errno::{set_errno, Errno};
+use libc::{dev_t, flock, mode_t, stat, statvfs, timespec, EINVAL};
+
+use std::ops::Range;
+use std::path::Path;
+
+/// The offset and length of a range for allocate, or EINVAL for a range
+/// that's empty or ends past what an off_t holds
+fn falloc_range(range: &Range<u64>) -> Result<(i64, i64), GlusterError> {
+    if range.start >= range.end || range.end > i64::MAX as u64 {
+        set_errno(Errno(EINVAL));
+        return Err(GlusterError::new(get_error()));
+    }
+    Ok((range.start as i64, (range.end - range.start) as i64))
+}
+
+/// The operations of a volume.  See the methods of the same name on
+/// Gluster for what each one does.
+pub trait GlusterFs {
+    type File: GlusterFileOps;
+    type Dir: Iterator<Item = Result<DirEntry, GlusterError>>;
+    type DirPlus: Iterator<Item = Result<DirEntryPlus, GlusterError>>;
+
+    fn open(&self, path: &Path, flags: i32) -> Result<Self::File, GlusterError>;
+    fn create(&self, path: &Path, flags: i32, mode: mode_t) -> Result<Self::File, GlusterError>;
+    fn truncate(&self, path: &Path, length: i64) -> Result<(), GlusterError>;
+    fn lsstat(&self, path: &Path) -> Result<stat, GlusterError>;
+    fn exists(&self, path: &Path) -> Result<bool, GlusterError>;
+    fn statvfs(&self, path: &Path) -> Result<statvfs, GlusterError>;
+    fn stat(&self, path: &Path) -> Result<stat, GlusterError>;
+    fn access(&self, path: &Path, mode: i32) -> Result<(), GlusterError>;
+    fn symlink(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError>;
+    fn readlink(&self, path: &Path, buf: &mut [u8]) -> Result<(), GlusterError>;
+    fn mknod(&self, path: &Path, mode: mode_t, dev: dev_t) -> Result<(), GlusterError>;
+    fn mkdir(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError>;
+    fn unlink(&self, path: &Path) -> Result<(), GlusterError>;
+    fn rmdir(&self, path: &Path) -> Result<(), GlusterError>;
+    fn remove_dir_all(&self, path: &Path) -> Result<(), GlusterError>;
+    fn rename(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError>;
+    fn link(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError>;
+    fn opendir(&self, path: &Path) -> Result<Self::Dir, GlusterError>;
+    fn opendir_plus(&self, path: &Path) -> Result<Self::DirPlus, GlusterError>;
+    fn getxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError>;
+    fn getxattr_bytes(&self, path: &Path, name: &str) -> Result<Vec<u8>, GlusterError>;
+    fn lgetxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError>;
+    fn listxattr(&self, path: &Path) -> Result<String, GlusterError>;
+    fn llistxattr(&self, path: &Path) -> Result<String, GlusterError>;
+    fn setxattr(
+        &self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+    ) -> Result<(), GlusterError>;
+    fn lsetxattr(
+        &self,
+        name: &str,
+        value: &[u8],
+        path: &Path,
+        flags: i32,
+    ) -> Result<(), GlusterError>;
+    fn removexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError>;
+    fn lremovexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError>;
+    fn getcwd(&self) -> Result<String, GlusterError>;
+    fn chdir(&self, path: &Path) -> Result<(), GlusterError>;
+    fn utimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError>;
+    fn lutimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError>;
+    fn chmod(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError>;
+    fn chown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError>;
+    fn lchown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError>;
+}
+
+/// The operations of an open file.  See the methods of the same name on
+/// GlusterFile for what each one does.
+pub trait GlusterFileOps: Sized {
+    fn read(
+        &self,
+        fill_buffer: &mut Vec<u8>,
+        count: usize,
+        flags: i32,
+    ) -> Result<isize, GlusterError>;
+    fn write(&self, buffer: &[u8], flags: i32) -> Result<isize, GlusterError>;
+    fn readv(&self, iov: &mut [&mut [u8]], flags: i32) -> Result<isize, GlusterError>;
+    fn writev(&self, iov: &[&[u8]], flags: i32) -> Result<isize, GlusterError>;
+    fn pread(
+        &self,
+        fill_buffer: &mut Vec<u8>,
+        count: usize,
+        offset: i64,
+        flags: i32,
+    ) -> Result<isize, GlusterError>;
+    fn pwrite(
+        &self,
+        buffer: &[u8],
+        count: usize,
+        offset: i64,
+        flags: i32,
+    ) -> Result<isize, GlusterError>;
+    fn preadv(&self, iov: &mut [&mut [u8]], offset: i64, flags: i32)
+        -> Result<isize, GlusterError>;
+    fn pwritev(&self, iov: &[&[u8]], offset: i64, flags: i32) -> Result<isize, GlusterError>;
+    fn lseek(&self, offset: i64, whence: i32) -> Result<i64, GlusterError>;
+    fn ftruncate(&self, length: i64) -> Result<(), GlusterError>;
+    fn fstat(&self) -> Result<stat, GlusterError>;
+    fn fsync(&self) -> Result<(), GlusterError>;
+    fn fdatasync(&self) -> Result<(), GlusterError>;
+    fn fgetxattr(&self, name: &str) -> Result<String, GlusterError>;
+    fn flistxattr(&self) -> Result<String, GlusterError>;
+    fn fsetxattr(&self, name: &str, value: &[u8], flags: i32) -> Result<(), GlusterError>;
+    fn fremovexattr(&self, name: &str) -> Result<(), GlusterError>;
+    fn fallocate(&self, offset: i64, keep_size: i32, len: usize) -> Result<(), GlusterError>;
+    fn discard(&self, offset: i64, len: usize) -> Result<(), GlusterError>;
+    fn zerofill(&self, offset: i64, len: i64) -> Result<(), GlusterError>;
+    fn allocate(&self, range: Range<u64>, mode: FallocMode) -> Result<(), GlusterError> {
+        let (offset, len) = falloc_range(&range)?;
+        let result = match mode {
+            FallocMode::Allocate => self.fallocate(offset, 0, len as usize),
+            FallocMode::KeepSize => self.fallocate(offset, 1, len as usize),
+            FallocMode::PunchHole => self.discard(offset, len as usize),
+            FallocMode::ZeroRange => self.zerofill(offset, len),
+        };
+        result.map_err(GlusterError::or_unsupported)
+    }
+    fn fchdir(&self) -> Result<(), GlusterError>;
+    fn futimens(&self, times: &[timespec; 2]) -> Result<(), GlusterError>;
+    fn posixlock(&self, command: PosixLockCmd, flock: &mut flock) -> Result<(), GlusterError>;
+    fn fchmod(&self, mode: mode_t) -> Result<(), GlusterError>;
+    fn fchown(&self, uid: u32, gid: u32) -> Result<(), GlusterError>;
+    fn dup(&self) -> Result<Self, GlusterError>;
+}
+
+impl GlusterFs for Gluster {
+    type File = GlusterFile;
+    type Dir = GlusterDirectory;
+    type DirPlus = GlusterDirectoryPlus;
+
+    fn open(&self, path: &Path, flags: i32) -> Result<GlusterFile, GlusterError> {
+        Gluster::open(self, path, flags)
+    }
+    fn create(&self, path: &Path, flags: i32, mode: mode_t) -> Result<GlusterFile, GlusterError> {
+        Gluster::create(self, path, flags, mode)
+    }
+    fn truncate(&self, path: &Path, length: i64) -> Result<(), GlusterError> {
+        Gluster::truncate(self, path, length)
+    }
+    fn lsstat(&self, path: &Path) -> Result<stat, GlusterError> {
+        Gluster::lsstat(self, path)
+    }
+    fn exists(&self, path: &Path) -> Result<bool, GlusterError> {
+        Gluster::exists(self, path)
+    }
+    fn statvfs(&self, path: &Path) -> Result<statvfs, GlusterError> {
+        Gluster::statvfs(self, path)
+    }
+    fn stat(&self, path: &Path) -> Result<stat, GlusterError> {
+        Gluster::stat(self, path)
+    }
+    fn access(&self, path: &Path, mode: i32) -> Result<(), GlusterError> {
+        Gluster::access(self, path, mode)
+    }
+    fn symlink(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        Gluster::symlink(self, oldpath, newpath)
+    }
+    fn readlink(&self, path: &Path, buf: &mut [u8]) -> Result<(), GlusterError> {
+        Gluster::readlink(self, path, buf)
+    }
+    fn mknod(&self, path: &Path, mode: mode_t, dev: dev_t) -> Result<(), GlusterError> {
+        Gluster::mknod(self, path, mode, dev)
+    }
+    fn mkdir(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        Gluster::mkdir(self, path, mode)
+    }
+    fn unlink(&self, path: &Path) -> Result<(), GlusterError> {
+        Gluster::unlink(self, path)
+    }
+    fn rmdir(&self, path: &Path) -> Result<(), GlusterError> {
+        Gluster::rmdir(self, path)
+    }
+    fn remove_dir_all(&self, path: &Path) -> Result<(), GlusterError> {
+        Gluster::remove_dir_all(self, path)
+    }
+    fn rename(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        Gluster::rename(self, oldpath, newpath)
+    }
+    fn link(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        Gluster::link(self, oldpath, newpath)
+    }
+    fn opendir(&self, path: &Path) -> Result<GlusterDirectory, GlusterError> {
+        Gluster::opendir(self, path)
+    }
+    fn opendir_plus(&self, path: &Path) -> Result<GlusterDirectoryPlus, GlusterError> {
+        Gluster::opendir_plus(self, path)
+    }
+    fn getxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
+        Gluster::getxattr(self, path, name)
+    }
+    fn getxattr_bytes(&self, path: &Path, name: &str) -> Result<Vec<u8>, GlusterError> {
+        Gluster::getxattr_bytes(self, path, name)
+    }
+    fn lgetxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
+        Gluster::lgetxattr(self, path, name)
+    }
+    fn listxattr(&self, path: &Path) -> Result<String, GlusterError> {
+        Gluster::listxattr(self, path)
+    }
+    fn llistxattr(&self, path: &Path) -> Result<String, GlusterError> {
+        Gluster::llistxattr(self, path)
+    }
+    fn setxattr(
+        &self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+    ) -> Result<(), GlusterError> {
+        Gluster::setxattr(self, path, name, value, flags)
+    }
+    fn lsetxattr(
+        &self,
+        name: &str,
+        value: &[u8],
+        path: &Path,
+        flags: i32,
+    ) -> Result<(), GlusterError> {
+        Gluster::lsetxattr(self, name, value, path, flags)
+    }
+    fn removexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
+        Gluster::removexattr(self, path, name)
+    }
+    fn lremovexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
+        Gluster::lremovexattr(self, path, name)
+    }
+    fn getcwd(&self) -> Result<String, GlusterError> {
+        Gluster::getcwd(self)
+    }
+    fn chdir(&self, path: &Path) -> Result<(), GlusterError> {
+        Gluster::chdir(self, path)
+    }
+    fn utimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        Gluster::utimens(self, path, times)
+    }
+    fn lutimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        Gluster::lutimens(self, path, times)
+    }
+    fn chmod(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        Gluster::chmod(self, path, mode)
+    }
+    fn chown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        Gluster::chown(self, path, uid, gid)
+    }
+    fn lchown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        Gluster::lchown(self, path, uid, gid)
+    }
+}
+
+impl GlusterFileOps for GlusterFile {
+    fn read(
+        &self,
+        fill_buffer: &mut Vec<u8>,
+        count: usize,
+        flags: i32,
+    ) -> Result<isize, GlusterError> {
+        GlusterFile::read(self, fill_buffer, count, flags)
+    }
+    fn write(&self, buffer: &[u8], flags: i32) -> Result<isize, GlusterError> {
+        GlusterFile::write(self, buffer, flags)
+    }
+    fn readv(&self, iov: &mut [&mut [u8]], flags: i32) -> Result<isize, GlusterError> {
+        GlusterFile::readv(self, iov, flags)
+    }
+    fn writev(&self, iov: &[&[u8]], flags: i32) -> Result<isize, GlusterError> {
+        GlusterFile::writev(self, iov, flags)
+    }
+    fn pread(
+        &self,
+        fill_buffer: &mut Vec<u8>,
+        count: usize,
+        offset: i64,
+        flags: i32,
+    ) -> Result<isize, GlusterError> {
+        GlusterFile::pread(self, fill_buffer, count, offset, flags)
+    }
+    fn pwrite(
+        &self,
+        buffer: &[u8],
+        count: usize,
+        offset: i64,
+        flags: i32,
+    ) -> Result<isize, GlusterError> {
+        GlusterFile::pwrite(self, buffer, count, offset, flags)
+    }
+    fn preadv(
+        &self,
+        iov: &mut [&mut [u8]],
+        offset: i64,
+        flags: i32,
+    ) -> Result<isize, GlusterError> {
+        GlusterFile::preadv(self, iov, offset, flags)
+    }
+    fn pwritev(&self, iov: &[&[u8]], offset: i64, flags: i32) -> Result<isize, GlusterError> {
+        GlusterFile::pwritev(self, iov, offset, flags)
+    }
+    fn lseek(&self, offset: i64, whence: i32) -> Result<i64, GlusterError> {
+        GlusterFile::lseek(self, offset, whence)
+    }
+    fn ftruncate(&self, length: i64) -> Result<(), GlusterError> {
+        GlusterFile::ftruncate(self, length)
+    }
+    fn fstat(&self) -> Result<stat, GlusterError> {
+        GlusterFile::fstat(self)
+    }
+    fn fsync(&self) -> Result<(), GlusterError> {
+        GlusterFile::fsync(self)
+    }
+    fn fdatasync(&self) -> Result<(), GlusterError> {
+        GlusterFile::fdatasync(self)
+    }
+    fn fgetxattr(&self, name: &str) -> Result<String, GlusterError> {
+        GlusterFile::fgetxattr(self, name)
+    }
+    fn flistxattr(&self) -> Result<String, GlusterError> {
+        GlusterFile::flistxattr(self)
+    }
+    fn fsetxattr(&self, name: &str, value: &[u8], flags: i32) -> Result<(), GlusterError> {
+        GlusterFile::fsetxattr(self, name, value, flags)
+    }
+    fn fremovexattr(&self, name: &str) -> Result<(), GlusterError> {
+        GlusterFile::fremovexattr(self, name)
+    }
+    fn fallocate(&self, offset: i64, keep_size: i32, len: usize) -> Result<(), GlusterError> {
+        GlusterFile::fallocate(self, offset, keep_size, len)
+    }
+    fn discard(&self, offset: i64, len: usize) -> Result<(), GlusterError> {
+        GlusterFile::discard(self, offset, len)
+    }
+    fn zerofill(&self, offset: i64, len: i64) -> Result<(), GlusterError> {
+        GlusterFile::zerofill(self, offset, len)
+    }
+    fn fchdir(&self) -> Result<(), GlusterError> {
+        GlusterFile::fchdir(self)
+    }
+    fn futimens(&self, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        GlusterFile::futimens(self, times)
+    }
+    fn posixlock(&self, command: PosixLockCmd, flock: &mut flock) -> Result<(), GlusterError> {
+        GlusterFile::posixlock(self, command, flock)
+    }
+    fn fchmod(&self, mode: mode_t) -> Result<(), GlusterError> {
+        GlusterFile::fchmod(self, mode)
+    }
+    fn fchown(&self, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        GlusterFile::fchown(self, uid, gid)
+    }
+    fn dup(&self) -> Result<GlusterFile, GlusterError> {
+        GlusterFile::dup(self)
+    }
+}
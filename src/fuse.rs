@@ -0,0 +1,934 @@
+//! Serve a volume over FUSE
+//! GlusterFuse answers kernel FUSE requests through the safe wrappers,
+//! which gives a userspace mount whose attribute caching and identity
+//! mapping are ours rather than glusterfs-fuse's.  Inode numbers are the
+//! ones gluster derives from each gfid, except that the directory being
+//! served is always inode 1 as FUSE requires.
+use crate::credentials::ImpersonationGuard;
+use crate::gluster::{self, FallocMode, Gluster, GlusterError, GlusterFile};
+use errno::errno;
+use fuser::{
+    BackgroundSession, FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyCreate,
+    ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs, ReplyWrite,
+    ReplyXattr, Request, TimeOrNow, FUSE_ROOT_ID,
+};
+use libc::{
+    c_int, gid_t, stat, timespec, EBADF, EINVAL, EIO, EOPNOTSUPP, ERANGE, EROFS, O_ACCMODE,
+    O_RDONLY, O_TRUNC, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFSOCK, UTIME_NOW,
+    UTIME_OMIT, W_OK,
+};
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The uid and gid root_squash maps root to
+const NOBODY: u32 = 65534;
+
+/// Past this many cached attributes the expired ones are dropped
+const ATTR_CACHE_PRUNE: usize = 100_000;
+
+/// How a volume is presented through FUSE
+#[derive(Clone, Debug)]
+pub struct FuseOptions {
+    /// Directory of the volume to serve as the root of the mount
+    pub subdir: PathBuf,
+    /// Shown as the source of the mount in /proc/mounts
+    pub fsname: String,
+    /// How long the kernel, and GlusterFuse itself, may reuse attributes
+    pub attr_timeout: Duration,
+    /// How long the kernel may reuse the result of a name lookup
+    pub entry_timeout: Duration,
+    /// Refuse every modification with EROFS
+    pub read_only: bool,
+    /// Perform each request as the uid, gid and groups of the process
+    /// that made it instead of as this process
+    pub impersonate: bool,
+    /// When impersonating, perform root's requests as nobody
+    pub root_squash: bool,
+    /// Let users other than the one mounting use the filesystem
+    pub allow_other: bool,
+}
+
+impl Default for FuseOptions {
+    fn default() -> Self {
+        FuseOptions {
+            subdir: PathBuf::from("/"),
+            fsname: "gfapi".to_string(),
+            attr_timeout: Duration::from_secs(1),
+            entry_timeout: Duration::from_secs(1),
+            read_only: false,
+            impersonate: false,
+            root_squash: false,
+            allow_other: false,
+        }
+    }
+}
+
+/// Something a request can fail with
+trait ErrorCode {
+    fn code(&self) -> c_int;
+}
+
+impl ErrorCode for c_int {
+    fn code(&self) -> c_int {
+        *self
+    }
+}
+
+impl ErrorCode for GlusterError {
+    /// Must be called before anything else can touch errno
+    fn code(&self) -> c_int {
+        match self {
+            GlusterError::NulError(_) => EINVAL,
+            GlusterError::Unsupported(_) => EOPNOTSUPP,
+            _ => match errno().0 {
+                0 => EIO,
+                code => code,
+            },
+        }
+    }
+}
+
+/// Unwrap a result or answer the request with its errno
+macro_rules! try_reply {
+    ($reply:expr, $result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(e) => {
+                $reply.error(ErrorCode::code(&e));
+                return;
+            }
+        }
+    };
+}
+
+struct Node {
+    path: PathBuf,
+    // How many lookups the kernel still holds on the inode
+    lookups: u64,
+}
+
+// gfapi fds may be used from any thread
+struct OpenFile {
+    file: GlusterFile,
+    path: PathBuf,
+}
+unsafe impl Send for OpenFile {}
+
+struct ListEntry {
+    ino: u64,
+    kind: FileType,
+    name: OsString,
+}
+
+/// A fuser Filesystem backed by a Gluster handle
+pub struct GlusterFuse {
+    cluster: Gluster,
+    options: FuseOptions,
+    // Gluster's inode number for the directory being served
+    root_ino: u64,
+    nodes: HashMap<u64, Node>,
+    attrs: HashMap<PathBuf, (stat, Instant)>,
+    files: HashMap<u64, OpenFile>,
+    // Directories are listed in full when opened so that the offsets
+    // handed to the kernel stay valid while the directory changes
+    dirs: HashMap<u64, Vec<ListEntry>>,
+    next_fh: u64,
+}
+
+impl GlusterFuse {
+    pub fn new(cluster: Gluster, options: FuseOptions) -> Result<GlusterFuse, GlusterError> {
+        let root = Path::new("/").join(&options.subdir);
+        let st = cluster.stat(&root)?;
+        if st.st_mode & S_IFMT != S_IFDIR {
+            return Err(GlusterError::new(format!(
+                "{} is not a directory",
+                root.display()
+            )));
+        }
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            FUSE_ROOT_ID,
+            Node {
+                path: root,
+                lookups: 1,
+            },
+        );
+        Ok(GlusterFuse {
+            cluster,
+            options,
+            root_ino: st.st_ino,
+            nodes,
+            attrs: HashMap::new(),
+            files: HashMap::new(),
+            dirs: HashMap::new(),
+            next_fh: 1,
+        })
+    }
+
+    pub fn mount_options(&self) -> Vec<MountOption> {
+        let mut options = vec![
+            MountOption::FSName(self.options.fsname.clone()),
+            MountOption::Subtype("gfapi".to_string()),
+            MountOption::DefaultPermissions,
+        ];
+        if self.options.read_only {
+            options.push(MountOption::RO);
+        }
+        if self.options.allow_other {
+            options.push(MountOption::AllowOther);
+        }
+        options
+    }
+
+    /// Serve requests until the filesystem is unmounted
+    pub fn mount<P: AsRef<Path>>(self, mountpoint: P) -> io::Result<()> {
+        let options = self.mount_options();
+        fuser::mount2(self, mountpoint, &options)
+    }
+
+    /// Serve requests from a background thread until the returned session
+    /// is dropped, which unmounts the filesystem
+    pub fn spawn_mount<P: AsRef<Path>>(self, mountpoint: P) -> io::Result<BackgroundSession> {
+        let options = self.mount_options();
+        fuser::spawn_mount2(self, mountpoint, &options)
+    }
+
+    /// Gluster and FUSE inode numbers only differ for the served directory
+    /// and gluster's inode 1, which trade places.
+    fn swap_root(&self, ino: u64) -> u64 {
+        if ino == self.root_ino {
+            FUSE_ROOT_ID
+        } else if ino == FUSE_ROOT_ID {
+            self.root_ino
+        } else {
+            ino
+        }
+    }
+
+    fn path(&self, ino: u64) -> Result<PathBuf, c_int> {
+        match self.nodes.get(&ino) {
+            Some(node) => Ok(node.path.clone()),
+            None => Err(libc::ENOENT),
+        }
+    }
+
+    fn child_path(&self, parent: u64, name: &OsStr) -> Result<PathBuf, c_int> {
+        let parent_path = self.path(parent)?;
+        match Path::new(name).components().next() {
+            Some(Component::Normal(_)) => Ok(parent_path.join(name)),
+            Some(Component::ParentDir) if parent != FUSE_ROOT_ID => {
+                Ok(parent_path.parent().unwrap_or(&parent_path).to_path_buf())
+            }
+            Some(Component::ParentDir) | Some(Component::CurDir) => Ok(parent_path),
+            _ => Err(EINVAL),
+        }
+    }
+
+    fn writable(&self) -> Result<(), c_int> {
+        if self.options.read_only {
+            return Err(EROFS);
+        }
+        Ok(())
+    }
+
+    /// Take on the identity of whoever made the request until the guard
+    /// is dropped
+    fn identity(&self, req: &Request<'_>) -> Result<Option<ImpersonationGuard>, GlusterError> {
+        if !self.options.impersonate {
+            return Ok(None);
+        }
+        let guard = if self.options.root_squash && req.uid() == 0 {
+            self.cluster.impersonate(NOBODY, NOBODY, &[])?
+        } else {
+            self.cluster
+                .impersonate(req.uid(), req.gid(), &groups_of(req.pid()))?
+        };
+        Ok(Some(guard))
+    }
+
+    fn lstat(&mut self, path: &Path) -> Result<stat, GlusterError> {
+        if let Some((st, fetched)) = self.attrs.get(path) {
+            if fetched.elapsed() < self.options.attr_timeout {
+                return Ok(*st);
+            }
+        }
+        let st = self.cluster.lsstat(path)?;
+        self.cache(path, st);
+        Ok(st)
+    }
+
+    fn cache(&mut self, path: &Path, st: stat) {
+        if self.options.attr_timeout == Duration::from_secs(0) {
+            return;
+        }
+        if self.attrs.len() >= ATTR_CACHE_PRUNE {
+            let timeout = self.options.attr_timeout;
+            self.attrs
+                .retain(|_, (_, fetched)| fetched.elapsed() < timeout);
+        }
+        self.attrs.insert(path.to_path_buf(), (st, Instant::now()));
+    }
+
+    /// Forget the attributes of path and of the directory it's in, whose
+    /// times and link count change along with it
+    fn invalidate(&mut self, path: &Path) {
+        self.attrs.remove(path);
+        if let Some(parent) = path.parent() {
+            self.attrs.remove(parent);
+        }
+    }
+
+    /// Record that the kernel looked up path
+    fn remember(&mut self, path: &Path, st: &stat) -> FileAttr {
+        let attr = self.to_attr(st);
+        let node = self.nodes.entry(attr.ino).or_insert_with(|| Node {
+            path: path.to_path_buf(),
+            lookups: 0,
+        });
+        // A hard link may have been reached under a different name
+        if attr.ino != FUSE_ROOT_ID {
+            node.path = path.to_path_buf();
+        }
+        node.lookups += 1;
+        attr
+    }
+
+    /// The entry to answer with for a file that was just created at path
+    fn created(&mut self, path: &Path) -> Result<FileAttr, GlusterError> {
+        self.invalidate(path);
+        let st = self.cluster.lsstat(path)?;
+        self.cache(path, st);
+        Ok(self.remember(path, &st))
+    }
+
+    fn to_attr(&self, st: &stat) -> FileAttr {
+        FileAttr {
+            ino: self.swap_root(st.st_ino),
+            size: st.st_size as u64,
+            blocks: st.st_blocks as u64,
+            atime: system_time(st.st_atime, st.st_atime_nsec),
+            mtime: system_time(st.st_mtime, st.st_mtime_nsec),
+            ctime: system_time(st.st_ctime, st.st_ctime_nsec),
+            crtime: UNIX_EPOCH,
+            kind: file_type(st.st_mode),
+            perm: (st.st_mode & 0o7777) as u16,
+            nlink: st.st_nlink as u32,
+            uid: st.st_uid,
+            gid: st.st_gid,
+            rdev: st.st_rdev as u32,
+            blksize: st.st_blksize as u32,
+            flags: 0,
+        }
+    }
+
+    fn add_file(&mut self, file: GlusterFile, path: PathBuf) -> u64 {
+        let fh = self.next_fh;
+        self.next_fh += 1;
+        self.files.insert(fh, OpenFile { file, path });
+        fh
+    }
+
+    fn file(&self, fh: u64) -> Result<&OpenFile, c_int> {
+        self.files.get(&fh).ok_or(EBADF)
+    }
+
+    fn list(&mut self, path: &Path) -> Result<Vec<ListEntry>, GlusterError> {
+        let mut entries = Vec::new();
+        for entry in self.cluster.opendir_plus(path)? {
+            let entry = entry?;
+            let name = entry.path.into_os_string();
+            // Gluster leaves the stat empty when it couldn't fetch one
+            if entry.stat.st_ino == 0 || name == "." || name == ".." {
+                entries.push(ListEntry {
+                    ino: self.swap_root(entry.inode),
+                    kind: entry_file_type(entry.file_type),
+                    name,
+                });
+                continue;
+            }
+            self.cache(&path.join(&name), entry.stat);
+            entries.push(ListEntry {
+                ino: self.swap_root(entry.stat.st_ino),
+                kind: file_type(entry.stat.st_mode),
+                name,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+fn system_time(secs: i64, nsecs: i64) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::new(secs as u64, nsecs as u32)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + Duration::from_nanos(nsecs as u64)
+    }
+}
+
+fn to_timespec(time: Option<TimeOrNow>) -> timespec {
+    let (tv_sec, tv_nsec) = match time {
+        None => (0, UTIME_OMIT),
+        Some(TimeOrNow::Now) => (0, UTIME_NOW),
+        Some(TimeOrNow::SpecificTime(time)) => match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => (since.as_secs() as i64, i64::from(since.subsec_nanos())),
+            Err(e) => {
+                let before = e.duration();
+                match before.subsec_nanos() {
+                    0 => (-(before.as_secs() as i64), 0),
+                    nanos => (
+                        -(before.as_secs() as i64) - 1,
+                        1_000_000_000 - i64::from(nanos),
+                    ),
+                }
+            }
+        },
+    };
+    timespec { tv_sec, tv_nsec }
+}
+
+fn file_type(mode: u32) -> FileType {
+    match mode & S_IFMT {
+        S_IFDIR => FileType::Directory,
+        S_IFLNK => FileType::Symlink,
+        S_IFCHR => FileType::CharDevice,
+        S_IFBLK => FileType::BlockDevice,
+        S_IFIFO => FileType::NamedPipe,
+        S_IFSOCK => FileType::Socket,
+        _ => FileType::RegularFile,
+    }
+}
+
+fn entry_file_type(file_type: gluster::FileType) -> FileType {
+    match file_type {
+        gluster::FileType::Directory => FileType::Directory,
+        gluster::FileType::Symlink => FileType::Symlink,
+        gluster::FileType::CharDevice => FileType::CharDevice,
+        gluster::FileType::BlockDevice => FileType::BlockDevice,
+        gluster::FileType::Fifo => FileType::NamedPipe,
+        gluster::FileType::Socket => FileType::Socket,
+        gluster::FileType::Regular | gluster::FileType::Unknown => FileType::RegularFile,
+    }
+}
+
+/// The supplementary groups of a process, which FUSE doesn't pass along
+fn groups_of(pid: u32) -> Vec<gid_t> {
+    let status = match std::fs::read_to_string(format!("/proc/{}/status", pid)) {
+        Ok(status) => status,
+        Err(_) => return Vec::new(),
+    };
+    status
+        .lines()
+        .find(|line| line.starts_with("Groups:"))
+        .map(|line| {
+            line["Groups:".len()..]
+                .split_whitespace()
+                .filter_map(|group| group.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl Filesystem for GlusterFuse {
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let path = try_reply!(reply, self.child_path(parent, name));
+        let _guard = try_reply!(reply, self.identity(req));
+        let st = try_reply!(reply, self.lstat(&path));
+        let attr = self.remember(&path, &st);
+        reply.entry(&self.options.entry_timeout, &attr, 0);
+    }
+
+    fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+        if ino == FUSE_ROOT_ID {
+            return;
+        }
+        if let Some(node) = self.nodes.get_mut(&ino) {
+            node.lookups = node.lookups.saturating_sub(nlookup);
+            if node.lookups == 0 {
+                if let Some(node) = self.nodes.remove(&ino) {
+                    self.attrs.remove(&node.path);
+                }
+            }
+        }
+    }
+
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        let path = try_reply!(reply, self.path(ino));
+        let _guard = try_reply!(reply, self.identity(req));
+        let st = try_reply!(reply, self.lstat(&path));
+        reply.attr(&self.options.attr_timeout, &self.to_attr(&st));
+    }
+
+    fn setattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        try_reply!(reply, self.writable());
+        let path = try_reply!(reply, self.path(ino));
+        let _guard = try_reply!(reply, self.identity(req));
+        self.invalidate(&path);
+        if let Some(mode) = mode {
+            try_reply!(reply, self.cluster.chmod(&path, mode & 0o7777));
+        }
+        if uid.is_some() || gid.is_some() {
+            // -1 leaves the owner or group as it is
+            try_reply!(
+                reply,
+                self.cluster
+                    .lchown(&path, uid.unwrap_or(u32::MAX), gid.unwrap_or(u32::MAX))
+            );
+        }
+        if let Some(size) = size {
+            match fh.and_then(|fh| self.files.get(&fh)) {
+                Some(open) => try_reply!(reply, open.file.ftruncate(size as i64)),
+                None => try_reply!(reply, self.cluster.truncate(&path, size as i64)),
+            }
+        }
+        if atime.is_some() || mtime.is_some() {
+            let times = [to_timespec(atime), to_timespec(mtime)];
+            try_reply!(reply, self.cluster.lutimens(&path, &times));
+        }
+        let st = try_reply!(reply, self.lstat(&path));
+        reply.attr(&self.options.attr_timeout, &self.to_attr(&st));
+    }
+
+    fn readlink(&mut self, req: &Request<'_>, ino: u64, reply: ReplyData) {
+        let path = try_reply!(reply, self.path(ino));
+        let _guard = try_reply!(reply, self.identity(req));
+        let mut buf = vec![0; libc::PATH_MAX as usize];
+        try_reply!(reply, self.cluster.readlink(&path, &mut buf));
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        reply.data(&buf[..len]);
+    }
+
+    fn mknod(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        try_reply!(reply, self.writable());
+        let path = try_reply!(reply, self.child_path(parent, name));
+        let _guard = try_reply!(reply, self.identity(req));
+        try_reply!(
+            reply,
+            self.cluster
+                .mknod(&path, mode & !umask, libc::dev_t::from(rdev))
+        );
+        let attr = try_reply!(reply, self.created(&path));
+        reply.entry(&self.options.entry_timeout, &attr, 0);
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        try_reply!(reply, self.writable());
+        let path = try_reply!(reply, self.child_path(parent, name));
+        let _guard = try_reply!(reply, self.identity(req));
+        try_reply!(reply, self.cluster.mkdir(&path, mode & !umask));
+        let attr = try_reply!(reply, self.created(&path));
+        reply.entry(&self.options.entry_timeout, &attr, 0);
+    }
+
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        try_reply!(reply, self.writable());
+        let path = try_reply!(reply, self.child_path(parent, name));
+        let _guard = try_reply!(reply, self.identity(req));
+        try_reply!(reply, self.cluster.unlink(&path));
+        self.invalidate(&path);
+        reply.ok();
+    }
+
+    fn rmdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        try_reply!(reply, self.writable());
+        let path = try_reply!(reply, self.child_path(parent, name));
+        let _guard = try_reply!(reply, self.identity(req));
+        try_reply!(reply, self.cluster.rmdir(&path));
+        self.invalidate(&path);
+        reply.ok();
+    }
+
+    fn symlink(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        link_name: &OsStr,
+        target: &Path,
+        reply: ReplyEntry,
+    ) {
+        try_reply!(reply, self.writable());
+        let path = try_reply!(reply, self.child_path(parent, link_name));
+        let _guard = try_reply!(reply, self.identity(req));
+        try_reply!(reply, self.cluster.symlink(target, &path));
+        let attr = try_reply!(reply, self.created(&path));
+        reply.entry(&self.options.entry_timeout, &attr, 0);
+    }
+
+    fn rename(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        try_reply!(reply, self.writable());
+        // gfapi has no renameat2 for RENAME_NOREPLACE or RENAME_EXCHANGE
+        if flags != 0 {
+            reply.error(EINVAL);
+            return;
+        }
+        let from = try_reply!(reply, self.child_path(parent, name));
+        let to = try_reply!(reply, self.child_path(newparent, newname));
+        let _guard = try_reply!(reply, self.identity(req));
+        // Whatever is at to is replaced, unless it's a link to the same
+        // inode, in which case gluster leaves both names alone
+        let moved = try_reply!(reply, self.lstat(&from)).st_ino;
+        let replaced = self
+            .lstat(&to)
+            .ok()
+            .map(|st| st.st_ino)
+            .filter(|ino| *ino != moved);
+        try_reply!(reply, self.cluster.rename(&from, &to));
+        self.invalidate(&to);
+        self.invalidate(&from);
+        if let Some(ino) = replaced {
+            // The kernel may still hold the replaced inode, but it no
+            // longer has a path of its own to be reached by
+            let ino = self.swap_root(ino);
+            if self.nodes.get(&ino).is_some_and(|node| node.path == to) {
+                self.nodes.remove(&ino);
+            }
+        }
+        self.attrs.retain(|path, _| !path.starts_with(&from));
+        for node in self.nodes.values_mut() {
+            if let Ok(rest) = node.path.strip_prefix(&from) {
+                node.path = to.join(rest);
+            }
+        }
+        // Open files invalidate their cached attributes by path as well
+        for open in self.files.values_mut() {
+            if let Ok(rest) = open.path.strip_prefix(&from) {
+                open.path = to.join(rest);
+            }
+        }
+        reply.ok();
+    }
+
+    fn link(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        try_reply!(reply, self.writable());
+        let from = try_reply!(reply, self.path(ino));
+        let to = try_reply!(reply, self.child_path(newparent, newname));
+        let _guard = try_reply!(reply, self.identity(req));
+        try_reply!(reply, self.cluster.link(&from, &to));
+        // The link count of the original changed too
+        self.attrs.remove(&from);
+        let attr = try_reply!(reply, self.created(&to));
+        reply.entry(&self.options.entry_timeout, &attr, 0);
+    }
+
+    fn open(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        if flags & O_ACCMODE != O_RDONLY || flags & O_TRUNC != 0 {
+            try_reply!(reply, self.writable());
+        }
+        let path = try_reply!(reply, self.path(ino));
+        let _guard = try_reply!(reply, self.identity(req));
+        let file = try_reply!(reply, self.cluster.open(&path, flags));
+        if flags & O_TRUNC != 0 {
+            self.invalidate(&path);
+        }
+        let fh = self.add_file(file, path);
+        reply.opened(fh, 0);
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let open = try_reply!(reply, self.file(fh));
+        let mut buf = Vec::with_capacity(size as usize);
+        try_reply!(reply, open.file.pread(&mut buf, size as usize, offset, 0));
+        reply.data(&buf);
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        let open = try_reply!(reply, self.file(fh));
+        let written = try_reply!(reply, open.file.pwrite(data, data.len(), offset, 0));
+        let path = open.path.clone();
+        self.attrs.remove(&path);
+        reply.written(written as u32);
+    }
+
+    fn flush(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _lock_owner: u64,
+        reply: ReplyEmpty,
+    ) {
+        // Writes go straight to gluster so there's nothing to flush
+        reply.ok();
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        // GlusterFile closes the fd when dropped
+        self.files.remove(&fh);
+        reply.ok();
+    }
+
+    fn fsync(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
+        let open = try_reply!(reply, self.file(fh));
+        if datasync {
+            try_reply!(reply, open.file.fdatasync());
+        } else {
+            try_reply!(reply, open.file.fsync());
+        }
+        reply.ok();
+    }
+
+    fn opendir(&mut self, req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        let path = try_reply!(reply, self.path(ino));
+        let _guard = try_reply!(reply, self.identity(req));
+        let entries = try_reply!(reply, self.list(&path));
+        let fh = self.next_fh;
+        self.next_fh += 1;
+        self.dirs.insert(fh, entries);
+        reply.opened(fh, 0);
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let entries = match self.dirs.get(&fh) {
+            Some(entries) => entries,
+            None => {
+                reply.error(EBADF);
+                return;
+            }
+        };
+        for (i, entry) in entries.iter().enumerate().skip(offset as usize) {
+            // The offset of an entry is where to carry on after it
+            if reply.add(entry.ino, i as i64 + 1, entry.kind, &entry.name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn releasedir(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        reply: ReplyEmpty,
+    ) {
+        self.dirs.remove(&fh);
+        reply.ok();
+    }
+
+    fn statfs(&mut self, req: &Request<'_>, ino: u64, reply: ReplyStatfs) {
+        let path = try_reply!(reply, self.path(ino));
+        let _guard = try_reply!(reply, self.identity(req));
+        let st = try_reply!(reply, self.cluster.statvfs(&path));
+        reply.statfs(
+            st.f_blocks,
+            st.f_bfree,
+            st.f_bavail,
+            st.f_files,
+            st.f_ffree,
+            st.f_bsize as u32,
+            st.f_namemax as u32,
+            st.f_frsize as u32,
+        );
+    }
+
+    fn setxattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        try_reply!(reply, self.writable());
+        let path = try_reply!(reply, self.path(ino));
+        let name = try_reply!(reply, name.to_str().ok_or(EINVAL));
+        let _guard = try_reply!(reply, self.identity(req));
+        try_reply!(reply, self.cluster.lsetxattr(name, value, &path, flags));
+        self.attrs.remove(&path);
+        reply.ok();
+    }
+
+    fn getxattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let path = try_reply!(reply, self.path(ino));
+        let name = try_reply!(reply, name.to_str().ok_or(EINVAL));
+        let _guard = try_reply!(reply, self.identity(req));
+        let value = try_reply!(reply, self.cluster.lgetxattr_bytes(&path, name));
+        if size == 0 {
+            reply.size(value.len() as u32);
+        } else if value.len() > size as usize {
+            reply.error(ERANGE);
+        } else {
+            reply.data(&value);
+        }
+    }
+
+    fn listxattr(&mut self, req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        let path = try_reply!(reply, self.path(ino));
+        let _guard = try_reply!(reply, self.identity(req));
+        let names = try_reply!(reply, self.cluster.llistxattr(&path)).into_bytes();
+        if size == 0 {
+            reply.size(names.len() as u32);
+        } else if names.len() > size as usize {
+            reply.error(ERANGE);
+        } else {
+            reply.data(&names);
+        }
+    }
+
+    fn removexattr(&mut self, req: &Request<'_>, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        try_reply!(reply, self.writable());
+        let path = try_reply!(reply, self.path(ino));
+        let name = try_reply!(reply, name.to_str().ok_or(EINVAL));
+        let _guard = try_reply!(reply, self.identity(req));
+        try_reply!(reply, self.cluster.lremovexattr(&path, name));
+        self.attrs.remove(&path);
+        reply.ok();
+    }
+
+    fn access(&mut self, req: &Request<'_>, ino: u64, mask: i32, reply: ReplyEmpty) {
+        if mask & W_OK != 0 {
+            try_reply!(reply, self.writable());
+        }
+        let path = try_reply!(reply, self.path(ino));
+        let _guard = try_reply!(reply, self.identity(req));
+        try_reply!(reply, self.cluster.access(&path, mask));
+        reply.ok();
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        try_reply!(reply, self.writable());
+        let path = try_reply!(reply, self.child_path(parent, name));
+        let _guard = try_reply!(reply, self.identity(req));
+        let file = try_reply!(reply, self.cluster.create(&path, flags, mode & !umask));
+        let attr = try_reply!(reply, self.created(&path));
+        let fh = self.add_file(file, path);
+        reply.created(&self.options.entry_timeout, &attr, 0, fh, 0);
+    }
+
+    fn fallocate(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        try_reply!(reply, self.writable());
+        let open = try_reply!(reply, self.file(fh));
+        let mode = match FallocMode::from_flags(mode) {
+            Some(mode) => mode,
+            None => {
+                reply.error(EOPNOTSUPP);
+                return;
+            }
+        };
+        // allocate turns negative values into EINVAL past i64::MAX
+        let start = offset as u64;
+        let range = start..start.saturating_add(length as u64);
+        try_reply!(reply, open.file.allocate(range, mode));
+        let path = open.path.clone();
+        self.attrs.remove(&path);
+        reply.ok();
+    }
+}
@@ -0,0 +1,238 @@
+//! Gluster file ids
+//! Every inode in a gluster volume is identified by a 16 byte GFID which,
+//! unlike a path, survives renames.  This module wraps the GFID in a type
+//! of its own and resolves between GFIDs, inodes and paths.
+use crate::glfs::*;
+use crate::gluster::{get_error, Gluster, GlusterError, GlusterFile};
+use libc::{c_void, stat, S_IFDIR, S_IFMT};
+use uuid::Uuid;
+
+use std::ffi::{CStr, CString, OsStr};
+use std::fmt;
+use std::mem::zeroed;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Virtual xattr holding the 16 byte GFID of a path
+pub const GFID_KEY: &str = "glusterfs.gfid";
+/// Prefix of the xattrs recording `<parent gfid>/<name>` for every link
+/// to a file.  Requires the volume's storage.gfid2path option.
+pub const GFID2PATH_KEY_PREFIX: &str = "trusted.gfid2path.";
+/// Virtual xattr that resolves a directory GFID to its path
+pub const GFID_TO_PATH_KEY: &str = "glusterfs.gfidtopath";
+
+/// The identity of an inode in a gluster volume
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Gfid(Uuid);
+
+impl Gfid {
+    /// The GFID gluster assigns to the root of every volume
+    pub fn root() -> Gfid {
+        let mut bytes = [0; 16];
+        bytes[15] = 1;
+        Gfid(Uuid::from_bytes(bytes))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Gfid, GlusterError> {
+        Ok(Gfid(Uuid::from_slice(bytes)?))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        self.0.as_bytes()
+    }
+
+    pub fn is_root(&self) -> bool {
+        *self == Gfid::root()
+    }
+
+    /// The path under the `.gfid` virtual directory that addresses this
+    /// inode on mounts with aux-gfid-mount enabled.
+    pub fn virtual_path(&self) -> PathBuf {
+        PathBuf::from(format!("/.gfid/{}", self))
+    }
+}
+
+impl fmt::Display for Gfid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.to_hyphenated())
+    }
+}
+
+impl FromStr for Gfid {
+    type Err = GlusterError;
+    fn from_str(s: &str) -> Result<Gfid, GlusterError> {
+        Ok(Gfid(Uuid::parse_str(s)?))
+    }
+}
+
+impl From<Uuid> for Gfid {
+    fn from(uuid: Uuid) -> Gfid {
+        Gfid(uuid)
+    }
+}
+
+impl From<Gfid> for Uuid {
+    fn from(gfid: Gfid) -> Uuid {
+        gfid.0
+    }
+}
+
+/// Split the value of a trusted.gfid2path xattr into the parent directory
+/// GFID and the name of the link inside it.
+pub fn parse_gfid2path(value: &[u8]) -> Result<(Gfid, PathBuf), GlusterError> {
+    // Some gluster versions include the trailing NUL in the value
+    let value = value.strip_suffix(&[0]).unwrap_or(value);
+    let split = value
+        .iter()
+        .position(|b| *b == b'/')
+        .ok_or_else(|| GlusterError::Error("gfid2path value is missing a '/'".into()))?;
+    let parent = String::from_utf8(value[..split].to_vec())?.parse()?;
+    let name = PathBuf::from(OsStr::from_bytes(&value[split + 1..]));
+    Ok((parent, name))
+}
+
+/// An inode looked up by its GFID through the handle api
+struct GfidObject {
+    cluster_handle: *mut glfs,
+    object: *mut glfs_object,
+}
+
+impl Drop for GfidObject {
+    fn drop(&mut self) {
+        unsafe {
+            let retcode = glfs!(glfs_h_close(self.object));
+            if retcode < 0 {
+                error!("{:?}", GlusterError::new(get_error()));
+            }
+        }
+    }
+}
+
+impl GfidObject {
+    fn lookup(
+        cluster: &Gluster,
+        gfid: &Gfid,
+        stat_buf: &mut stat,
+    ) -> Result<GfidObject, GlusterError> {
+        let mut handle = *gfid.as_bytes();
+        unsafe {
+            let object = glfs!(glfs_h_create_from_handle(
+                cluster.cluster_handle,
+                handle.as_mut_ptr(),
+                handle.len() as i32,
+                stat_buf,
+            ));
+            if object.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(GfidObject {
+                cluster_handle: cluster.cluster_handle,
+                object,
+            })
+        }
+    }
+
+    /// Read an xattr off the inode, or list them all when name is None
+    fn getxattr(&self, name: Option<&CStr>) -> Result<Vec<u8>, GlusterError> {
+        let name = name.map_or(ptr::null(), |n| n.as_ptr());
+        unsafe {
+            let size = glfs!(glfs_h_getxattrs(
+                self.cluster_handle,
+                self.object,
+                name,
+                ptr::null_mut(),
+                0,
+            ));
+            if size < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(size as usize);
+            let ret_code = glfs!(glfs_h_getxattrs(
+                self.cluster_handle,
+                self.object,
+                name,
+                xattr_val_buff.as_mut_ptr() as *mut c_void,
+                xattr_val_buff.capacity(),
+            ));
+            if ret_code < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            // Set the buffer to the size of bytes read into it
+            xattr_val_buff.set_len(ret_code as usize);
+            Ok(xattr_val_buff)
+        }
+    }
+}
+
+impl Gluster {
+    /// Look up the GFID of a path
+    pub fn gfid(&self, path: &Path) -> Result<Gfid, GlusterError> {
+        traced!(DEBUG, "gfid", volume = self.volume, path = path);
+        let buf = self.getxattr_bytes(path, GFID_KEY)?;
+        Gfid::from_bytes(&buf)
+    }
+
+    /// Open a file by its GFID without resolving a path
+    pub fn open_by_gfid(&self, gfid: &Gfid, flags: i32) -> Result<GlusterFile, GlusterError> {
+        traced!(DEBUG, "open_by_gfid", volume = self.volume);
+        let mut stat_buf: stat = unsafe { zeroed() };
+        let object = GfidObject::lookup(self, gfid, &mut stat_buf)?;
+        unsafe {
+            let file_handle = glfs!(glfs_h_open(self.cluster_handle, object.object, flags));
+            if file_handle.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(GlusterFile {
+                file_handle,
+                volume: Arc::clone(&self.volume),
+            })
+        }
+    }
+
+    /// Stat an inode by its GFID without resolving a path
+    pub fn stat_by_gfid(&self, gfid: &Gfid) -> Result<stat, GlusterError> {
+        traced!(DEBUG, "stat_by_gfid", volume = self.volume);
+        let mut stat_buf: stat = unsafe { zeroed() };
+        GfidObject::lookup(self, gfid, &mut stat_buf)?;
+        Ok(stat_buf)
+    }
+
+    /// Find every path that links to this GFID.  Directories resolve to
+    /// their single path.  Files are resolved through the trusted.gfid2path
+    /// xattrs, so the volume must have storage.gfid2path enabled and this
+    /// client must be allowed to read trusted xattrs.
+    pub fn gfid_to_paths(&self, gfid: &Gfid) -> Result<Vec<PathBuf>, GlusterError> {
+        traced!(DEBUG, "gfid_to_paths", volume = self.volume);
+        if gfid.is_root() {
+            return Ok(vec![PathBuf::from("/")]);
+        }
+        let mut stat_buf: stat = unsafe { zeroed() };
+        let object = GfidObject::lookup(self, gfid, &mut stat_buf)?;
+        if stat_buf.st_mode & S_IFMT == S_IFDIR {
+            let key = CString::new(GFID_TO_PATH_KEY)?;
+            let value = object.getxattr(Some(&key))?;
+            let value = value.strip_suffix(&[0]).unwrap_or(&value);
+            return Ok(vec![PathBuf::from(OsStr::from_bytes(value))]);
+        }
+
+        let mut paths = Vec::new();
+        let names = object.getxattr(None)?;
+        for name in names.split(|b| *b == 0) {
+            if !name.starts_with(GFID2PATH_KEY_PREFIX.as_bytes()) {
+                continue;
+            }
+            let key = CString::new(name)?;
+            let (parent, file_name) = parse_gfid2path(&object.getxattr(Some(&key))?)?;
+            // The parent is a directory so it has exactly one path
+            for mut path in self.gfid_to_paths(&parent)? {
+                path.push(&file_name);
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+}
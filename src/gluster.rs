@@ -1,20 +1,28 @@
-use errno::{errno, Errno};
+use errno::{errno, set_errno, Errno};
+use crate::fs::GlusterFileOps;
 use crate::glfs::*;
 use libc::{
-    c_uchar, c_void, dev_t, dirent, flock, ino_t, mode_t, stat, statvfs, timespec, DT_DIR, ENOENT,
-    LOCK_EX, LOCK_SH, LOCK_UN,
+    c_char, c_int, c_uchar, c_void, dev_t, dirent, flock, ino_t, mode_t, stat, statvfs, timespec,
+    DT_BLK, DT_CHR, DT_DIR, DT_FIFO, DT_LNK, DT_REG, DT_SOCK, EINVAL, ENOENT, ENOSYS, ENXIO,
+    EOPNOTSUPP, ERANGE, FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE, FALLOC_FL_ZERO_RANGE, LOCK_EX,
+    LOCK_SH, LOCK_UN, SEEK_DATA, SEEK_HOLE, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT,
+    S_IFREG, S_IFSOCK,
 };
 use uuid::Uuid;
 
 use std::error::Error as err;
-use std::ffi::{CStr, CString, IntoStringError, NulError};
+use std::ffi::{CStr, CString, IntoStringError, NulError, OsStr};
 use std::fmt;
 use std::io::Error;
 use std::mem::zeroed;
+use std::num::ParseIntError;
+use std::ops::Range;
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::ptr;
+use std::str::FromStr;
 use std::string::FromUtf8Error;
+use std::sync::Arc;
 
 /// Custom error handling for the library
 #[derive(Debug)]
@@ -25,6 +33,10 @@ pub enum GlusterError {
     IntoStringError(IntoStringError),
     IoError(Error),
     NulError(NulError),
+    ParseError(uuid::parser::ParseError),
+    /// The volume, or the filesystem of its bricks, doesn't support what
+    /// was asked, so the caller can fall back to something else
+    Unsupported(String),
 }
 
 impl fmt::Display for GlusterError {
@@ -42,6 +54,8 @@ impl err for GlusterError {
             GlusterError::IntoStringError(ref e) => e.description(),
             GlusterError::IoError(ref e) => e.description(),
             GlusterError::NulError(ref e) => e.description(),
+            GlusterError::ParseError(ref e) => e.description(),
+            GlusterError::Unsupported(ref e) => &e,
         }
     }
     fn cause(&self) -> Option<&dyn err> {
@@ -52,12 +66,14 @@ impl err for GlusterError {
             GlusterError::IntoStringError(ref e) => e.cause(),
             GlusterError::IoError(ref e) => e.cause(),
             GlusterError::NulError(ref e) => e.cause(),
+            GlusterError::ParseError(ref e) => e.cause(),
+            GlusterError::Unsupported(_) => None,
         }
     }
 }
 impl GlusterError {
     /// Create a new GlusterError with a String message
-    fn new(err: String) -> GlusterError {
+    pub(crate) fn new(err: String) -> GlusterError {
         GlusterError::Error(err)
     }
 
@@ -70,6 +86,17 @@ impl GlusterError {
             GlusterError::IntoStringError(ref err) => err.description().to_string(),
             GlusterError::IoError(ref err) => err.description().to_string(),
             GlusterError::NulError(ref err) => err.description().to_string(),
+            GlusterError::ParseError(ref err) => err.description().to_string(),
+            GlusterError::Unsupported(ref err) => err.to_string(),
+        }
+    }
+
+    /// Unsupported in place of an error from a call that failed with
+    /// EOPNOTSUPP or ENOSYS
+    pub(crate) fn or_unsupported(self) -> GlusterError {
+        match errno().0 {
+            EOPNOTSUPP | ENOSYS => GlusterError::Unsupported(self.to_string()),
+            _ => self,
         }
     }
 }
@@ -104,17 +131,41 @@ impl From<Error> for GlusterError {
     }
 }
 
-//impl From<uuid::parser::ParseError> for GlusterError {
-//fn from(err: uuid::parser::ParseError) -> GlusterError {
-//GlusterError::ParseError(err)
-//}
-//}
+impl From<uuid::parser::ParseError> for GlusterError {
+    fn from(err: uuid::parser::ParseError) -> GlusterError {
+        GlusterError::ParseError(err)
+    }
+}
 
-fn get_error() -> String {
+pub(crate) fn get_error() -> String {
     let error = errno();
     format!("{}", error)
 }
 
+/// Run one of the glfs xattr getters, first asking how large the value is
+fn read_xattr<F>(get: F) -> Result<Vec<u8>, GlusterError>
+where
+    F: Fn(*mut c_void, usize) -> isize,
+{
+    loop {
+        let size = get(ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(GlusterError::new(get_error()));
+        }
+        let mut buf: Vec<u8> = vec![0; size as usize];
+        let read = get(buf.as_mut_ptr() as *mut c_void, buf.len());
+        if read < 0 {
+            // The value grew between the two calls
+            if errno() == Errno(ERANGE) {
+                continue;
+            }
+            return Err(GlusterError::new(get_error()));
+        }
+        buf.truncate(read as usize);
+        return Ok(buf);
+    }
+}
+
 /// Apply or remove an advisory lock on the open file.
 pub enum PosixLockCmd {
     /// Place  an  exclusive  lock.  Only one process may hold an
@@ -138,7 +189,7 @@ impl Into<i32> for PosixLockCmd {
 }
 
 #[repr(i32)]
-#[derive(PartialEq, Debug, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 ///  None to Trace correspond to the equivalent gluster log levels
 pub enum GlusterLogLevel {
     None = 0,
@@ -153,6 +204,16 @@ pub enum GlusterLogLevel {
     Trace,
 }
 
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+/// Requests glfs_sysrq understands, GLFS_SYSRQ_HELP and GLFS_SYSRQ_STATEDUMP
+pub enum GlusterSysRq {
+    /// Log the requests gfapi supports
+    Help = b'h',
+    /// Write a statedump to the statedump path
+    StateDump = b's',
+}
+
 // pub type glfs_io_cbk = ::std::option::Option<extern "C" fn(fd: *mut glfs_fd_t,
 // ret: ssize_t,
 // data: *mut c_void)
@@ -164,13 +225,15 @@ pub enum GlusterLogLevel {
 
 #[derive(Debug)]
 pub struct Gluster {
-    cluster_handle: *mut glfs,
+    pub(crate) cluster_handle: *mut glfs,
+    pub(crate) volume: Arc<str>,
 }
 
 /// Gluster file descriptor
 #[derive(Debug)]
 pub struct GlusterFile {
-    file_handle: *mut glfs_fd,
+    pub(crate) file_handle: *mut glfs_fd,
+    pub(crate) volume: Arc<str>,
 }
 
 impl Drop for GlusterFile {
@@ -180,7 +243,7 @@ impl Drop for GlusterFile {
             return;
         }
         unsafe {
-            let retcode = glfs_close(self.file_handle);
+            let retcode = glfs!(glfs_close(self.file_handle));
             if retcode < 0 {
                 error!("{:?}", GlusterError::new(get_error()));
             }
@@ -199,7 +262,7 @@ impl Drop for Gluster {
             return;
         }
         unsafe {
-            let retcode = glfs_fini(self.cluster_handle);
+            let retcode = glfs!(glfs_fini(self.cluster_handle));
             if retcode < 0 {
                 error!("{:?}", GlusterError::new(get_error()));
             }
@@ -207,6 +270,71 @@ impl Drop for Gluster {
     }
 }
 
+/// What kind of file a directory entry is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FileType {
+    Regular,
+    Directory,
+    Symlink,
+    BlockDevice,
+    CharDevice,
+    Fifo,
+    Socket,
+    /// The backend didn't say, and the entry couldn't be statted
+    Unknown,
+}
+
+impl FileType {
+    /// From the d_type of a dirent.  DT_UNKNOWN, which bricks on
+    /// filesystems that don't record the type return, becomes Unknown.
+    pub fn from_d_type(d_type: c_uchar) -> FileType {
+        match d_type {
+            DT_REG => FileType::Regular,
+            DT_DIR => FileType::Directory,
+            DT_LNK => FileType::Symlink,
+            DT_BLK => FileType::BlockDevice,
+            DT_CHR => FileType::CharDevice,
+            DT_FIFO => FileType::Fifo,
+            DT_SOCK => FileType::Socket,
+            _ => FileType::Unknown,
+        }
+    }
+
+    /// From the st_mode of a stat
+    pub fn from_mode(mode: mode_t) -> FileType {
+        match mode & S_IFMT {
+            S_IFREG => FileType::Regular,
+            S_IFDIR => FileType::Directory,
+            S_IFLNK => FileType::Symlink,
+            S_IFBLK => FileType::BlockDevice,
+            S_IFCHR => FileType::CharDevice,
+            S_IFIFO => FileType::Fifo,
+            S_IFSOCK => FileType::Socket,
+            _ => FileType::Unknown,
+        }
+    }
+
+    /// From d_type, or the stat when d_type is DT_UNKNOWN
+    fn from_d_type_or_mode(d_type: c_uchar, stat: Option<&stat>) -> FileType {
+        match (FileType::from_d_type(d_type), stat) {
+            (FileType::Unknown, Some(stat)) => FileType::from_mode(stat.st_mode),
+            (file_type, _) => file_type,
+        }
+    }
+
+    pub fn is_dir(self) -> bool {
+        self == FileType::Directory
+    }
+
+    pub fn is_file(self) -> bool {
+        self == FileType::Regular
+    }
+
+    pub fn is_symlink(self) -> bool {
+        self == FileType::Symlink
+    }
+}
+
 /// This uses readdirplus which is very efficient in Gluster.  In addition
 /// to returning directory entries this also stats each file.
 #[derive(Debug)]
@@ -221,7 +349,7 @@ impl Drop for GlusterDirectoryPlus {
             return;
         }
         unsafe {
-            let retcode = glfs_closedir(self.dir_handle);
+            let retcode = glfs!(glfs_closedir(self.dir_handle));
             if retcode < 0 {
                 error!("{:?}", GlusterError::new(get_error()));
             }
@@ -232,19 +360,24 @@ impl Drop for GlusterDirectoryPlus {
 pub struct DirEntryPlus {
     pub path: PathBuf,
     pub inode: ino_t,
-    pub file_type: c_uchar,
+    pub file_type: FileType,
     pub stat: stat,
 }
 
 impl Iterator for GlusterDirectoryPlus {
     type Item = Result<DirEntryPlus, GlusterError>;
     fn next(&mut self) -> Option<Self::Item> {
+        traced!(TRACE, "readdirplus");
         let mut dirent: dirent = unsafe { zeroed() };
         let mut next_entry: *mut dirent = ptr::null_mut();
         unsafe {
             let mut stat_buf: stat = zeroed();
-            let ret_code =
-                glfs_readdirplus_r(self.dir_handle, &mut stat_buf, &mut dirent, &mut next_entry);
+            let ret_code = glfs!(glfs_readdirplus_r(
+                self.dir_handle,
+                &mut stat_buf,
+                &mut dirent,
+                &mut next_entry,
+            ));
             if ret_code < 0 {
                 return Some(Err(GlusterError::new(get_error())));
             }
@@ -254,9 +387,9 @@ impl Iterator for GlusterDirectoryPlus {
             }
             let file_name = CStr::from_ptr(dirent.d_name.as_ptr());
             Some(Ok(DirEntryPlus {
-                path: PathBuf::from(file_name.to_string_lossy().into_owned()),
+                path: PathBuf::from(OsStr::from_bytes(file_name.to_bytes())),
                 inode: dirent.d_ino,
-                file_type: dirent.d_type,
+                file_type: FileType::from_d_type_or_mode(dirent.d_type, Some(&stat_buf)),
                 stat: stat_buf,
             }))
         }
@@ -266,6 +399,10 @@ impl Iterator for GlusterDirectoryPlus {
 #[derive(Debug)]
 pub struct GlusterDirectory {
     pub dir_handle: *mut glfs_fd,
+    cluster_handle: *mut glfs,
+    /// To lstat entries that come back as DT_UNKNOWN.  None for
+    /// directories opened through a handle, whose entries stay Unknown.
+    path: Option<PathBuf>,
 }
 
 impl Drop for GlusterDirectory {
@@ -275,7 +412,7 @@ impl Drop for GlusterDirectory {
             return;
         }
         unsafe {
-            let retcode = glfs_closedir(self.dir_handle);
+            let retcode = glfs!(glfs_closedir(self.dir_handle));
             if retcode < 0 {
                 error!("{:?}", GlusterError::new(get_error()));
             }
@@ -287,16 +424,17 @@ impl Drop for GlusterDirectory {
 pub struct DirEntry {
     pub path: PathBuf,
     pub inode: ino_t,
-    pub file_type: c_uchar,
+    pub file_type: FileType,
 }
 
 impl Iterator for GlusterDirectory {
     type Item = Result<DirEntry, GlusterError>;
     fn next(&mut self) -> Option<Self::Item> {
+        traced!(TRACE, "readdir");
         let mut dirent: dirent = unsafe { zeroed() };
         let mut next_entry: *mut dirent = ptr::null_mut();
         unsafe {
-            let ret_code = glfs_readdir_r(self.dir_handle, &mut dirent, &mut next_entry);
+            let ret_code = glfs!(glfs_readdir_r(self.dir_handle, &mut dirent, &mut next_entry));
             if ret_code < 0 {
                 return Some(Err(GlusterError::new(get_error())));
             }
@@ -305,10 +443,225 @@ impl Iterator for GlusterDirectory {
                 return None;
             }
             let file_name = CStr::from_ptr(dirent.d_name.as_ptr());
+            let path = PathBuf::from(OsStr::from_bytes(file_name.to_bytes()));
+            let file_type = match FileType::from_d_type(dirent.d_type) {
+                FileType::Unknown => self.lstat_type(&path),
+                file_type => file_type,
+            };
             Some(Ok(DirEntry {
-                path: PathBuf::from(file_name.to_string_lossy().into_owned()),
+                path,
+                inode: dirent.d_ino,
+                file_type,
+            }))
+        }
+    }
+}
+
+/// A position in a directory listing from GlusterDirectory::tell.  Brick
+/// offsets are stable, so a cookie can be kept, for example as a string
+/// through Display and FromStr, and used to resume the listing from a
+/// later opendir_at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DirCookie(i64);
+
+impl fmt::Display for DirCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for DirCookie {
+    type Err = ParseIntError;
+    fn from_str(s: &str) -> Result<DirCookie, ParseIntError> {
+        Ok(DirCookie(s.parse()?))
+    }
+}
+
+impl GlusterDirectory {
+    /// The type of an entry the brick didn't give a d_type for.  An entry
+    /// that can't be statted, for example because it was removed since
+    /// the directory was read, is left Unknown.
+    fn lstat_type(&self, name: &Path) -> FileType {
+        let path = match self.path {
+            Some(ref dir) => dir.join(name),
+            None => return FileType::Unknown,
+        };
+        let path = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(path) => path,
+            Err(_) => return FileType::Unknown,
+        };
+        unsafe {
+            let mut stat_buf: stat = zeroed();
+            let ret_code = glfs!(glfs_lstat(self.cluster_handle, path.as_ptr(), &mut stat_buf));
+            if ret_code < 0 {
+                return FileType::Unknown;
+            }
+            FileType::from_mode(stat_buf.st_mode)
+        }
+    }
+
+    /// Where the listing is, to resume after the entries read so far
+    pub fn tell(&self) -> DirCookie {
+        unsafe { DirCookie(glfs_telldir(self.dir_handle) as i64) }
+    }
+
+    /// Go back, or forward, to where tell was called
+    pub fn seek(&mut self, cookie: DirCookie) {
+        unsafe { glfs_seekdir(self.dir_handle, cookie.0 as _) }
+    }
+
+    /// Read up to n entries, fewer only at the end of the directory
+    pub fn read_batch(&mut self, n: usize) -> Result<Vec<DirEntry>, GlusterError> {
+        self.by_ref().take(n).collect()
+    }
+}
+
+/// Uses xreaddirplus, which along with each entry's stat returns a handle
+/// on its inode that can be opened or statted without another lookup
+#[derive(Debug)]
+pub struct GlusterDirectoryXPlus {
+    dir_handle: *mut glfs_fd,
+    cluster_handle: *mut glfs,
+    volume: Arc<str>,
+}
+
+impl Drop for GlusterDirectoryXPlus {
+    fn drop(&mut self) {
+        unsafe {
+            let retcode = glfs!(glfs_closedir(self.dir_handle));
+            if retcode < 0 {
+                error!("{:?}", GlusterError::new(get_error()));
+            }
+        }
+    }
+}
+
+/// An entry from xreaddirplus.  gfapi's stat and handle for it are freed
+/// when the entry is dropped.
+#[derive(Debug)]
+pub struct DirEntryXPlus {
+    pub path: PathBuf,
+    pub inode: ino_t,
+    pub file_type: FileType,
+    /// None if gfapi couldn't stat the entry
+    pub stat: Option<stat>,
+    xstat: *mut glfs_xreaddirp_stat,
+    /// Null for . and .., which gfapi doesn't hand out handles for
+    object: *mut glfs_object,
+    cluster_handle: *mut glfs,
+    volume: Arc<str>,
+}
+
+impl Drop for DirEntryXPlus {
+    fn drop(&mut self) {
+        unsafe { glfs_free(self.xstat as *mut c_void) }
+    }
+}
+
+impl DirEntryXPlus {
+    pub fn has_handle(&self) -> bool {
+        !self.object.is_null()
+    }
+
+    /// The handle, or EINVAL for an entry without one
+    fn object(&self) -> Result<*mut glfs_object, GlusterError> {
+        if self.object.is_null() {
+            set_errno(Errno(EINVAL));
+            return Err(GlusterError::new(get_error()));
+        }
+        Ok(self.object)
+    }
+
+    /// Open the entry through its handle, without resolving its path
+    pub fn open(&self, flags: i32) -> Result<GlusterFile, GlusterError> {
+        traced!(DEBUG, "open", volume = self.volume, path = self.path.as_path());
+        let object = self.object()?;
+        unsafe {
+            let file_handle = glfs!(glfs_h_open(self.cluster_handle, object, flags));
+            if file_handle.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(GlusterFile {
+                file_handle,
+                volume: Arc::clone(&self.volume),
+            })
+        }
+    }
+
+    /// Open the entry as a directory through its handle
+    pub fn opendir(&self) -> Result<GlusterDirectory, GlusterError> {
+        traced!(DEBUG, "opendir", volume = self.volume, path = self.path.as_path());
+        let object = self.object()?;
+        unsafe {
+            let dir_handle = glfs!(glfs_h_opendir(self.cluster_handle, object));
+            if dir_handle.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(GlusterDirectory {
+                dir_handle,
+                cluster_handle: self.cluster_handle,
+                path: None,
+            })
+        }
+    }
+
+    /// Stat the entry again through its handle.  The stat field is what
+    /// it was when the directory was read.
+    pub fn restat(&self) -> Result<stat, GlusterError> {
+        traced!(DEBUG, "stat", volume = self.volume, path = self.path.as_path());
+        let object = self.object()?;
+        unsafe {
+            let mut stat_buf: stat = zeroed();
+            let ret_code = glfs!(glfs_h_stat(self.cluster_handle, object, &mut stat_buf));
+            if ret_code < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(stat_buf)
+        }
+    }
+}
+
+impl Iterator for GlusterDirectoryXPlus {
+    type Item = Result<DirEntryXPlus, GlusterError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        traced!(TRACE, "xreaddirplus");
+        let mut dirent: dirent = unsafe { zeroed() };
+        let mut next_entry: *mut dirent = ptr::null_mut();
+        let mut xstat: *mut glfs_xreaddirp_stat = ptr::null_mut();
+        unsafe {
+            let valid = glfs!(glfs_xreaddirplus_r(
+                self.dir_handle,
+                GFAPI_XREADDIRP_STAT | GFAPI_XREADDIRP_HANDLE,
+                &mut xstat,
+                &mut dirent,
+                &mut next_entry,
+            ));
+            if valid < 0 {
+                return Some(Err(GlusterError::new(get_error())));
+            }
+            if next_entry.is_null() {
+                // End of stream reached
+                return None;
+            }
+            let valid = valid as u32;
+            let stat = match valid & GFAPI_XREADDIRP_STAT {
+                0 => None,
+                _ => glfs_xreaddirplus_get_stat(xstat).as_ref().copied(),
+            };
+            let object = match valid & GFAPI_XREADDIRP_HANDLE {
+                0 => ptr::null_mut(),
+                _ => glfs_xreaddirplus_get_object(xstat),
+            };
+            let file_name = CStr::from_ptr(dirent.d_name.as_ptr());
+            Some(Ok(DirEntryXPlus {
+                path: PathBuf::from(OsStr::from_bytes(file_name.to_bytes())),
                 inode: dirent.d_ino,
-                file_type: dirent.d_type,
+                file_type: FileType::from_d_type_or_mode(dirent.d_type, stat.as_ref()),
+                stat,
+                xstat,
+                object,
+                cluster_handle: self.cluster_handle,
+                volume: Arc::clone(&self.volume),
             }))
         }
     }
@@ -318,38 +671,64 @@ impl Gluster {
     /// Connect to a GlusterFS cluster and return a connection handle glfs_t
     /// port is usually 24007 but may differ depending on how the service was configured
     pub fn connect(volume_name: &str, server: &str, port: u16) -> Result<Gluster, GlusterError> {
+        Gluster::connect_servers(volume_name, &[(server, port)])
+    }
+
+    /// Connect to a GlusterFS cluster through the first reachable server
+    /// in the list.  The other servers are used as backups if the one
+    /// serving the volfile goes away.
+    pub fn connect_servers(
+        volume_name: &str,
+        servers: &[(&str, u16)],
+    ) -> Result<Gluster, GlusterError> {
+        if servers.is_empty() {
+            return Err(GlusterError::new("no volfile servers given".to_string()));
+        }
         let vol_name = CString::new(volume_name)?;
         let vol_transport = CString::new("tcp")?;
-        let vol_host = CString::new(server)?;
+        let vol_hosts = servers
+            .iter()
+            .map(|(server, _)| CString::new(*server))
+            .collect::<Result<Vec<CString>, NulError>>()?;
         unsafe {
-            let cluster_handle = glfs_new(vol_name.as_ptr());
+            let cluster_handle = glfs!(glfs_new(vol_name.as_ptr()));
             if cluster_handle.is_null() {
                 return Err(GlusterError::new("glfs_new failed".to_string()));
             }
-            let ret_code = glfs_set_volfile_server(
-                cluster_handle,
-                vol_transport.as_ptr(),
-                vol_host.as_ptr(),
-                port as ::libc::c_int,
-            );
-            if ret_code < 0 {
-                // We call glfs_fini here because Gluster hasn't been created yet
-                // so Drop won't be run.
-                glfs_fini(cluster_handle);
-                return Err(GlusterError::new(get_error()));
+            for (vol_host, (_, port)) in vol_hosts.iter().zip(servers) {
+                let ret_code = glfs!(glfs_set_volfile_server(
+                    cluster_handle,
+                    vol_transport.as_ptr(),
+                    vol_host.as_ptr(),
+                    *port as ::libc::c_int,
+                ));
+                if ret_code < 0 {
+                    // We call glfs_fini here because Gluster hasn't been created yet
+                    // so Drop won't be run.
+                    glfs!(glfs_fini(cluster_handle));
+                    return Err(GlusterError::new(get_error()));
+                }
             }
 
-            let ret_code = glfs_init(cluster_handle);
+            let ret_code = glfs!(glfs_init(cluster_handle));
             if ret_code < 0 {
                 // We call glfs_fini here because Gluster hasn't been created yet
                 // so Drop won't be run.
-                glfs_fini(cluster_handle);
+                glfs!(glfs_fini(cluster_handle));
                 return Err(GlusterError::new(get_error()));
             }
-            Ok(Gluster { cluster_handle })
+            Ok(Gluster {
+                cluster_handle,
+                volume: volume_name.into(),
+            })
         }
     }
 
+    /// The name of the volume this is connected to
+    pub fn volume_name(&self) -> &str {
+        &self.volume
+    }
+
     /// This function specifies logging parameters for the virtual mount.
     /// Sets the log file to write to
     pub fn set_logging(
@@ -357,9 +736,41 @@ impl Gluster {
         logfile: &Path,
         loglevel: GlusterLogLevel,
     ) -> Result<(), GlusterError> {
+        traced!(DEBUG, "set_logging", volume = self.volume);
         let path = CString::new(logfile.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_set_logging(self.cluster_handle, path.as_ptr(), loglevel as i32);
+            let ret_code = glfs!(glfs_set_logging(
+                self.cluster_handle,
+                path.as_ptr(),
+                loglevel as i32,
+            ));
+            if ret_code < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the directory statedumps are written to, /var/run/gluster by
+    /// default.  The directory must already exist.
+    pub fn set_statedump_path(&self, path: &Path) -> Result<(), GlusterError> {
+        traced!(DEBUG, "set_statedump_path", volume = self.volume, path = path);
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        unsafe {
+            let ret_code = glfs!(glfs_set_statedump_path(self.cluster_handle, path.as_ptr()));
+            if ret_code < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask gfapi to do something out of band, like a SysRq key does for
+    /// the kernel
+    pub fn sysrq(&self, request: GlusterSysRq) -> Result<(), GlusterError> {
+        traced!(DEBUG, "sysrq", volume = self.volume);
+        unsafe {
+            let ret_code = glfs!(glfs_sysrq(self.cluster_handle, request as u8 as c_char));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -376,17 +787,18 @@ impl Gluster {
     /// to be parsed into a volume graph before it's really usable.  
     // TODO: Change this from String to a struct
     pub fn get_volfile(&self) -> Result<String, GlusterError> {
+        traced!(DEBUG, "get_volfile", volume = self.volume);
         // Start with 1K buffer and see if that works.  Even small clusters
         // have pretty large volfiles.
         let capacity = 1024;
         let mut buffer: Vec<u8> = Vec::with_capacity(capacity);
         unsafe {
             // This will likely fail and gluster will tell me the size it needs
-            let ret = glfs_get_volfile(
+            let ret = glfs!(glfs_get_volfile(
                 self.cluster_handle,
                 buffer.as_mut_ptr() as *mut c_void,
                 buffer.capacity() as usize,
-            );
+            ));
             if ret > 0 {
                 //>0: filled N bytes of buffer
                 buffer.truncate(ret as usize);
@@ -404,11 +816,11 @@ impl Gluster {
                     capacity + ret.abs() as usize
                 );
                 let mut buffer: Vec<u8> = Vec::with_capacity(capacity + ret.abs() as usize);
-                let retry = glfs_get_volfile(
+                let retry = glfs!(glfs_get_volfile(
                     self.cluster_handle,
                     buffer.as_mut_ptr() as *mut c_void,
                     buffer.capacity() as usize,
-                );
+                ));
                 if retry > 0 {
                     //>0: filled N bytes of buffer
                     buffer.truncate(retry as usize);
@@ -432,15 +844,16 @@ impl Gluster {
 
     /// Fetch the volume uuid from the glusterd management server
     pub fn get_volume_id(&self) -> Result<Uuid, GlusterError> {
+        traced!(DEBUG, "get_volume_id", volume = self.volume);
         // Give it plenty of room
         let mut buff: Vec<u8> = Vec::with_capacity(128);
 
         unsafe {
-            let ret_code = glfs_get_volumeid(
+            let ret_code = glfs!(glfs_get_volumeid(
                 self.cluster_handle,
                 buff.as_mut_ptr() as *mut i8,
                 buff.capacity(),
-            );
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -452,13 +865,17 @@ impl Gluster {
     }
 
     pub fn open(&self, path: &Path, flags: i32) -> Result<GlusterFile, GlusterError> {
+        traced!(DEBUG, "open", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let file_handle = glfs_open(self.cluster_handle, path.as_ptr(), flags);
+            let file_handle = glfs!(glfs_open(self.cluster_handle, path.as_ptr(), flags));
             if file_handle.is_null() {
                 return Err(GlusterError::new(get_error()));
             }
-            Ok(GlusterFile { file_handle })
+            Ok(GlusterFile {
+                file_handle,
+                volume: Arc::clone(&self.volume),
+            })
         }
     }
 
@@ -468,20 +885,25 @@ impl Gluster {
         flags: i32,
         mode: mode_t,
     ) -> Result<GlusterFile, GlusterError> {
+        traced!(DEBUG, "create", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let file_handle = glfs_creat(self.cluster_handle, path.as_ptr(), flags, mode);
+            let file_handle = glfs!(glfs_creat(self.cluster_handle, path.as_ptr(), flags, mode));
             if file_handle.is_null() {
                 return Err(GlusterError::new(get_error()));
             }
-            Ok(GlusterFile { file_handle })
+            Ok(GlusterFile {
+                file_handle,
+                volume: Arc::clone(&self.volume),
+            })
         }
     }
     pub fn truncate(&self, path: &Path, length: i64) -> Result<(), GlusterError> {
+        traced!(DEBUG, "truncate", volume = self.volume, path = path, length = length);
         let path = CString::new(path.as_os_str().as_bytes())?;
 
         unsafe {
-            let ret_code = glfs_truncate(self.cluster_handle, path.as_ptr(), length);
+            let ret_code = glfs!(glfs_truncate(self.cluster_handle, path.as_ptr(), length));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -489,10 +911,11 @@ impl Gluster {
         Ok(())
     }
     pub fn lsstat(&self, path: &Path) -> Result<stat, GlusterError> {
+        traced!(DEBUG, "lstat", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
             let mut stat_buf: stat = zeroed();
-            let ret_code = glfs_lstat(self.cluster_handle, path.as_ptr(), &mut stat_buf);
+            let ret_code = glfs!(glfs_lstat(self.cluster_handle, path.as_ptr(), &mut stat_buf));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -501,10 +924,11 @@ impl Gluster {
     }
     /// Tests for the existance of a file.  Returns true/false respectively.
     pub fn exists(&self, path: &Path) -> Result<bool, GlusterError> {
+        traced!(DEBUG, "exists", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
             let mut stat_buf: stat = zeroed();
-            let ret_code = glfs_stat(self.cluster_handle, path.as_ptr(), &mut stat_buf);
+            let ret_code = glfs!(glfs_stat(self.cluster_handle, path.as_ptr(), &mut stat_buf));
             if ret_code < 0 {
                 let error = errno();
                 if error == Errno(ENOENT) {
@@ -517,10 +941,11 @@ impl Gluster {
     }
 
     pub fn statvfs(&self, path: &Path) -> Result<statvfs, GlusterError> {
+        traced!(DEBUG, "statvfs", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
             let mut stat_buf: statvfs = zeroed();
-            let ret_code = glfs_statvfs(self.cluster_handle, path.as_ptr(), &mut stat_buf);
+            let ret_code = glfs!(glfs_statvfs(self.cluster_handle, path.as_ptr(), &mut stat_buf));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -529,10 +954,11 @@ impl Gluster {
     }
 
     pub fn stat(&self, path: &Path) -> Result<stat, GlusterError> {
+        traced!(DEBUG, "stat", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
             let mut stat_buf: stat = zeroed();
-            let ret_code = glfs_stat(self.cluster_handle, path.as_ptr(), &mut stat_buf);
+            let ret_code = glfs!(glfs_stat(self.cluster_handle, path.as_ptr(), &mut stat_buf));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -540,9 +966,10 @@ impl Gluster {
         }
     }
     pub fn access(&self, path: &Path, mode: i32) -> Result<(), GlusterError> {
+        traced!(DEBUG, "access", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_access(self.cluster_handle, path.as_ptr(), mode);
+            let ret_code = glfs!(glfs_access(self.cluster_handle, path.as_ptr(), mode));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -551,10 +978,15 @@ impl Gluster {
     }
 
     pub fn symlink(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        traced!(DEBUG, "symlink", volume = self.volume, path = newpath);
         let old_path = CString::new(oldpath.as_os_str().as_bytes())?;
         let new_path = CString::new(newpath.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_symlink(self.cluster_handle, old_path.as_ptr(), new_path.as_ptr());
+            let ret_code = glfs!(glfs_symlink(
+                self.cluster_handle,
+                old_path.as_ptr(),
+                new_path.as_ptr(),
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -563,14 +995,15 @@ impl Gluster {
     }
 
     pub fn readlink(&self, path: &Path, buf: &mut [u8]) -> Result<(), GlusterError> {
+        traced!(DEBUG, "readlink", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_readlink(
+            let ret_code = glfs!(glfs_readlink(
                 self.cluster_handle,
                 path.as_ptr(),
                 buf.as_mut_ptr() as *mut i8,
                 buf.len(),
-            );
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -579,9 +1012,10 @@ impl Gluster {
     }
 
     pub fn mknod(&self, path: &Path, mode: mode_t, dev: dev_t) -> Result<(), GlusterError> {
+        traced!(DEBUG, "mknod", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_mknod(self.cluster_handle, path.as_ptr(), mode, dev);
+            let ret_code = glfs!(glfs_mknod(self.cluster_handle, path.as_ptr(), mode, dev));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -590,9 +1024,10 @@ impl Gluster {
     }
 
     pub fn mkdir(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        traced!(DEBUG, "mkdir", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_mkdir(self.cluster_handle, path.as_ptr(), mode);
+            let ret_code = glfs!(glfs_mkdir(self.cluster_handle, path.as_ptr(), mode));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -601,9 +1036,10 @@ impl Gluster {
     }
 
     pub fn unlink(&self, path: &Path) -> Result<(), GlusterError> {
+        traced!(DEBUG, "unlink", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_unlink(self.cluster_handle, path.as_ptr());
+            let ret_code = glfs!(glfs_unlink(self.cluster_handle, path.as_ptr()));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -611,9 +1047,10 @@ impl Gluster {
         Ok(())
     }
     pub fn rmdir(&self, path: &Path) -> Result<(), GlusterError> {
+        traced!(DEBUG, "rmdir", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_rmdir(self.cluster_handle, path.as_ptr());
+            let ret_code = glfs!(glfs_rmdir(self.cluster_handle, path.as_ptr()));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -645,6 +1082,7 @@ impl Gluster {
     /// Removes a directory at this path, after removing all its contents.
     /// Use carefully!
     pub fn remove_dir_all(&self, path: &Path) -> Result<(), GlusterError> {
+        traced!(DEBUG, "remove_dir_all", volume = self.volume, path = path);
         trace!("Removing {}", path.display());
         let mut stack: Vec<PathBuf> = vec![path.to_path_buf()];
         let mut done = false;
@@ -680,7 +1118,7 @@ impl Gluster {
                         continue;
                     }
                     match dir_entry.file_type {
-                        DT_DIR => {
+                        FileType::Directory => {
                             let mut p = PathBuf::from(&p);
                             p.push(dir_entry.path);
                             trace!("pushing: {}", p.display());
@@ -720,10 +1158,15 @@ impl Gluster {
     }
 
     pub fn rename(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        traced!(DEBUG, "rename", volume = self.volume, path = oldpath);
         let old_path = CString::new(oldpath.as_os_str().as_bytes())?;
         let new_path = CString::new(newpath.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_rename(self.cluster_handle, old_path.as_ptr(), new_path.as_ptr());
+            let ret_code = glfs!(glfs_rename(
+                self.cluster_handle,
+                old_path.as_ptr(),
+                new_path.as_ptr(),
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -732,10 +1175,15 @@ impl Gluster {
     }
 
     pub fn link(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        traced!(DEBUG, "link", volume = self.volume, path = newpath);
         let old_path = CString::new(oldpath.as_os_str().as_bytes())?;
         let new_path = CString::new(newpath.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_link(self.cluster_handle, old_path.as_ptr(), new_path.as_ptr());
+            let ret_code = glfs!(glfs_link(
+                self.cluster_handle,
+                old_path.as_ptr(),
+                new_path.as_ptr(),
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -744,34 +1192,78 @@ impl Gluster {
     }
 
     pub fn opendir(&self, path: &Path) -> Result<GlusterDirectory, GlusterError> {
-        let path = CString::new(path.as_os_str().as_bytes())?;
+        traced!(DEBUG, "opendir", volume = self.volume, path = path);
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let dir_handle = glfs_opendir(self.cluster_handle, path.as_ptr());
-            Ok(GlusterDirectory { dir_handle })
+            let dir_handle = glfs!(glfs_opendir(self.cluster_handle, c_path.as_ptr()));
+            if dir_handle.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(GlusterDirectory {
+                dir_handle,
+                cluster_handle: self.cluster_handle,
+                path: Some(path.to_path_buf()),
+            })
         }
     }
 
+    /// Open a directory and pick the listing up where cookie was taken,
+    /// from this or an earlier GlusterDirectory of the same path
+    pub fn opendir_at(
+        &self,
+        path: &Path,
+        cookie: DirCookie,
+    ) -> Result<GlusterDirectory, GlusterError> {
+        traced!(DEBUG, "opendir_at", volume = self.volume, path = path);
+        let mut dir = self.opendir(path)?;
+        dir.seek(cookie);
+        Ok(dir)
+    }
+
     // Readdir plus opendir
     pub fn opendir_plus(&self, path: &Path) -> Result<GlusterDirectoryPlus, GlusterError> {
+        traced!(DEBUG, "opendir_plus", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let dir_handle = glfs_opendir(self.cluster_handle, path.as_ptr());
+            let dir_handle = glfs!(glfs_opendir(self.cluster_handle, path.as_ptr()));
+            if dir_handle.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
             Ok(GlusterDirectoryPlus { dir_handle })
         }
     }
 
+    /// Open a directory for xreaddirplus, whose entries carry a stat and a
+    /// handle
+    pub fn opendir_xplus(&self, path: &Path) -> Result<GlusterDirectoryXPlus, GlusterError> {
+        traced!(DEBUG, "opendir_xplus", volume = self.volume, path = path);
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        unsafe {
+            let dir_handle = glfs!(glfs_opendir(self.cluster_handle, path.as_ptr()));
+            if dir_handle.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(GlusterDirectoryXPlus {
+                dir_handle,
+                cluster_handle: self.cluster_handle,
+                volume: Arc::clone(&self.volume),
+            })
+        }
+    }
+
     pub fn getxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
+        traced!(DEBUG, "getxattr", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         let name = CString::new(name)?;
         let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
-            let ret_code = glfs_getxattr(
+            let ret_code = glfs!(glfs_getxattr(
                 self.cluster_handle,
                 path.as_ptr(),
                 name.as_ptr(),
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
                 xattr_val_buff.len(),
-            );
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -781,18 +1273,37 @@ impl Gluster {
         }
     }
 
+    /// Read the raw value of an extended attribute.  Unlike getxattr this
+    /// doesn't assume the value is text, which matters for the binary
+    /// attributes gluster keeps such as quota accounting.
+    pub fn getxattr_bytes(&self, path: &Path, name: &str) -> Result<Vec<u8>, GlusterError> {
+        traced!(DEBUG, "getxattr_bytes", volume = self.volume, path = path);
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let name = CString::new(name)?;
+        read_xattr(|value, size| unsafe {
+            glfs!(glfs_getxattr(
+                self.cluster_handle,
+                path.as_ptr(),
+                name.as_ptr(),
+                value,
+                size,
+            ))
+        })
+    }
+
     pub fn lgetxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
+        traced!(DEBUG, "lgetxattr", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         let name = CString::new(name)?;
         let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
-            let ret_code = glfs_lgetxattr(
+            let ret_code = glfs!(glfs_lgetxattr(
                 self.cluster_handle,
                 path.as_ptr(),
                 name.as_ptr(),
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
                 xattr_val_buff.len(),
-            );
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -802,16 +1313,44 @@ impl Gluster {
         }
     }
 
+    /// Like getxattr_bytes, but reads the attribute of a symlink rather
+    /// than of the file it points to.
+    pub fn lgetxattr_bytes(&self, path: &Path, name: &str) -> Result<Vec<u8>, GlusterError> {
+        traced!(DEBUG, "lgetxattr_bytes", volume = self.volume, path = path);
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let name = CString::new(name)?;
+        read_xattr(|value, size| unsafe {
+            glfs!(glfs_lgetxattr(
+                self.cluster_handle,
+                path.as_ptr(),
+                name.as_ptr(),
+                value,
+                size,
+            ))
+        })
+    }
+
     pub fn listxattr(&self, path: &Path) -> Result<String, GlusterError> {
+        traced!(DEBUG, "listxattr", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
-        let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
-            let ret_code = glfs_listxattr(
+            // Ask gluster how long the list is before allocating for it
+            let size = glfs!(glfs_listxattr(
+                self.cluster_handle,
+                path.as_ptr(),
+                ptr::null_mut(),
+                0,
+            ));
+            if size < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(size as usize);
+            let ret_code = glfs!(glfs_listxattr(
                 self.cluster_handle,
                 path.as_ptr(),
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
-                xattr_val_buff.len(),
-            );
+                xattr_val_buff.capacity(),
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -821,15 +1360,26 @@ impl Gluster {
         }
     }
     pub fn llistxattr(&self, path: &Path) -> Result<String, GlusterError> {
+        traced!(DEBUG, "llistxattr", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
-        let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
-            let ret_code = glfs_llistxattr(
+            // Ask gluster how long the list is before allocating for it
+            let size = glfs!(glfs_llistxattr(
+                self.cluster_handle,
+                path.as_ptr(),
+                ptr::null_mut(),
+                0,
+            ));
+            if size < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(size as usize);
+            let ret_code = glfs!(glfs_llistxattr(
                 self.cluster_handle,
                 path.as_ptr(),
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
-                xattr_val_buff.len(),
-            );
+                xattr_val_buff.capacity(),
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -845,17 +1395,18 @@ impl Gluster {
         value: &[u8],
         flags: i32,
     ) -> Result<(), GlusterError> {
+        traced!(DEBUG, "setxattr", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         let name = CString::new(name)?;
         unsafe {
-            let ret_code = glfs_setxattr(
+            let ret_code = glfs!(glfs_setxattr(
                 self.cluster_handle,
                 path.as_ptr(),
                 name.as_ptr(),
                 value.as_ptr() as *const c_void,
                 value.len(),
                 flags,
-            );
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -869,17 +1420,18 @@ impl Gluster {
         path: &Path,
         flags: i32,
     ) -> Result<(), GlusterError> {
+        traced!(DEBUG, "lsetxattr", volume = self.volume, path = path);
         let name = CString::new(name)?;
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_lsetxattr(
+            let ret_code = glfs!(glfs_lsetxattr(
                 self.cluster_handle,
                 path.as_ptr(),
                 name.as_ptr(),
                 value.as_ptr() as *const c_void,
                 value.len(),
                 flags,
-            );
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -887,10 +1439,15 @@ impl Gluster {
         Ok(())
     }
     pub fn removexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
+        traced!(DEBUG, "removexattr", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         let name = CString::new(name)?;
         unsafe {
-            let ret_code = glfs_removexattr(self.cluster_handle, path.as_ptr(), name.as_ptr());
+            let ret_code = glfs!(glfs_removexattr(
+                self.cluster_handle,
+                path.as_ptr(),
+                name.as_ptr(),
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -898,10 +1455,15 @@ impl Gluster {
         Ok(())
     }
     pub fn lremovexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
+        traced!(DEBUG, "lremovexattr", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         let name = CString::new(name)?;
         unsafe {
-            let ret_code = glfs_lremovexattr(self.cluster_handle, path.as_ptr(), name.as_ptr());
+            let ret_code = glfs!(glfs_lremovexattr(
+                self.cluster_handle,
+                path.as_ptr(),
+                name.as_ptr(),
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -909,20 +1471,25 @@ impl Gluster {
         Ok(())
     }
     pub fn getcwd(&self) -> Result<String, GlusterError> {
-        let mut cwd_val_buff: Vec<u8> = Vec::with_capacity(1024);
+        traced!(DEBUG, "getcwd", volume = self.volume);
+        let mut cwd_val_buff: Vec<u8> = Vec::with_capacity(4096);
         unsafe {
-            let cwd = glfs_getcwd(
+            let cwd = glfs!(glfs_getcwd(
                 self.cluster_handle,
                 cwd_val_buff.as_mut_ptr() as *mut i8,
-                cwd_val_buff.len(),
-            );
+                cwd_val_buff.capacity(),
+            ));
+            if cwd.is_null() {
+                return Err(GlusterError::new(get_error()));
+            }
             Ok(CStr::from_ptr(cwd).to_string_lossy().into_owned())
         }
     }
     pub fn chdir(&self, path: &Path) -> Result<(), GlusterError> {
+        traced!(DEBUG, "chdir", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_chdir(self.cluster_handle, path.as_ptr());
+            let ret_code = glfs!(glfs_chdir(self.cluster_handle, path.as_ptr()));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -932,9 +1499,10 @@ impl Gluster {
     /// times[0] specifies the new "last access time" (atime);
     /// times[1] specifies the new "last modification time" (mtime).
     pub fn utimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        traced!(DEBUG, "utimens", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_utimens(self.cluster_handle, path.as_ptr(), times.as_ptr());
+            let ret_code = glfs!(glfs_utimens(self.cluster_handle, path.as_ptr(), times.as_ptr()));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -945,9 +1513,10 @@ impl Gluster {
     /// times[0] specifies the new "last access time" (atime);
     /// times[1] specifies the new "last modification time" (mtime).
     pub fn lutimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        traced!(DEBUG, "lutimens", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_lutimens(self.cluster_handle, path.as_ptr(), times.as_ptr());
+            let ret_code = glfs!(glfs_lutimens(self.cluster_handle, path.as_ptr(), times.as_ptr()));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -955,9 +1524,10 @@ impl Gluster {
         Ok(())
     }
     pub fn chmod(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        traced!(DEBUG, "chmod", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_chmod(self.cluster_handle, path.as_ptr(), mode);
+            let ret_code = glfs!(glfs_chmod(self.cluster_handle, path.as_ptr(), mode));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -965,9 +1535,10 @@ impl Gluster {
         Ok(())
     }
     pub fn chown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        traced!(DEBUG, "chown", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_chown(self.cluster_handle, path.as_ptr(), uid, gid);
+            let ret_code = glfs!(glfs_chown(self.cluster_handle, path.as_ptr(), uid, gid));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -976,9 +1547,10 @@ impl Gluster {
     }
 
     pub fn lchown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        traced!(DEBUG, "lchown", volume = self.volume, path = path);
         let path = CString::new(path.as_os_str().as_bytes())?;
         unsafe {
-            let ret_code = glfs_lchown(self.cluster_handle, path.as_ptr(), uid, gid);
+            let ret_code = glfs!(glfs_lchown(self.cluster_handle, path.as_ptr(), uid, gid));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -987,7 +1559,131 @@ impl Gluster {
     }
 }
 
+/// What GlusterFile::allocate does to a range
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FallocMode {
+    /// Reserve space for the range, growing the file if it ends past the
+    /// end
+    Allocate,
+    /// Reserve space for the range without changing the file size
+    KeepSize,
+    /// Free the space of the range, which then reads as zeros, without
+    /// changing the file size
+    PunchHole,
+    /// Have the range read as zeros, growing the file if it ends past the
+    /// end
+    ZeroRange,
+}
+
+impl FallocMode {
+    /// The mode for the flags of fallocate(2), if it's one of these
+    pub fn from_flags(flags: c_int) -> Option<FallocMode> {
+        match flags {
+            0 => Some(FallocMode::Allocate),
+            FALLOC_FL_KEEP_SIZE => Some(FallocMode::KeepSize),
+            f if f == FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE => Some(FallocMode::PunchHole),
+            FALLOC_FL_ZERO_RANGE => Some(FallocMode::ZeroRange),
+            _ => None,
+        }
+    }
+}
+
+/// Whether an extent of a file holds data or is a hole
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExtentKind {
+    Data,
+    /// Reads as zeros without taking up space on the bricks
+    Hole,
+}
+
+/// A run of data or hole in a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Extent {
+    pub kind: ExtentKind,
+    pub offset: i64,
+    pub len: i64,
+}
+
+/// The extent at offset in a file of size bytes, found with seek, an
+/// lseek on the file.  Where SEEK_DATA isn't supported everything is data.
+pub(crate) fn next_extent<F>(
+    seek: F,
+    offset: i64,
+    size: i64,
+) -> Result<Option<Extent>, GlusterError>
+where
+    F: Fn(i64, c_int) -> Result<i64, GlusterError>,
+{
+    if offset >= size {
+        return Ok(None);
+    }
+    let unsupported = || errno() == Errno(EINVAL) || errno() == Errno(EOPNOTSUPP);
+    let data = match seek(offset, SEEK_DATA) {
+        Ok(data) => data.min(size),
+        // Only a hole left before the end
+        Err(_) if errno() == Errno(ENXIO) => size,
+        Err(_) if unsupported() => offset,
+        Err(e) => return Err(e),
+    };
+    if data > offset {
+        return Ok(Some(Extent {
+            kind: ExtentKind::Hole,
+            offset,
+            len: data - offset,
+        }));
+    }
+    let hole = match seek(offset, SEEK_HOLE) {
+        Ok(hole) => hole.min(size),
+        Err(_) if unsupported() => size,
+        Err(e) => return Err(e),
+    };
+    if hole <= offset {
+        // The file shrank under us
+        return Ok(None);
+    }
+    Ok(Some(Extent {
+        kind: ExtentKind::Data,
+        offset,
+        len: hole - offset,
+    }))
+}
+
+/// Iterates the data and holes of a file, from GlusterFile::extents
+#[derive(Debug)]
+pub struct Extents<'a> {
+    file: &'a GlusterFile,
+    offset: i64,
+    size: i64,
+}
+
+impl<'a> Iterator for Extents<'a> {
+    type Item = Result<Extent, GlusterError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let file = self.file;
+        match next_extent(
+            |offset, whence| file.lseek(offset, whence),
+            self.offset,
+            self.size,
+        ) {
+            Ok(Some(extent)) => {
+                self.offset = extent.offset + extent.len;
+                Some(Ok(extent))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                self.offset = self.size;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
 impl GlusterFile {
+    /// The name of the volume the file is on
+    pub fn volume_name(&self) -> &str {
+        &self.volume
+    }
+
     pub fn read(
         &self,
         fill_buffer: &mut Vec<u8>,
@@ -997,13 +1693,14 @@ impl GlusterFile {
         self.pread(fill_buffer, count, 0, flags)
     }
     pub fn write(&self, buffer: &[u8], flags: i32) -> Result<isize, GlusterError> {
+        traced!(DEBUG, "write", volume = self.volume, length = buffer.len());
         unsafe {
-            let write_size = glfs_write(
+            let write_size = glfs!(glfs_write(
                 self.file_handle,
                 buffer.as_ptr() as *mut c_void,
                 buffer.len(),
                 flags,
-                );
+                ));
             if write_size < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1042,13 +1739,14 @@ impl GlusterFile {
     }
     */
     pub fn readv(&self, iov: &mut [&mut [u8]], flags: i32) -> Result<isize, GlusterError> {
+        traced!(DEBUG, "readv", volume = self.volume);
         unsafe {
-            let read_size = glfs_readv(
+            let read_size = glfs!(glfs_readv(
                 self.file_handle,
                 iov.as_ptr() as *const iovec,
                 iov.len() as i32,
                 flags,
-            );
+            ));
             if read_size < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1056,13 +1754,14 @@ impl GlusterFile {
         }
     }
     pub fn writev(&self, iov: &[&[u8]], flags: i32) -> Result<isize, GlusterError> {
+        traced!(DEBUG, "writev", volume = self.volume);
         unsafe {
-            let write_size = glfs_writev(
+            let write_size = glfs!(glfs_writev(
                 self.file_handle,
                 iov.as_ptr() as *const iovec,
                 iov.len() as i32,
                 flags,
-            );
+            ));
             if write_size < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1078,15 +1777,16 @@ impl GlusterFile {
         offset: i64,
         flags: i32,
     ) -> Result<isize, GlusterError> {
+        traced!(DEBUG, "pread", volume = self.volume, offset = offset, length = count);
         unsafe {
-            let read_size = glfs_pread(
+            let read_size = glfs!(glfs_pread(
                 self.file_handle,
                 fill_buffer.as_mut_ptr() as *mut c_void,
                 count,
                 offset,
                 flags,
                 std::ptr::null_mut(),
-            );
+            ));
             if read_size < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1101,8 +1801,9 @@ impl GlusterFile {
         offset: i64,
         flags: i32,
     ) -> Result<isize, GlusterError> {
+        traced!(DEBUG, "pwrite", volume = self.volume, offset = offset, length = count);
         unsafe {
-            let write_size = glfs_pwrite(
+            let write_size = glfs!(glfs_pwrite(
                 self.file_handle,
                 buffer.as_ptr() as *mut c_void,
                 count,
@@ -1110,7 +1811,7 @@ impl GlusterFile {
                 flags,
                 std::ptr::null_mut(),
                 std::ptr::null_mut()
-            );
+            ));
             if write_size < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1124,14 +1825,15 @@ impl GlusterFile {
         offset: i64,
         flags: i32,
     ) -> Result<isize, GlusterError> {
+        traced!(DEBUG, "preadv", volume = self.volume, offset = offset);
         unsafe {
-            let read_size = glfs_preadv(
+            let read_size = glfs!(glfs_preadv(
                 self.file_handle,
                 iov.as_ptr() as *const iovec,
                 iov.len() as i32,
                 offset,
                 flags,
-            );
+            ));
             if read_size < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1140,14 +1842,15 @@ impl GlusterFile {
     }
     // TODO: Use C IoVec
     pub fn pwritev(&self, iov: &[&[u8]], offset: i64, flags: i32) -> Result<isize, GlusterError> {
+        traced!(DEBUG, "pwritev", volume = self.volume, offset = offset);
         unsafe {
-            let write_size = glfs_pwritev(
+            let write_size = glfs!(glfs_pwritev(
                 self.file_handle,
                 iov.as_ptr() as *const iovec,
                 iov.len() as i32,
                 offset,
                 flags,
-            );
+            ));
             if write_size < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1155,17 +1858,69 @@ impl GlusterFile {
         }
     }
     pub fn lseek(&self, offset: i64, whence: i32) -> Result<i64, GlusterError> {
+        traced!(DEBUG, "lseek", volume = self.volume, offset = offset);
         unsafe {
-            let file_offset = glfs_lseek(self.file_handle, offset, whence);
+            let file_offset = glfs!(glfs_lseek(self.file_handle, offset, whence));
             if file_offset < 0 {
                 return Err(GlusterError::new(get_error()));
             }
             Ok(file_offset)
         }
     }
+
+    /// The data and holes of the file as it is now, in order.  Finding them
+    /// moves the file offset.
+    pub fn extents(&self) -> Result<Extents<'_>, GlusterError> {
+        Ok(Extents {
+            file: self,
+            offset: 0,
+            size: self.fstat()?.st_size,
+        })
+    }
+
+    /// Have the bricks copy len bytes at src_off to dst at dst_off, without
+    /// the data passing through this client.  Like copy_file_range(2) this
+    /// can copy less than asked, and returns how much it copied, 0 at the
+    /// end of the file.  Volumes that can't copy between the two files,
+    /// for example because they're on different bricks, fail with EXDEV,
+    /// EOPNOTSUPP or ENOSYS.
+    pub fn copy_range_to(
+        &self,
+        dst: &GlusterFile,
+        src_off: i64,
+        dst_off: i64,
+        len: usize,
+    ) -> Result<usize, GlusterError> {
+        traced!(DEBUG, "copy_file_range", volume = self.volume, offset = src_off, length = len);
+        let mut src_off = src_off;
+        let mut dst_off = dst_off;
+        unsafe {
+            let copied = glfs!(glfs_copy_file_range(
+                self.file_handle,
+                &mut src_off,
+                dst.file_handle,
+                &mut dst_off,
+                len,
+                0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            ));
+            if copied < 0 {
+                return Err(GlusterError::new(get_error()));
+            }
+            Ok(copied as usize)
+        }
+    }
     pub fn ftruncate(&self, length: i64) -> Result<(), GlusterError> {
+        traced!(DEBUG, "ftruncate", volume = self.volume, length = length);
         unsafe {
-            let ret_code = glfs_ftruncate(self.file_handle, length, std::ptr::null_mut(), std::ptr::null_mut());
+            let ret_code = glfs!(glfs_ftruncate(
+                self.file_handle,
+                length,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1173,9 +1928,10 @@ impl GlusterFile {
         Ok(())
     }
     pub fn fstat(&self) -> Result<stat, GlusterError> {
+        traced!(DEBUG, "fstat", volume = self.volume);
         unsafe {
             let mut stat_buf: stat = zeroed();
-            let ret_code = glfs_fstat(self.file_handle, &mut stat_buf);
+            let ret_code = glfs!(glfs_fstat(self.file_handle, &mut stat_buf));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1183,8 +1939,13 @@ impl GlusterFile {
         }
     }
     pub fn fsync(&self) -> Result<(), GlusterError> {
+        traced!(DEBUG, "fsync", volume = self.volume);
         unsafe {
-            let ret_code = glfs_fsync(self.file_handle, std::ptr::null_mut(), std::ptr::null_mut());
+            let ret_code = glfs!(glfs_fsync(
+                self.file_handle,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1193,8 +1954,13 @@ impl GlusterFile {
     }
 
     pub fn fdatasync(&self) -> Result<(), GlusterError> {
+        traced!(DEBUG, "fdatasync", volume = self.volume);
         unsafe {
-            let ret_code = glfs_fdatasync(self.file_handle, std::ptr::null_mut(), std::ptr::null_mut());
+            let ret_code = glfs!(glfs_fdatasync(
+                self.file_handle,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1202,15 +1968,16 @@ impl GlusterFile {
         Ok(())
     }
     pub fn fgetxattr(&self, name: &str) -> Result<String, GlusterError> {
+        traced!(DEBUG, "fgetxattr", volume = self.volume);
         let name = CString::new(name)?;
         let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
-            let ret_code = glfs_fgetxattr(
+            let ret_code = glfs!(glfs_fgetxattr(
                 self.file_handle,
                 name.as_ptr(),
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
                 xattr_val_buff.len(),
-            );
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1221,13 +1988,14 @@ impl GlusterFile {
     }
 
     pub fn flistxattr(&self) -> Result<String, GlusterError> {
+        traced!(DEBUG, "flistxattr", volume = self.volume);
         let mut xattr_val_buff: Vec<u8> = Vec::with_capacity(1024);
         unsafe {
-            let ret_code = glfs_flistxattr(
+            let ret_code = glfs!(glfs_flistxattr(
                 self.file_handle,
                 xattr_val_buff.as_mut_ptr() as *mut c_void,
                 xattr_val_buff.len(),
-            );
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1238,15 +2006,16 @@ impl GlusterFile {
     }
 
     pub fn fsetxattr(&self, name: &str, value: &[u8], flags: i32) -> Result<(), GlusterError> {
+        traced!(DEBUG, "fsetxattr", volume = self.volume);
         let name = CString::new(name)?;
         unsafe {
-            let ret_code = glfs_fsetxattr(
+            let ret_code = glfs!(glfs_fsetxattr(
                 self.file_handle,
                 name.as_ptr(),
                 value.as_ptr() as *const c_void,
                 value.len(),
                 flags,
-            );
+            ));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1254,10 +2023,11 @@ impl GlusterFile {
         Ok(())
     }
     pub fn fremovexattr(&self, name: &str) -> Result<(), GlusterError> {
+        traced!(DEBUG, "fremovexattr", volume = self.volume);
         let name = CString::new(name)?;
 
         unsafe {
-            let ret_code = glfs_fremovexattr(self.file_handle, name.as_ptr());
+            let ret_code = glfs!(glfs_fremovexattr(self.file_handle, name.as_ptr()));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1265,8 +2035,9 @@ impl GlusterFile {
         Ok(())
     }
     pub fn fallocate(&self, offset: i64, keep_size: i32, len: usize) -> Result<(), GlusterError> {
+        traced!(DEBUG, "fallocate", volume = self.volume, offset = offset, length = len);
         unsafe {
-            let ret_code = glfs_fallocate(self.file_handle, keep_size, offset, len);
+            let ret_code = glfs!(glfs_fallocate(self.file_handle, keep_size, offset, len));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1274,8 +2045,9 @@ impl GlusterFile {
         Ok(())
     }
     pub fn discard(&self, offset: i64, len: usize) -> Result<(), GlusterError> {
+        traced!(DEBUG, "discard", volume = self.volume, offset = offset, length = len);
         unsafe {
-            let ret_code = glfs_discard(self.file_handle, offset, len);
+            let ret_code = glfs!(glfs_discard(self.file_handle, offset, len));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1283,8 +2055,9 @@ impl GlusterFile {
         Ok(())
     }
     pub fn zerofill(&self, offset: i64, len: i64) -> Result<(), GlusterError> {
+        traced!(DEBUG, "zerofill", volume = self.volume, offset = offset, length = len);
         unsafe {
-            let ret_code = glfs_zerofill(self.file_handle, offset, len);
+            let ret_code = glfs!(glfs_zerofill(self.file_handle, offset, len));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1292,9 +2065,17 @@ impl GlusterFile {
         Ok(())
     }
 
+    /// Allocate, punch out or zero the bytes of range, depending on mode.
+    /// A mode the volume can't do fails with GlusterError::Unsupported.
+    pub fn allocate(&self, range: Range<u64>, mode: FallocMode) -> Result<(), GlusterError> {
+        // Shared with the other backends through the trait
+        GlusterFileOps::allocate(self, range, mode)
+    }
+
     pub fn fchdir(&self) -> Result<(), GlusterError> {
+        traced!(DEBUG, "fchdir", volume = self.volume);
         unsafe {
-            let ret_code = glfs_fchdir(self.file_handle);
+            let ret_code = glfs!(glfs_fchdir(self.file_handle));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1304,8 +2085,9 @@ impl GlusterFile {
     /// times[0] specifies the new "last access time" (atime);
     /// times[1] specifies the new "last modification time" (mtime).
     pub fn futimens(&self, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        traced!(DEBUG, "futimens", volume = self.volume);
         unsafe {
-            let ret_code = glfs_futimens(self.file_handle, times.as_ptr());
+            let ret_code = glfs!(glfs_futimens(self.file_handle, times.as_ptr()));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1314,8 +2096,9 @@ impl GlusterFile {
     }
 
     pub fn posixlock(&self, command: PosixLockCmd, flock: &mut flock) -> Result<(), GlusterError> {
+        traced!(DEBUG, "posixlock", volume = self.volume);
         unsafe {
-            let ret_code = glfs_posix_lock(self.file_handle, command.into(), flock);
+            let ret_code = glfs!(glfs_posix_lock(self.file_handle, command.into(), flock));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1323,8 +2106,9 @@ impl GlusterFile {
         Ok(())
     }
     pub fn fchmod(&self, mode: mode_t) -> Result<(), GlusterError> {
+        traced!(DEBUG, "fchmod", volume = self.volume);
         unsafe {
-            let ret_code = glfs_fchmod(self.file_handle, mode);
+            let ret_code = glfs!(glfs_fchmod(self.file_handle, mode));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1332,8 +2116,9 @@ impl GlusterFile {
         Ok(())
     }
     pub fn fchown(&self, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        traced!(DEBUG, "fchown", volume = self.volume);
         unsafe {
-            let ret_code = glfs_fchown(self.file_handle, uid, gid);
+            let ret_code = glfs!(glfs_fchown(self.file_handle, uid, gid));
             if ret_code < 0 {
                 return Err(GlusterError::new(get_error()));
             }
@@ -1353,9 +2138,13 @@ impl GlusterFile {
     // }
     //
     pub fn dup(&self) -> Result<GlusterFile, GlusterError> {
+        traced!(DEBUG, "dup", volume = self.volume);
         unsafe {
-            let file_handle = glfs_dup(self.file_handle);
-            Ok(GlusterFile { file_handle })
+            let file_handle = glfs!(glfs_dup(self.file_handle));
+            Ok(GlusterFile {
+                file_handle,
+                volume: Arc::clone(&self.volume),
+            })
         }
     }
 }
@@ -0,0 +1,162 @@
+//! What glfs! does around each call when metrics or tracing are enabled
+use errno::{errno, set_errno};
+
+#[cfg(feature = "tracing")]
+use std::cell::RefCell;
+#[cfg(feature = "tracing")]
+use std::path::Path;
+#[cfg(feature = "tracing")]
+use std::sync::Arc;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+/// How a glfs return value reports failure
+pub(crate) trait Outcome {
+    /// None on failure, otherwise the value as a byte count where that
+    /// makes sense
+    fn succeeded(&self) -> Option<u64>;
+}
+
+macro_rules! signed_outcome {
+    ($($t:ty),*) => {$(
+        impl Outcome for $t {
+            fn succeeded(&self) -> Option<u64> {
+                if *self < 0 {
+                    None
+                } else {
+                    Some(*self as u64)
+                }
+            }
+        }
+    )*};
+}
+
+signed_outcome!(i32, isize, i64);
+
+impl<T> Outcome for *mut T {
+    fn succeeded(&self) -> Option<u64> {
+        if self.is_null() {
+            None
+        } else {
+            Some(0)
+        }
+    }
+}
+
+/// Make the glfs call named name.  errno is left as the call set it so
+/// callers can go on to read it.
+pub(crate) fn call<R: Outcome>(name: &'static str, f: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "metrics")]
+    let start = Instant::now();
+    let ret = f();
+    let saved = errno();
+    let outcome = match name {
+        // Negative only says how much bigger the buffer needs to be
+        "glfs_get_volfile" => Some(0),
+        _ => ret.succeeded(),
+    };
+    #[cfg(feature = "metrics")]
+    crate::metrics::record(name, start.elapsed(), outcome, saved.0);
+    #[cfg(feature = "tracing")]
+    finish(outcome, saved.0);
+    set_errno(saved);
+    ret
+}
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    /// The spans of the operations traced! has open on this thread
+    static OPERATIONS: RefCell<Vec<tracing::Span>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Keeps an operation's span entered and where glfs! finds it, until
+/// the end of the block traced! was used in
+#[cfg(feature = "tracing")]
+pub(crate) struct Operation {
+    entered: Option<tracing::span::EnteredSpan>,
+}
+
+#[cfg(feature = "tracing")]
+impl Operation {
+    pub(crate) fn enter(span: tracing::Span) -> Operation {
+        OPERATIONS.with(|operations| operations.borrow_mut().push(span.clone()));
+        Operation {
+            entered: Some(span.entered()),
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Operation {
+    /// Exiting and closing the span runs the subscriber, which is free to
+    /// change errno, while the caller has yet to read the one the
+    /// operation left
+    fn drop(&mut self) {
+        let saved = errno();
+        self.entered.take();
+        OPERATIONS.with(|operations| operations.borrow_mut().pop());
+        set_errno(saved);
+    }
+}
+
+/// Fill in the result of the innermost operation traced! opened
+#[cfg(feature = "tracing")]
+fn finish(outcome: Option<u64>, errno: i32) {
+    OPERATIONS.with(|operations| {
+        let operations = operations.borrow();
+        let span = match operations.last() {
+            Some(span) => span,
+            None => return,
+        };
+        match outcome {
+            Some(0) => {}
+            Some(size) => {
+                span.record("size", size);
+            }
+            None => {
+                span.record("errno", errno);
+            }
+        }
+    });
+}
+
+/// Values traced! can put in a span's fields
+#[cfg(feature = "tracing")]
+pub(crate) trait Field {
+    fn record_in(&self, span: &tracing::Span, field: &'static str);
+}
+
+#[cfg(feature = "tracing")]
+impl<T: Field + ?Sized> Field for &T {
+    fn record_in(&self, span: &tracing::Span, field: &'static str) {
+        (**self).record_in(span, field)
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Field for Path {
+    fn record_in(&self, span: &tracing::Span, field: &'static str) {
+        span.record(field, tracing::field::display(self.display()));
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Field for Arc<str> {
+    fn record_in(&self, span: &tracing::Span, field: &'static str) {
+        span.record(field, &**self);
+    }
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! integer_field {
+    ($($t:ty),*) => {$(
+        impl Field for $t {
+            fn record_in(&self, span: &tracing::Span, field: &'static str) {
+                span.record(field, *self as i64);
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "tracing")]
+integer_field!(i64, usize);
@@ -0,0 +1,230 @@
+//! Profiles kept by gluster's io-stats translator
+//! io-stats sits at the top of every client graph and counts each fop with
+//! its latency as seen from the client, including the time spent on the
+//! network and the bricks, which the metrics module can't see.  Setting
+//! trusted.io-stats-dump on the root has it write what it has counted to a
+//! file, which Gluster::io_stats reads back and parses.
+use crate::gluster::{Gluster, GlusterError};
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Setting this on the root dumps the io-stats counters to the file named
+/// by the value
+pub const IO_STATS_DUMP_KEY: &str = "trusted.io-stats-dump";
+/// Where io-stats writes dumps, unless gluster was built with another
+/// localstatedir
+pub const IO_STATS_DUMP_DIR: &str = "/var/run/gluster";
+
+static NEXT_DUMP: AtomicUsize = AtomicUsize::new(0);
+
+/// Reads and writes of one block size, counted under the largest power of
+/// two not above the size of each call
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockSizeCount {
+    pub size: u64,
+    pub reads: u64,
+    pub writes: u64,
+}
+
+/// Calls of one fop and their latencies
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FopStats {
+    /// For example WRITE or LOOKUP
+    pub fop: String,
+    pub calls: u64,
+    pub avg_latency: Duration,
+    pub min_latency: Duration,
+    pub max_latency: Duration,
+}
+
+/// What io-stats counted since the client started or over the last
+/// interval
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IoStatsPeriod {
+    /// None for the cumulative stats, otherwise which interval this is
+    pub interval: Option<u64>,
+    pub duration: Duration,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Smallest block size first, leaving out sizes never used
+    pub block_sizes: Vec<BlockSizeCount>,
+    pub fops: Vec<FopStats>,
+    /// Only kept for the cumulative stats
+    pub open_fds: Option<u64>,
+    pub max_open_fds: Option<u64>,
+}
+
+impl IoStatsPeriod {
+    pub fn fop(&self, fop: &str) -> Option<&FopStats> {
+        self.fops.iter().find(|stats| stats.fop == fop)
+    }
+}
+
+/// A parsed io-stats dump
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IoStats {
+    pub cumulative: Option<IoStatsPeriod>,
+    /// Since the previous dump
+    pub interval: Option<IoStatsPeriod>,
+}
+
+/// The microseconds of a latency column, which io-stats follows with us
+fn latency(column: Option<&str>) -> Option<Duration> {
+    let micros: f64 = column?.parse().ok()?;
+    if micros.is_finite() && micros >= 0.0 {
+        Some(Duration::from_secs_f64(micros / 1e6))
+    } else {
+        None
+    }
+}
+
+/// WRITE  9  120.50 us  90.00 us  200.00 us
+fn parse_fop(line: &str) -> Option<FopStats> {
+    let mut columns = line.split_whitespace().filter(|column| *column != "us");
+    Some(FopStats {
+        fop: columns.next()?.to_string(),
+        calls: columns.next()?.parse().ok()?,
+        avg_latency: latency(columns.next())?,
+        min_latency: latency(columns.next())?,
+        max_latency: latency(columns.next())?,
+    })
+}
+
+impl IoStats {
+    pub fn open(path: &Path) -> Result<IoStats, GlusterError> {
+        IoStats::parse(BufReader::new(File::open(path)?))
+    }
+
+    pub fn parse<R: BufRead>(reader: R) -> Result<IoStats, GlusterError> {
+        let mut stats = IoStats::default();
+        let mut period: Option<IoStatsPeriod> = None;
+        // The sizes of the block size row being read
+        let mut sizes: Vec<u64> = Vec::new();
+        let mut in_fops = false;
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if let Some(heading) = line
+                .strip_prefix("===")
+                .and_then(|line| line.strip_suffix("stats ==="))
+            {
+                stats.finish(period.take());
+                let interval = heading.trim().strip_prefix("Interval ");
+                period = Some(IoStatsPeriod {
+                    interval: interval.and_then(|n| n.trim().parse().ok()),
+                    ..IoStatsPeriod::default()
+                });
+                in_fops = false;
+                continue;
+            }
+            let current = match period.as_mut() {
+                Some(current) => current,
+                None => continue,
+            };
+            if in_fops {
+                if line.starts_with("------") {
+                    in_fops = false;
+                } else if !line.starts_with("---") {
+                    current.fops.extend(parse_fop(line));
+                }
+                continue;
+            }
+            if line.starts_with("Fop ") {
+                in_fops = true;
+                continue;
+            }
+            if let Some(fds) = line.strip_prefix("Current open fd's:") {
+                let mut words = fds.split_whitespace();
+                current.open_fds = words.next().and_then(|n| n.parse().ok());
+                current.max_open_fds = words.nth(3).and_then(|n| n.parse().ok());
+                continue;
+            }
+            let (label, values) = match line.split_once(':') {
+                Some((label, values)) => (label.trim(), values.split_whitespace()),
+                None => continue,
+            };
+            let number = values
+                .clone()
+                .next()
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0);
+            match label {
+                "Duration" => current.duration = Duration::from_secs(number),
+                "BytesRead" => current.bytes_read = number,
+                "BytesWritten" => current.bytes_written = number,
+                "Block Size" => {
+                    sizes = values
+                        .filter_map(|size| size.trim_end_matches("B+").parse().ok())
+                        .collect();
+                    for &size in &sizes {
+                        if !current.block_sizes.iter().any(|count| count.size == size) {
+                            current.block_sizes.push(BlockSizeCount {
+                                size,
+                                ..BlockSizeCount::default()
+                            });
+                        }
+                    }
+                }
+                "Read Count" | "Read" | "Write Count" | "Write" => {
+                    for (size, value) in sizes.iter().zip(values) {
+                        let count = value.parse().unwrap_or(0);
+                        let block = current
+                            .block_sizes
+                            .iter_mut()
+                            .find(|block| block.size == *size);
+                        if let Some(block) = block {
+                            if label.starts_with("Read") {
+                                block.reads = count;
+                            } else {
+                                block.writes = count;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        stats.finish(period);
+        Ok(stats)
+    }
+
+    fn finish(&mut self, period: Option<IoStatsPeriod>) {
+        if let Some(mut period) = period {
+            period.block_sizes.sort_by_key(|block| block.size);
+            match period.interval {
+                None => self.cumulative = Some(period),
+                Some(_) => self.interval = Some(period),
+            }
+        }
+    }
+}
+
+impl Gluster {
+    /// Dump and parse the client's io-stats.  Each dump starts a new
+    /// interval, so the interval stats cover the time since the last one.
+    pub fn io_stats(&self) -> Result<IoStats, GlusterError> {
+        self.io_stats_in(Path::new(IO_STATS_DUMP_DIR))
+    }
+
+    /// Like io_stats, for a gluster that writes its dumps to dir instead of
+    /// IO_STATS_DUMP_DIR
+    pub fn io_stats_in(&self, dir: &Path) -> Result<IoStats, GlusterError> {
+        // io-stats only takes a file name, and writes it under dir
+        let name = format!(
+            "gfapi-io-stats-{}-{}",
+            std::process::id(),
+            NEXT_DUMP.fetch_add(1, Ordering::SeqCst)
+        );
+        self.setxattr(Path::new("/"), IO_STATS_DUMP_KEY, name.as_bytes(), 0)?;
+        // io-stats reports success even when it couldn't write the file,
+        // which shows up here as ENOENT
+        let path = dir.join(name);
+        let stats = IoStats::open(&path);
+        let _ = fs::remove_file(&path);
+        stats
+    }
+}
@@ -7,5 +7,70 @@
 #[macro_use]
 extern crate log;
 
+/// Make a glfs call, recording it in the metrics module and the current
+/// operation's span when those features are on.  Has to be used inside an
+/// unsafe block.
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+macro_rules! glfs {
+    ($function:ident($($arg:expr),* $(,)?)) => {
+        crate::instrument::call(stringify!($function), || $function($($arg),*))
+    };
+}
+
+#[cfg(not(any(feature = "metrics", feature = "tracing")))]
+macro_rules! glfs {
+    ($function:ident($($arg:expr),* $(,)?)) => {
+        $function($($arg),*)
+    };
+}
+
+/// With the tracing feature, open a span named after a filesystem
+/// operation that lasts until the end of the enclosing block.  volume,
+/// path, offset and length are given here, glfs! fills in size and errno.
+#[cfg(feature = "tracing")]
+macro_rules! traced {
+    ($level:ident, $op:literal $(, $field:ident = $value:expr)* $(,)?) => {
+        let _span = tracing::span!(
+            tracing::Level::$level,
+            $op,
+            volume = tracing::field::Empty,
+            path = tracing::field::Empty,
+            offset = tracing::field::Empty,
+            length = tracing::field::Empty,
+            size = tracing::field::Empty,
+            errno = tracing::field::Empty,
+        );
+        $( crate::instrument::Field::record_in(&$value, &_span, stringify!($field)); )*
+        let _span = crate::instrument::Operation::enter(_span);
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! traced {
+    ($($ignored:tt)*) => {};
+}
+
+pub mod acl;
+pub mod copy;
+pub mod credentials;
+pub mod fs;
+#[cfg(feature = "fuse")]
+pub mod fuse;
+pub mod gfid;
 pub mod glfs;
 pub mod gluster;
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+mod instrument;
+pub mod iostats;
+pub mod localfs;
+pub mod logging;
+pub mod memfs;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod pool;
+pub mod quota;
+pub mod resilient;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod statedump;
+pub mod url;
@@ -0,0 +1,952 @@
+//! A backend backed by a local directory
+//! LocalFs implements the traits in fs on top of a directory of the host
+//! filesystem so services can run on a laptop without a gluster volume.
+//! Paths are interpreted relative to that directory, xattrs and record
+//! locks go to the host filesystem, and the virtual xattrs gluster answers
+//! itself, like the gfid and pathinfo, are emulated with values derived
+//! from the host inode so they stay the same between runs.
+//!
+//! Symlinks are followed by the host, so an absolute or escaping target
+//! points outside the directory just like it would on a fuse mount.
+use crate::fs::{GlusterFileOps, GlusterFs};
+use crate::gfid::{Gfid, GFID_KEY};
+use crate::gluster::{get_error, DirEntry, DirEntryPlus, FileType, GlusterError, PosixLockCmd};
+use errno::{set_errno, Errno};
+use libc::{
+    c_char, c_int, c_void, dev_t, flock, iovec, mode_t, stat, statvfs, timespec, AT_FDCWD,
+    AT_SYMLINK_NOFOLLOW, DIR, ENOENT, EPERM, FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE,
+    FALLOC_FL_ZERO_RANGE, F_OFD_SETLK, F_RDLCK, F_UNLCK, F_WRLCK,
+};
+
+use std::ffi::{CStr, CString, OsStr};
+use std::io;
+use std::mem::zeroed;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Where a file lives on the bricks.  Answered by the client, never stored.
+pub const PATHINFO_KEY: &str = "trusted.glusterfs.pathinfo";
+/// The gfid of a path formatted as a uuid string
+pub const GFID_STRING_KEY: &str = "glusterfs.gfid.string";
+
+fn last_error() -> GlusterError {
+    GlusterError::new(get_error())
+}
+
+fn fail<T>(code: i32) -> Result<T, GlusterError> {
+    set_errno(Errno(code));
+    Err(last_error())
+}
+
+/// Report a std::io error the same way as a failed libc call
+fn io_error(err: io::Error) -> GlusterError {
+    match err.raw_os_error() {
+        Some(code) => {
+            set_errno(Errno(code));
+            last_error()
+        }
+        None => GlusterError::IoError(err),
+    }
+}
+
+/// Run one of the libc xattr getters, first asking for the size
+fn read_xattr<F>(get: F) -> Result<Vec<u8>, GlusterError>
+where
+    F: Fn(*mut c_void, usize) -> isize,
+{
+    loop {
+        let size = get(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(last_error());
+        }
+        let mut buf: Vec<u8> = vec![0; size as usize];
+        let read = get(buf.as_mut_ptr() as *mut c_void, buf.len());
+        if read < 0 {
+            // The value grew between the two calls
+            if errno::errno() == Errno(libc::ERANGE) {
+                continue;
+            }
+            return Err(last_error());
+        }
+        buf.truncate(read as usize);
+        return Ok(buf);
+    }
+}
+
+/// A gfid that stays the same for an inode.  The root is given the gfid
+/// gluster always gives its root.
+fn emulated_gfid(stat_buf: &stat, is_root: bool) -> Gfid {
+    if is_root {
+        return Gfid::root();
+    }
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&stat_buf.st_dev.to_be_bytes());
+    bytes[8..].copy_from_slice(&stat_buf.st_ino.to_be_bytes());
+    Gfid::from_bytes(&bytes).expect("16 bytes make a gfid")
+}
+
+#[derive(Debug)]
+struct LocalInner {
+    root: PathBuf,
+    hostname: String,
+    /// The working directory, relative to root
+    cwd: Mutex<PathBuf>,
+}
+
+/// A directory of the host filesystem used as a volume.  Cloning it is
+/// cheap and the clones share the working directory.
+#[derive(Clone, Debug)]
+pub struct LocalFs {
+    inner: Arc<LocalInner>,
+}
+
+impl LocalFs {
+    /// Serve the directory at root, which must already exist
+    pub fn new(root: &Path) -> Result<LocalFs, GlusterError> {
+        let root = root.canonicalize().map_err(io_error)?;
+        if !root.is_dir() {
+            return fail(libc::ENOTDIR);
+        }
+        let mut hostname = [0u8; 256];
+        let hostname = unsafe {
+            if libc::gethostname(hostname.as_mut_ptr() as *mut c_char, hostname.len()) < 0 {
+                return Err(last_error());
+            }
+            CStr::from_ptr(hostname.as_ptr() as *const c_char)
+                .to_string_lossy()
+                .into_owned()
+        };
+        Ok(LocalFs {
+            inner: Arc::new(LocalInner {
+                root,
+                hostname,
+                cwd: Mutex::new(PathBuf::from("/")),
+            }),
+        })
+    }
+
+    /// The host directory being served
+    pub fn root(&self) -> &Path {
+        &self.inner.root
+    }
+
+    fn cwd(&self) -> PathBuf {
+        self.inner
+            .cwd
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn set_cwd(&self, path: PathBuf) {
+        *self.inner.cwd.lock().unwrap_or_else(|e| e.into_inner()) = path;
+    }
+
+    /// Turn a path on the volume into an absolute one without . or ..
+    /// components.  .. is resolved lexically and never leaves the root.
+    fn volume_path(&self, path: &Path) -> PathBuf {
+        let mut resolved = if path.has_root() {
+            PathBuf::from("/")
+        } else {
+            self.cwd()
+        };
+        for component in path.components() {
+            match component {
+                Component::RootDir => resolved = PathBuf::from("/"),
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::Normal(name) => resolved.push(name),
+                Component::CurDir | Component::Prefix(_) => {}
+            }
+        }
+        resolved
+    }
+
+    fn host_path(&self, path: &Path) -> PathBuf {
+        let volume_path = self.volume_path(path);
+        self.inner
+            .root
+            .join(volume_path.strip_prefix("/").unwrap_or(&volume_path))
+    }
+
+    fn host_cstring(&self, path: &Path) -> Result<CString, GlusterError> {
+        Ok(CString::new(self.host_path(path).as_os_str().as_bytes())?)
+    }
+
+    fn pathinfo(&self, volume_path: &Path) -> String {
+        format!(
+            "(<POSIX({}):{}:{}>)",
+            self.inner.root.display(),
+            self.inner.hostname,
+            self.host_path(volume_path).display()
+        )
+    }
+
+    /// Answer the xattrs gluster emulates itself, or None for any other
+    fn virtual_xattr(&self, volume_path: &Path, stat_buf: &stat, name: &str) -> Option<Vec<u8>> {
+        let is_root = volume_path == Path::new("/");
+        match name {
+            GFID_KEY => Some(emulated_gfid(stat_buf, is_root).as_bytes().to_vec()),
+            GFID_STRING_KEY => Some(emulated_gfid(stat_buf, is_root).to_string().into_bytes()),
+            PATHINFO_KEY => Some(self.pathinfo(volume_path).into_bytes()),
+            _ => None,
+        }
+    }
+
+    fn is_virtual_xattr(name: &str) -> bool {
+        name == GFID_KEY || name == GFID_STRING_KEY || name == PATHINFO_KEY
+    }
+
+    fn stat_at(&self, path: &Path, flags: c_int) -> Result<stat, GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe {
+            let mut stat_buf: stat = zeroed();
+            if libc::fstatat(AT_FDCWD, path.as_ptr(), &mut stat_buf, flags) < 0 {
+                return Err(last_error());
+            }
+            Ok(stat_buf)
+        }
+    }
+
+    fn get_xattr(&self, path: &Path, name: &str, follow: bool) -> Result<Vec<u8>, GlusterError> {
+        let flags = if follow { 0 } else { AT_SYMLINK_NOFOLLOW };
+        let stat_buf = self.stat_at(path, flags)?;
+        if let Some(value) = self.virtual_xattr(&self.volume_path(path), &stat_buf, name) {
+            return Ok(value);
+        }
+        let host = self.host_cstring(path)?;
+        let name = CString::new(name)?;
+        read_xattr(|buf, size| unsafe {
+            if follow {
+                libc::getxattr(host.as_ptr(), name.as_ptr(), buf, size)
+            } else {
+                libc::lgetxattr(host.as_ptr(), name.as_ptr(), buf, size)
+            }
+        })
+    }
+
+    fn list_xattr(&self, path: &Path, follow: bool) -> Result<String, GlusterError> {
+        let host = self.host_cstring(path)?;
+        let list = read_xattr(|buf, size| unsafe {
+            if follow {
+                libc::listxattr(host.as_ptr(), buf as *mut c_char, size)
+            } else {
+                libc::llistxattr(host.as_ptr(), buf as *mut c_char, size)
+            }
+        })?;
+        Ok(String::from_utf8_lossy(&list).into_owned())
+    }
+
+    fn set_xattr(
+        &self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+        follow: bool,
+    ) -> Result<(), GlusterError> {
+        if LocalFs::is_virtual_xattr(name) {
+            return fail(EPERM);
+        }
+        let host = self.host_cstring(path)?;
+        let name = CString::new(name)?;
+        unsafe {
+            let set = if follow {
+                libc::setxattr
+            } else {
+                libc::lsetxattr
+            };
+            let ret_code = set(
+                host.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+                flags,
+            );
+            if ret_code < 0 {
+                return Err(last_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &str, follow: bool) -> Result<(), GlusterError> {
+        if LocalFs::is_virtual_xattr(name) {
+            return fail(EPERM);
+        }
+        let host = self.host_cstring(path)?;
+        let name = CString::new(name)?;
+        unsafe {
+            let ret_code = if follow {
+                libc::removexattr(host.as_ptr(), name.as_ptr())
+            } else {
+                libc::lremovexattr(host.as_ptr(), name.as_ptr())
+            };
+            if ret_code < 0 {
+                return Err(last_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn utimensat(
+        &self,
+        path: &Path,
+        times: &[timespec; 2],
+        flags: c_int,
+    ) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe {
+            if libc::utimensat(AT_FDCWD, path.as_ptr(), times.as_ptr(), flags) < 0 {
+                return Err(last_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn open_file(&self, path: &Path, flags: i32, mode: mode_t) -> Result<LocalFile, GlusterError> {
+        let host = self.host_cstring(path)?;
+        unsafe {
+            let fd = libc::open(host.as_ptr(), flags | libc::O_CLOEXEC, mode as libc::c_uint);
+            if fd < 0 {
+                return Err(last_error());
+            }
+            Ok(LocalFile {
+                fd,
+                fs: self.clone(),
+                path: self.volume_path(path),
+            })
+        }
+    }
+
+    fn open_dir(&self, path: &Path) -> Result<LocalDirectory, GlusterError> {
+        let host = self.host_cstring(path)?;
+        unsafe {
+            let dir = libc::opendir(host.as_ptr());
+            if dir.is_null() {
+                return Err(last_error());
+            }
+            Ok(LocalDirectory { dir })
+        }
+    }
+}
+
+/// Checks a libc return code, which is negative on failure
+macro_rules! check {
+    ($ret_code:expr) => {
+        if $ret_code < 0 {
+            return Err(last_error());
+        }
+    };
+}
+
+impl GlusterFs for LocalFs {
+    type File = LocalFile;
+    type Dir = LocalDirectory;
+    type DirPlus = LocalDirectoryPlus;
+
+    fn open(&self, path: &Path, flags: i32) -> Result<LocalFile, GlusterError> {
+        self.open_file(path, flags, 0)
+    }
+
+    fn create(&self, path: &Path, flags: i32, mode: mode_t) -> Result<LocalFile, GlusterError> {
+        self.open_file(path, flags | libc::O_CREAT, mode)
+    }
+
+    fn truncate(&self, path: &Path, length: i64) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe { check!(libc::truncate(path.as_ptr(), length)) }
+        Ok(())
+    }
+
+    fn lsstat(&self, path: &Path) -> Result<stat, GlusterError> {
+        self.stat_at(path, AT_SYMLINK_NOFOLLOW)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, GlusterError> {
+        match self.stat_at(path, 0) {
+            Ok(_) => Ok(true),
+            Err(_) if errno::errno() == Errno(ENOENT) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn statvfs(&self, path: &Path) -> Result<statvfs, GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe {
+            let mut stat_buf: statvfs = zeroed();
+            check!(libc::statvfs(path.as_ptr(), &mut stat_buf));
+            Ok(stat_buf)
+        }
+    }
+
+    fn stat(&self, path: &Path) -> Result<stat, GlusterError> {
+        self.stat_at(path, 0)
+    }
+
+    fn access(&self, path: &Path, mode: i32) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe { check!(libc::access(path.as_ptr(), mode)) }
+        Ok(())
+    }
+
+    /// The target is stored as given, so relative targets work the same
+    /// on the host as on the volume
+    fn symlink(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        let target = CString::new(oldpath.as_os_str().as_bytes())?;
+        let path = self.host_cstring(newpath)?;
+        unsafe { check!(libc::symlink(target.as_ptr(), path.as_ptr())) }
+        Ok(())
+    }
+
+    fn readlink(&self, path: &Path, buf: &mut [u8]) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe {
+            check!(libc::readlink(
+                path.as_ptr(),
+                buf.as_mut_ptr() as *mut c_char,
+                buf.len()
+            ));
+        }
+        Ok(())
+    }
+
+    fn mknod(&self, path: &Path, mode: mode_t, dev: dev_t) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe { check!(libc::mknod(path.as_ptr(), mode, dev)) }
+        Ok(())
+    }
+
+    fn mkdir(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe { check!(libc::mkdir(path.as_ptr(), mode)) }
+        Ok(())
+    }
+
+    fn unlink(&self, path: &Path) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe { check!(libc::unlink(path.as_ptr())) }
+        Ok(())
+    }
+
+    fn rmdir(&self, path: &Path) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe { check!(libc::rmdir(path.as_ptr())) }
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<(), GlusterError> {
+        std::fs::remove_dir_all(self.host_path(path)).map_err(io_error)
+    }
+
+    fn rename(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        let oldpath = self.host_cstring(oldpath)?;
+        let newpath = self.host_cstring(newpath)?;
+        unsafe { check!(libc::rename(oldpath.as_ptr(), newpath.as_ptr())) }
+        Ok(())
+    }
+
+    fn link(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        let oldpath = self.host_cstring(oldpath)?;
+        let newpath = self.host_cstring(newpath)?;
+        unsafe { check!(libc::link(oldpath.as_ptr(), newpath.as_ptr())) }
+        Ok(())
+    }
+
+    fn opendir(&self, path: &Path) -> Result<LocalDirectory, GlusterError> {
+        self.open_dir(path)
+    }
+
+    fn opendir_plus(&self, path: &Path) -> Result<LocalDirectoryPlus, GlusterError> {
+        Ok(LocalDirectoryPlus {
+            dir: self.open_dir(path)?,
+        })
+    }
+
+    fn getxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
+        let value = self.get_xattr(path, name, true)?;
+        Ok(String::from_utf8_lossy(&value).into_owned())
+    }
+
+    fn getxattr_bytes(&self, path: &Path, name: &str) -> Result<Vec<u8>, GlusterError> {
+        self.get_xattr(path, name, true)
+    }
+
+    fn lgetxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
+        let value = self.get_xattr(path, name, false)?;
+        Ok(String::from_utf8_lossy(&value).into_owned())
+    }
+
+    fn listxattr(&self, path: &Path) -> Result<String, GlusterError> {
+        self.list_xattr(path, true)
+    }
+
+    fn llistxattr(&self, path: &Path) -> Result<String, GlusterError> {
+        self.list_xattr(path, false)
+    }
+
+    fn setxattr(
+        &self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+    ) -> Result<(), GlusterError> {
+        self.set_xattr(path, name, value, flags, true)
+    }
+
+    fn lsetxattr(
+        &self,
+        name: &str,
+        value: &[u8],
+        path: &Path,
+        flags: i32,
+    ) -> Result<(), GlusterError> {
+        self.set_xattr(path, name, value, flags, false)
+    }
+
+    fn removexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
+        self.remove_xattr(path, name, true)
+    }
+
+    fn lremovexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
+        self.remove_xattr(path, name, false)
+    }
+
+    fn getcwd(&self) -> Result<String, GlusterError> {
+        Ok(self.cwd().to_string_lossy().into_owned())
+    }
+
+    fn chdir(&self, path: &Path) -> Result<(), GlusterError> {
+        let stat_buf = self.stat_at(path, 0)?;
+        if stat_buf.st_mode & libc::S_IFMT != libc::S_IFDIR {
+            return fail(libc::ENOTDIR);
+        }
+        self.set_cwd(self.volume_path(path));
+        Ok(())
+    }
+
+    fn utimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        self.utimensat(path, times, 0)
+    }
+
+    fn lutimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        self.utimensat(path, times, AT_SYMLINK_NOFOLLOW)
+    }
+
+    fn chmod(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe { check!(libc::chmod(path.as_ptr(), mode)) }
+        Ok(())
+    }
+
+    fn chown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe { check!(libc::chown(path.as_ptr(), uid, gid)) }
+        Ok(())
+    }
+
+    fn lchown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        let path = self.host_cstring(path)?;
+        unsafe { check!(libc::lchown(path.as_ptr(), uid, gid)) }
+        Ok(())
+    }
+}
+
+/// Reads a directory with readdir(3).  Entries are listed in whatever
+/// order the host filesystem returns them.
+#[derive(Debug)]
+pub struct LocalDirectory {
+    dir: *mut DIR,
+}
+
+impl Drop for LocalDirectory {
+    fn drop(&mut self) {
+        unsafe {
+            if libc::closedir(self.dir) < 0 {
+                error!("{:?}", last_error());
+            }
+        }
+    }
+}
+
+impl LocalDirectory {
+    fn next_dirent(&mut self) -> Option<Result<(DirEntry, c_int), GlusterError>> {
+        unsafe {
+            // readdir only sets errno on failure
+            set_errno(Errno(0));
+            let dirent = libc::readdir(self.dir);
+            if dirent.is_null() {
+                if errno::errno() != Errno(0) {
+                    return Some(Err(last_error()));
+                }
+                return None;
+            }
+            let name = CStr::from_ptr((*dirent).d_name.as_ptr());
+            let dir_fd = libc::dirfd(self.dir);
+            let file_type = match FileType::from_d_type((*dirent).d_type) {
+                // Some filesystems don't fill in d_type
+                FileType::Unknown => {
+                    let mut stat_buf: stat = zeroed();
+                    match libc::fstatat(dir_fd, name.as_ptr(), &mut stat_buf, AT_SYMLINK_NOFOLLOW) {
+                        0 => FileType::from_mode(stat_buf.st_mode),
+                        _ => FileType::Unknown,
+                    }
+                }
+                file_type => file_type,
+            };
+            Some(Ok((
+                DirEntry {
+                    path: PathBuf::from(OsStr::from_bytes(name.to_bytes())),
+                    inode: (*dirent).d_ino,
+                    file_type,
+                },
+                dir_fd,
+            )))
+        }
+    }
+}
+
+impl Iterator for LocalDirectory {
+    type Item = Result<DirEntry, GlusterError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_dirent()
+            .map(|entry| entry.map(|(entry, _)| entry))
+    }
+}
+
+/// Reads a directory and lstats every entry, like readdirplus
+#[derive(Debug)]
+pub struct LocalDirectoryPlus {
+    dir: LocalDirectory,
+}
+
+impl Iterator for LocalDirectoryPlus {
+    type Item = Result<DirEntryPlus, GlusterError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let (entry, dir_fd) = match self.dir.next_dirent()? {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(e)),
+        };
+        let name = match CString::new(entry.path.as_os_str().as_bytes()) {
+            Ok(name) => name,
+            Err(e) => return Some(Err(e.into())),
+        };
+        unsafe {
+            let mut stat_buf: stat = zeroed();
+            if libc::fstatat(dir_fd, name.as_ptr(), &mut stat_buf, AT_SYMLINK_NOFOLLOW) < 0 {
+                return Some(Err(last_error()));
+            }
+            Some(Ok(DirEntryPlus {
+                path: entry.path,
+                inode: entry.inode,
+                file_type: match entry.file_type {
+                    FileType::Unknown => FileType::from_mode(stat_buf.st_mode),
+                    file_type => file_type,
+                },
+                stat: stat_buf,
+            }))
+        }
+    }
+}
+
+/// A file descriptor on the host
+#[derive(Debug)]
+pub struct LocalFile {
+    fd: c_int,
+    fs: LocalFs,
+    /// Where the file was opened on the volume, for fchdir and pathinfo
+    path: PathBuf,
+}
+
+impl Drop for LocalFile {
+    fn drop(&mut self) {
+        unsafe {
+            if libc::close(self.fd) < 0 {
+                error!("{:?}", last_error());
+            }
+        }
+    }
+}
+
+impl LocalFile {
+    fn get_xattr(&self, name: &str) -> Result<Vec<u8>, GlusterError> {
+        if LocalFs::is_virtual_xattr(name) {
+            let stat_buf = self.fstat()?;
+            if let Some(value) = self.fs.virtual_xattr(&self.path, &stat_buf, name) {
+                return Ok(value);
+            }
+        }
+        let name = CString::new(name)?;
+        read_xattr(|buf, size| unsafe { libc::fgetxattr(self.fd, name.as_ptr(), buf, size) })
+    }
+
+    fn fallocate_mode(&self, mode: c_int, offset: i64, len: i64) -> Result<(), GlusterError> {
+        unsafe { check!(libc::fallocate(self.fd, mode, offset, len)) }
+        Ok(())
+    }
+}
+
+fn iovecs(iov: &[&[u8]]) -> Vec<iovec> {
+    iov.iter()
+        .map(|buf| iovec {
+            iov_base: buf.as_ptr() as *mut c_void,
+            iov_len: buf.len(),
+        })
+        .collect()
+}
+
+fn iovecs_mut(iov: &mut [&mut [u8]]) -> Vec<iovec> {
+    iov.iter_mut()
+        .map(|buf| iovec {
+            iov_base: buf.as_mut_ptr() as *mut c_void,
+            iov_len: buf.len(),
+        })
+        .collect()
+}
+
+impl GlusterFileOps for LocalFile {
+    fn read(
+        &self,
+        fill_buffer: &mut Vec<u8>,
+        count: usize,
+        _flags: i32,
+    ) -> Result<isize, GlusterError> {
+        fill_buffer.clear();
+        fill_buffer.reserve(count);
+        unsafe {
+            let read_size = libc::read(self.fd, fill_buffer.as_mut_ptr() as *mut c_void, count);
+            check!(read_size);
+            fill_buffer.set_len(read_size as usize);
+            Ok(read_size)
+        }
+    }
+
+    fn write(&self, buffer: &[u8], _flags: i32) -> Result<isize, GlusterError> {
+        unsafe {
+            let write_size = libc::write(self.fd, buffer.as_ptr() as *const c_void, buffer.len());
+            check!(write_size);
+            Ok(write_size)
+        }
+    }
+
+    fn readv(&self, iov: &mut [&mut [u8]], _flags: i32) -> Result<isize, GlusterError> {
+        let iov = iovecs_mut(iov);
+        unsafe {
+            let read_size = libc::readv(self.fd, iov.as_ptr(), iov.len() as c_int);
+            check!(read_size);
+            Ok(read_size)
+        }
+    }
+
+    fn writev(&self, iov: &[&[u8]], _flags: i32) -> Result<isize, GlusterError> {
+        let iov = iovecs(iov);
+        unsafe {
+            let write_size = libc::writev(self.fd, iov.as_ptr(), iov.len() as c_int);
+            check!(write_size);
+            Ok(write_size)
+        }
+    }
+
+    fn pread(
+        &self,
+        fill_buffer: &mut Vec<u8>,
+        count: usize,
+        offset: i64,
+        _flags: i32,
+    ) -> Result<isize, GlusterError> {
+        fill_buffer.clear();
+        fill_buffer.reserve(count);
+        unsafe {
+            let read_size = libc::pread(
+                self.fd,
+                fill_buffer.as_mut_ptr() as *mut c_void,
+                count,
+                offset,
+            );
+            check!(read_size);
+            fill_buffer.set_len(read_size as usize);
+            Ok(read_size)
+        }
+    }
+
+    fn pwrite(
+        &self,
+        buffer: &[u8],
+        count: usize,
+        offset: i64,
+        _flags: i32,
+    ) -> Result<isize, GlusterError> {
+        let count = count.min(buffer.len());
+        unsafe {
+            let write_size = libc::pwrite(self.fd, buffer.as_ptr() as *const c_void, count, offset);
+            check!(write_size);
+            Ok(write_size)
+        }
+    }
+
+    fn preadv(
+        &self,
+        iov: &mut [&mut [u8]],
+        offset: i64,
+        _flags: i32,
+    ) -> Result<isize, GlusterError> {
+        let iov = iovecs_mut(iov);
+        unsafe {
+            let read_size = libc::preadv(self.fd, iov.as_ptr(), iov.len() as c_int, offset);
+            check!(read_size);
+            Ok(read_size)
+        }
+    }
+
+    fn pwritev(&self, iov: &[&[u8]], offset: i64, _flags: i32) -> Result<isize, GlusterError> {
+        let iov = iovecs(iov);
+        unsafe {
+            let write_size = libc::pwritev(self.fd, iov.as_ptr(), iov.len() as c_int, offset);
+            check!(write_size);
+            Ok(write_size)
+        }
+    }
+
+    fn lseek(&self, offset: i64, whence: i32) -> Result<i64, GlusterError> {
+        unsafe {
+            let position = libc::lseek(self.fd, offset, whence);
+            check!(position);
+            Ok(position)
+        }
+    }
+
+    fn ftruncate(&self, length: i64) -> Result<(), GlusterError> {
+        unsafe { check!(libc::ftruncate(self.fd, length)) }
+        Ok(())
+    }
+
+    fn fstat(&self) -> Result<stat, GlusterError> {
+        unsafe {
+            let mut stat_buf: stat = zeroed();
+            check!(libc::fstat(self.fd, &mut stat_buf));
+            Ok(stat_buf)
+        }
+    }
+
+    fn fsync(&self) -> Result<(), GlusterError> {
+        unsafe { check!(libc::fsync(self.fd)) }
+        Ok(())
+    }
+
+    fn fdatasync(&self) -> Result<(), GlusterError> {
+        unsafe { check!(libc::fdatasync(self.fd)) }
+        Ok(())
+    }
+
+    fn fgetxattr(&self, name: &str) -> Result<String, GlusterError> {
+        let value = self.get_xattr(name)?;
+        Ok(String::from_utf8_lossy(&value).into_owned())
+    }
+
+    fn flistxattr(&self) -> Result<String, GlusterError> {
+        let list =
+            read_xattr(|buf, size| unsafe { libc::flistxattr(self.fd, buf as *mut c_char, size) })?;
+        Ok(String::from_utf8_lossy(&list).into_owned())
+    }
+
+    fn fsetxattr(&self, name: &str, value: &[u8], flags: i32) -> Result<(), GlusterError> {
+        if LocalFs::is_virtual_xattr(name) {
+            return fail(EPERM);
+        }
+        let name = CString::new(name)?;
+        unsafe {
+            check!(libc::fsetxattr(
+                self.fd,
+                name.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+                flags
+            ));
+        }
+        Ok(())
+    }
+
+    fn fremovexattr(&self, name: &str) -> Result<(), GlusterError> {
+        if LocalFs::is_virtual_xattr(name) {
+            return fail(EPERM);
+        }
+        let name = CString::new(name)?;
+        unsafe { check!(libc::fremovexattr(self.fd, name.as_ptr())) }
+        Ok(())
+    }
+
+    fn fallocate(&self, offset: i64, keep_size: i32, len: usize) -> Result<(), GlusterError> {
+        let mode = if keep_size != 0 {
+            FALLOC_FL_KEEP_SIZE
+        } else {
+            0
+        };
+        self.fallocate_mode(mode, offset, len as i64)
+    }
+
+    fn discard(&self, offset: i64, len: usize) -> Result<(), GlusterError> {
+        self.fallocate_mode(
+            FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE,
+            offset,
+            len as i64,
+        )
+    }
+
+    fn zerofill(&self, offset: i64, len: i64) -> Result<(), GlusterError> {
+        self.fallocate_mode(FALLOC_FL_ZERO_RANGE, offset, len)
+    }
+
+    fn fchdir(&self) -> Result<(), GlusterError> {
+        let stat_buf = self.fstat()?;
+        if stat_buf.st_mode & libc::S_IFMT != libc::S_IFDIR {
+            return fail(libc::ENOTDIR);
+        }
+        self.fs.set_cwd(self.path.clone());
+        Ok(())
+    }
+
+    fn futimens(&self, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        unsafe { check!(libc::futimens(self.fd, times.as_ptr())) }
+        Ok(())
+    }
+
+    /// Uses open file description locks, which like gluster's belong to
+    /// the open file rather than the whole process.  Conflicting locks
+    /// fail with EAGAIN instead of waiting.
+    fn posixlock(&self, command: PosixLockCmd, flock: &mut flock) -> Result<(), GlusterError> {
+        flock.l_type = match command {
+            PosixLockCmd::Shared => F_RDLCK,
+            PosixLockCmd::Exclusive => F_WRLCK,
+            PosixLockCmd::Unlock => F_UNLCK,
+        } as _;
+        // Required to be zero for OFD locks
+        flock.l_pid = 0;
+        unsafe { check!(libc::fcntl(self.fd, F_OFD_SETLK, flock as *mut flock)) }
+        Ok(())
+    }
+
+    fn fchmod(&self, mode: mode_t) -> Result<(), GlusterError> {
+        unsafe { check!(libc::fchmod(self.fd, mode)) }
+        Ok(())
+    }
+
+    fn fchown(&self, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        unsafe { check!(libc::fchown(self.fd, uid, gid)) }
+        Ok(())
+    }
+
+    fn dup(&self) -> Result<LocalFile, GlusterError> {
+        unsafe {
+            let fd = libc::fcntl(self.fd, libc::F_DUPFD_CLOEXEC, 0);
+            check!(fd);
+            Ok(LocalFile {
+                fd,
+                fs: self.fs.clone(),
+                path: self.path.clone(),
+            })
+        }
+    }
+}
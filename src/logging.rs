@@ -0,0 +1,442 @@
+//! Gluster's own log messages, read back into the log crate
+//! gfapi only knows how to write its log to a file.  forward_logging hands
+//! it a FIFO instead and re-emits every line through the log crate, so
+//! gluster's messages end up wherever the application's go.  Records are
+//! logged with a target of gfapi::<xlator>, for example
+//! gfapi::0-myvol-client-0, so they can be filtered like any other module.
+//! LogReader parses logs already written to disk.
+use crate::gluster::{get_error, Gluster, GlusterError, GlusterLogLevel};
+use libc::{fcntl, mkfifo, F_GETFL, F_SETFL, O_NONBLOCK};
+
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static NEXT_FIFO: AtomicUsize = AtomicUsize::new(0);
+
+/// One line of a gluster log:
+/// [2026-10-17 10:00:00.123456 +0000] E [MSGID: 114031] [client-rpc-fops.c:1234:func] 0-vol-client-0: message
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogRecord {
+    pub timestamp: SystemTime,
+    pub level: GlusterLogLevel,
+    /// Not every message has an id
+    pub msgid: Option<u32>,
+    /// The source file and line that logged it
+    pub file: String,
+    pub line: u32,
+    pub function: String,
+    /// The translator that logged it, prefixed with its graph number
+    pub xlator: String,
+    pub message: String,
+}
+
+impl LogRecord {
+    /// Parse a line as gluster writes it, None if it isn't the start of a
+    /// record
+    pub fn parse_line(line: &str) -> Option<LogRecord> {
+        let rest = line.strip_prefix('[')?;
+        let end = rest.find(']')?;
+        let timestamp = parse_timestamp(&rest[..end])?;
+        LogRecord::parse_body(timestamp, &rest[end + 1..])
+    }
+
+    /// Parse what follows the timestamp:
+    /// E [MSGID: 114031] [client-rpc-fops.c:1234:func] 0-vol-client-0: message
+    fn parse_body(timestamp: SystemTime, rest: &str) -> Option<LogRecord> {
+        let rest = rest.trim_start();
+        let (level, rest) = rest.split_once(' ')?;
+        let level = match level {
+            "M" => GlusterLogLevel::Emerg,
+            "A" => GlusterLogLevel::Alert,
+            "C" => GlusterLogLevel::Critical,
+            "E" => GlusterLogLevel::Error,
+            "W" => GlusterLogLevel::Warning,
+            "N" => GlusterLogLevel::Notice,
+            "I" => GlusterLogLevel::Info,
+            "D" => GlusterLogLevel::Debug,
+            "T" => GlusterLogLevel::Trace,
+            _ => return None,
+        };
+
+        let mut rest = rest.trim_start();
+        let mut msgid = None;
+        if let Some(after) = rest.strip_prefix("[MSGID: ") {
+            let end = after.find(']')?;
+            msgid = Some(after[..end].trim().parse().ok()?);
+            rest = after[end + 1..].trim_start();
+        }
+
+        // [file.c:123:function]
+        let after = rest.strip_prefix('[')?;
+        let end = after.find(']')?;
+        let mut location = after[..end].splitn(3, ':');
+        let file = location.next()?.to_string();
+        let line = location.next()?.parse().ok()?;
+        let function = location.next().unwrap_or_default().to_string();
+        let rest = after[end + 1..].trim_start();
+
+        let (xlator, message) = match rest.split_once(": ") {
+            Some((xlator, message)) if !xlator.contains(' ') => (xlator, message),
+            _ => ("", rest.strip_suffix(':').unwrap_or(rest)),
+        };
+        Some(LogRecord {
+            timestamp,
+            level,
+            msgid,
+            file,
+            line,
+            function,
+            xlator: xlator.to_string(),
+            message: message.trim_end().to_string(),
+        })
+    }
+
+    /// The log crate level gluster's level maps to
+    pub fn log_level(&self) -> log::Level {
+        level_of(&self.level)
+    }
+
+    /// Emit the record through the log crate
+    pub fn log(&self) {
+        self.log_with("");
+    }
+
+    fn log_with(&self, suffix: &str) {
+        let target = target_of(&self.xlator);
+        if !log_enabled!(target: &target, self.log_level()) {
+            return;
+        }
+        let args = match self.msgid {
+            Some(msgid) => format!("[MSGID: {}] {}{}", msgid, self.message, suffix),
+            None => format!("{}{}", self.message, suffix),
+        };
+        log::logger().log(
+            &log::Record::builder()
+                .level(self.log_level())
+                .target(&target)
+                .file(Some(&self.file))
+                .line(Some(self.line))
+                .module_path(Some(&target))
+                .args(format_args!("{}", args))
+                .build(),
+        );
+    }
+}
+
+/// What a gluster log is made of
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogEntry {
+    /// A message, with the lines of a multi-line message joined by \n
+    Record(LogRecord),
+    /// gluster's summary of a message it logged count more times than it
+    /// wrote out, from the record's timestamp until last:
+    /// The message "E [MSGID: 114031] [...] 0-vol-client-0: message" repeated 5 times between [...] and [...]
+    Repeated {
+        record: LogRecord,
+        count: u64,
+        last: SystemTime,
+    },
+}
+
+impl LogEntry {
+    /// Parse the first line of an entry, None if the line doesn't start
+    /// one
+    pub fn parse_line(line: &str) -> Option<LogEntry> {
+        if let Some(record) = LogRecord::parse_line(line) {
+            return Some(LogEntry::Record(record));
+        }
+        let rest = line.strip_prefix("The message \"")?;
+        let quote = rest.rfind("\" repeated ")?;
+        let (body, rest) = (&rest[..quote], &rest[quote + "\" repeated ".len()..]);
+        let (count, rest) = rest.split_once(" times between [")?;
+        let (first, rest) = rest.split_once("] and [")?;
+        let last = rest.trim_end().strip_suffix(']')?;
+        let first = parse_timestamp(first)?;
+        Some(LogEntry::Repeated {
+            record: LogRecord::parse_body(first, body)?,
+            count: count.parse().ok()?,
+            last: parse_timestamp(last)?,
+        })
+    }
+
+    pub fn record(&self) -> &LogRecord {
+        match self {
+            LogEntry::Record(record) | LogEntry::Repeated { record, .. } => record,
+        }
+    }
+
+    fn record_mut(&mut self) -> &mut LogRecord {
+        match self {
+            LogEntry::Record(record) | LogEntry::Repeated { record, .. } => record,
+        }
+    }
+
+    /// How many times the message was logged
+    pub fn count(&self) -> u64 {
+        match self {
+            LogEntry::Record(_) => 1,
+            LogEntry::Repeated { count, .. } => *count,
+        }
+    }
+
+    /// Emit the entry through the log crate
+    pub fn log(&self) {
+        match self {
+            LogEntry::Record(record) => record.log(),
+            LogEntry::Repeated { record, count, .. } => {
+                record.log_with(&format!(" (repeated {} times)", count))
+            }
+        }
+    }
+}
+
+/// Reads the entries of a gluster log, such as one set_logging wrote.
+/// Lines that don't start an entry are the rest of a multi-line message
+/// and are joined onto it, except before the first entry where they're
+/// skipped.
+pub struct LogReader<R> {
+    reader: R,
+    /// Read, but lines may still follow that belong to it
+    pending: Option<LogEntry>,
+    /// The start of a repeat summary whose quoted message spans lines
+    partial: Option<String>,
+    buf: Vec<u8>,
+}
+
+impl LogReader<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<LogReader<BufReader<File>>, GlusterError> {
+        Ok(LogReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> LogReader<R> {
+    pub fn new(reader: R) -> LogReader<R> {
+        LogReader {
+            reader,
+            pending: None,
+            partial: None,
+            buf: Vec::new(),
+        }
+    }
+
+    /// The next line without its line ending, None at the end
+    fn read_line(&mut self) -> Result<Option<String>, GlusterError> {
+        self.buf.clear();
+        if self.reader.read_until(b'\n', &mut self.buf)? == 0 {
+            return Ok(None);
+        }
+        let line = String::from_utf8_lossy(&self.buf);
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    fn continue_pending(&mut self, text: &str) {
+        if let Some(entry) = self.pending.as_mut() {
+            let message = &mut entry.record_mut().message;
+            message.push('\n');
+            message.push_str(text);
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for LogReader<R> {
+    type Item = Result<LogEntry, GlusterError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.read_line() {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    if let Some(partial) = self.partial.take() {
+                        self.continue_pending(&partial);
+                    }
+                    return self.pending.take().map(Ok);
+                }
+                Err(e) => return Some(Err(e)),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let text = match self.partial.take() {
+                // A new entry means the summary was never finished
+                Some(partial) if LogEntry::parse_line(&line).is_some() => {
+                    self.continue_pending(&partial);
+                    line
+                }
+                Some(partial) => format!("{}\n{}", partial, line),
+                None => line,
+            };
+            match LogEntry::parse_line(&text) {
+                Some(entry) => {
+                    if let Some(previous) = self.pending.replace(entry) {
+                        return Some(Ok(previous));
+                    }
+                }
+                None if text.starts_with("The message \"") => self.partial = Some(text),
+                None => self.continue_pending(&text),
+            }
+        }
+    }
+}
+
+fn level_of(level: &GlusterLogLevel) -> log::Level {
+    match level {
+        GlusterLogLevel::None
+        | GlusterLogLevel::Emerg
+        | GlusterLogLevel::Alert
+        | GlusterLogLevel::Critical
+        | GlusterLogLevel::Error => log::Level::Error,
+        GlusterLogLevel::Warning => log::Level::Warn,
+        GlusterLogLevel::Notice | GlusterLogLevel::Info => log::Level::Info,
+        GlusterLogLevel::Debug => log::Level::Debug,
+        GlusterLogLevel::Trace => log::Level::Trace,
+    }
+}
+
+fn target_of(xlator: &str) -> String {
+    if xlator.is_empty() {
+        "gfapi".to_string()
+    } else {
+        format!("gfapi::{}", xlator)
+    }
+}
+
+/// Parse 2026-10-17 10:00:00.123456 with an optional +0000 style offset,
+/// which older gluster releases leave off for UTC
+pub(crate) fn parse_timestamp(text: &str) -> Option<SystemTime> {
+    let mut parts = text.split_whitespace();
+    let mut date = parts.next()?.splitn(3, '-');
+    let year: i64 = date.next()?.parse().ok()?;
+    let month: i64 = date.next()?.parse().ok()?;
+    let day: i64 = date.next()?.parse().ok()?;
+    let (time, fraction) = parts.next()?.split_once('.')?;
+    let mut time = time.splitn(3, ':');
+    let hours: i64 = time.next()?.parse().ok()?;
+    let minutes: i64 = time.next()?.parse().ok()?;
+    let seconds: i64 = time.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || fraction.len() > 9 {
+        return None;
+    }
+    let nanos: u32 = format!("{:0<9}", fraction).parse().ok()?;
+    let offset = match parts.next() {
+        // Checked for ASCII first since lossily decoded lines can hold
+        // multibyte characters that slicing by byte would split
+        Some(offset) if offset.len() == 5 && offset.is_ascii() => {
+            let sign = match &offset[..1] {
+                "+" => 1,
+                "-" => -1,
+                _ => return None,
+            };
+            let hours: i64 = offset[1..3].parse().ok()?;
+            let minutes: i64 = offset[3..].parse().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+    let secs =
+        days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds - offset;
+    let secs = u64::try_from(secs).ok()?;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+/// Days since 1970-01-01, from Howard Hinnant's days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Re-emit every entry read from a gluster log through the log crate,
+/// until the end of input.  Unlike LogReader this logs each line as it
+/// arrives, so lines that don't start an entry, such as the rest of a
+/// multi-line message, are logged on their own like the entry before them.
+pub fn forward_log<R: BufRead>(mut reader: R) {
+    let mut last: Option<LogRecord> = None;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("reading the gfapi log failed: {}", e);
+                return;
+            }
+        }
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.is_empty() {
+            continue;
+        }
+        match LogEntry::parse_line(line) {
+            Some(entry) => {
+                entry.log();
+                last = Some(entry.record().clone());
+            }
+            None => {
+                let level = last.as_ref().map_or(log::Level::Info, LogRecord::log_level);
+                let target = last
+                    .as_ref()
+                    .map_or_else(|| target_of(""), |record| target_of(&record.xlator));
+                log!(target: &target, level, "{}", line);
+            }
+        }
+    }
+}
+
+impl Gluster {
+    /// Send gfapi's log messages at level and above to the log crate
+    /// instead of a file.  gfapi writes them to a FIFO which a background
+    /// thread reads until the connection is dropped.
+    pub fn forward_logging(&self, level: GlusterLogLevel) -> Result<(), GlusterError> {
+        let path = std::env::temp_dir().join(format!(
+            "gfapi-log-{}-{}",
+            std::process::id(),
+            NEXT_FIFO.fetch_add(1, Ordering::SeqCst)
+        ));
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        if unsafe { mkfifo(c_path.as_ptr(), 0o600) } < 0 {
+            return Err(GlusterError::new(get_error()));
+        }
+        let opened = OpenOptions::new()
+            .read(true)
+            .custom_flags(O_NONBLOCK)
+            .open(&path)
+            .and_then(|reader| {
+                // Hold the write side until gfapi has it open, or the
+                // reader would see the end of the log straight away
+                let writer = OpenOptions::new().write(true).open(&path)?;
+                Ok((reader, writer))
+            });
+        let result = opened
+            .map_err(GlusterError::from)
+            .and_then(|(reader, writer)| {
+                self.set_logging(&path, level)?;
+                drop(writer);
+                Ok(reader)
+            });
+        // gfapi keeps its own descriptor, the name isn't needed any more
+        let _ = fs::remove_file(&path);
+        let reader = result?;
+        unsafe {
+            let flags = fcntl(reader.as_raw_fd(), F_GETFL);
+            fcntl(reader.as_raw_fd(), F_SETFL, flags & !O_NONBLOCK);
+        }
+        thread::Builder::new()
+            .name("gfapi-log".to_string())
+            .spawn(move || forward_log(BufReader::new(reader)))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,1587 @@
+//! An in-memory filesystem
+//! MemFs implements the traits in fs without a cluster: inodes with hard
+//! links, symlinks, permission bits, extended attributes and byte range
+//! locks all live in memory, so code written against GlusterFs can be
+//! tested in milliseconds.  Failures set errno the same way gfapi does so
+//! callers that inspect it behave the same on both.
+use crate::credentials::Credentials;
+use crate::fs::{GlusterFileOps, GlusterFs};
+use crate::gluster::{get_error, DirEntry, DirEntryPlus, FileType, GlusterError, PosixLockCmd};
+use errno::{set_errno, Errno};
+use libc::{
+    dev_t, flock, gid_t, ino_t, mode_t, off_t, stat, statvfs, time_t, timespec, uid_t, EACCES,
+    EAGAIN, EBADF, EBUSY, EEXIST, EFBIG, EINVAL, EISDIR, ELOOP, ENAMETOOLONG, ENODATA, ENOENT,
+    ENOTDIR, ENOTEMPTY, ENXIO, EOPNOTSUPP, EOVERFLOW, EPERM, F_OK, O_ACCMODE, O_APPEND,
+    O_DIRECTORY, O_EXCL, O_NOFOLLOW, O_RDONLY, O_TRUNC, O_WRONLY, R_OK, SEEK_CUR, SEEK_DATA,
+    SEEK_END, SEEK_HOLE, SEEK_SET, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFREG,
+    S_IFSOCK, S_ISGID, S_ISUID, S_ISVTX, UTIME_NOW, UTIME_OMIT, W_OK, XATTR_CREATE, XATTR_REPLACE,
+    X_OK,
+};
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::mem::zeroed;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::vec;
+
+const ROOT_INO: ino_t = 1;
+/// Reported as st_dev for every inode
+const DEVICE: u64 = 0x6d656d;
+/// Linux gives up resolving after this many symlinks
+const MAX_SYMLINK_DEPTH: u32 = 40;
+const NAME_MAX: usize = 255;
+const BLOCK_SIZE: u64 = 4096;
+/// The capacity statvfs reports
+const TOTAL_BLOCKS: u64 = 1 << 20;
+const TOTAL_INODES: u64 = 1 << 20;
+/// The largest a file can grow until set_max_file_size says otherwise.
+/// File data is held in one buffer, so this is also how much memory a
+/// single write far into a file can take.
+const MAX_FILE_SIZE: u64 = 1 << 30;
+
+/// Internally failures are just the errno they will be reported with
+type MemResult<T> = Result<T, i32>;
+
+fn fail<T>(code: i32) -> Result<T, GlusterError> {
+    set_errno(Errno(code));
+    Err(GlusterError::new(get_error()))
+}
+
+fn check<T>(result: MemResult<T>) -> Result<T, GlusterError> {
+    result.or_else(fail)
+}
+
+fn now() -> timespec {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    timespec {
+        tv_sec: elapsed.as_secs() as time_t,
+        tv_nsec: elapsed.subsec_nanos() as _,
+    }
+}
+
+enum Node {
+    File(Vec<u8>),
+    Dir {
+        entries: BTreeMap<OsString, ino_t>,
+        parent: ino_t,
+    },
+    Symlink(PathBuf),
+    /// Devices, fifos and sockets made with mknod
+    Special(dev_t),
+}
+
+#[derive(Clone, Copy)]
+struct Lock {
+    owner: u64,
+    start: i64,
+    /// Exclusive, i64::MAX for locks that run to the end of the file
+    end: i64,
+    exclusive: bool,
+}
+
+struct Inode {
+    /// Includes the file type bits
+    mode: mode_t,
+    uid: uid_t,
+    gid: gid_t,
+    nlink: u64,
+    atime: timespec,
+    mtime: timespec,
+    ctime: timespec,
+    node: Node,
+    xattrs: BTreeMap<String, Vec<u8>>,
+    /// Open files keep an inode alive after its last link is removed
+    open_count: usize,
+    locks: Vec<Lock>,
+}
+
+impl Inode {
+    fn is_dir(&self) -> bool {
+        matches!(self.node, Node::Dir { .. })
+    }
+
+    fn size(&self) -> u64 {
+        match self.node {
+            Node::File(ref data) => data.len() as u64,
+            Node::Dir { .. } => BLOCK_SIZE,
+            Node::Symlink(ref target) => target.as_os_str().len() as u64,
+            Node::Special(_) => 0,
+        }
+    }
+
+    fn file_type(&self) -> FileType {
+        FileType::from_mode(self.mode)
+    }
+
+    fn data_mut(&mut self) -> MemResult<&mut Vec<u8>> {
+        match self.node {
+            Node::File(ref mut data) => Ok(data),
+            Node::Dir { .. } => Err(EISDIR),
+            _ => Err(EINVAL),
+        }
+    }
+
+    fn modified(&mut self) {
+        self.mtime = now();
+        self.ctime = self.mtime;
+    }
+}
+
+struct State {
+    inodes: HashMap<ino_t, Inode>,
+    next_ino: ino_t,
+    next_owner: u64,
+    cwd: ino_t,
+    creds: Credentials,
+    max_file_size: u64,
+}
+
+impl State {
+    fn inode(&self, ino: ino_t) -> MemResult<&Inode> {
+        self.inodes.get(&ino).ok_or(ENOENT)
+    }
+
+    fn inode_mut(&mut self, ino: ino_t) -> MemResult<&mut Inode> {
+        self.inodes.get_mut(&ino).ok_or(ENOENT)
+    }
+
+    /// The end of a range starting at offset that a file may grow to,
+    /// as a length for its data
+    fn file_end(&self, offset: i64, len: i64) -> MemResult<usize> {
+        match offset.checked_add(len) {
+            Some(end) if end as u64 <= self.max_file_size => Ok(end as usize),
+            _ => Err(EFBIG),
+        }
+    }
+
+    fn is_owner(&self, ino: ino_t) -> MemResult<bool> {
+        Ok(self.creds.uid == 0 || self.creds.uid == self.inode(ino)?.uid)
+    }
+
+    fn in_group(&self, gid: gid_t) -> bool {
+        self.creds.gid == gid || self.creds.groups.contains(&gid)
+    }
+
+    /// Check the read (4), write (2) and execute (1) bits in want against
+    /// the class of the mode the caller falls into
+    fn require(&self, ino: ino_t, want: mode_t) -> MemResult<()> {
+        let inode = self.inode(ino)?;
+        let allowed = if self.creds.uid == 0 {
+            // Root may do anything except execute a file nobody may execute
+            want & 0o1 == 0 || inode.is_dir() || inode.mode & 0o111 != 0
+        } else {
+            let bits = if self.creds.uid == inode.uid {
+                inode.mode >> 6
+            } else if self.in_group(inode.gid) {
+                inode.mode >> 3
+            } else {
+                inode.mode
+            };
+            bits & want == want
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(EACCES)
+        }
+    }
+
+    /// The directory must be searchable to look names up in it
+    fn search(&self, ino: ino_t) -> MemResult<()> {
+        if !self.inode(ino)?.is_dir() {
+            return Err(ENOTDIR);
+        }
+        self.require(ino, 0o1)
+    }
+
+    fn parent(&self, ino: ino_t) -> MemResult<ino_t> {
+        match self.inode(ino)?.node {
+            Node::Dir { parent, .. } => Ok(parent),
+            _ => Err(ENOTDIR),
+        }
+    }
+
+    fn child(&self, dir: ino_t, name: &OsStr) -> MemResult<ino_t> {
+        match self.inode(dir)?.node {
+            Node::Dir { ref entries, .. } => entries.get(name).copied().ok_or(ENOENT),
+            _ => Err(ENOTDIR),
+        }
+    }
+
+    fn lookup(&self, start: ino_t, path: &Path, follow: bool, depth: &mut u32) -> MemResult<ino_t> {
+        let mut current = start;
+        let mut components = path.components().peekable();
+        while let Some(component) = components.next() {
+            let last = components.peek().is_none();
+            match component {
+                Component::RootDir => current = ROOT_INO,
+                Component::CurDir | Component::Prefix(_) => {}
+                Component::ParentDir => {
+                    self.search(current)?;
+                    current = self.parent(current)?;
+                }
+                Component::Normal(name) => {
+                    self.search(current)?;
+                    let child = self.child(current, name)?;
+                    if let Node::Symlink(ref target) = self.inode(child)?.node {
+                        if !last || follow {
+                            *depth += 1;
+                            if *depth > MAX_SYMLINK_DEPTH {
+                                return Err(ELOOP);
+                            }
+                            if target.as_os_str().is_empty() {
+                                return Err(ENOENT);
+                            }
+                            // Relative targets start from the link's directory
+                            current = self.lookup(current, target, true, depth)?;
+                            continue;
+                        }
+                    }
+                    current = child;
+                }
+            }
+        }
+        Ok(current)
+    }
+
+    fn resolve(&self, path: &Path, follow: bool) -> MemResult<ino_t> {
+        if path.as_os_str().is_empty() {
+            return Err(ENOENT);
+        }
+        // A trailing slash means the path has to be a directory
+        let trailing = path.as_os_str().as_bytes().ends_with(b"/");
+        let ino = self.lookup(self.cwd, path, follow || trailing, &mut 0)?;
+        if trailing && !self.inode(ino)?.is_dir() {
+            return Err(ENOTDIR);
+        }
+        Ok(ino)
+    }
+
+    /// Find the directory a path's last component lives in.  Paths with no
+    /// last component to operate on, like / or .., fail with no_name.
+    fn resolve_parent(&self, path: &Path, no_name: i32) -> MemResult<(ino_t, OsString)> {
+        if path.as_os_str().is_empty() {
+            return Err(ENOENT);
+        }
+        let name = path.file_name().ok_or(no_name)?;
+        if name.len() > NAME_MAX {
+            return Err(ENAMETOOLONG);
+        }
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => {
+                self.lookup(self.cwd, parent, true, &mut 0)?
+            }
+            _ => self.cwd,
+        };
+        self.search(parent)?;
+        Ok((parent, name.to_os_string()))
+    }
+
+    /// Adding or removing names needs write and search permission
+    fn may_modify_dir(&self, dir: ino_t) -> MemResult<()> {
+        self.require(dir, 0o3)
+    }
+
+    /// In a sticky directory only the owner of an entry or of the directory
+    /// may remove or rename it
+    fn check_sticky(&self, dir: ino_t, ino: ino_t) -> MemResult<()> {
+        let dir_inode = self.inode(dir)?;
+        if dir_inode.mode & S_ISVTX != 0
+            && self.creds.uid != 0
+            && self.creds.uid != dir_inode.uid
+            && self.creds.uid != self.inode(ino)?.uid
+        {
+            return Err(EPERM);
+        }
+        Ok(())
+    }
+
+    fn alloc(&mut self, mode: mode_t, gid: gid_t, node: Node) -> ino_t {
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        let time = now();
+        let nlink = if let Node::Dir { .. } = node { 2 } else { 1 };
+        self.inodes.insert(
+            ino,
+            Inode {
+                mode,
+                uid: self.creds.uid,
+                gid,
+                nlink,
+                atime: time,
+                mtime: time,
+                ctime: time,
+                node,
+                xattrs: BTreeMap::new(),
+                open_count: 0,
+                locks: Vec::new(),
+            },
+        );
+        ino
+    }
+
+    fn add_entry(&mut self, dir: ino_t, name: OsString, ino: ino_t) -> MemResult<()> {
+        let is_dir = match self.inode_mut(ino)?.node {
+            Node::Dir { ref mut parent, .. } => {
+                *parent = dir;
+                true
+            }
+            _ => false,
+        };
+        let dir_inode = self.inode_mut(dir)?;
+        if let Node::Dir {
+            ref mut entries, ..
+        } = dir_inode.node
+        {
+            entries.insert(name, ino);
+        }
+        if is_dir {
+            dir_inode.nlink += 1;
+        }
+        dir_inode.modified();
+        Ok(())
+    }
+
+    fn remove_entry(&mut self, dir: ino_t, name: &OsStr) -> MemResult<ino_t> {
+        let dir_inode = self.inode_mut(dir)?;
+        let ino = match dir_inode.node {
+            Node::Dir {
+                ref mut entries, ..
+            } => entries.remove(name).ok_or(ENOENT)?,
+            _ => return Err(ENOTDIR),
+        };
+        dir_inode.modified();
+        if self.inode(ino)?.is_dir() {
+            self.inode_mut(dir)?.nlink -= 1;
+        }
+        Ok(ino)
+    }
+
+    /// Drop a link to an inode, freeing it once nothing refers to it
+    fn unlinked(&mut self, ino: ino_t) -> MemResult<()> {
+        let inode = self.inode_mut(ino)?;
+        inode.nlink = if inode.is_dir() { 0 } else { inode.nlink - 1 };
+        inode.ctime = now();
+        self.release(ino);
+        Ok(())
+    }
+
+    fn release(&mut self, ino: ino_t) {
+        if let Some(inode) = self.inodes.get(&ino) {
+            if inode.nlink == 0 && inode.open_count == 0 {
+                self.inodes.remove(&ino);
+            }
+        }
+    }
+
+    /// Create a new name for a fresh inode.  New entries take the group of
+    /// a setgid directory and new directories inherit the setgid bit.
+    fn create_node(&mut self, path: &Path, mode: mode_t, node: Node) -> MemResult<ino_t> {
+        let (parent, name) = self.resolve_parent(path, EEXIST)?;
+        self.may_modify_dir(parent)?;
+        if self.child(parent, &name).is_ok() {
+            return Err(EEXIST);
+        }
+        let parent_inode = self.inode(parent)?;
+        let (gid, mode) = if parent_inode.mode & S_ISGID != 0 {
+            let inherit = if mode & S_IFMT == S_IFDIR { S_ISGID } else { 0 };
+            (parent_inode.gid, mode | inherit)
+        } else {
+            (self.creds.gid, mode)
+        };
+        let ino = self.alloc(mode, gid, node);
+        self.add_entry(parent, name, ino)?;
+        Ok(ino)
+    }
+
+    fn open_inode(&mut self, ino: ino_t, flags: i32) -> MemResult<()> {
+        let inode = self.inode(ino)?;
+        if let Node::Symlink(_) = inode.node {
+            // Only reachable with O_NOFOLLOW
+            return Err(ELOOP);
+        }
+        let access = flags & O_ACCMODE;
+        if flags & O_DIRECTORY != 0 && !inode.is_dir() {
+            return Err(ENOTDIR);
+        }
+        if inode.is_dir() && access != O_RDONLY {
+            return Err(EISDIR);
+        }
+        self.require(
+            ino,
+            match access {
+                O_RDONLY => 0o4,
+                O_WRONLY => 0o2,
+                _ => 0o6,
+            },
+        )?;
+        let inode = self.inode_mut(ino)?;
+        if flags & O_TRUNC != 0 && access != O_RDONLY {
+            if let Node::File(ref mut data) = inode.node {
+                data.clear();
+                inode.modified();
+            }
+        }
+        inode.open_count += 1;
+        Ok(())
+    }
+
+    fn stat(&self, ino: ino_t) -> MemResult<stat> {
+        let inode = self.inode(ino)?;
+        let size = inode.size();
+        let mut stat_buf: stat = unsafe { zeroed() };
+        stat_buf.st_dev = DEVICE as _;
+        stat_buf.st_ino = ino;
+        stat_buf.st_mode = inode.mode;
+        stat_buf.st_nlink = inode.nlink as _;
+        stat_buf.st_uid = inode.uid;
+        stat_buf.st_gid = inode.gid;
+        if let Node::Special(dev) = inode.node {
+            stat_buf.st_rdev = dev;
+        }
+        stat_buf.st_size = size as off_t;
+        stat_buf.st_blksize = BLOCK_SIZE as _;
+        stat_buf.st_blocks = size.div_ceil(512) as _;
+        stat_buf.st_atime = inode.atime.tv_sec;
+        stat_buf.st_atime_nsec = inode.atime.tv_nsec as _;
+        stat_buf.st_mtime = inode.mtime.tv_sec;
+        stat_buf.st_mtime_nsec = inode.mtime.tv_nsec as _;
+        stat_buf.st_ctime = inode.ctime.tv_sec;
+        stat_buf.st_ctime_nsec = inode.ctime.tv_nsec as _;
+        Ok(stat_buf)
+    }
+
+    fn truncate(&mut self, ino: ino_t, length: i64) -> MemResult<()> {
+        if length < 0 {
+            return Err(EINVAL);
+        }
+        let length = self.file_end(length, 0)?;
+        let inode = self.inode_mut(ino)?;
+        inode.data_mut()?.resize(length, 0);
+        inode.modified();
+        Ok(())
+    }
+
+    fn read_at(&mut self, ino: ino_t, offset: i64, count: usize) -> MemResult<Vec<u8>> {
+        if offset < 0 {
+            return Err(EINVAL);
+        }
+        let data = self.inode_mut(ino)?.data_mut()?;
+        let start = (offset as usize).min(data.len());
+        let end = start.saturating_add(count).min(data.len());
+        Ok(data[start..end].to_vec())
+    }
+
+    fn write_at(&mut self, ino: ino_t, offset: i64, buffer: &[u8]) -> MemResult<usize> {
+        if offset < 0 || offset.checked_add(buffer.len() as i64).is_none() {
+            return Err(EINVAL);
+        }
+        let end = self.file_end(offset, buffer.len() as i64)?;
+        let inode = self.inode_mut(ino)?;
+        let data = inode.data_mut()?;
+        let start = offset as usize;
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(buffer);
+        inode.modified();
+        Ok(buffer.len())
+    }
+
+    /// Zero a range of a file, growing it to cover the range if grow is set
+    fn zero_range(&mut self, ino: ino_t, offset: i64, len: i64, grow: bool) -> MemResult<()> {
+        if offset < 0 || len <= 0 {
+            return Err(EINVAL);
+        }
+        let end = if grow {
+            self.file_end(offset, len)?
+        } else {
+            // Past the end there is nothing to zero
+            offset.checked_add(len).ok_or(EFBIG)? as usize
+        };
+        let inode = self.inode_mut(ino)?;
+        let data = inode.data_mut()?;
+        if grow && data.len() < end {
+            data.resize(end, 0);
+        }
+        let end = end.min(data.len());
+        let start = (offset as usize).min(end);
+        data[start..end].iter_mut().for_each(|b| *b = 0);
+        inode.modified();
+        Ok(())
+    }
+
+    fn chmod(&mut self, ino: ino_t, mode: mode_t) -> MemResult<()> {
+        if !self.is_owner(ino)? {
+            return Err(EPERM);
+        }
+        let mut mode = mode & 0o7777;
+        if self.creds.uid != 0 && !self.in_group(self.inode(ino)?.gid) {
+            mode &= !S_ISGID;
+        }
+        let inode = self.inode_mut(ino)?;
+        inode.mode = (inode.mode & S_IFMT) | mode;
+        inode.ctime = now();
+        Ok(())
+    }
+
+    /// uid or gid of u32::MAX leave that id unchanged, like passing -1 to
+    /// chown(2)
+    fn chown(&mut self, ino: ino_t, uid: u32, gid: u32) -> MemResult<()> {
+        let inode = self.inode(ino)?;
+        let uid = if uid == u32::MAX { inode.uid } else { uid };
+        let gid = if gid == u32::MAX { inode.gid } else { gid };
+        if self.creds.uid != 0
+            && (uid != inode.uid
+                || self.creds.uid != inode.uid
+                || (gid != inode.gid && !self.in_group(gid)))
+        {
+            return Err(EPERM);
+        }
+        let inode = self.inode_mut(ino)?;
+        inode.uid = uid;
+        inode.gid = gid;
+        if !inode.is_dir() {
+            inode.mode &= !(S_ISUID | S_ISGID);
+        }
+        inode.ctime = now();
+        Ok(())
+    }
+
+    fn utimens(&mut self, ino: ino_t, times: &[timespec; 2]) -> MemResult<()> {
+        let only_now = times
+            .iter()
+            .all(|t| t.tv_nsec == UTIME_NOW || t.tv_nsec == UTIME_OMIT);
+        if !self.is_owner(ino)? {
+            if !only_now {
+                return Err(EPERM);
+            }
+            self.require(ino, 0o2)?;
+        }
+        let time = now();
+        let pick = |t: &timespec| match t.tv_nsec {
+            UTIME_OMIT => None,
+            UTIME_NOW => Some(time),
+            _ => Some(*t),
+        };
+        let inode = self.inode_mut(ino)?;
+        if let Some(atime) = pick(&times[0]) {
+            inode.atime = atime;
+        }
+        if let Some(mtime) = pick(&times[1]) {
+            inode.mtime = mtime;
+        }
+        inode.ctime = time;
+        Ok(())
+    }
+
+    /// Check the caller may get or set an xattr in name's namespace
+    fn xattr_allowed(&self, ino: ino_t, name: &str, write: bool) -> MemResult<()> {
+        let denied = if write { EPERM } else { ENODATA };
+        if name.starts_with("user.") {
+            let inode = self.inode(ino)?;
+            if !matches!(inode.node, Node::File(_) | Node::Dir { .. }) {
+                return Err(denied);
+            }
+            self.require(ino, if write { 0o2 } else { 0o4 })
+        } else if name.starts_with("trusted.") {
+            if self.creds.uid == 0 {
+                Ok(())
+            } else {
+                Err(denied)
+            }
+        } else if name.starts_with("security.") || name.starts_with("system.") {
+            if write && !self.is_owner(ino)? {
+                return Err(EPERM);
+            }
+            Ok(())
+        } else {
+            Err(EOPNOTSUPP)
+        }
+    }
+
+    fn getxattr(&self, ino: ino_t, name: &str) -> MemResult<Vec<u8>> {
+        self.xattr_allowed(ino, name, false)?;
+        self.inode(ino)?.xattrs.get(name).cloned().ok_or(ENODATA)
+    }
+
+    /// Names followed by a NUL each, like listxattr(2)
+    fn listxattr(&self, ino: ino_t) -> MemResult<String> {
+        let mut list = String::new();
+        for name in self.inode(ino)?.xattrs.keys() {
+            if name.starts_with("trusted.") && self.creds.uid != 0 {
+                continue;
+            }
+            list.push_str(name);
+            list.push('\0');
+        }
+        Ok(list)
+    }
+
+    fn setxattr(&mut self, ino: ino_t, name: &str, value: &[u8], flags: i32) -> MemResult<()> {
+        self.xattr_allowed(ino, name, true)?;
+        let inode = self.inode_mut(ino)?;
+        let exists = inode.xattrs.contains_key(name);
+        if flags & XATTR_CREATE != 0 && exists {
+            return Err(EEXIST);
+        }
+        if flags & XATTR_REPLACE != 0 && !exists {
+            return Err(ENODATA);
+        }
+        inode.xattrs.insert(name.to_string(), value.to_vec());
+        inode.ctime = now();
+        Ok(())
+    }
+
+    fn removexattr(&mut self, ino: ino_t, name: &str) -> MemResult<()> {
+        self.xattr_allowed(ino, name, true)?;
+        let inode = self.inode_mut(ino)?;
+        inode.xattrs.remove(name).ok_or(ENODATA)?;
+        inode.ctime = now();
+        Ok(())
+    }
+
+    fn path_of(&self, ino: ino_t) -> MemResult<PathBuf> {
+        let mut names = Vec::new();
+        let mut current = ino;
+        while current != ROOT_INO {
+            let parent = self.parent(current)?;
+            let name = match self.inode(parent)?.node {
+                Node::Dir { ref entries, .. } => entries
+                    .iter()
+                    .find(|(_, child)| **child == current)
+                    .map(|(name, _)| name.clone())
+                    // The directory was removed
+                    .ok_or(ENOENT)?,
+                _ => return Err(ENOTDIR),
+            };
+            names.push(name);
+            current = parent;
+        }
+        let mut path = PathBuf::from("/");
+        path.extend(names.iter().rev());
+        Ok(path)
+    }
+
+    fn list_dir(&self, ino: ino_t) -> MemResult<Vec<(OsString, ino_t)>> {
+        self.require(ino, 0o4)?;
+        match self.inode(ino)?.node {
+            Node::Dir {
+                ref entries,
+                parent,
+            } => {
+                let mut list = vec![(OsString::from("."), ino), (OsString::from(".."), parent)];
+                list.extend(entries.iter().map(|(name, child)| (name.clone(), *child)));
+                Ok(list)
+            }
+            _ => Err(ENOTDIR),
+        }
+    }
+}
+
+/// An in-memory filesystem.  Cloning it is cheap and the clones share the
+/// same tree.  Operations are performed as root until set_credentials
+/// says otherwise.
+#[derive(Clone)]
+pub struct MemFs {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for MemFs {
+    fn default() -> MemFs {
+        MemFs::new()
+    }
+}
+
+impl MemFs {
+    /// An empty filesystem with a root directory owned by root
+    pub fn new() -> MemFs {
+        let mut state = State {
+            inodes: HashMap::new(),
+            next_ino: ROOT_INO,
+            next_owner: 0,
+            cwd: ROOT_INO,
+            creds: Credentials::new(0, 0, &[]),
+            max_file_size: MAX_FILE_SIZE,
+        };
+        state.alloc(
+            S_IFDIR | 0o755,
+            0,
+            Node::Dir {
+                entries: BTreeMap::new(),
+                parent: ROOT_INO,
+            },
+        );
+        MemFs {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Perform every following operation as creds.  Unlike
+    /// Gluster::impersonate this applies to the whole filesystem rather
+    /// than the calling thread.
+    pub fn set_credentials(&self, creds: Credentials) {
+        self.lock().creds = creds;
+    }
+
+    pub fn credentials(&self) -> Credentials {
+        self.lock().creds.clone()
+    }
+
+    /// Fail writes, truncates and allocations that would grow a file past
+    /// size bytes with EFBIG, as a filesystem does past its limit
+    pub fn set_max_file_size(&self, size: u64) {
+        self.lock().max_file_size = size;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // Every operation leaves the state consistent before it can panic
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn with_state<F, T>(&self, f: F) -> Result<T, GlusterError>
+    where
+        F: FnOnce(&mut State) -> MemResult<T>,
+    {
+        check(f(&mut self.lock()))
+    }
+
+    fn new_file(&self, state: &mut State, ino: ino_t, flags: i32) -> MemFile {
+        let owner = state.next_owner;
+        state.next_owner += 1;
+        MemFile {
+            fs: self.clone(),
+            ino,
+            flags,
+            owner,
+            offset: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    fn list<F, T>(&self, path: &Path, f: F) -> Result<MemDir<T>, GlusterError>
+    where
+        F: Fn(&State, OsString, ino_t) -> MemResult<T>,
+    {
+        self.with_state(|state| {
+            let ino = state.resolve(path, true)?;
+            let entries = state
+                .list_dir(ino)?
+                .into_iter()
+                .map(|(name, child)| f(state, name, child))
+                .collect::<MemResult<Vec<T>>>()?;
+            Ok(MemDir {
+                entries: entries.into_iter(),
+            })
+        })
+    }
+}
+
+/// A snapshot of a directory's entries taken when it was opened
+pub struct MemDir<T> {
+    entries: vec::IntoIter<T>,
+}
+
+impl<T> Iterator for MemDir<T> {
+    type Item = Result<T, GlusterError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(Ok)
+    }
+}
+
+impl GlusterFs for MemFs {
+    type File = MemFile;
+    type Dir = MemDir<DirEntry>;
+    type DirPlus = MemDir<DirEntryPlus>;
+
+    fn open(&self, path: &Path, flags: i32) -> Result<MemFile, GlusterError> {
+        let mut state = self.lock();
+        let ino = check(state.resolve(path, flags & O_NOFOLLOW == 0))?;
+        check(state.open_inode(ino, flags))?;
+        Ok(self.new_file(&mut state, ino, flags))
+    }
+
+    fn create(&self, path: &Path, flags: i32, mode: mode_t) -> Result<MemFile, GlusterError> {
+        let mut state = self.lock();
+        let (parent, name) = check(state.resolve_parent(path, EISDIR))?;
+        let ino = match state.child(parent, &name) {
+            Ok(_) if flags & O_EXCL != 0 => return fail(EEXIST),
+            Ok(_) => {
+                let ino = check(state.resolve(path, flags & O_NOFOLLOW == 0))?;
+                check(state.open_inode(ino, flags))?;
+                ino
+            }
+            Err(ENOENT) => {
+                // The creator gets the access it asked for whatever the mode
+                let ino = check(state.create_node(
+                    path,
+                    S_IFREG | (mode & 0o7777),
+                    Node::File(Vec::new()),
+                ))?;
+                check(state.inode_mut(ino))?.open_count += 1;
+                ino
+            }
+            Err(e) => return fail(e),
+        };
+        Ok(self.new_file(&mut state, ino, flags))
+    }
+
+    fn truncate(&self, path: &Path, length: i64) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, true)?;
+            state.require(ino, 0o2)?;
+            state.truncate(ino, length)
+        })
+    }
+
+    fn lsstat(&self, path: &Path) -> Result<stat, GlusterError> {
+        self.with_state(|state| state.stat(state.resolve(path, false)?))
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, GlusterError> {
+        match self.lock().resolve(path, true) {
+            Ok(_) => Ok(true),
+            Err(ENOENT) => Ok(false),
+            Err(e) => fail(e),
+        }
+    }
+
+    fn statvfs(&self, path: &Path) -> Result<statvfs, GlusterError> {
+        self.with_state(|state| {
+            state.resolve(path, true)?;
+            let used: u64 = state
+                .inodes
+                .values()
+                .map(|inode| inode.size().div_ceil(BLOCK_SIZE))
+                .sum();
+            let mut stat_buf: statvfs = unsafe { zeroed() };
+            stat_buf.f_bsize = BLOCK_SIZE as _;
+            stat_buf.f_frsize = BLOCK_SIZE as _;
+            stat_buf.f_blocks = TOTAL_BLOCKS as _;
+            stat_buf.f_bfree = TOTAL_BLOCKS.saturating_sub(used) as _;
+            stat_buf.f_bavail = stat_buf.f_bfree;
+            stat_buf.f_files = TOTAL_INODES as _;
+            stat_buf.f_ffree = TOTAL_INODES.saturating_sub(state.inodes.len() as u64) as _;
+            stat_buf.f_favail = stat_buf.f_ffree;
+            stat_buf.f_fsid = DEVICE as _;
+            stat_buf.f_namemax = NAME_MAX as _;
+            Ok(stat_buf)
+        })
+    }
+
+    fn stat(&self, path: &Path) -> Result<stat, GlusterError> {
+        self.with_state(|state| state.stat(state.resolve(path, true)?))
+    }
+
+    fn access(&self, path: &Path, mode: i32) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            if mode & !(R_OK | W_OK | X_OK) != 0 {
+                return Err(EINVAL);
+            }
+            let ino = state.resolve(path, true)?;
+            if mode == F_OK {
+                return Ok(());
+            }
+            state.require(ino, mode as mode_t)
+        })
+    }
+
+    fn symlink(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            state.create_node(
+                newpath,
+                S_IFLNK | 0o777,
+                Node::Symlink(oldpath.to_path_buf()),
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Copies as much of the target as fits into buf.  Like readlink(2)
+    /// no NUL terminator is added.
+    fn readlink(&self, path: &Path, buf: &mut [u8]) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, false)?;
+            match state.inode(ino)?.node {
+                Node::Symlink(ref target) => {
+                    let target = target.as_os_str().as_bytes();
+                    let len = target.len().min(buf.len());
+                    buf[..len].copy_from_slice(&target[..len]);
+                    Ok(())
+                }
+                _ => Err(EINVAL),
+            }
+        })
+    }
+
+    fn mknod(&self, path: &Path, mode: mode_t, dev: dev_t) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let perms = mode & 0o7777;
+            let (mode, node) = match mode & S_IFMT {
+                0 | S_IFREG => (S_IFREG | perms, Node::File(Vec::new())),
+                kind @ (S_IFCHR | S_IFBLK) => {
+                    if state.creds.uid != 0 {
+                        return Err(EPERM);
+                    }
+                    (kind | perms, Node::Special(dev))
+                }
+                kind @ (S_IFIFO | S_IFSOCK) => (kind | perms, Node::Special(0)),
+                S_IFDIR => return Err(EPERM),
+                _ => return Err(EINVAL),
+            };
+            state.create_node(path, mode, node)?;
+            Ok(())
+        })
+    }
+
+    fn mkdir(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            state.create_node(
+                path,
+                S_IFDIR | (mode & 0o7777),
+                Node::Dir {
+                    entries: BTreeMap::new(),
+                    // Set when the entry is added
+                    parent: ROOT_INO,
+                },
+            )?;
+            Ok(())
+        })
+    }
+
+    fn unlink(&self, path: &Path) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let (parent, name) = state.resolve_parent(path, EISDIR)?;
+            let ino = state.child(parent, &name)?;
+            if state.inode(ino)?.is_dir() {
+                return Err(EISDIR);
+            }
+            state.may_modify_dir(parent)?;
+            state.check_sticky(parent, ino)?;
+            state.remove_entry(parent, &name)?;
+            state.unlinked(ino)
+        })
+    }
+
+    fn rmdir(&self, path: &Path) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            // Path ignores a trailing ".", which would have dir/. remove dir
+            let bytes = path.as_os_str().as_bytes();
+            let last = bytes.rsplit(|b| *b == b'/').find(|c| !c.is_empty());
+            if last == Some(&b"."[..]) {
+                return Err(EINVAL);
+            }
+            let (parent, name) = state.resolve_parent(path, EBUSY)?;
+            let ino = state.child(parent, &name)?;
+            match state.inode(ino)?.node {
+                Node::Dir { ref entries, .. } if !entries.is_empty() => return Err(ENOTEMPTY),
+                Node::Dir { .. } => {}
+                _ => return Err(ENOTDIR),
+            }
+            state.may_modify_dir(parent)?;
+            state.check_sticky(parent, ino)?;
+            state.remove_entry(parent, &name)?;
+            state.unlinked(ino)
+        })
+    }
+
+    /// Removes path and everything beneath it
+    fn remove_dir_all(&self, path: &Path) -> Result<(), GlusterError> {
+        for entry in self.opendir(path)? {
+            let entry = entry?;
+            if entry.path == Path::new(".") || entry.path == Path::new("..") {
+                continue;
+            }
+            let child = path.join(&entry.path);
+            if entry.file_type.is_dir() {
+                self.remove_dir_all(&child)?;
+            } else {
+                self.unlink(&child)?;
+            }
+        }
+        self.rmdir(path)
+    }
+
+    fn rename(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let (old_parent, old_name) = state.resolve_parent(oldpath, EBUSY)?;
+            let (new_parent, new_name) = state.resolve_parent(newpath, EBUSY)?;
+            let ino = state.child(old_parent, &old_name)?;
+            state.may_modify_dir(old_parent)?;
+            state.may_modify_dir(new_parent)?;
+            state.check_sticky(old_parent, ino)?;
+            let is_dir = state.inode(ino)?.is_dir();
+            if is_dir {
+                // A directory can't be moved beneath itself
+                let mut current = new_parent;
+                loop {
+                    if current == ino {
+                        return Err(EINVAL);
+                    }
+                    if current == ROOT_INO {
+                        break;
+                    }
+                    current = state.parent(current)?;
+                }
+            }
+            match state.child(new_parent, &new_name) {
+                // Renaming a file over one of its own links does nothing
+                Ok(target) if target == ino => return Ok(()),
+                Ok(target) => {
+                    state.check_sticky(new_parent, target)?;
+                    match state.inode(target)?.node {
+                        Node::Dir { ref entries, .. } => {
+                            if !is_dir {
+                                return Err(EISDIR);
+                            }
+                            if !entries.is_empty() {
+                                return Err(ENOTEMPTY);
+                            }
+                        }
+                        _ if is_dir => return Err(ENOTDIR),
+                        _ => {}
+                    }
+                    state.remove_entry(new_parent, &new_name)?;
+                    state.unlinked(target)?;
+                }
+                Err(ENOENT) => {}
+                Err(e) => return Err(e),
+            }
+            state.remove_entry(old_parent, &old_name)?;
+            state.add_entry(new_parent, new_name, ino)?;
+            state.inode_mut(ino)?.ctime = now();
+            Ok(())
+        })
+    }
+
+    fn link(&self, oldpath: &Path, newpath: &Path) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(oldpath, false)?;
+            if state.inode(ino)?.is_dir() {
+                return Err(EPERM);
+            }
+            let (parent, name) = state.resolve_parent(newpath, EEXIST)?;
+            state.may_modify_dir(parent)?;
+            if state.child(parent, &name).is_ok() {
+                return Err(EEXIST);
+            }
+            state.add_entry(parent, name, ino)?;
+            let inode = state.inode_mut(ino)?;
+            inode.nlink += 1;
+            inode.ctime = now();
+            Ok(())
+        })
+    }
+
+    fn opendir(&self, path: &Path) -> Result<MemDir<DirEntry>, GlusterError> {
+        self.list(path, |state, name, ino| {
+            Ok(DirEntry {
+                path: PathBuf::from(name),
+                inode: ino,
+                file_type: state.inode(ino)?.file_type(),
+            })
+        })
+    }
+
+    fn opendir_plus(&self, path: &Path) -> Result<MemDir<DirEntryPlus>, GlusterError> {
+        self.list(path, |state, name, ino| {
+            Ok(DirEntryPlus {
+                path: PathBuf::from(name),
+                inode: ino,
+                file_type: state.inode(ino)?.file_type(),
+                stat: state.stat(ino)?,
+            })
+        })
+    }
+
+    fn getxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
+        let value = self.getxattr_bytes(path, name)?;
+        Ok(String::from_utf8_lossy(&value).into_owned())
+    }
+
+    fn getxattr_bytes(&self, path: &Path, name: &str) -> Result<Vec<u8>, GlusterError> {
+        self.with_state(|state| state.getxattr(state.resolve(path, true)?, name))
+    }
+
+    fn lgetxattr(&self, path: &Path, name: &str) -> Result<String, GlusterError> {
+        let value = self.with_state(|state| state.getxattr(state.resolve(path, false)?, name))?;
+        Ok(String::from_utf8_lossy(&value).into_owned())
+    }
+
+    fn listxattr(&self, path: &Path) -> Result<String, GlusterError> {
+        self.with_state(|state| state.listxattr(state.resolve(path, true)?))
+    }
+
+    fn llistxattr(&self, path: &Path) -> Result<String, GlusterError> {
+        self.with_state(|state| state.listxattr(state.resolve(path, false)?))
+    }
+
+    fn setxattr(
+        &self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+    ) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, true)?;
+            state.setxattr(ino, name, value, flags)
+        })
+    }
+
+    fn lsetxattr(
+        &self,
+        name: &str,
+        value: &[u8],
+        path: &Path,
+        flags: i32,
+    ) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, false)?;
+            state.setxattr(ino, name, value, flags)
+        })
+    }
+
+    fn removexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, true)?;
+            state.removexattr(ino, name)
+        })
+    }
+
+    fn lremovexattr(&self, path: &Path, name: &str) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, false)?;
+            state.removexattr(ino, name)
+        })
+    }
+
+    fn getcwd(&self) -> Result<String, GlusterError> {
+        self.with_state(|state| {
+            let path = state.path_of(state.cwd)?;
+            Ok(path.to_string_lossy().into_owned())
+        })
+    }
+
+    fn chdir(&self, path: &Path) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, true)?;
+            state.search(ino)?;
+            state.cwd = ino;
+            Ok(())
+        })
+    }
+
+    fn utimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, true)?;
+            state.utimens(ino, times)
+        })
+    }
+
+    fn lutimens(&self, path: &Path, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, false)?;
+            state.utimens(ino, times)
+        })
+    }
+
+    fn chmod(&self, path: &Path, mode: mode_t) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, true)?;
+            state.chmod(ino, mode)
+        })
+    }
+
+    fn chown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, true)?;
+            state.chown(ino, uid, gid)
+        })
+    }
+
+    fn lchown(&self, path: &Path, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        self.with_state(|state| {
+            let ino = state.resolve(path, false)?;
+            state.chown(ino, uid, gid)
+        })
+    }
+}
+
+/// An open file on a MemFs.  The inode stays readable after its last link
+/// is removed until every file open on it is dropped.
+pub struct MemFile {
+    fs: MemFs,
+    ino: ino_t,
+    flags: i32,
+    /// Locks are owned by this file and its dups.  Like POSIX record locks
+    /// they are all released when any one of them is dropped.
+    owner: u64,
+    /// Shared with dups like the file description of a POSIX fd
+    offset: Arc<Mutex<i64>>,
+}
+
+impl Drop for MemFile {
+    fn drop(&mut self) {
+        let mut state = self.fs.lock();
+        if let Some(inode) = state.inodes.get_mut(&self.ino) {
+            inode.open_count -= 1;
+            let owner = self.owner;
+            inode.locks.retain(|lock| lock.owner != owner);
+        }
+        state.release(self.ino);
+    }
+}
+
+impl MemFile {
+    fn with_state<F, T>(&self, f: F) -> Result<T, GlusterError>
+    where
+        F: FnOnce(&mut State, ino_t) -> MemResult<T>,
+    {
+        check(f(&mut self.fs.lock(), self.ino))
+    }
+
+    fn readable(&self) -> MemResult<()> {
+        if self.flags & O_ACCMODE == O_WRONLY {
+            return Err(EBADF);
+        }
+        Ok(())
+    }
+
+    fn writable(&self) -> MemResult<()> {
+        if self.flags & O_ACCMODE == O_RDONLY {
+            return Err(EBADF);
+        }
+        Ok(())
+    }
+
+    fn offset(&self) -> MutexGuard<'_, i64> {
+        self.offset.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Read at the file offset, advancing it unless offset is given
+    fn read_from(&self, offset: Option<i64>, count: usize) -> Result<Vec<u8>, GlusterError> {
+        let mut current = self.offset();
+        let data = self.with_state(|state, ino| {
+            self.readable()?;
+            state.read_at(ino, offset.unwrap_or(*current), count)
+        })?;
+        if offset.is_none() {
+            *current += data.len() as i64;
+        }
+        Ok(data)
+    }
+
+    /// Write at the file offset, or the end with O_APPEND, advancing it
+    /// unless offset is given
+    fn write_to(&self, offset: Option<i64>, buffer: &[u8]) -> Result<isize, GlusterError> {
+        let mut current = self.offset();
+        let append = self.flags & O_APPEND != 0;
+        let end = self.with_state(|state, ino| {
+            self.writable()?;
+            let start = match offset {
+                _ if append => state.inode(ino)?.size() as i64,
+                Some(offset) => offset,
+                None => *current,
+            };
+            Ok(start + state.write_at(ino, start, buffer)? as i64)
+        })?;
+        if offset.is_none() {
+            *current = end;
+        }
+        Ok(buffer.len() as isize)
+    }
+
+    fn scatter(data: &[u8], iov: &mut [&mut [u8]]) -> isize {
+        let mut rest = data;
+        for buf in iov.iter_mut() {
+            let len = buf.len().min(rest.len());
+            buf[..len].copy_from_slice(&rest[..len]);
+            rest = &rest[len..];
+        }
+        data.len() as isize
+    }
+
+    /// Turn the whence, start and length of a flock into a byte range.
+    /// current is the file offset, which has to be read before the state
+    /// is locked.
+    fn lock_range(&self, state: &State, flock: &flock, current: i64) -> MemResult<(i64, i64)> {
+        let base = match flock.l_whence as i32 {
+            SEEK_SET => 0,
+            SEEK_CUR => current,
+            SEEK_END => state.inode(self.ino)?.size() as i64,
+            _ => return Err(EINVAL),
+        };
+        let start = base.checked_add(flock.l_start).ok_or(EOVERFLOW)?;
+        let (start, end) = match flock.l_len {
+            0 => (start, i64::MAX),
+            len if len > 0 => (start, start.checked_add(len).ok_or(EOVERFLOW)?),
+            len => (start.checked_add(len).ok_or(EINVAL)?, start),
+        };
+        if start < 0 {
+            return Err(EINVAL);
+        }
+        Ok((start, end))
+    }
+}
+
+/// Remove owner's locks over start..end, splitting any that only
+/// partially overlap it
+fn unlock_range(locks: &mut Vec<Lock>, owner: u64, start: i64, end: i64) {
+    let mut kept = Vec::with_capacity(locks.len());
+    for lock in locks.drain(..) {
+        if lock.owner != owner || lock.end <= start || lock.start >= end {
+            kept.push(lock);
+            continue;
+        }
+        if lock.start < start {
+            kept.push(Lock { end: start, ..lock });
+        }
+        if lock.end > end {
+            kept.push(Lock { start: end, ..lock });
+        }
+    }
+    *locks = kept;
+}
+
+impl GlusterFileOps for MemFile {
+    fn read(
+        &self,
+        fill_buffer: &mut Vec<u8>,
+        count: usize,
+        _flags: i32,
+    ) -> Result<isize, GlusterError> {
+        let data = self.read_from(None, count)?;
+        fill_buffer.clear();
+        fill_buffer.extend_from_slice(&data);
+        Ok(data.len() as isize)
+    }
+
+    fn write(&self, buffer: &[u8], _flags: i32) -> Result<isize, GlusterError> {
+        self.write_to(None, buffer)
+    }
+
+    fn readv(&self, iov: &mut [&mut [u8]], _flags: i32) -> Result<isize, GlusterError> {
+        let count = iov.iter().map(|buf| buf.len()).sum();
+        let data = self.read_from(None, count)?;
+        Ok(MemFile::scatter(&data, iov))
+    }
+
+    fn writev(&self, iov: &[&[u8]], _flags: i32) -> Result<isize, GlusterError> {
+        self.write_to(None, &iov.concat())
+    }
+
+    fn pread(
+        &self,
+        fill_buffer: &mut Vec<u8>,
+        count: usize,
+        offset: i64,
+        _flags: i32,
+    ) -> Result<isize, GlusterError> {
+        let data = self.read_from(Some(offset), count)?;
+        fill_buffer.clear();
+        fill_buffer.extend_from_slice(&data);
+        Ok(data.len() as isize)
+    }
+
+    fn pwrite(
+        &self,
+        buffer: &[u8],
+        count: usize,
+        offset: i64,
+        _flags: i32,
+    ) -> Result<isize, GlusterError> {
+        self.write_to(Some(offset), &buffer[..count.min(buffer.len())])
+    }
+
+    fn preadv(
+        &self,
+        iov: &mut [&mut [u8]],
+        offset: i64,
+        _flags: i32,
+    ) -> Result<isize, GlusterError> {
+        let count = iov.iter().map(|buf| buf.len()).sum();
+        let data = self.read_from(Some(offset), count)?;
+        Ok(MemFile::scatter(&data, iov))
+    }
+
+    fn pwritev(&self, iov: &[&[u8]], offset: i64, _flags: i32) -> Result<isize, GlusterError> {
+        self.write_to(Some(offset), &iov.concat())
+    }
+
+    /// SEEK_DATA and SEEK_HOLE treat the whole file as data
+    fn lseek(&self, offset: i64, whence: i32) -> Result<i64, GlusterError> {
+        let mut current = self.offset();
+        let size = self.with_state(|state, ino| Ok(state.inode(ino)?.size() as i64))?;
+        let position = match whence {
+            SEEK_SET => offset,
+            SEEK_CUR => match current.checked_add(offset) {
+                Some(position) => position,
+                None => return fail(EOVERFLOW),
+            },
+            SEEK_END => match size.checked_add(offset) {
+                Some(position) => position,
+                None => return fail(EOVERFLOW),
+            },
+            SEEK_DATA | SEEK_HOLE if offset < 0 || offset >= size => return fail(ENXIO),
+            SEEK_DATA => offset,
+            SEEK_HOLE => size,
+            _ => return fail(EINVAL),
+        };
+        if position < 0 {
+            return fail(EINVAL);
+        }
+        *current = position;
+        Ok(position)
+    }
+
+    fn ftruncate(&self, length: i64) -> Result<(), GlusterError> {
+        self.with_state(|state, ino| {
+            self.writable()?;
+            state.truncate(ino, length)
+        })
+    }
+
+    fn fstat(&self) -> Result<stat, GlusterError> {
+        self.with_state(|state, ino| state.stat(ino))
+    }
+
+    fn fsync(&self) -> Result<(), GlusterError> {
+        Ok(())
+    }
+
+    fn fdatasync(&self) -> Result<(), GlusterError> {
+        Ok(())
+    }
+
+    fn fgetxattr(&self, name: &str) -> Result<String, GlusterError> {
+        let value = self.with_state(|state, ino| state.getxattr(ino, name))?;
+        Ok(String::from_utf8_lossy(&value).into_owned())
+    }
+
+    fn flistxattr(&self) -> Result<String, GlusterError> {
+        self.with_state(|state, ino| state.listxattr(ino))
+    }
+
+    fn fsetxattr(&self, name: &str, value: &[u8], flags: i32) -> Result<(), GlusterError> {
+        self.with_state(|state, ino| state.setxattr(ino, name, value, flags))
+    }
+
+    fn fremovexattr(&self, name: &str) -> Result<(), GlusterError> {
+        self.with_state(|state, ino| state.removexattr(ino, name))
+    }
+
+    fn fallocate(&self, offset: i64, keep_size: i32, len: usize) -> Result<(), GlusterError> {
+        self.with_state(|state, ino| {
+            self.writable()?;
+            if offset < 0 || len == 0 || len > i64::MAX as usize {
+                return Err(EINVAL);
+            }
+            // Allocating never changes existing data, only the size
+            let end = state.file_end(offset, len as i64)?;
+            let inode = state.inode_mut(ino)?;
+            let data = inode.data_mut()?;
+            if keep_size == 0 && data.len() < end {
+                data.resize(end, 0);
+                inode.modified();
+            }
+            Ok(())
+        })
+    }
+
+    fn discard(&self, offset: i64, len: usize) -> Result<(), GlusterError> {
+        self.with_state(|state, ino| {
+            self.writable()?;
+            state.zero_range(ino, offset, len as i64, false)
+        })
+    }
+
+    fn zerofill(&self, offset: i64, len: i64) -> Result<(), GlusterError> {
+        self.with_state(|state, ino| {
+            self.writable()?;
+            state.zero_range(ino, offset, len, true)
+        })
+    }
+
+    fn fchdir(&self) -> Result<(), GlusterError> {
+        self.with_state(|state, ino| {
+            state.search(ino)?;
+            state.cwd = ino;
+            Ok(())
+        })
+    }
+
+    fn futimens(&self, times: &[timespec; 2]) -> Result<(), GlusterError> {
+        self.with_state(|state, ino| state.utimens(ino, times))
+    }
+
+    /// Shared and exclusive locks over the range flock describes.  A lock
+    /// that conflicts with one held through another file fails with EAGAIN
+    /// rather than waiting.
+    fn posixlock(&self, command: PosixLockCmd, flock: &mut flock) -> Result<(), GlusterError> {
+        let current = *self.offset();
+        self.with_state(|state, ino| {
+            let (start, end) = self.lock_range(state, flock, current)?;
+            let exclusive = match command {
+                PosixLockCmd::Unlock => {
+                    unlock_range(&mut state.inode_mut(ino)?.locks, self.owner, start, end);
+                    return Ok(());
+                }
+                PosixLockCmd::Shared => {
+                    self.readable()?;
+                    false
+                }
+                PosixLockCmd::Exclusive => {
+                    self.writable()?;
+                    true
+                }
+            };
+            let inode = state.inode_mut(ino)?;
+            let conflict = inode.locks.iter().any(|lock| {
+                lock.owner != self.owner
+                    && lock.start < end
+                    && start < lock.end
+                    && (exclusive || lock.exclusive)
+            });
+            if conflict {
+                return Err(EAGAIN);
+            }
+            // A new lock replaces whatever this owner held over the range
+            unlock_range(&mut inode.locks, self.owner, start, end);
+            inode.locks.push(Lock {
+                owner: self.owner,
+                start,
+                end,
+                exclusive,
+            });
+            Ok(())
+        })
+    }
+
+    fn fchmod(&self, mode: mode_t) -> Result<(), GlusterError> {
+        self.with_state(|state, ino| state.chmod(ino, mode))
+    }
+
+    fn fchown(&self, uid: u32, gid: u32) -> Result<(), GlusterError> {
+        self.with_state(|state, ino| state.chown(ino, uid, gid))
+    }
+
+    /// The new file shares its offset with this one, like dup(2)
+    fn dup(&self) -> Result<MemFile, GlusterError> {
+        self.with_state(|state, ino| {
+            state.inode_mut(ino)?.open_count += 1;
+            Ok(())
+        })?;
+        Ok(MemFile {
+            fs: self.fs.clone(),
+            ino: self.ino,
+            flags: self.flags,
+            owner: self.owner,
+            offset: self.offset.clone(),
+        })
+    }
+}
@@ -0,0 +1,248 @@
+//! Counts, errors and latencies of the glfs calls made by this crate
+//! With the metrics feature every call through Gluster, GlusterFile, the
+//! directory iterators and gfid handles is recorded here under the name of
+//! the glfs function.  metrics() takes a snapshot which can be inspected
+//! directly or rendered for Prometheus.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the latency buckets, in microseconds
+pub const LATENCY_BUCKETS_US: [u64; 16] = [
+    50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 10_000_000,
+];
+
+static REGISTRY: Mutex<BTreeMap<&'static str, Operation>> = Mutex::new(BTreeMap::new());
+
+/// Which way a call moves data, so its result can be counted as bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transfer {
+    Read,
+    Write,
+    /// Copied by the bricks with copy_file_range, never reaching the client
+    Copy,
+}
+
+/// Latencies counted into LATENCY_BUCKETS_US, with a final bucket for
+/// anything slower
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    /// One more than LATENCY_BUCKETS_US, not cumulative
+    pub counts: Vec<u64>,
+    pub sum: Duration,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        if self.counts.is_empty() {
+            self.counts = vec![0; LATENCY_BUCKETS_US.len() + 1];
+        }
+        let micros = elapsed.as_micros();
+        let bucket = LATENCY_BUCKETS_US
+            .iter()
+            .position(|&bound| micros <= u128::from(bound))
+            .unwrap_or(LATENCY_BUCKETS_US.len());
+        self.counts[bucket] += 1;
+        self.sum += elapsed;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count() {
+            0 => None,
+            count => Some(Duration::from_nanos(
+                (self.sum.as_nanos() / u128::from(count)) as u64,
+            )),
+        }
+    }
+
+    /// The bucket bound at or below which the q quantile falls, None if
+    /// nothing was recorded or it's past the last bound
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let wanted = (q * self.count() as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= wanted {
+                return LATENCY_BUCKETS_US
+                    .get(bucket)
+                    .map(|&bound| Duration::from_micros(bound));
+            }
+        }
+        None
+    }
+}
+
+/// What was recorded for one glfs function
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Operation {
+    pub calls: u64,
+    /// Failed calls by errno
+    pub errors: BTreeMap<i32, u64>,
+    pub latency: Histogram,
+    /// Bytes read, written or copied by successful calls that move data
+    pub bytes: u64,
+}
+
+impl Operation {
+    pub fn error_count(&self) -> u64 {
+        self.errors.values().sum()
+    }
+}
+
+/// A snapshot of everything recorded so far
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Keyed by glfs function name, for example glfs_pread
+    pub operations: BTreeMap<&'static str, Operation>,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Copied server side, counted apart since the client never sees them
+    pub bytes_copied: u64,
+}
+
+impl Metrics {
+    pub fn operation(&self, name: &str) -> Option<&Operation> {
+        self.operations.get(name)
+    }
+
+    /// Render in the Prometheus text exposition format
+    pub fn prometheus(&self) -> String {
+        let mut out = String::new();
+        // Writing to a String can't fail
+        let _ = self.write_prometheus(&mut out);
+        out
+    }
+
+    fn write_prometheus(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "# HELP gluster_calls_total glfs calls made.")?;
+        writeln!(out, "# TYPE gluster_calls_total counter")?;
+        for (name, op) in &self.operations {
+            writeln!(out, "gluster_calls_total{{op=\"{}\"}} {}", name, op.calls)?;
+        }
+        writeln!(
+            out,
+            "# HELP gluster_errors_total glfs calls that failed, by errno."
+        )?;
+        writeln!(out, "# TYPE gluster_errors_total counter")?;
+        for (name, op) in &self.operations {
+            for (code, count) in &op.errors {
+                writeln!(
+                    out,
+                    "gluster_errors_total{{op=\"{}\",errno=\"{}\"}} {}",
+                    name, code, count
+                )?;
+            }
+        }
+        writeln!(
+            out,
+            "# HELP gluster_call_duration_seconds Time spent in glfs calls."
+        )?;
+        writeln!(out, "# TYPE gluster_call_duration_seconds histogram")?;
+        for (name, op) in &self.operations {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS_US.iter().zip(&op.latency.counts) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "gluster_call_duration_seconds_bucket{{op=\"{}\",le=\"{}\"}} {}",
+                    name,
+                    *bound as f64 / 1e6,
+                    cumulative
+                )?;
+            }
+            writeln!(
+                out,
+                "gluster_call_duration_seconds_bucket{{op=\"{}\",le=\"+Inf\"}} {}",
+                name,
+                op.latency.count()
+            )?;
+            writeln!(
+                out,
+                "gluster_call_duration_seconds_sum{{op=\"{}\"}} {}",
+                name,
+                op.latency.sum.as_secs_f64()
+            )?;
+            writeln!(
+                out,
+                "gluster_call_duration_seconds_count{{op=\"{}\"}} {}",
+                name,
+                op.latency.count()
+            )?;
+        }
+        writeln!(
+            out,
+            "# HELP gluster_read_bytes_total Bytes read from volumes."
+        )?;
+        writeln!(out, "# TYPE gluster_read_bytes_total counter")?;
+        writeln!(out, "gluster_read_bytes_total {}", self.bytes_read)?;
+        writeln!(
+            out,
+            "# HELP gluster_written_bytes_total Bytes written to volumes."
+        )?;
+        writeln!(out, "# TYPE gluster_written_bytes_total counter")?;
+        writeln!(out, "gluster_written_bytes_total {}", self.bytes_written)?;
+        writeln!(
+            out,
+            "# HELP gluster_copied_bytes_total Bytes copied server side within volumes."
+        )?;
+        writeln!(out, "# TYPE gluster_copied_bytes_total counter")?;
+        writeln!(out, "gluster_copied_bytes_total {}", self.bytes_copied)
+    }
+}
+
+/// Take a snapshot of the calls recorded since start up or the last reset
+pub fn metrics() -> Metrics {
+    let registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let mut snapshot = Metrics {
+        operations: registry.clone(),
+        ..Metrics::default()
+    };
+    snapshot.bytes_read = bytes(&snapshot, Transfer::Read);
+    snapshot.bytes_written = bytes(&snapshot, Transfer::Write);
+    snapshot.bytes_copied = bytes(&snapshot, Transfer::Copy);
+    snapshot
+}
+
+/// Forget everything recorded so far
+pub fn reset() {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+fn transfer(name: &str) -> Option<Transfer> {
+    // glfs_readdir, glfs_readlink and friends don't move file data
+    match name {
+        "glfs_read" | "glfs_readv" | "glfs_pread" | "glfs_preadv" => Some(Transfer::Read),
+        "glfs_write" | "glfs_writev" | "glfs_pwrite" | "glfs_pwritev" => Some(Transfer::Write),
+        "glfs_copy_file_range" => Some(Transfer::Copy),
+        _ => None,
+    }
+}
+
+fn bytes(snapshot: &Metrics, direction: Transfer) -> u64 {
+    snapshot
+        .operations
+        .iter()
+        .filter(|(name, _)| transfer(name) == Some(direction))
+        .map(|(_, op)| op.bytes)
+        .sum()
+}
+
+/// Record a glfs call that took elapsed, with outcome None if it failed
+/// with errno
+pub(crate) fn record(name: &'static str, elapsed: Duration, outcome: Option<u64>, errno: i32) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let op = registry.entry(name).or_default();
+    op.calls += 1;
+    op.latency.observe(elapsed);
+    match outcome {
+        Some(size) if transfer(name).is_some() => op.bytes += size,
+        Some(_) => {}
+        None => *op.errors.entry(errno).or_insert(0) += 1,
+    }
+}
@@ -0,0 +1,237 @@
+//! A shared registry of volume handles
+//! Initializing a Gluster handle fetches the volfile, builds the client
+//! graph and connects to every brick, which takes seconds and a fair amount
+//! of memory.  GlusterPool lets every part of a program share one handle
+//! per volume, checks those handles are still healthy and tears down the
+//! ones nobody has used for a while.
+use crate::gluster::{Gluster, GlusterError};
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Identifies a volume: its name and the volfile servers to fetch it from
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PoolKey {
+    pub volume: String,
+    pub servers: Vec<(String, u16)>,
+}
+
+impl PoolKey {
+    pub fn new(volume: &str, servers: &[(&str, u16)]) -> PoolKey {
+        PoolKey {
+            volume: volume.to_string(),
+            servers: servers
+                .iter()
+                .map(|(host, port)| (host.to_string(), *port))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// How often maintenance checks a handle by running statvfs on /
+    pub health_check_interval: Duration,
+    /// Handles that haven't been handed out for this long, and that
+    /// nobody outside the pool still holds, are torn down
+    pub idle_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> PoolConfig {
+        PoolConfig {
+            health_check_interval: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(600),
+        }
+    }
+}
+
+#[derive(Default)]
+struct SlotState {
+    handle: Option<Arc<Gluster>>,
+    last_used: Option<Instant>,
+    last_checked: Option<Instant>,
+}
+
+/// One volume.  Each has its own lock so a slow glfs_init for one volume
+/// doesn't hold up handing out handles to the others.
+#[derive(Default)]
+struct Slot {
+    state: Mutex<SlotState>,
+}
+
+struct PoolInner {
+    config: PoolConfig,
+    slots: Mutex<HashMap<PoolKey, Arc<Slot>>>,
+}
+
+/// Lazily connects to volumes and hands out shared handles to them.
+/// Cloning the pool is cheap and the clones share the same handles.
+#[derive(Clone)]
+pub struct GlusterPool {
+    inner: Arc<PoolInner>,
+}
+
+// A thread that panicked while holding a lock can't leave the maps in a
+// broken state, so keep going with whatever it left behind.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl GlusterPool {
+    pub fn new(config: PoolConfig) -> GlusterPool {
+        GlusterPool {
+            inner: Arc::new(PoolInner {
+                config,
+                slots: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Get a handle to a volume, connecting to it if the pool doesn't
+    /// have a handle yet.  Concurrent callers asking for the same volume
+    /// wait for a single connection attempt.
+    pub fn get(&self, volume: &str, servers: &[(&str, u16)]) -> Result<Arc<Gluster>, GlusterError> {
+        self.get_key(&PoolKey::new(volume, servers))
+    }
+
+    pub fn get_key(&self, key: &PoolKey) -> Result<Arc<Gluster>, GlusterError> {
+        let slot = lock(&self.inner.slots)
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let mut state = lock(&slot.state);
+        let handle = match state.handle {
+            Some(ref handle) => handle.clone(),
+            None => {
+                let servers: Vec<(&str, u16)> = key
+                    .servers
+                    .iter()
+                    .map(|(host, port)| (host.as_str(), *port))
+                    .collect();
+                trace!("connecting to volume {}", key.volume);
+                let handle = Arc::new(Gluster::connect_servers(&key.volume, &servers)?);
+                state.handle = Some(handle.clone());
+                state.last_checked = Some(Instant::now());
+                handle
+            }
+        };
+        state.last_used = Some(Instant::now());
+        Ok(handle)
+    }
+
+    /// Drop the pool's handle to a volume.  Callers still holding it keep
+    /// a working handle until they drop it, the next get reconnects.
+    pub fn evict(&self, key: &PoolKey) {
+        let slot = lock(&self.inner.slots).remove(key);
+        if let Some(slot) = slot {
+            lock(&slot.state).handle = None;
+        }
+    }
+
+    /// The volumes the pool currently holds a handle to
+    pub fn keys(&self) -> Vec<PoolKey> {
+        lock(&self.inner.slots).keys().cloned().collect()
+    }
+
+    /// Run one round of maintenance: tear down idle handles and health
+    /// check the others, evicting any that fail.  Returns the keys that
+    /// were removed.
+    pub fn maintain(&self) -> Vec<PoolKey> {
+        let slots: Vec<(PoolKey, Arc<Slot>)> = lock(&self.inner.slots)
+            .iter()
+            .map(|(key, slot)| (key.clone(), slot.clone()))
+            .collect();
+        let mut removed = Vec::new();
+        for (key, slot) in slots {
+            if !self.maintain_slot(&key, &slot) {
+                continue;
+            }
+            let mut slots = lock(&self.inner.slots);
+            // Leave the slot alone if someone is reconnecting it right now
+            let empty = slot
+                .state
+                .try_lock()
+                .is_ok_and(|state| state.handle.is_none());
+            let current = slots.get(&key).is_some_and(|s| Arc::ptr_eq(s, &slot));
+            if empty && current {
+                slots.remove(&key);
+                removed.push(key);
+            }
+        }
+        removed
+    }
+
+    /// Returns true if the slot's handle was dropped
+    fn maintain_slot(&self, key: &PoolKey, slot: &Slot) -> bool {
+        let config = &self.inner.config;
+        let now = Instant::now();
+        let handle = {
+            let mut state = lock(&slot.state);
+            let handle = match state.handle {
+                Some(ref handle) => handle.clone(),
+                None => return true,
+            };
+            let idle = match state.last_used {
+                Some(used) => now.duration_since(used) >= config.idle_timeout,
+                None => true,
+            };
+            // The pool's reference plus the clone above
+            if idle && Arc::strong_count(&handle) == 2 {
+                debug!("tearing down idle handle to volume {}", key.volume);
+                state.handle = None;
+                return true;
+            }
+            let due = match state.last_checked {
+                Some(checked) => now.duration_since(checked) >= config.health_check_interval,
+                None => true,
+            };
+            if !due {
+                return false;
+            }
+            state.last_checked = Some(now);
+            handle
+        };
+
+        // Don't hold the lock over the network round trip
+        if let Err(e) = handle.statvfs(Path::new("/")) {
+            error!("health check of volume {} failed: {:?}", key.volume, e);
+            let mut state = lock(&slot.state);
+            if let Some(ref current) = state.handle {
+                if Arc::ptr_eq(current, &handle) {
+                    state.handle = None;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Start a thread that runs maintenance every health_check_interval.
+    /// The thread exits once every clone of the pool has been dropped.
+    pub fn start_maintenance(&self) -> Result<thread::JoinHandle<()>, GlusterError> {
+        let weak: Weak<PoolInner> = Arc::downgrade(&self.inner);
+        let interval = self.inner.config.health_check_interval;
+        let handle = thread::Builder::new()
+            .name("gluster-pool".to_string())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                match weak.upgrade() {
+                    Some(inner) => {
+                        GlusterPool { inner }.maintain();
+                    }
+                    None => return,
+                }
+            })?;
+        Ok(handle)
+    }
+}
+
+impl Default for GlusterPool {
+    fn default() -> GlusterPool {
+        GlusterPool::new(PoolConfig::default())
+    }
+}
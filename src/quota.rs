@@ -0,0 +1,165 @@
+//! Directory quota accounting
+//! The quota translator keeps its bookkeeping in binary extended attributes
+//! on every directory it tracks.  This module decodes them into typed structs
+//! so callers don't need to run `gluster volume quota list`.
+use crate::gluster::{Gluster, GlusterError};
+use errno::{errno, Errno};
+use libc::ENODATA;
+
+use std::convert::TryInto;
+use std::path::Path;
+
+/// Disk usage of a directory: size, file count and directory count
+pub const QUOTA_SIZE_KEY: &str = "trusted.glusterfs.quota.size";
+/// Hard and soft limit on the disk usage of a directory
+pub const QUOTA_LIMIT_KEY: &str = "trusted.glusterfs.quota.limit-set";
+/// Hard and soft limit on the number of files and directories
+pub const QUOTA_INODE_LIMIT_KEY: &str = "trusted.glusterfs.quota.inode-limit-set";
+/// The soft limit gluster applies when a limit doesn't set its own
+/// and the volume's default-soft-limit hasn't been changed.
+pub const DEFAULT_SOFT_LIMIT_PERCENT: u8 = 80;
+
+/// Usage accounted by the quota translator for a directory and
+/// everything below it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QuotaUsage {
+    /// Bytes used.  Gluster's accounting can briefly go negative
+    /// while it heals so this is kept signed.
+    pub size: i64,
+    pub file_count: i64,
+    pub dir_count: i64,
+}
+
+/// A single limit as configured by `gluster volume quota limit-usage`
+/// or `limit-objects`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuotaLimit {
+    /// Bytes for a usage limit, files + directories for an object limit
+    pub hard_limit: i64,
+    /// Percentage of the hard limit at which gluster starts warning.
+    /// None means the volume's default-soft-limit applies.
+    pub soft_limit_percent: Option<u8>,
+}
+
+/// The limits set on a directory.  Either may be missing.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QuotaLimits {
+    pub usage: Option<QuotaLimit>,
+    pub objects: Option<QuotaLimit>,
+}
+
+fn be_i64(buf: &[u8], index: usize) -> i64 {
+    let start = index * 8;
+    i64::from_be_bytes(buf[start..start + 8].try_into().unwrap())
+}
+
+impl QuotaUsage {
+    /// Decode the value of the trusted.glusterfs.quota.size xattr.
+    /// Gluster 3.7 and later store three big endian i64s: size, file count
+    /// and dir count.  Older volumes only store the size.
+    pub fn from_xattr(buf: &[u8]) -> Result<QuotaUsage, GlusterError> {
+        match buf.len() {
+            8 => Ok(QuotaUsage {
+                size: be_i64(buf, 0),
+                file_count: 0,
+                dir_count: 0,
+            }),
+            24 => Ok(QuotaUsage {
+                size: be_i64(buf, 0),
+                file_count: be_i64(buf, 1),
+                dir_count: be_i64(buf, 2),
+            }),
+            len => Err(GlusterError::Error(format!(
+                "quota size xattr has unexpected length {}",
+                len
+            ))),
+        }
+    }
+
+    /// Files and directories counted against an object limit
+    pub fn objects(&self) -> i64 {
+        self.file_count + self.dir_count
+    }
+
+    /// Percentage of the hard limit used by the size of this directory
+    pub fn size_percent(&self, limit: &QuotaLimit) -> f64 {
+        percent(self.size, limit.hard_limit)
+    }
+
+    /// Percentage of the hard limit used by the objects in this directory
+    pub fn objects_percent(&self, limit: &QuotaLimit) -> f64 {
+        percent(self.objects(), limit.hard_limit)
+    }
+}
+
+fn percent(used: i64, limit: i64) -> f64 {
+    if limit <= 0 {
+        return 0.0;
+    }
+    used as f64 * 100.0 / limit as f64
+}
+
+impl QuotaLimit {
+    /// Decode the value of a limit-set or inode-limit-set xattr.  These are
+    /// two big endian i64s: the hard limit followed by the soft limit
+    /// percentage, where -1 means the volume default.
+    pub fn from_xattr(buf: &[u8]) -> Result<QuotaLimit, GlusterError> {
+        if buf.len() != 16 {
+            return Err(GlusterError::Error(format!(
+                "quota limit xattr has unexpected length {}",
+                buf.len()
+            )));
+        }
+        let soft_limit = be_i64(buf, 1);
+        let soft_limit_percent = match soft_limit {
+            -1 => None,
+            0..=100 => Some(soft_limit as u8),
+            _ => {
+                return Err(GlusterError::Error(format!(
+                    "quota soft limit {} is not a percentage",
+                    soft_limit
+                )))
+            }
+        };
+        Ok(QuotaLimit {
+            hard_limit: be_i64(buf, 0),
+            soft_limit_percent,
+        })
+    }
+
+    /// The soft limit in the same unit as the hard limit.  default_percent
+    /// should be the volume's default-soft-limit and is only used when this
+    /// limit doesn't set its own.
+    pub fn soft_limit(&self, default_percent: u8) -> i64 {
+        let percent = self.soft_limit_percent.unwrap_or(default_percent);
+        // Widen so hard limits near i64::MAX don't overflow
+        (i128::from(self.hard_limit) * i128::from(percent) / 100) as i64
+    }
+}
+
+impl Gluster {
+    /// Usage the quota translator has accounted for a directory.  Quota
+    /// must be enabled on the volume for this to work.
+    pub fn quota_usage(&self, dir: &Path) -> Result<QuotaUsage, GlusterError> {
+        let buf = self.getxattr_bytes(dir, QUOTA_SIZE_KEY)?;
+        QuotaUsage::from_xattr(&buf)
+    }
+
+    /// Usage and object limits configured on a directory.  Limits inherited
+    /// from a parent directory aren't reported here.
+    pub fn quota_limits(&self, dir: &Path) -> Result<QuotaLimits, GlusterError> {
+        Ok(QuotaLimits {
+            usage: self.quota_limit(dir, QUOTA_LIMIT_KEY)?,
+            objects: self.quota_limit(dir, QUOTA_INODE_LIMIT_KEY)?,
+        })
+    }
+
+    fn quota_limit(&self, dir: &Path, key: &str) -> Result<Option<QuotaLimit>, GlusterError> {
+        match self.getxattr_bytes(dir, key) {
+            Ok(buf) => Ok(Some(QuotaLimit::from_xattr(&buf)?)),
+            // No limit has been set on this directory
+            Err(_) if errno() == Errno(ENODATA) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,382 @@
+//! Handles that survive the cluster going away
+//! When every brick restarts, a Gluster handle can be left returning
+//! ENOTCONN and the files opened through it go stale.  ResilientGluster
+//! notices that, builds a fresh glfs instance and reopens the files it
+//! tracks at the path, flags and offset they had.
+use crate::gluster::{Gluster, GlusterError, GlusterFile};
+use errno::{errno, set_errno, Errno};
+use libc::{
+    mode_t, stat, ENOTCONN, ESTALE, O_APPEND, O_CREAT, O_EXCL, O_TRUNC, SEEK_CUR, SEEK_SET,
+};
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// What a file will be reopened with.  Handed to the reopen policy so the
+/// application can decide whether picking up where it left off is safe,
+/// for example when nobody else could have written to the file meanwhile.
+#[derive(Debug)]
+pub struct ReopenInfo<'a> {
+    pub path: &'a Path,
+    pub flags: i32,
+    pub offset: i64,
+}
+
+type ReopenPolicy = dyn Fn(&ReopenInfo<'_>) -> bool + Send + Sync;
+
+struct Connection {
+    // Bumped on every reconnect so files can tell their fd is from an
+    // older instance
+    generation: u64,
+    cluster: Arc<Gluster>,
+}
+
+struct Inner {
+    volume: String,
+    servers: Vec<(String, u16)>,
+    connection: Mutex<Connection>,
+    reopen_policy: Box<ReopenPolicy>,
+}
+
+/// A Gluster handle that reconnects after losing the cluster.  Cloning it
+/// is cheap and the clones share the same connection.
+#[derive(Clone)]
+pub struct ResilientGluster {
+    inner: Arc<Inner>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Errors that mean the connection rather than the operation failed
+fn is_disconnected() -> bool {
+    errno() == Errno(ENOTCONN)
+}
+
+/// A failed operation on a tracked file with the errno of the glfs call
+/// that failed, taken by ? before later calls or cleanup can change it
+struct Failed {
+    error: GlusterError,
+    errno: Errno,
+}
+
+impl From<GlusterError> for Failed {
+    fn from(error: GlusterError) -> Failed {
+        Failed {
+            error,
+            errno: errno(),
+        }
+    }
+}
+
+impl Failed {
+    /// The error to hand back, with errno set to the failing call's again
+    fn into_error(self) -> GlusterError {
+        set_errno(self.errno);
+        self.error
+    }
+}
+
+impl ResilientGluster {
+    /// Connect like Gluster::connect_servers.  Tracked files are always
+    /// reopened after a reconnect.
+    pub fn connect(
+        volume: &str,
+        servers: &[(&str, u16)],
+    ) -> Result<ResilientGluster, GlusterError> {
+        ResilientGluster::connect_with_policy(volume, servers, |_| true)
+    }
+
+    /// Connect like Gluster::connect_servers.  After a reconnect each tracked
+    /// file is only reopened if policy returns true, otherwise operations
+    /// on it fail until the policy allows it.
+    pub fn connect_with_policy<F>(
+        volume: &str,
+        servers: &[(&str, u16)],
+        policy: F,
+    ) -> Result<ResilientGluster, GlusterError>
+    where
+        F: Fn(&ReopenInfo<'_>) -> bool + Send + Sync + 'static,
+    {
+        let cluster = Gluster::connect_servers(volume, servers)?;
+        Ok(ResilientGluster {
+            inner: Arc::new(Inner {
+                volume: volume.to_string(),
+                servers: servers
+                    .iter()
+                    .map(|(host, port)| (host.to_string(), *port))
+                    .collect(),
+                connection: Mutex::new(Connection {
+                    generation: 0,
+                    cluster: Arc::new(cluster),
+                }),
+                reopen_policy: Box::new(policy),
+            }),
+        })
+    }
+
+    /// The current underlying handle.  It isn't replaced if it later
+    /// disconnects, so prefer call for anything long lived.
+    pub fn handle(&self) -> Arc<Gluster> {
+        lock(&self.inner.connection).cluster.clone()
+    }
+
+    fn current(&self) -> (u64, Arc<Gluster>) {
+        let connection = lock(&self.inner.connection);
+        (connection.generation, connection.cluster.clone())
+    }
+
+    /// Replace the handle for generation with a fresh one.  If another
+    /// thread already did so, its handle is returned instead of connecting
+    /// a second time.
+    fn reconnect_from(&self, generation: u64) -> Result<(u64, Arc<Gluster>), GlusterError> {
+        let mut connection = lock(&self.inner.connection);
+        if connection.generation == generation {
+            info!("reconnecting to volume {}", self.inner.volume);
+            let servers: Vec<(&str, u16)> = self
+                .inner
+                .servers
+                .iter()
+                .map(|(host, port)| (host.as_str(), *port))
+                .collect();
+            // Anyone still holding the old handle keeps it alive until
+            // they're done with it
+            connection.cluster = Arc::new(Gluster::connect_servers(&self.inner.volume, &servers)?);
+            connection.generation += 1;
+        }
+        Ok((connection.generation, connection.cluster.clone()))
+    }
+
+    /// Force a fresh glfs instance
+    pub fn reconnect(&self) -> Result<Arc<Gluster>, GlusterError> {
+        let (generation, _) = self.current();
+        Ok(self.reconnect_from(generation)?.1)
+    }
+
+    /// Run f against the current handle.  If it fails with ENOTCONN the
+    /// handle is replaced and f is retried once.
+    pub fn call<F, T>(&self, f: F) -> Result<T, GlusterError>
+    where
+        F: Fn(&Gluster) -> Result<T, GlusterError>,
+    {
+        let (generation, cluster) = self.current();
+        match f(&cluster) {
+            Err(_) if is_disconnected() => {
+                let (_, cluster) = self.reconnect_from(generation)?;
+                f(&cluster)
+            }
+            result => result,
+        }
+    }
+
+    pub fn open(&self, path: &Path, flags: i32) -> Result<ResilientFile, GlusterError> {
+        let (generation, cluster) = self.current();
+        let file = match cluster.open(path, flags) {
+            Err(_) if is_disconnected() => {
+                let (generation, cluster) = self.reconnect_from(generation)?;
+                let file = cluster.open(path, flags)?;
+                FileState::new(file, generation, cluster, 0)
+            }
+            result => FileState::new(result?, generation, cluster, 0),
+        };
+        Ok(ResilientFile::new(self, path, flags, file))
+    }
+
+    pub fn create(
+        &self,
+        path: &Path,
+        flags: i32,
+        mode: mode_t,
+    ) -> Result<ResilientFile, GlusterError> {
+        let (generation, cluster) = self.current();
+        let file = match cluster.create(path, flags, mode) {
+            Err(_) if is_disconnected() => {
+                let (generation, cluster) = self.reconnect_from(generation)?;
+                let file = cluster.create(path, flags, mode)?;
+                FileState::new(file, generation, cluster, 0)
+            }
+            result => FileState::new(result?, generation, cluster, 0),
+        };
+        Ok(ResilientFile::new(self, path, flags, file))
+    }
+}
+
+struct FileState {
+    // Declared before _cluster so the fd is closed before the glfs
+    // instance it belongs to can be torn down
+    file: Option<GlusterFile>,
+    generation: u64,
+    _cluster: Arc<Gluster>,
+    offset: i64,
+}
+
+impl FileState {
+    fn new(file: GlusterFile, generation: u64, cluster: Arc<Gluster>, offset: i64) -> FileState {
+        FileState {
+            file: Some(file),
+            generation,
+            _cluster: cluster,
+            offset,
+        }
+    }
+}
+
+/// A file that is reopened at its recorded path, flags and offset after
+/// its ResilientGluster reconnects.  The file offset is tracked here rather
+/// than by gluster so it can be restored.
+pub struct ResilientFile {
+    cluster: ResilientGluster,
+    path: PathBuf,
+    flags: i32,
+    state: Mutex<FileState>,
+}
+
+impl ResilientFile {
+    fn new(cluster: &ResilientGluster, path: &Path, flags: i32, state: FileState) -> ResilientFile {
+        ResilientFile {
+            cluster: cluster.clone(),
+            path: path.to_path_buf(),
+            // Reopening must not create, fail on or truncate the file again
+            flags: flags & !(O_CREAT | O_EXCL | O_TRUNC),
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reopen the file against the newest handle, if the policy allows
+    fn reopen(&self, state: &mut FileState) -> Result<(), GlusterError> {
+        let (generation, cluster) = self.cluster.current();
+        // Close the stale fd before anything else
+        state.file = None;
+        let info = ReopenInfo {
+            path: &self.path,
+            flags: self.flags,
+            offset: state.offset,
+        };
+        if !(self.cluster.inner.reopen_policy)(&info) {
+            return Err(GlusterError::Error(format!(
+                "{} was not reopened after reconnecting",
+                self.path.display()
+            )));
+        }
+        debug!(
+            "reopening {} at offset {}",
+            self.path.display(),
+            state.offset
+        );
+        let file = cluster.open(&self.path, self.flags)?;
+        if state.offset != 0 {
+            file.lseek(state.offset, SEEK_SET)?;
+        }
+        *state = FileState::new(file, generation, cluster, state.offset);
+        Ok(())
+    }
+
+    /// Run f against the open file.  If it failed with ENOTCONN the
+    /// cluster is reconnected and the file reopened, and with ESTALE only
+    /// the file is reopened, and then f is run again if replay is set.
+    /// Other errors, EBADF included, are the operation's own and returned
+    /// as they are.
+    fn with_file<F, T>(&self, replay: bool, mut f: F) -> Result<T, GlusterError>
+    where
+        F: FnMut(&GlusterFile, &mut i64) -> Result<T, Failed>,
+    {
+        let mut state = lock(&self.state);
+        let (generation, _) = self.cluster.current();
+        if state.file.is_none() || state.generation != generation {
+            // Another user of the handle reconnected, our fd is stale
+            self.reopen(&mut state)?;
+        }
+        let FileState { file, offset, .. } = &mut *state;
+        let failed = match f(file.as_ref().unwrap(), offset) {
+            Ok(value) => return Ok(value),
+            Err(failed) => failed,
+        };
+        match failed.errno {
+            Errno(ENOTCONN) => {
+                self.cluster.reconnect_from(state.generation)?;
+                self.reopen(&mut state)?;
+            }
+            // The file was replaced underneath us but the connection is
+            // fine, so the other tracked files are left alone
+            Errno(ESTALE) => self.reopen(&mut state)?,
+            _ => return Err(failed.into_error()),
+        }
+        if !replay {
+            return Err(failed.into_error());
+        }
+        let FileState { file, offset, .. } = &mut *state;
+        f(file.as_ref().unwrap(), offset).map_err(Failed::into_error)
+    }
+
+    /// Read up to count bytes at the current offset and advance it
+    pub fn read(&self, fill_buffer: &mut Vec<u8>, count: usize) -> Result<isize, GlusterError> {
+        self.with_file(true, |file, offset| {
+            let read = file.pread(fill_buffer, count, *offset, 0)?;
+            *offset += read as i64;
+            Ok(read)
+        })
+    }
+
+    /// Write at the current offset, or the end of the file if it was
+    /// opened with O_APPEND, and advance the offset
+    pub fn write(&self, buffer: &[u8]) -> Result<isize, GlusterError> {
+        let append = self.flags & O_APPEND != 0;
+        // An append that failed may still have reached the file, and
+        // writing it again would add the data twice
+        self.with_file(!append, |file, offset| {
+            if append {
+                let written = file.write(buffer, 0)?;
+                *offset = file.lseek(0, SEEK_CUR)?;
+                return Ok(written);
+            }
+            let written = file.pwrite(buffer, buffer.len(), *offset, 0)?;
+            *offset += written as i64;
+            Ok(written)
+        })
+    }
+
+    /// Read at offset without moving the file offset
+    pub fn pread(
+        &self,
+        fill_buffer: &mut Vec<u8>,
+        count: usize,
+        offset: i64,
+    ) -> Result<isize, GlusterError> {
+        self.with_file(true, |file, _| {
+            Ok(file.pread(fill_buffer, count, offset, 0)?)
+        })
+    }
+
+    /// Write at offset without moving the file offset
+    pub fn pwrite(&self, buffer: &[u8], offset: i64) -> Result<isize, GlusterError> {
+        self.with_file(true, |file, _| {
+            Ok(file.pwrite(buffer, buffer.len(), offset, 0)?)
+        })
+    }
+
+    pub fn lseek(&self, offset: i64, whence: i32) -> Result<i64, GlusterError> {
+        self.with_file(true, |file, current| {
+            // Sync gluster's offset with ours so SEEK_CUR works
+            file.lseek(*current, SEEK_SET)?;
+            *current = file.lseek(offset, whence)?;
+            Ok(*current)
+        })
+    }
+
+    pub fn fstat(&self) -> Result<stat, GlusterError> {
+        self.with_file(true, |file, _| Ok(file.fstat()?))
+    }
+
+    pub fn fsync(&self) -> Result<(), GlusterError> {
+        self.with_file(true, |file, _| Ok(file.fsync()?))
+    }
+
+    pub fn ftruncate(&self, length: i64) -> Result<(), GlusterError> {
+        self.with_file(true, |file, _| Ok(file.ftruncate(length)?))
+    }
+}
@@ -0,0 +1,1289 @@
+//! An S3 compatible gateway in front of a volume
+//! Buckets are the directories under a root directory of the volume and
+//! object keys are paths inside them, so objects put through the gateway
+//! are ordinary files to every other client of the volume.  ETags, content
+//! types and x-amz-meta-* headers are kept in user.s3.* extended
+//! attributes.  Uploads in progress live under .s3-uploads in each bucket,
+//! which listings leave out.
+//!
+//! S3Gateway only turns requests into responses so any HTTP server can sit
+//! in front of it.  Only path style requests (http://host/bucket/key) are
+//! understood and signatures aren't checked, so the gateway belongs behind
+//! something that authenticates clients.
+use crate::fs::{GlusterFileOps, GlusterFs};
+use crate::gluster::GlusterError;
+use errno::errno;
+use libc::{
+    stat, EACCES, EEXIST, EISDIR, ENOENT, ENOTDIR, ENOTEMPTY, EPERM, O_CREAT, O_EXCL, O_RDONLY,
+    O_WRONLY, S_IFDIR, S_IFMT, S_IFREG,
+};
+
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The ETag of an object, without the quotes
+pub const ETAG_KEY: &str = "user.s3.etag";
+pub const CONTENT_TYPE_KEY: &str = "user.s3.content-type";
+/// The x-amz-meta-* headers of an object, one "name: value" line each
+pub const METADATA_KEY: &str = "user.s3.metadata";
+/// The key a multipart upload completes to, set on its directory
+const UPLOAD_KEY_KEY: &str = "user.s3.upload-key";
+
+/// Names starting with this are the gateway's own and can't be keys
+const RESERVED_PREFIX: &str = ".s3-";
+const UPLOADS_DIR: &str = ".s3-uploads";
+const COPY_CHUNK: usize = 1 << 20;
+const MAX_KEYS: usize = 1000;
+const MAX_PART_NUMBER: u32 = 10000;
+const XMLNS: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+/// An HTTP request as the gateway needs it, whatever server received it
+pub struct S3Request<'a> {
+    pub method: &'a str,
+    /// Still percent encoded and without the query string
+    pub path: &'a str,
+    pub query: &'a str,
+    pub headers: &'a [(String, String)],
+    pub body: &'a mut dyn Read,
+}
+
+impl S3Request<'_> {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub struct S3Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// Also what a HEAD response advertises even though body is empty
+    pub content_length: u64,
+    pub body: Box<dyn Read>,
+}
+
+impl S3Response {
+    fn new(status: u16) -> S3Response {
+        S3Response {
+            status,
+            headers: Vec::new(),
+            content_length: 0,
+            body: Box::new(io::empty()),
+        }
+    }
+
+    fn xml(status: u16, xml: String) -> S3Response {
+        S3Response {
+            status,
+            headers: vec![("Content-Type".to_string(), "application/xml".to_string())],
+            content_length: xml.len() as u64,
+            body: Box::new(Cursor::new(xml.into_bytes())),
+        }
+    }
+
+    fn header(mut self, name: &str, value: String) -> S3Response {
+        self.headers.push((name.to_string(), value));
+        self
+    }
+
+    /// Read the whole body into memory
+    pub fn body_bytes(mut self) -> io::Result<Vec<u8>> {
+        let mut body = Vec::new();
+        self.body.read_to_end(&mut body)?;
+        Ok(body)
+    }
+}
+
+#[derive(Debug)]
+struct S3Error {
+    status: u16,
+    code: &'static str,
+    message: String,
+}
+
+impl S3Error {
+    fn new<S: Into<String>>(status: u16, code: &'static str, message: S) -> S3Error {
+        S3Error {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid<S: Into<String>>(message: S) -> S3Error {
+        S3Error::new(400, "InvalidArgument", message)
+    }
+
+    fn response(&self, resource: &str, head: bool) -> S3Response {
+        if head {
+            return S3Response::new(self.status);
+        }
+        S3Response::xml(
+            self.status,
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{}</Code>\
+                 <Message>{}</Message><Resource>{}</Resource></Error>",
+                self.code,
+                xml_escape(&self.message),
+                xml_escape(resource)
+            ),
+        )
+    }
+}
+
+/// Turn a failed filesystem call into an S3 error, reporting ENOENT as
+/// missing.  Reads errno so it must be used right after the call.
+fn fs_error(missing: &'static str) -> impl Fn(GlusterError) -> S3Error {
+    move |e| {
+        let code = errno().0;
+        let message = e.to_string();
+        match code {
+            ENOENT => S3Error::new(404, missing, message),
+            EACCES | EPERM => S3Error::new(403, "AccessDenied", message),
+            // S3 keys can be both an object and a prefix of other keys
+            // but a path can't be both a file and a directory
+            ENOTDIR | EISDIR | EEXIST => S3Error::new(
+                409,
+                "OperationAborted",
+                "the key conflicts with an existing object or prefix",
+            ),
+            _ => S3Error::new(500, "InternalError", message),
+        }
+    }
+}
+
+struct Query(Vec<(String, String)>);
+
+impl Query {
+    fn parse(query: &str) -> Result<Query, S3Error> {
+        let mut pairs = Vec::new();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = match pair.find('=') {
+                Some(i) => (&pair[..i], &pair[i + 1..]),
+                None => (pair, ""),
+            };
+            pairs.push((percent_decode(name)?, percent_decode(value)?));
+        }
+        Ok(Query(pairs))
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.0.iter().any(|(key, _)| key == name)
+    }
+}
+
+/// What is stored alongside an object's data
+struct ObjectMeta {
+    etag: String,
+    content_type: Option<String>,
+    metadata: Vec<(String, String)>,
+}
+
+impl ObjectMeta {
+    fn from_headers(request: &S3Request<'_>) -> ObjectMeta {
+        let metadata = request
+            .headers
+            .iter()
+            .filter(|(name, _)| name.to_ascii_lowercase().starts_with("x-amz-meta-"))
+            .map(|(name, value)| {
+                (
+                    name["x-amz-meta-".len()..].to_ascii_lowercase(),
+                    value.trim().to_string(),
+                )
+            })
+            .collect();
+        ObjectMeta {
+            etag: String::new(),
+            content_type: request.header("content-type").map(str::to_string),
+            metadata,
+        }
+    }
+
+    /// The extended attributes to store, leaving out what isn't set
+    fn xattrs(&self) -> Vec<(&'static str, Vec<u8>)> {
+        let mut xattrs = Vec::new();
+        if !self.etag.is_empty() {
+            xattrs.push((ETAG_KEY, self.etag.clone().into_bytes()));
+        }
+        if let Some(ref content_type) = self.content_type {
+            xattrs.push((CONTENT_TYPE_KEY, content_type.clone().into_bytes()));
+        }
+        if !self.metadata.is_empty() {
+            let lines: String = self
+                .metadata
+                .iter()
+                .map(|(name, value)| format!("{}: {}\n", name, value))
+                .collect();
+            xattrs.push((METADATA_KEY, lines.into_bytes()));
+        }
+        xattrs
+    }
+
+    /// Without st there's no ETag to fall back on when none was stored
+    fn load<F: GlusterFs>(fs: &F, path: &Path, st: Option<&stat>) -> ObjectMeta {
+        let text = |name| {
+            fs.getxattr_bytes(path, name)
+                .ok()
+                .map(|value| String::from_utf8_lossy(&value).into_owned())
+        };
+        let metadata = text(METADATA_KEY)
+            .map(|lines| {
+                lines
+                    .lines()
+                    .filter_map(|line| {
+                        let i = line.find(": ")?;
+                        Some((line[..i].to_string(), line[i + 2..].to_string()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        ObjectMeta {
+            etag: text(ETAG_KEY)
+                .or_else(|| st.map(fallback_etag))
+                .unwrap_or_default(),
+            content_type: text(CONTENT_TYPE_KEY),
+            metadata,
+        }
+    }
+
+    fn headers(&self, st: &stat, mut response: S3Response) -> S3Response {
+        response = response
+            .header("ETag", format!("\"{}\"", self.etag))
+            .header("Last-Modified", http_date(st.st_mtime))
+            .header("Accept-Ranges", "bytes".to_string())
+            .header(
+                "Content-Type",
+                self.content_type
+                    .clone()
+                    .unwrap_or_else(|| "binary/octet-stream".to_string()),
+            );
+        for (name, value) in &self.metadata {
+            response = response.header(&format!("x-amz-meta-{}", name), value.clone());
+        }
+        response
+    }
+}
+
+/// For files written by something other than the gateway.  The dash marks
+/// it as not being an MD5 like multipart ETags, so clients don't try to
+/// verify downloads with it.
+fn fallback_etag(st: &stat) -> String {
+    format!("{:x}{:x}-{:x}", st.st_ino, st.st_mtime, st.st_size)
+}
+
+/// Streams part of a file as a response body
+struct FileReader<T: GlusterFileOps> {
+    file: T,
+    offset: i64,
+    remaining: u64,
+}
+
+impl<T: GlusterFileOps> Read for FileReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = (buf.len() as u64).min(self.remaining) as usize;
+        if count == 0 {
+            return Ok(0);
+        }
+        let mut chunk = Vec::with_capacity(count);
+        self.file
+            .pread(&mut chunk, count, self.offset, 0)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        buf[..chunk.len()].copy_from_slice(&chunk);
+        self.offset += chunk.len() as i64;
+        self.remaining -= chunk.len() as u64;
+        Ok(chunk.len())
+    }
+}
+
+fn write_all<T: GlusterFileOps>(file: &T, mut data: &[u8], mut offset: i64) -> Result<(), S3Error> {
+    while !data.is_empty() {
+        let written = file
+            .pwrite(data, data.len(), offset, 0)
+            .map_err(fs_error("InternalError"))?;
+        if written <= 0 {
+            return Err(S3Error::new(500, "InternalError", "short write"));
+        }
+        data = &data[written as usize..];
+        offset += written as i64;
+    }
+    Ok(())
+}
+
+/// Write all of body to file and return its MD5
+fn copy_in<T: GlusterFileOps>(file: &T, body: &mut dyn Read) -> Result<md5::Digest, S3Error> {
+    let mut md5 = md5::Context::new();
+    let mut buf = vec![0; COPY_CHUNK];
+    let mut offset = 0;
+    loop {
+        let count = match body.read(&mut buf) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(S3Error::new(400, "IncompleteBody", e.to_string())),
+        };
+        md5.consume(&buf[..count]);
+        write_all(file, &buf[..count], offset)?;
+        offset += count as i64;
+    }
+    Ok(md5.compute())
+}
+
+fn new_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_nanos() as u64)
+        .unwrap_or(0);
+    format!(
+        "{:016x}{:08x}{:08x}",
+        nanos,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    )
+}
+
+fn is_dir(st: &stat) -> bool {
+    st.st_mode & S_IFMT == S_IFDIR
+}
+
+fn is_file(st: &stat) -> bool {
+    st.st_mode & S_IFMT == S_IFREG
+}
+
+/// A key or a common prefix found while listing
+struct Listed {
+    key: String,
+    // None for a common prefix
+    stat: Option<stat>,
+}
+
+/// Serves S3 requests out of the directories under root
+pub struct S3Gateway<F: GlusterFs> {
+    fs: F,
+    root: PathBuf,
+}
+
+impl<F: GlusterFs> S3Gateway<F>
+where
+    F::File: 'static,
+{
+    pub fn new(fs: F, root: &Path) -> S3Gateway<F> {
+        S3Gateway {
+            fs,
+            root: Path::new("/").join(root),
+        }
+    }
+
+    pub fn handle(&self, mut request: S3Request<'_>) -> S3Response {
+        let head = request.method == "HEAD";
+        match self.dispatch(&mut request) {
+            Ok(response) => response,
+            Err(e) => {
+                debug!("{} {}: {:?}", request.method, request.path, e);
+                e.response(request.path, head)
+            }
+        }
+    }
+
+    fn dispatch(&self, request: &mut S3Request<'_>) -> Result<S3Response, S3Error> {
+        let path = request.path.trim_start_matches('/');
+        let (bucket, key) = match path.find('/') {
+            Some(i) => (percent_decode(&path[..i])?, percent_decode(&path[i + 1..])?),
+            None => (percent_decode(path)?, String::new()),
+        };
+        let query = Query::parse(request.query)?;
+        let (bucket, key) = (bucket.as_str(), key.as_str());
+        match (request.method, bucket.is_empty(), key.is_empty()) {
+            ("GET", true, _) => self.list_buckets(),
+            (_, true, _) => Err(S3Error::new(405, "MethodNotAllowed", "not a bucket")),
+            ("PUT", _, true) => self.create_bucket(bucket),
+            ("DELETE", _, true) => self.delete_bucket(bucket),
+            ("HEAD", _, true) => self.check_bucket(bucket).map(|_| S3Response::new(200)),
+            ("GET", _, true) if query.get("list-type") == Some("2") => {
+                self.list_objects(bucket, &query)
+            }
+            ("GET", _, true) => Err(S3Error::new(
+                501,
+                "NotImplemented",
+                "only ListObjectsV2 is supported",
+            )),
+            ("PUT", _, _) if query.has("uploadId") => {
+                self.upload_part(bucket, key, &query, request)
+            }
+            ("PUT", _, _) if request.header("x-amz-copy-source").is_some() => Err(S3Error::new(
+                501,
+                "NotImplemented",
+                "CopyObject is not supported",
+            )),
+            ("PUT", _, _) => self.put_object(bucket, key, request),
+            ("POST", _, _) if query.has("uploads") => self.create_upload(bucket, key, request),
+            ("POST", _, _) if query.has("uploadId") => {
+                self.complete_upload(bucket, key, &query, request)
+            }
+            ("DELETE", _, _) if query.has("uploadId") => self.abort_upload(bucket, key, &query),
+            ("DELETE", _, _) => self.delete_object(bucket, key),
+            ("GET", _, _) => self.get_object(bucket, key, request.header("range"), false),
+            ("HEAD", _, _) => self.get_object(bucket, key, request.header("range"), true),
+            _ => Err(S3Error::new(405, "MethodNotAllowed", request.method)),
+        }
+    }
+
+    fn bucket_path(&self, bucket: &str) -> Result<PathBuf, S3Error> {
+        if bucket.starts_with('.') || bucket.contains('/') {
+            return Err(S3Error::new(400, "InvalidBucketName", bucket));
+        }
+        Ok(self.root.join(bucket))
+    }
+
+    /// The directory of a bucket that must already exist
+    fn check_bucket(&self, bucket: &str) -> Result<PathBuf, S3Error> {
+        let path = self.bucket_path(bucket)?;
+        let st = self.fs.stat(&path).map_err(fs_error("NoSuchBucket"))?;
+        if !is_dir(&st) {
+            return Err(S3Error::new(404, "NoSuchBucket", bucket));
+        }
+        Ok(path)
+    }
+
+    fn object_path(&self, bucket_path: &Path, key: &str) -> Result<PathBuf, S3Error> {
+        if key.len() > 1024 {
+            return Err(S3Error::new(
+                400,
+                "KeyTooLongError",
+                "keys are at most 1024 bytes",
+            ));
+        }
+        let parts: Vec<&str> = key.split('/').collect();
+        for (i, part) in parts.iter().enumerate() {
+            // Only a trailing slash, naming a directory, may leave a part empty
+            if (part.is_empty() && i + 1 != parts.len())
+                || *part == "."
+                || *part == ".."
+                || part.starts_with(RESERVED_PREFIX)
+                || part.contains('\0')
+            {
+                return Err(S3Error::invalid(format!(
+                    "{} can't be stored as a path",
+                    key
+                )));
+            }
+        }
+        Ok(bucket_path.join(key))
+    }
+
+    /// Create the directories between bucket_path and dir
+    fn make_dirs(&self, bucket_path: &Path, dir: &Path) -> Result<(), S3Error> {
+        let relative = dir
+            .strip_prefix(bucket_path)
+            .unwrap_or_else(|_| Path::new(""));
+        let mut current = bucket_path.to_path_buf();
+        for component in relative.components() {
+            current.push(component);
+            if let Err(e) = self.fs.mkdir(&current, 0o755) {
+                if errno().0 != EEXIST {
+                    return Err(fs_error("NoSuchKey")(e));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the directories that deleting path left empty
+    fn prune_dirs(&self, bucket_path: &Path, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            if current == bucket_path || !current.starts_with(bucket_path) {
+                break;
+            }
+            if self.fs.rmdir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+
+    /// Somewhere in the bucket to write an object before it gets its name
+    fn temp_path(&self, bucket_path: &Path) -> Result<PathBuf, S3Error> {
+        let uploads = bucket_path.join(UPLOADS_DIR);
+        self.make_dirs(bucket_path, &uploads)?;
+        Ok(uploads.join(format!("put-{}", new_id())))
+    }
+
+    /// Give the finished file at temp its name, cleaning up on failure
+    fn publish(&self, bucket_path: &Path, temp: &Path, path: &Path) -> Result<(), S3Error> {
+        let result = match path.parent() {
+            Some(parent) => self.make_dirs(bucket_path, parent),
+            None => Ok(()),
+        }
+        .and_then(|_| self.fs.rename(temp, path).map_err(fs_error("NoSuchKey")));
+        if result.is_err() {
+            let _ = self.fs.unlink(temp);
+        }
+        result
+    }
+
+    fn list_buckets(&self) -> Result<S3Response, S3Error> {
+        let mut buckets = Vec::new();
+        for entry in self
+            .fs
+            .opendir_plus(&self.root)
+            .map_err(fs_error("NoSuchBucket"))?
+        {
+            let entry = entry.map_err(fs_error("NoSuchBucket"))?;
+            let name = match entry.path.to_str() {
+                Some(name) if !name.starts_with('.') => name.to_string(),
+                _ => continue,
+            };
+            if is_dir(&entry.stat) {
+                buckets.push((name, entry.stat.st_ctime));
+            }
+        }
+        buckets.sort();
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ListAllMyBucketsResult xmlns=\"{}\">\
+             <Owner><ID>gfapi</ID><DisplayName>gfapi</DisplayName></Owner><Buckets>",
+            XMLNS
+        );
+        for (name, created) in buckets {
+            xml.push_str(&format!(
+                "<Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket>",
+                xml_escape(&name),
+                iso8601(created)
+            ));
+        }
+        xml.push_str("</Buckets></ListAllMyBucketsResult>");
+        Ok(S3Response::xml(200, xml))
+    }
+
+    fn create_bucket(&self, bucket: &str) -> Result<S3Response, S3Error> {
+        let path = self.bucket_path(bucket)?;
+        if let Err(e) = self.fs.mkdir(&path, 0o755) {
+            if errno().0 == EEXIST {
+                return Err(S3Error::new(409, "BucketAlreadyOwnedByYou", bucket));
+            }
+            return Err(fs_error("NoSuchBucket")(e));
+        }
+        Ok(S3Response::new(200).header("Location", format!("/{}", bucket)))
+    }
+
+    fn delete_bucket(&self, bucket: &str) -> Result<S3Response, S3Error> {
+        let path = self.check_bucket(bucket)?;
+        // Unfinished uploads don't keep a bucket alive
+        let uploads = path.join(UPLOADS_DIR);
+        if self.fs.exists(&uploads).unwrap_or(false) {
+            self.fs
+                .remove_dir_all(&uploads)
+                .map_err(fs_error("NoSuchBucket"))?;
+        }
+        if let Err(e) = self.fs.rmdir(&path) {
+            if errno().0 == ENOTEMPTY {
+                return Err(S3Error::new(409, "BucketNotEmpty", bucket));
+            }
+            return Err(fs_error("NoSuchBucket")(e));
+        }
+        Ok(S3Response::new(204))
+    }
+
+    fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        request: &mut S3Request<'_>,
+    ) -> Result<S3Response, S3Error> {
+        let bucket_path = self.check_bucket(bucket)?;
+        let path = self.object_path(&bucket_path, key)?;
+        if key.ends_with('/') {
+            // Consoles create "folders" as empty objects named like this
+            let mut byte = [0];
+            if request.body.read(&mut byte).unwrap_or(0) != 0 {
+                return Err(S3Error::invalid("keys ending in / must be empty"));
+            }
+            self.make_dirs(&bucket_path, &path)?;
+            return Ok(S3Response::new(200).header("ETag", format!("\"{:x}\"", md5::compute(b""))));
+        }
+
+        let mut meta = ObjectMeta::from_headers(request);
+        let temp = self.temp_path(&bucket_path)?;
+        let file = self
+            .fs
+            .create(&temp, O_WRONLY | O_CREAT | O_EXCL, 0o644)
+            .map_err(fs_error("NoSuchBucket"))?;
+        let written = copy_in(&file, request.body).and_then(|digest| {
+            meta.etag = format!("{:x}", digest);
+            for (name, value) in meta.xattrs() {
+                file.fsetxattr(name, &value, 0)
+                    .map_err(fs_error("InternalError"))?;
+            }
+            file.fsync().map_err(fs_error("InternalError"))
+        });
+        drop(file);
+        if let Err(e) = written {
+            let _ = self.fs.unlink(&temp);
+            return Err(e);
+        }
+        self.publish(&bucket_path, &temp, &path)?;
+        Ok(S3Response::new(200).header("ETag", format!("\"{}\"", meta.etag)))
+    }
+
+    fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        range: Option<&str>,
+        head: bool,
+    ) -> Result<S3Response, S3Error> {
+        let bucket_path = self.check_bucket(bucket)?;
+        let path = self.object_path(&bucket_path, key)?;
+        let st = self.fs.stat(&path).map_err(fs_error("NoSuchKey"))?;
+        if key.ends_with('/') || !is_file(&st) {
+            return Err(S3Error::new(404, "NoSuchKey", key));
+        }
+        let meta = ObjectMeta::load(&self.fs, &path, Some(&st));
+        let size = st.st_size as u64;
+        let (status, start, length) = match range.and_then(|range| parse_range(range, size)) {
+            None => (200, 0, size),
+            Some((start, _)) if start >= size => {
+                let error = S3Error::new(416, "InvalidRange", "the range starts past the end");
+                return Ok(error
+                    .response(key, head)
+                    .header("Content-Range", format!("bytes */{}", size)));
+            }
+            Some((start, end)) => {
+                let end = end.min(size - 1);
+                (206, start, end - start + 1)
+            }
+        };
+        let mut response = meta.headers(&st, S3Response::new(status));
+        if status == 206 {
+            response = response.header(
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, start + length - 1, size),
+            );
+        }
+        response.content_length = length;
+        if !head {
+            let file = self
+                .fs
+                .open(&path, O_RDONLY)
+                .map_err(fs_error("NoSuchKey"))?;
+            response.body = Box::new(FileReader {
+                file,
+                offset: start as i64,
+                remaining: length,
+            });
+        }
+        Ok(response)
+    }
+
+    fn delete_object(&self, bucket: &str, key: &str) -> Result<S3Response, S3Error> {
+        let bucket_path = self.check_bucket(bucket)?;
+        let path = self.object_path(&bucket_path, key)?;
+        let result = if key.ends_with('/') {
+            self.fs.rmdir(&path)
+        } else {
+            self.fs.unlink(&path)
+        };
+        if let Err(e) = result {
+            // Deleting what isn't there succeeds, as does a folder that
+            // still has objects in it
+            match errno().0 {
+                ENOENT | ENOTEMPTY => return Ok(S3Response::new(204)),
+                _ => return Err(fs_error("NoSuchKey")(e)),
+            }
+        }
+        self.prune_dirs(&bucket_path, &path);
+        Ok(S3Response::new(204))
+    }
+
+    fn list_objects(&self, bucket: &str, query: &Query) -> Result<S3Response, S3Error> {
+        let bucket_path = self.check_bucket(bucket)?;
+        let prefix = query.get("prefix").unwrap_or("");
+        let delimiter = query.get("delimiter").filter(|d| !d.is_empty());
+        let max_keys = match query.get("max-keys") {
+            Some(max) => max
+                .parse::<usize>()
+                .map_err(|_| S3Error::invalid("max-keys must be a number"))?
+                .min(MAX_KEYS),
+            None => MAX_KEYS,
+        };
+        let token = match query.get("continuation-token") {
+            Some(token) => Some(
+                hex_decode(token)
+                    .and_then(|key| String::from_utf8(key).ok())
+                    .ok_or_else(|| S3Error::invalid("bad continuation-token"))?,
+            ),
+            None => None,
+        };
+        let start = token
+            .clone()
+            .or_else(|| query.get("start-after").map(str::to_string));
+        let url_encode = query.get("encoding-type") == Some("url");
+        let encode = |text: &str| {
+            if url_encode {
+                xml_escape(&percent_encode(text))
+            } else {
+                xml_escape(text)
+            }
+        };
+
+        // Only the directory the prefix names in full needs walking, and
+        // with the usual / delimiter only its top level
+        let dir_prefix = match prefix.rfind('/') {
+            Some(i) => &prefix[..=i],
+            None => "",
+        };
+        let mut found = Vec::new();
+        if self.object_path(&bucket_path, dir_prefix).is_ok() {
+            self.walk(
+                &bucket_path.join(dir_prefix),
+                dir_prefix,
+                delimiter == Some("/"),
+                &mut found,
+            )?;
+        }
+        found.retain(|listed| listed.key.starts_with(prefix));
+        found.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let mut contents = String::new();
+        let mut common_prefixes = String::new();
+        let mut count = 0;
+        let mut last: Option<String> = None;
+        let mut truncated = false;
+        for listed in &found {
+            if let Some(ref start) = start {
+                if listed.key <= *start {
+                    continue;
+                }
+            }
+            // Keys with the delimiter after the prefix roll up into one
+            // common prefix, as does a directory when listing one level
+            let common = match (delimiter, listed.stat) {
+                (_, None) => Some(listed.key.clone()),
+                (Some(delimiter), Some(_)) => listed.key[prefix.len()..]
+                    .find(delimiter)
+                    .map(|i| listed.key[..prefix.len() + i + delimiter.len()].to_string()),
+                (None, Some(_)) => None,
+            };
+            if let Some(ref common) = common {
+                let seen = last.as_ref() == Some(common)
+                    || start
+                        .as_ref()
+                        .is_some_and(|start| start.starts_with(common.as_str()));
+                if seen {
+                    continue;
+                }
+            }
+            if count == max_keys {
+                truncated = true;
+                break;
+            }
+            count += 1;
+            match common {
+                Some(common) => {
+                    common_prefixes.push_str(&format!(
+                        "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                        encode(&common)
+                    ));
+                    last = Some(common);
+                }
+                None => {
+                    let st = listed.stat.unwrap();
+                    let meta =
+                        ObjectMeta::load(&self.fs, &bucket_path.join(&listed.key), Some(&st));
+                    contents.push_str(&format!(
+                        "<Contents><Key>{}</Key><LastModified>{}</LastModified>\
+                         <ETag>&quot;{}&quot;</ETag><Size>{}</Size>\
+                         <StorageClass>STANDARD</StorageClass></Contents>",
+                        encode(&listed.key),
+                        iso8601(st.st_mtime),
+                        xml_escape(&meta.etag),
+                        st.st_size
+                    ));
+                    last = Some(listed.key.clone());
+                }
+            }
+        }
+
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ListBucketResult xmlns=\"{}\">\
+             <Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys>\
+             <IsTruncated>{}</IsTruncated>",
+            XMLNS,
+            xml_escape(bucket),
+            encode(prefix),
+            count,
+            max_keys,
+            truncated
+        );
+        if let Some(delimiter) = delimiter {
+            xml.push_str(&format!("<Delimiter>{}</Delimiter>", encode(delimiter)));
+        }
+        if url_encode {
+            xml.push_str("<EncodingType>url</EncodingType>");
+        }
+        if let Some(token) = query.get("continuation-token") {
+            xml.push_str(&format!(
+                "<ContinuationToken>{}</ContinuationToken>",
+                xml_escape(token)
+            ));
+        }
+        if let Some(start_after) = query.get("start-after") {
+            xml.push_str(&format!("<StartAfter>{}</StartAfter>", encode(start_after)));
+        }
+        if let (true, Some(last)) = (truncated, last) {
+            xml.push_str(&format!(
+                "<NextContinuationToken>{}</NextContinuationToken>",
+                hex_encode(last.as_bytes())
+            ));
+        }
+        xml.push_str(&contents);
+        xml.push_str(&common_prefixes);
+        xml.push_str("</ListBucketResult>");
+        Ok(S3Response::xml(200, xml))
+    }
+
+    /// Collect the objects under dir, whose keys start with key_prefix.
+    /// When shallow, subdirectories are collected as prefixes instead of
+    /// being descended into.
+    fn walk(
+        &self,
+        dir: &Path,
+        key_prefix: &str,
+        shallow: bool,
+        found: &mut Vec<Listed>,
+    ) -> Result<(), S3Error> {
+        let entries = match self.fs.opendir_plus(dir) {
+            Ok(entries) => entries,
+            // A prefix naming nothing lists nothing
+            Err(_) if errno().0 == ENOENT || errno().0 == ENOTDIR => return Ok(()),
+            Err(e) => return Err(fs_error("NoSuchKey")(e)),
+        };
+        for entry in entries {
+            let entry = entry.map_err(fs_error("NoSuchKey"))?;
+            let name = match entry.path.to_str() {
+                Some(".") | Some("..") | None => continue,
+                Some(name) if name.starts_with(RESERVED_PREFIX) => continue,
+                Some(name) => name.to_string(),
+            };
+            let st = match entry.stat.st_ino {
+                // Gluster leaves the stat empty when it couldn't fetch one
+                0 => match self.fs.lsstat(&dir.join(&name)) {
+                    Ok(st) => st,
+                    Err(_) => continue,
+                },
+                _ => entry.stat,
+            };
+            let key = format!("{}{}", key_prefix, name);
+            if is_dir(&st) {
+                let key = key + "/";
+                if shallow {
+                    found.push(Listed { key, stat: None });
+                } else {
+                    self.walk(&dir.join(&name), &key, shallow, found)?;
+                }
+            } else if is_file(&st) {
+                found.push(Listed {
+                    key,
+                    stat: Some(st),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn upload_dir(&self, bucket_path: &Path, key: &str, query: &Query) -> Result<PathBuf, S3Error> {
+        let id = query.get("uploadId").unwrap_or("");
+        let no_upload = || S3Error::new(404, "NoSuchUpload", id);
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(no_upload());
+        }
+        let dir = bucket_path.join(UPLOADS_DIR).join(id);
+        match self.fs.getxattr_bytes(&dir, UPLOAD_KEY_KEY) {
+            Ok(ref target) if target.as_slice() == key.as_bytes() => Ok(dir),
+            _ => Err(no_upload()),
+        }
+    }
+
+    fn create_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        request: &S3Request<'_>,
+    ) -> Result<S3Response, S3Error> {
+        let bucket_path = self.check_bucket(bucket)?;
+        self.object_path(&bucket_path, key)?;
+        if key.ends_with('/') {
+            return Err(S3Error::invalid("keys ending in / must be empty"));
+        }
+        let id = new_id();
+        let dir = bucket_path.join(UPLOADS_DIR).join(&id);
+        self.make_dirs(&bucket_path, &dir)?;
+        let meta = ObjectMeta::from_headers(request);
+        let mut xattrs = meta.xattrs();
+        xattrs.push((UPLOAD_KEY_KEY, key.as_bytes().to_vec()));
+        for (name, value) in xattrs {
+            self.fs
+                .setxattr(&dir, name, &value, 0)
+                .map_err(fs_error("InternalError"))?;
+        }
+        Ok(S3Response::xml(
+            200,
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<InitiateMultipartUploadResult \
+                 xmlns=\"{}\"><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId>\
+                 </InitiateMultipartUploadResult>",
+                XMLNS,
+                xml_escape(bucket),
+                xml_escape(key),
+                id
+            ),
+        ))
+    }
+
+    fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        query: &Query,
+        request: &mut S3Request<'_>,
+    ) -> Result<S3Response, S3Error> {
+        let bucket_path = self.check_bucket(bucket)?;
+        let dir = self.upload_dir(&bucket_path, key, query)?;
+        let number = query
+            .get("partNumber")
+            .and_then(|n| n.parse::<u32>().ok())
+            .filter(|n| (1..=MAX_PART_NUMBER).contains(n))
+            .ok_or_else(|| S3Error::invalid("partNumber must be from 1 to 10000"))?;
+        // Parts are written aside first so a retried part replaces the
+        // earlier attempt in one go
+        let temp = dir.join(format!("tmp-{}", new_id()));
+        let file = self
+            .fs
+            .create(&temp, O_WRONLY | O_CREAT | O_EXCL, 0o644)
+            .map_err(fs_error("NoSuchUpload"))?;
+        let etag = copy_in(&file, request.body).and_then(|digest| {
+            let etag = format!("{:x}", digest);
+            file.fsetxattr(ETAG_KEY, etag.as_bytes(), 0)
+                .map_err(fs_error("InternalError"))?;
+            Ok(etag)
+        });
+        drop(file);
+        let etag = match etag {
+            Ok(etag) => etag,
+            Err(e) => {
+                let _ = self.fs.unlink(&temp);
+                return Err(e);
+            }
+        };
+        self.publish(&bucket_path, &temp, &part_path(&dir, number))?;
+        Ok(S3Response::new(200).header("ETag", format!("\"{}\"", etag)))
+    }
+
+    fn complete_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        query: &Query,
+        request: &mut S3Request<'_>,
+    ) -> Result<S3Response, S3Error> {
+        let bucket_path = self.check_bucket(bucket)?;
+        let dir = self.upload_dir(&bucket_path, key, query)?;
+        let path = self.object_path(&bucket_path, key)?;
+        let mut body = String::new();
+        request
+            .body
+            .take(1 << 20)
+            .read_to_string(&mut body)
+            .map_err(|e| S3Error::new(400, "MalformedXML", e.to_string()))?;
+        let parts = parse_parts(&body)?;
+        if parts.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(S3Error::new(
+                400,
+                "InvalidPartOrder",
+                "parts must be in ascending order",
+            ));
+        }
+        for (number, etag) in &parts {
+            let stored = self.fs.getxattr_bytes(&part_path(&dir, *number), ETAG_KEY);
+            if stored.ok().as_deref() != Some(etag.as_bytes()) {
+                return Err(S3Error::new(400, "InvalidPart", format!("part {}", number)));
+            }
+        }
+
+        let temp = dir.join(format!("tmp-{}", new_id()));
+        let file = self
+            .fs
+            .create(&temp, O_WRONLY | O_CREAT | O_EXCL, 0o644)
+            .map_err(fs_error("NoSuchUpload"))?;
+        let assembled = self.assemble(&file, &dir, &parts).and_then(|etag| {
+            let mut meta = ObjectMeta::load(&self.fs, &dir, None);
+            meta.etag = etag;
+            for (name, value) in meta.xattrs() {
+                file.fsetxattr(name, &value, 0)
+                    .map_err(fs_error("InternalError"))?;
+            }
+            file.fsync().map_err(fs_error("InternalError"))?;
+            Ok(meta.etag)
+        });
+        drop(file);
+        let etag = match assembled {
+            Ok(etag) => etag,
+            Err(e) => {
+                let _ = self.fs.unlink(&temp);
+                return Err(e);
+            }
+        };
+        self.publish(&bucket_path, &temp, &path)?;
+        if let Err(e) = self.fs.remove_dir_all(&dir) {
+            warn!("removing finished upload {} failed: {}", dir.display(), e);
+        }
+        Ok(S3Response::xml(
+            200,
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<CompleteMultipartUploadResult \
+                 xmlns=\"{}\"><Location>/{}/{}</Location><Bucket>{}</Bucket><Key>{}</Key>\
+                 <ETag>&quot;{}&quot;</ETag></CompleteMultipartUploadResult>",
+                XMLNS,
+                xml_escape(bucket),
+                xml_escape(&percent_encode(key)),
+                xml_escape(bucket),
+                xml_escape(key),
+                etag
+            ),
+        ))
+    }
+
+    /// Copy the parts one after the other into file and return the ETag
+    /// S3 gives the result: the MD5 of the parts' MD5s and their count
+    fn assemble(
+        &self,
+        file: &F::File,
+        dir: &Path,
+        parts: &[(u32, String)],
+    ) -> Result<String, S3Error> {
+        let mut md5 = md5::Context::new();
+        let mut offset = 0;
+        for (number, etag) in parts {
+            md5.consume(hex_decode(etag).unwrap_or_default());
+            let part = self
+                .fs
+                .open(&part_path(dir, *number), O_RDONLY)
+                .map_err(fs_error("NoSuchUpload"))?;
+            let mut part_offset = 0;
+            loop {
+                let mut chunk = Vec::with_capacity(COPY_CHUNK);
+                let read = part
+                    .pread(&mut chunk, COPY_CHUNK, part_offset, 0)
+                    .map_err(fs_error("NoSuchUpload"))?;
+                if read == 0 {
+                    break;
+                }
+                write_all(file, &chunk, offset)?;
+                part_offset += read as i64;
+                offset += read as i64;
+            }
+        }
+        Ok(format!("{:x}-{}", md5.compute(), parts.len()))
+    }
+
+    fn abort_upload(&self, bucket: &str, key: &str, query: &Query) -> Result<S3Response, S3Error> {
+        let bucket_path = self.check_bucket(bucket)?;
+        let dir = self.upload_dir(&bucket_path, key, query)?;
+        self.fs
+            .remove_dir_all(&dir)
+            .map_err(fs_error("NoSuchUpload"))?;
+        Ok(S3Response::new(204))
+    }
+}
+
+fn part_path(dir: &Path, number: u32) -> PathBuf {
+    dir.join(format!("{:05}", number))
+}
+
+/// The part numbers and ETags of a CompleteMultipartUpload body
+fn parse_parts(xml: &str) -> Result<Vec<(u32, String)>, S3Error> {
+    let malformed = || S3Error::new(400, "MalformedXML", "expected a list of parts");
+    let mut parts = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<Part>") {
+        let end = start + rest[start..].find("</Part>").ok_or_else(malformed)?;
+        let part = &rest[start..end];
+        let number = element(part, "PartNumber")
+            .and_then(|number| number.trim().parse().ok())
+            .ok_or_else(malformed)?;
+        let etag = element(part, "ETag").ok_or_else(malformed)?;
+        parts.push((
+            number,
+            xml_unescape(etag).trim().trim_matches('"').to_string(),
+        ));
+        rest = &rest[end + "</Part>".len()..];
+    }
+    if parts.is_empty() {
+        return Err(malformed());
+    }
+    Ok(parts)
+}
+
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(&xml[start..end])
+}
+
+/// A single byte range of an object of size bytes as (first, last).  last
+/// may lie past the end, and first there means the range can't be served.
+/// Anything else, like several ranges, is ignored the way S3 ignores it.
+fn parse_range(header: &str, size: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let dash = spec.find('-')?;
+    let (first, last) = (spec[..dash].trim(), spec[dash + 1..].trim());
+    match (first.is_empty(), last.is_empty()) {
+        (false, true) => Some((first.parse().ok()?, u64::MAX)),
+        (false, false) => {
+            let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+            if last < first {
+                return None;
+            }
+            Some((first, last))
+        }
+        // The last n bytes
+        (true, false) => match last.parse::<u64>().ok()? {
+            0 => Some((size, size)),
+            suffix => Some((size.saturating_sub(suffix), u64::MAX)),
+        },
+        (true, true) => None,
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn percent_decode(text: &str) -> Result<String, S3Error> {
+    let bad = || S3Error::new(400, "InvalidURI", text);
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get(i + 1..i + 3).ok_or_else(bad)?;
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| bad())?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| bad())
+}
+
+/// Encode everything but unreserved characters and /
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    let digit = |byte: u8| (byte as char).to_digit(16);
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| match *pair {
+            [high, low] => Some((digit(high)? << 4 | digit(low)?) as u8),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Split seconds since the epoch into a civil date and time of day, using
+/// Howard Hinnant's days_from_civil algorithm in reverse
+fn civil(secs: i64) -> (i64, u32, u32, i64, i64) {
+    let days = secs.div_euclid(86400);
+    let time = secs.rem_euclid(86400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day, days, time)
+}
+
+fn iso8601(secs: i64) -> String {
+    let (year, month, day, _, time) = civil(secs);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.000Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn http_date(secs: i64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (year, month, day, days, time) = civil(secs);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
@@ -0,0 +1,432 @@
+//! Gluster statedumps, triggered and parsed
+//! A statedump is gluster's snapshot of a process's internals: memory
+//! accounting per translator, mempools, inode and fd tables and the call
+//! frames still in flight.  Gluster::statedump has gfapi dump the client
+//! it's connected through, which is where to start looking for a leak in a
+//! long running client.
+use crate::gfid::Gfid;
+use crate::gluster::{get_error, Gluster, GlusterError, GlusterSysRq};
+use crate::logging::parse_timestamp;
+use errno::{set_errno, Errno};
+use libc::ENOENT;
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// One [section] of a dump with its key=value lines in order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub entries: Vec<(String, String)>,
+}
+
+impl Section {
+    /// The first value of key.  Tables prefix their keys with the section
+    /// name, so xlator.api.vol.itable.lru_size can be looked up as lru_size.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| {
+                k == key
+                    || k.strip_prefix(self.name.as_str())
+                        .and_then(|k| k.strip_prefix('.'))
+                        == Some(key)
+            })
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The first value of key parsed as T, None if missing or malformed
+    pub fn parse<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.trim().parse().ok()
+    }
+}
+
+/// Allocations of one memory type, as counted by gluster's memory
+/// accounting
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// For example gf_common_mt_inode_ctx
+    pub type_name: String,
+    /// Bytes currently allocated
+    pub size: u64,
+    pub num_allocs: u64,
+    pub max_size: u64,
+    pub max_num_allocs: u64,
+    /// Allocations ever made, including those since freed
+    pub total_allocs: u64,
+}
+
+/// A translator and what the dump has to say about it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Xlator {
+    /// For example protocol/client, or global for the process itself
+    pub kind: String,
+    pub name: String,
+    pub memory: Vec<MemoryUsage>,
+    /// The translator's own xlator.<kind>.<name> sections
+    pub sections: Vec<Section>,
+}
+
+impl Xlator {
+    /// Bytes currently allocated across every memory type
+    pub fn allocated(&self) -> u64 {
+        self.memory.iter().map(|usage| usage.size).sum()
+    }
+}
+
+/// A pool of preallocated objects from the [mempool] section
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemPool {
+    /// For example glusterfs:dict_t
+    pub name: String,
+    /// Objects handed out and not yet returned, active-count or hot-count
+    /// depending on the release
+    pub in_use: Option<u64>,
+    /// The size of each object including gluster's header
+    pub padded_size: Option<u64>,
+    /// Every key=value line of the pool, including the above
+    pub fields: Vec<(String, String)>,
+}
+
+/// An inode held in an inode table
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Inode {
+    /// The table list it's on: active, lru, purge or invalidate
+    pub list: String,
+    pub gfid: Option<Gfid>,
+    pub nlookup: Option<u64>,
+    pub fd_count: Option<u64>,
+    pub ref_count: Option<u64>,
+    /// Gluster's ia_type_t: 1 for a regular file, 2 for a directory
+    pub ia_type: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InodeTable {
+    /// The name of the table's section, for example xlator.api.vol.itable
+    pub name: String,
+    pub lru_limit: Option<u64>,
+    pub active_size: Option<u64>,
+    pub lru_size: Option<u64>,
+    pub purge_size: Option<u64>,
+    pub inodes: Vec<Inode>,
+}
+
+/// An open fd in an fd table
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Fd {
+    /// The slot in the table
+    pub index: usize,
+    pub pid: Option<i64>,
+    pub refcount: Option<u64>,
+    pub flags: Option<i32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FdTable {
+    /// The name of the table's section, for example xlator.api.vol.fdtable
+    pub name: String,
+    pub refcount: Option<u64>,
+    pub maxfds: Option<u64>,
+    pub first_free: Option<u64>,
+    pub fds: Vec<Fd>,
+}
+
+/// A frame of a call stack: one translator's part in a fop
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub translator: Option<String>,
+    pub complete: bool,
+    pub wind_from: Option<String>,
+    pub wind_to: Option<String>,
+    pub unwind_from: Option<String>,
+    pub unwind_to: Option<String>,
+}
+
+/// A fop still in flight
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallStack {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// Negative for fops gluster makes internally
+    pub pid: Option<i64>,
+    pub unique: Option<u64>,
+    /// For example LOOKUP
+    pub op: Option<String>,
+    pub frames: Vec<Frame>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallPool {
+    /// How many stacks the pool says it holds
+    pub count: Option<u64>,
+    pub stacks: Vec<CallStack>,
+}
+
+/// A parsed statedump
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateDump {
+    /// The file it was read from, if it was read from one
+    pub path: Option<PathBuf>,
+    pub start: Option<SystemTime>,
+    pub end: Option<SystemTime>,
+    /// Every section in the order written
+    pub sections: Vec<Section>,
+}
+
+impl StateDump {
+    pub fn open(path: &Path) -> Result<StateDump, GlusterError> {
+        let mut dump = StateDump::parse(BufReader::new(File::open(path)?))?;
+        dump.path = Some(path.to_path_buf());
+        Ok(dump)
+    }
+
+    pub fn parse<R: BufRead>(reader: R) -> Result<StateDump, GlusterError> {
+        let mut dump = StateDump::default();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if let Some(time) = line.strip_prefix("DUMP-START-TIME:") {
+                dump.start = parse_timestamp(time.trim());
+            } else if let Some(time) = line.strip_prefix("DUMP-END-TIME:") {
+                dump.end = parse_timestamp(time.trim());
+            } else if line.starts_with('[') && line.ends_with(']') {
+                dump.sections.push(Section {
+                    name: line[1..line.len() - 1].to_string(),
+                    entries: Vec::new(),
+                });
+            } else if let (Some((key, value)), Some(section)) =
+                (line.split_once('='), dump.sections.last_mut())
+            {
+                section.entries.push((key.to_string(), value.to_string()));
+            }
+        }
+        Ok(dump)
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// The sections named <prefix>.<rest>, with rest
+    fn children<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a Section)> {
+        self.sections.iter().filter_map(move |section| {
+            let rest = section.name.strip_prefix(prefix)?.strip_prefix('.')?;
+            Some((rest, section))
+        })
+    }
+
+    /// Every translator with memory accounting, global included, in the
+    /// order dumped
+    pub fn xlators(&self) -> Vec<Xlator> {
+        let mut xlators: Vec<Xlator> = Vec::new();
+        for section in &self.sections {
+            let (owner, what) = match section.name.split_once(" - ") {
+                Some(split) => split,
+                None => continue,
+            };
+            let (kind, name) = owner.split_once('.').unwrap_or((owner, ""));
+            let index = match xlators
+                .iter()
+                .position(|xl| xl.kind == kind && xl.name == name)
+            {
+                Some(index) => index,
+                None => {
+                    let prefix = format!("xlator.{}.{}", kind.replace('/', "."), name);
+                    xlators.push(Xlator {
+                        kind: kind.to_string(),
+                        name: name.to_string(),
+                        memory: Vec::new(),
+                        sections: self.children(&prefix).map(|(_, s)| s.clone()).collect(),
+                    });
+                    xlators.len() - 1
+                }
+            };
+            let type_name = what
+                .strip_prefix("usage-type ")
+                .and_then(|what| what.strip_suffix(" memusage"));
+            if let Some(type_name) = type_name {
+                xlators[index].memory.push(MemoryUsage {
+                    type_name: type_name.to_string(),
+                    size: section.parse("size").unwrap_or(0),
+                    num_allocs: section.parse("num_allocs").unwrap_or(0),
+                    max_size: section.parse("max_size").unwrap_or(0),
+                    max_num_allocs: section.parse("max_num_allocs").unwrap_or(0),
+                    total_allocs: section.parse("total_allocs").unwrap_or(0),
+                });
+            }
+        }
+        xlators
+    }
+
+    pub fn mempools(&self) -> Vec<MemPool> {
+        let mut pools: Vec<MemPool> = Vec::new();
+        let section = match self.section("mempool") {
+            Some(section) => section,
+            None => return pools,
+        };
+        for (key, value) in &section.entries {
+            if key == "pool-name" {
+                pools.push(MemPool {
+                    name: value.clone(),
+                    ..MemPool::default()
+                });
+            }
+            let pool = match pools.last_mut() {
+                Some(pool) if !key.starts_with("---") => pool,
+                _ => continue,
+            };
+            let number = value.trim().parse().ok();
+            match key.as_str() {
+                "active-count" | "hot-count" => pool.in_use = number,
+                "padded-sizeof" | "padded_sizeof" => pool.padded_size = number,
+                _ => {}
+            }
+            pool.fields.push((key.clone(), value.clone()));
+        }
+        pools
+    }
+
+    pub fn inode_tables(&self) -> Vec<InodeTable> {
+        self.sections
+            .iter()
+            .filter(|section| section.name.ends_with("itable"))
+            .map(|table| InodeTable {
+                name: table.name.clone(),
+                lru_limit: table.parse("lru_limit"),
+                active_size: table.parse("active_size"),
+                lru_size: table.parse("lru_size"),
+                purge_size: table.parse("purge_size"),
+                inodes: self
+                    .children(&table.name)
+                    .filter_map(|(rest, section)| {
+                        let (list, number) = rest.split_once('.')?;
+                        number.parse::<u64>().ok()?;
+                        Some(Inode {
+                            list: list.to_string(),
+                            gfid: section.get("gfid").and_then(|gfid| gfid.parse().ok()),
+                            nlookup: section.parse("nlookup"),
+                            fd_count: section.parse("fd-count"),
+                            ref_count: section.parse("ref"),
+                            ia_type: section.parse("ia_type"),
+                        })
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub fn fd_tables(&self) -> Vec<FdTable> {
+        self.sections
+            .iter()
+            .filter(|section| section.name.ends_with("fdtable"))
+            .map(|table| FdTable {
+                name: table.name.clone(),
+                refcount: table.parse("refcount"),
+                maxfds: table.parse("maxfds"),
+                first_free: table.parse("first_free"),
+                fds: self
+                    .children(&table.name)
+                    .filter_map(|(rest, section)| {
+                        let index = rest.strip_prefix("fdentry[")?.strip_suffix(']')?;
+                        Some(Fd {
+                            index: index.parse().ok()?,
+                            pid: section.parse("pid"),
+                            refcount: section.parse("refcount"),
+                            flags: section.parse("flags"),
+                        })
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// The fops in flight when the dump was taken, None if the dump has no
+    /// call pool
+    pub fn call_pool(&self) -> Option<CallPool> {
+        let pool = self.section("global.callpool")?;
+        let stacks = self
+            .children("global.callpool")
+            .filter(|(rest, _)| {
+                rest.strip_prefix("stack.")
+                    .is_some_and(|n| !n.contains('.'))
+            })
+            .map(|(_, stack)| CallStack {
+                uid: stack.parse("uid"),
+                gid: stack.parse("gid"),
+                pid: stack.parse("pid"),
+                unique: stack.parse("unique"),
+                op: stack.get("op").map(str::to_string),
+                frames: self
+                    .children(&stack.name)
+                    .filter(|(rest, _)| rest.starts_with("frame."))
+                    .map(|(_, frame)| Frame {
+                        translator: frame.get("translator").map(str::to_string),
+                        complete: frame.parse::<i32>("complete").is_some_and(|c| c != 0),
+                        wind_from: frame.get("wind_from").map(str::to_string),
+                        wind_to: frame.get("wind_to").map(str::to_string),
+                        unwind_from: frame.get("unwind_from").map(str::to_string),
+                        unwind_to: frame.get("unwind_to").map(str::to_string),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Some(CallPool {
+            count: pool.parse("callpool.cnt"),
+            stacks,
+        })
+    }
+}
+
+/// The modification times of the statedumps whose names start with prefix
+fn dumps_in(dir: &Path, prefix: &str) -> Result<HashMap<PathBuf, SystemTime>, GlusterError> {
+    let mut dumps = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with(prefix) {
+            dumps.insert(entry.path(), entry.metadata()?.modified()?);
+        }
+    }
+    Ok(dumps)
+}
+
+impl Gluster {
+    /// Have gfapi write a statedump of this client into dir and parse it.
+    /// The dump is left in dir as glusterdump.<pid>.dump.<seconds> for
+    /// the caller to keep or remove.  dir stays the handle's statedump
+    /// path afterwards, as if set with set_statedump_path, since gfapi
+    /// can't say what it was before.
+    pub fn statedump(&self, dir: &Path) -> Result<StateDump, GlusterError> {
+        let prefix = format!("glusterdump.{}.dump.", std::process::id());
+        self.set_statedump_path(dir)?;
+        let before = dumps_in(dir, &prefix)?;
+        self.sysrq(GlusterSysRq::StateDump)?;
+        // gfapi doesn't fail the sysrq when it couldn't write the dump, so
+        // only a dump that is new or changed since is ours.  Dumps in the
+        // same second overwrite each other.
+        let mut newest: Option<(SystemTime, PathBuf)> = None;
+        for (path, modified) in dumps_in(dir, &prefix)? {
+            if before.get(&path) == Some(&modified) {
+                continue;
+            }
+            let newer = match newest {
+                Some((time, _)) => modified >= time,
+                None => true,
+            };
+            if newer {
+                newest = Some((modified, path));
+            }
+        }
+        match newest {
+            Some((_, path)) => StateDump::open(&path),
+            None => {
+                set_errno(Errno(ENOENT));
+                Err(GlusterError::new(get_error()))
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! gluster:// URLs naming a path on a volume
+//! gluster://host[:port]/volume[/path] is what the command line tools take.
+//! The path is used as written rather than percent decoded so it can be
+//! pasted from a shell.
+use crate::gluster::{Gluster, GlusterError};
+use errno::{set_errno, Errno};
+use libc::EINVAL;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The port glusterd serves volfiles on
+pub const DEFAULT_PORT: u16 = 24007;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlusterUrl {
+    pub host: String,
+    pub port: u16,
+    pub volume: String,
+    /// Always absolute, / for the root of the volume
+    pub path: PathBuf,
+}
+
+fn invalid(url: &str, reason: &str) -> GlusterError {
+    set_errno(Errno(EINVAL));
+    GlusterError::new(format!("{}: {}", url, reason))
+}
+
+impl GlusterUrl {
+    pub fn parse(url: &str) -> Result<GlusterUrl, GlusterError> {
+        let rest = url
+            .strip_prefix("gluster://")
+            .ok_or_else(|| invalid(url, "expected gluster://host[:port]/volume/path"))?;
+        let (authority, rest) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => (rest, ""),
+        };
+        // IPv6 addresses are bracketed so their colons aren't the port's
+        let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+            let end = bracketed
+                .find(']')
+                .ok_or_else(|| invalid(url, "unterminated [ in host"))?;
+            (&bracketed[..end], bracketed[end + 1..].strip_prefix(':'))
+        } else {
+            match authority.rfind(':') {
+                Some(i) => (&authority[..i], Some(&authority[i + 1..])),
+                None => (authority, None),
+            }
+        };
+        if host.is_empty() {
+            return Err(invalid(url, "no host"));
+        }
+        let port = match port {
+            Some(port) => port.parse::<u16>().map_err(|_| invalid(url, "bad port"))?,
+            None => DEFAULT_PORT,
+        };
+        let (volume, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if volume.is_empty() {
+            return Err(invalid(url, "no volume"));
+        }
+        Ok(GlusterUrl {
+            host: host.to_string(),
+            port,
+            volume: volume.to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+
+    pub fn connect(&self) -> Result<Gluster, GlusterError> {
+        Gluster::connect(&self.volume, &self.host, self.port)
+    }
+
+    /// Whether both URLs are on the same volume of the same cluster
+    pub fn same_volume(&self, other: &GlusterUrl) -> bool {
+        self.host == other.host && self.port == other.port && self.volume == other.volume
+    }
+
+    /// The same volume with another path
+    pub fn with_path<P: AsRef<Path>>(&self, path: P) -> GlusterUrl {
+        GlusterUrl {
+            path: Path::new("/").join(path),
+            ..self.clone()
+        }
+    }
+
+    /// Resolve another argument naming a path on this volume, either a
+    /// URL of the same volume or a path from the volume's root
+    pub fn resolve(&self, other: &str) -> Result<PathBuf, GlusterError> {
+        if other.starts_with("gluster://") {
+            let url = GlusterUrl::parse(other)?;
+            if !self.same_volume(&url) {
+                return Err(invalid(other, "not on the same volume"));
+            }
+            return Ok(url.path);
+        }
+        Ok(Path::new("/").join(other))
+    }
+}
+
+impl FromStr for GlusterUrl {
+    type Err = GlusterError;
+
+    fn from_str(url: &str) -> Result<GlusterUrl, GlusterError> {
+        GlusterUrl::parse(url)
+    }
+}
+
+impl fmt::Display for GlusterUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "gluster://[{}]", self.host)?;
+        } else {
+            write!(f, "gluster://{}", self.host)?;
+        }
+        if self.port != DEFAULT_PORT {
+            write!(f, ":{}", self.port)?;
+        }
+        write!(f, "/{}{}", self.volume, self.path.display())
+    }
+}
@@ -0,0 +1,85 @@
+use gfapi_sys::acl::*;
+
+fn rwx(bits: u16) -> AclPerms {
+    AclPerms::from_bits(bits)
+}
+
+#[test]
+fn acl_xattr_round_trip() {
+    // Written in a scrambled order on purpose, Acl sorts the entries
+    let acl = Acl::new(vec![
+        AclEntry {
+            tag: AclTag::Other,
+            perms: rwx(0o0),
+        },
+        AclEntry {
+            tag: AclTag::Group(100),
+            perms: rwx(0o5),
+        },
+        AclEntry {
+            tag: AclTag::UserObj,
+            perms: rwx(0o7),
+        },
+        AclEntry {
+            tag: AclTag::Mask,
+            perms: rwx(0o7),
+        },
+        AclEntry {
+            tag: AclTag::User(1000),
+            perms: rwx(0o6),
+        },
+        AclEntry {
+            tag: AclTag::GroupObj,
+            perms: rwx(0o5),
+        },
+    ])
+    .unwrap();
+    assert_eq!(
+        acl.to_string(),
+        "user::rwx\nuser:1000:rw-\ngroup::r-x\ngroup:100:r-x\nmask::rwx\nother::---\n"
+    );
+
+    let buf = acl.to_xattr();
+    // Same bytes setfacl -m u:1000:rw,g:100:rx produces on linux
+    assert_eq!(&buf[..4], &[2, 0, 0, 0]);
+    assert_eq!(&buf[4..12], &[1, 0, 7, 0, 0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(&buf[12..20], &[2, 0, 6, 0, 0xe8, 0x03, 0, 0]);
+    assert_eq!(buf.len(), 4 + 6 * 8);
+    assert_eq!(Acl::from_xattr(&buf).unwrap(), acl);
+}
+
+#[test]
+fn acl_from_mode() {
+    let acl = Acl::from_mode(0o100754);
+    assert_eq!(acl.to_string(), "user::rwx\ngroup::r-x\nother::r--\n");
+    assert_eq!(Acl::from_xattr(&acl.to_xattr()).unwrap(), acl);
+}
+
+#[test]
+fn invalid_acls() {
+    let user = AclEntry {
+        tag: AclTag::UserObj,
+        perms: rwx(0o7),
+    };
+    let group = AclEntry {
+        tag: AclTag::GroupObj,
+        perms: rwx(0o7),
+    };
+    let other = AclEntry {
+        tag: AclTag::Other,
+        perms: rwx(0o7),
+    };
+    let named = AclEntry {
+        tag: AclTag::User(1000),
+        perms: rwx(0o7),
+    };
+    assert!(Acl::new(vec![user, group]).is_err());
+    assert!(Acl::new(vec![user, user, group, other]).is_err());
+    // Named entries need a mask
+    assert!(Acl::new(vec![user, named, group, other]).is_err());
+    assert!(Acl::new(vec![]).unwrap().is_empty());
+
+    assert!(Acl::from_xattr(&[2, 0, 0, 0, 1]).is_err());
+    assert!(Acl::from_xattr(&[1, 0, 0, 0]).is_err());
+    assert!(Acl::from_xattr(&[2, 0, 0, 0, 0x40, 0, 7, 0, 0, 0, 0, 0]).is_err());
+}
@@ -0,0 +1,27 @@
+//! Hooks the stub library in gfapi-stub exports for the tests run against
+//! it.  Not every test uses all of them.
+#![allow(dead_code)]
+
+use libc::{c_char, c_int, c_void, gid_t, size_t, uid_t};
+
+use std::ffi::CString;
+
+#[link(name = "gfapi")]
+extern "C" {
+    pub fn gfapi_stub_inject(function: *const c_char, errno: c_int, count: c_int);
+    pub fn gfapi_stub_set_volfile(volfile: *const c_void, len: size_t);
+    pub fn gfapi_stub_fs_ids(
+        uid: *mut uid_t,
+        gid: *mut gid_t,
+        groups: *mut gid_t,
+        len: size_t,
+    ) -> size_t;
+    pub fn gfapi_stub_unknown_d_type(on: c_int);
+}
+
+/// Fail the next count calls to function on this thread with errno code,
+/// or every call if count is 0
+pub fn inject(function: &str, code: i32, count: i32) {
+    let function = CString::new(function).unwrap();
+    unsafe { gfapi_stub_inject(function.as_ptr(), code, count) };
+}
@@ -0,0 +1,164 @@
+//! Mounts a stub-backed volume, so it needs GFAPI_STUB_DIR as described in
+//! tests/stub.rs, the fuse feature and permission to mount FUSE
+//! filesystems.  Where mounting isn't allowed the tests return early.
+#![cfg(all(feature = "fuse", gfapi_stub))]
+
+use gfapi_sys::fuse::{FuseOptions, GlusterFuse};
+use gfapi_sys::gluster::Gluster;
+use libc::{c_char, ERANGE, EROFS, O_RDWR};
+
+use std::ffi::CString;
+use std::fs;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+fn mountpoint(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gfapi-fuse-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn mount(cluster: Gluster, options: FuseOptions, dir: &Path) -> Option<fuser::BackgroundSession> {
+    let fs = GlusterFuse::new(cluster, options).unwrap();
+    match fs.spawn_mount(dir) {
+        Ok(session) => Some(session),
+        Err(e) => {
+            eprintln!("skipping, can't mount FUSE here: {}", e);
+            None
+        }
+    }
+}
+
+#[test]
+fn files_round_trip_through_the_mount() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    cluster.mkdir(Path::new("/served"), 0o755).unwrap();
+    cluster.mkdir(Path::new("/served/dir"), 0o755).unwrap();
+    cluster
+        .create(Path::new("/outside"), O_RDWR, 0o644)
+        .unwrap();
+    let options = FuseOptions {
+        subdir: PathBuf::from("served"),
+        ..FuseOptions::default()
+    };
+    let dir = mountpoint("rw");
+    let session = match mount(cluster, options, &dir) {
+        Some(session) => session,
+        None => return,
+    };
+
+    assert_eq!(fs::metadata(&dir).unwrap().ino(), 1);
+    fs::write(dir.join("dir/file"), b"hello world").unwrap();
+    assert_eq!(fs::read(dir.join("dir/file")).unwrap(), b"hello world");
+    assert_eq!(fs::metadata(dir.join("dir/file")).unwrap().len(), 11);
+
+    fs::rename(dir.join("dir"), dir.join("moved")).unwrap();
+    std::os::unix::fs::symlink("file", dir.join("moved/link")).unwrap();
+    assert_eq!(fs::read(dir.join("moved/link")).unwrap(), b"hello world");
+
+    let mut names = fs::read_dir(dir.join("moved"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["file", "link"]);
+
+    // Renaming over a file leaves only the moved inode at the name
+    fs::write(dir.join("moved/old"), b"replaced").unwrap();
+    fs::write(dir.join("moved/new"), b"moved").unwrap();
+    let old = fs::metadata(dir.join("moved/old")).unwrap().ino();
+    let new = fs::metadata(dir.join("moved/new")).unwrap();
+    let (new, mode) = (new.ino(), new.mode());
+    let replaced = fs::File::open(dir.join("moved/old")).unwrap();
+    fs::rename(dir.join("moved/new"), dir.join("moved/old")).unwrap();
+    assert_eq!(fs::metadata(dir.join("moved/old")).unwrap().ino(), new);
+    assert_ne!(new, old);
+    assert_eq!(fs::read(dir.join("moved/old")).unwrap(), b"moved");
+    // Nor is the moved file reached through the replaced one
+    let _ = replaced.set_permissions(fs::Permissions::from_mode(0o600));
+    assert_eq!(fs::metadata(dir.join("moved/old")).unwrap().mode(), mode);
+    drop(replaced);
+    fs::remove_file(dir.join("moved/old")).unwrap();
+
+    // Writes through a file opened before a rename show at its new name
+    let mut open = fs::File::create(dir.join("moved/before")).unwrap();
+    fs::rename(dir.join("moved/before"), dir.join("moved/after")).unwrap();
+    assert_eq!(fs::metadata(dir.join("moved/after")).unwrap().len(), 0);
+    open.write_all(b"written").unwrap();
+    assert_eq!(fs::metadata(dir.join("moved/after")).unwrap().len(), 7);
+    drop(open);
+    fs::remove_file(dir.join("moved/after")).unwrap();
+
+    // Only the subdirectory is served
+    assert!(!dir.join("outside").exists());
+    assert!(!dir.join("../outside").exists());
+
+    fs::remove_file(dir.join("moved/link")).unwrap();
+    fs::remove_file(dir.join("moved/file")).unwrap();
+    fs::remove_dir(dir.join("moved")).unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    drop(session);
+}
+
+#[test]
+fn read_only_mounts_refuse_changes() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let file = cluster.create(Path::new("/file"), O_RDWR, 0o644).unwrap();
+    file.write(b"data", 0).unwrap();
+    drop(file);
+    let options = FuseOptions {
+        read_only: true,
+        ..FuseOptions::default()
+    };
+    let dir = mountpoint("ro");
+    let session = match mount(cluster, options, &dir) {
+        Some(session) => session,
+        None => return,
+    };
+
+    assert_eq!(fs::read(dir.join("file")).unwrap(), b"data");
+    let error = fs::write(dir.join("file"), b"changed").unwrap_err();
+    assert_eq!(error.raw_os_error(), Some(EROFS));
+    let error = fs::create_dir(dir.join("new")).unwrap_err();
+    assert_eq!(error.raw_os_error(), Some(EROFS));
+    drop(session);
+}
+
+#[test]
+fn xattrs_are_listed_through_the_mount() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    cluster.mkdir(Path::new("/listed"), 0o755).unwrap();
+    cluster
+        .create(Path::new("/listed/file"), O_RDWR, 0o644)
+        .unwrap();
+    cluster
+        .setxattr(Path::new("/listed/file"), "user.colour", b"blue", 0)
+        .unwrap();
+    let options = FuseOptions {
+        subdir: PathBuf::from("listed"),
+        ..FuseOptions::default()
+    };
+    let dir = mountpoint("xattr");
+    let session = match mount(cluster, options, &dir) {
+        Some(session) => session,
+        None => return,
+    };
+
+    let file = CString::new(dir.join("file").as_os_str().as_bytes()).unwrap();
+    let size = unsafe { libc::listxattr(file.as_ptr(), std::ptr::null_mut(), 0) };
+    assert!(size > 0);
+    let mut names = vec![0 as c_char; size as usize];
+    let read = unsafe { libc::listxattr(file.as_ptr(), names.as_mut_ptr(), names.len()) };
+    assert_eq!(read, size);
+    let names = names.iter().map(|c| *c as u8).collect::<Vec<_>>();
+    assert!(names.split(|b| *b == 0).any(|name| name == b"user.colour"));
+
+    // Too small a buffer is refused rather than truncated
+    let mut short = vec![0 as c_char; 1];
+    let read = unsafe { libc::listxattr(file.as_ptr(), short.as_mut_ptr(), short.len()) };
+    assert_eq!(read, -1);
+    assert_eq!(errno::errno().0, ERANGE);
+    drop(session);
+}
@@ -0,0 +1,153 @@
+//! Runs the gfcli binary against stub volumes, so it needs GFAPI_STUB_DIR
+//! as described in tests/stub.rs.  GFAPI_STUB_ROOT makes each invocation
+//! see the same volume.
+#![cfg(all(feature = "cli", gfapi_stub))]
+
+use serde_json::Value;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+struct Volume {
+    root: PathBuf,
+}
+
+impl Volume {
+    fn new(name: &str) -> Volume {
+        let root = std::env::temp_dir().join(format!("gfcli-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        Volume { root }
+    }
+
+    fn run(&self, args: &[&str], stdin: &[u8]) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_gfcli"))
+            .args(args)
+            .env("GFAPI_STUB_ROOT", &self.root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(stdin).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Run a command that must succeed and return its output
+    fn ok(&self, args: &[&str]) -> String {
+        let output = self.run(args, b"");
+        assert!(
+            output.status.success(),
+            "{:?}: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn json(&self, args: &[&str]) -> Value {
+        let mut args = args.to_vec();
+        args.insert(0, "--json");
+        serde_json::from_str(&self.ok(&args)).unwrap()
+    }
+}
+
+impl Drop for Volume {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+const URL: &str = "gluster://localhost/test";
+
+fn url(path: &str) -> String {
+    format!("{}{}", URL, path)
+}
+
+#[test]
+fn files_round_trip() {
+    let volume = Volume::new("files");
+    volume.ok(&["mkdir", "-p", &url("/a/b/c")]);
+    // -p accepts what already exists
+    volume.ok(&["mkdir", "-p", &url("/a/b")]);
+    assert!(!volume.run(&["mkdir", &url("/a/b")], b"").status.success());
+
+    let output = volume.run(&["put", "-", &url("/a/b/hello")], b"hello world");
+    assert!(output.status.success());
+    assert_eq!(volume.ok(&["cat", &url("/a/b/hello")]), "hello world");
+
+    let local = volume.root.with_extension("download");
+    volume.ok(&["get", &url("/a/b/hello"), local.to_str().unwrap()]);
+    assert_eq!(fs::read(&local).unwrap(), b"hello world");
+    // Putting into a directory keeps the local name
+    volume.ok(&["put", local.to_str().unwrap(), &url("/a")]);
+    let name = local.file_name().unwrap().to_str().unwrap();
+    assert_eq!(
+        volume.ok(&["cat", &url(&format!("/a/{}", name))]),
+        "hello world"
+    );
+    fs::remove_file(&local).unwrap();
+
+    volume.ok(&["mv", &url("/a/b/hello"), "/a/b/c"]);
+    volume.ok(&["ln", "-s", "c/hello", &url("/a/b/link")]);
+    volume.ok(&["ln", "/a/b/c/hello", &url("/a/hard")]);
+    assert_eq!(volume.ok(&["ls", &url("/a/b")]), "c\nlink\n");
+    let long = volume.ok(&["ls", "-l", &url("/a/b")]);
+    assert!(long.contains("link -> c/hello"), "{}", long);
+    assert!(long.starts_with("d"), "{}", long);
+
+    let stat = volume.json(&["stat", &url("/a/hard")]);
+    assert_eq!(stat[0]["type"], "file");
+    assert_eq!(stat[0]["size"], 11);
+    assert_eq!(stat[0]["nlink"], 2);
+
+    volume.ok(&["chmod", "600", &url("/a/hard")]);
+    let listing = volume.json(&["ls", &url("/a")]);
+    let entries = listing[0]["entries"].as_array().unwrap();
+    let hard = entries
+        .iter()
+        .find(|entry| entry["name"] == "hard")
+        .unwrap();
+    assert_eq!(hard["mode"], "0600");
+
+    assert!(!volume.run(&["rm", &url("/a")], b"").status.success());
+    volume.ok(&["rm", "-r", &url("/a")]);
+    volume.ok(&["rm", "-f", &url("/a")]);
+    assert_eq!(volume.ok(&["ls", &url("/")]), "");
+}
+
+#[test]
+fn xattrs_and_volume_info() {
+    let volume = Volume::new("info");
+    volume.run(&["put", "-", &url("/f")], b"");
+    volume.ok(&["xattr", "set", &url("/f"), "user.colour", "blue"]);
+    assert_eq!(
+        volume.ok(&["xattr", "get", &url("/f"), "user.colour"]),
+        "blue\n"
+    );
+    assert_eq!(
+        volume.ok(&["xattr", "get", "--hex", &url("/f"), "user.colour"]),
+        "0x626c7565\n"
+    );
+    let names = volume.json(&["xattr", "list", &url("/f")]);
+    assert!(names
+        .as_array()
+        .unwrap()
+        .contains(&Value::from("user.colour")));
+
+    let df = volume.json(&["df", &url("/")]);
+    assert!(df["size"].as_u64().unwrap() > 0);
+    assert!(volume.ok(&["volfile", URL]).contains("volume"));
+    let id = volume.json(&["volume-id", URL]);
+    assert_eq!(id["volume_id"].as_str().unwrap().len(), 36);
+
+    let output = volume.run(&["cat", &url("/missing")], b"");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("/missing"), "{}", stderr);
+    let output = volume.run(&["cat", "localhost/test/f"], b"");
+    assert!(!output.status.success());
+    assert!(!Path::new(&volume.root.join("missing")).exists());
+}
@@ -0,0 +1,35 @@
+use gfapi_sys::gfid::*;
+
+use std::path::{Path, PathBuf};
+
+#[test]
+fn gfid_round_trip() {
+    let gfid: Gfid = "6f1b2c64-3c57-4a09-9e10-2e52b5f0e1c3".parse().unwrap();
+    assert_eq!(gfid.to_string(), "6f1b2c64-3c57-4a09-9e10-2e52b5f0e1c3");
+    assert_eq!(Gfid::from_bytes(gfid.as_bytes()).unwrap(), gfid);
+    assert_eq!(
+        gfid.virtual_path(),
+        Path::new("/.gfid/6f1b2c64-3c57-4a09-9e10-2e52b5f0e1c3")
+    );
+    assert!(!gfid.is_root());
+    assert_eq!(
+        Gfid::root().to_string(),
+        "00000000-0000-0000-0000-000000000001"
+    );
+    assert!("not-a-gfid".parse::<Gfid>().is_err());
+    assert!(Gfid::from_bytes(&[1, 2, 3]).is_err());
+}
+
+#[test]
+fn gfid2path_value() {
+    let (parent, name) =
+        parse_gfid2path(b"00000000-0000-0000-0000-000000000001/report.txt\0").unwrap();
+    assert!(parent.is_root());
+    assert_eq!(name, PathBuf::from("report.txt"));
+
+    // Names are kept byte for byte even when they aren't UTF-8
+    let (_, name) = parse_gfid2path(b"6f1b2c64-3c57-4a09-9e10-2e52b5f0e1c3/caf\xe9").unwrap();
+    assert_eq!(name.as_os_str().len(), 4);
+
+    assert!(parse_gfid2path(b"report.txt").is_err());
+}
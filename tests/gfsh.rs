@@ -0,0 +1,105 @@
+//! Runs the gfsh binary against stub volumes with commands piped to its
+//! stdin, so it needs GFAPI_STUB_DIR as described in tests/stub.rs.
+#![cfg(all(feature = "shell", gfapi_stub))]
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+struct Volume {
+    root: PathBuf,
+    local: PathBuf,
+}
+
+impl Volume {
+    fn new(name: &str) -> Volume {
+        let base = std::env::temp_dir().join(format!("gfsh-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let (root, local) = (base.join("volume"), base.join("local"));
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&local).unwrap();
+        Volume { root, local }
+    }
+
+    /// Run a session in the local directory, starting in dir on the volume
+    fn session(&self, dir: &str, script: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_gfsh"))
+            .arg("--history")
+            .arg(self.local.join(".history"))
+            .arg(format!("gluster://localhost/vol{}", dir))
+            .current_dir(&self.local)
+            .env("GFAPI_STUB_ROOT", &self.root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+}
+
+impl Drop for Volume {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(self.root.parent().unwrap());
+    }
+}
+
+#[test]
+fn working_directory_and_transfers() {
+    let volume = Volume::new("transfers");
+    fs::create_dir(volume.root.join("start")).unwrap();
+    fs::write(volume.local.join("with space.txt"), "hello\n").unwrap();
+
+    let output = volume.session(
+        "/start",
+        "pwd\n\
+         mkdir -p a/b\n\
+         cd a\n\
+         pwd\n\
+         put 'with space.txt' b\n\
+         cd b\n\
+         cat with\\ space.txt\n\
+         mv \"with space.txt\" ../moved.txt\n\
+         cd ..\n\
+         ls\n\
+         get moved.txt\n",
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "/start");
+    assert_eq!(lines[1], "/start/a");
+    assert!(lines.contains(&"hello"), "{}", stdout);
+    assert!(lines.contains(&"moved.txt"), "{}", stdout);
+    assert_eq!(
+        fs::read_to_string(volume.root.join("start/a/moved.txt")).unwrap(),
+        "hello\n"
+    );
+    assert_eq!(
+        fs::read_to_string(volume.local.join("moved.txt")).unwrap(),
+        "hello\n"
+    );
+}
+
+#[test]
+fn errors_keep_the_session_going() {
+    let volume = Volume::new("errors");
+    fs::create_dir_all(volume.root.join("dir/sub")).unwrap();
+
+    let output = volume.session("/", "rm dir\ncd missing\nfrobnicate\nrm -r dir\nls -a\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is a directory"), "{}", stderr);
+    assert!(stderr.contains("missing"), "{}", stderr);
+    assert!(stderr.contains("unknown command frobnicate"), "{}", stderr);
+    // The last command worked, so the session did too
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!volume.root.join("dir").exists());
+}
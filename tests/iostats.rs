@@ -0,0 +1,138 @@
+//! Parsing io-stats dumps.  The last test runs against the stub library as
+//! described in tests/stub.rs.
+use gfapi_sys::iostats::{BlockSizeCount, IoStats};
+
+use std::time::Duration;
+
+const DUMP: &str = "
+=== Cumulative stats ===
+      Duration : 3600 secs
+     BytesRead : 1048576
+  BytesWritten : 2048
+
+Block Size   :               1B+            1024B+          131072B+
+Read Count   :                 0                 0                 8
+Write Count  :                 3                 2                 0
+
+Block Size   :           262144B+
+Read Count   :                 1
+Write Count  :                 0
+
+Fop           Call Count    Avg-Latency    Min-Latency    Max-Latency
+---           ----------    -----------    -----------    -----------
+WRITE                  5      250.00 us     100.00 us      900.00 us
+READ                   9     1500.50 us     800.00 us     4000.00 us
+FLUSH                  2           0 us           0 us           0 us
+------ ----- ----- ----- ----- ----- ----- ----- ----- ----- -----
+
+Current open fd's: 3 Max open fd's: 12 time 2026-10-17 10:00:00.000001
+
+==========Open File Stats========
+
+COUNT:  \t  FILE NAME
+=== Interval 7 stats ===
+      Duration : 30 secs
+     BytesRead : 0
+  BytesWritten : 1024
+
+Block Size   :            1024B+
+Read Count   :                 0
+Write Count  :                 1
+
+Fop           Call Count    Avg-Latency    Min-Latency    Max-Latency
+---           ----------    -----------    -----------    -----------
+WRITE                  1      300.00 us     300.00 us      300.00 us
+------ ----- ----- ----- ----- ----- ----- ----- ----- ----- -----
+";
+
+#[test]
+fn parse_io_stats() {
+    let stats = IoStats::parse(DUMP.as_bytes()).unwrap();
+
+    let cumulative = stats.cumulative.unwrap();
+    assert_eq!(cumulative.interval, None);
+    assert_eq!(cumulative.duration, Duration::from_secs(3600));
+    assert_eq!(cumulative.bytes_read, 1_048_576);
+    assert_eq!(cumulative.bytes_written, 2048);
+    assert_eq!(
+        cumulative.block_sizes,
+        vec![
+            BlockSizeCount {
+                size: 1,
+                reads: 0,
+                writes: 3
+            },
+            BlockSizeCount {
+                size: 1024,
+                reads: 0,
+                writes: 2
+            },
+            BlockSizeCount {
+                size: 131_072,
+                reads: 8,
+                writes: 0
+            },
+            BlockSizeCount {
+                size: 262_144,
+                reads: 1,
+                writes: 0
+            },
+        ]
+    );
+    assert_eq!(cumulative.fops.len(), 3);
+    let read = cumulative.fop("READ").unwrap();
+    assert_eq!(read.calls, 9);
+    assert_eq!(read.avg_latency, Duration::from_nanos(1_500_500));
+    assert_eq!(read.min_latency, Duration::from_micros(800));
+    assert_eq!(read.max_latency, Duration::from_millis(4));
+    assert_eq!(cumulative.fop("FLUSH").unwrap().calls, 2);
+    assert_eq!(
+        cumulative.fop("FLUSH").unwrap().max_latency,
+        Duration::from_secs(0)
+    );
+    assert!(cumulative.fop("LOOKUP").is_none());
+    assert_eq!(cumulative.open_fds, Some(3));
+    assert_eq!(cumulative.max_open_fds, Some(12));
+
+    let interval = stats.interval.unwrap();
+    assert_eq!(interval.interval, Some(7));
+    assert_eq!(interval.duration, Duration::from_secs(30));
+    assert_eq!(interval.bytes_written, 1024);
+    assert_eq!(interval.block_sizes.len(), 1);
+    assert_eq!(interval.fops.len(), 1);
+    assert_eq!(
+        interval.fop("WRITE").unwrap().avg_latency,
+        Duration::from_micros(300)
+    );
+    assert_eq!(interval.open_fds, None);
+}
+
+#[test]
+fn parse_empty_io_stats() {
+    let stats = IoStats::parse("".as_bytes()).unwrap();
+    assert_eq!(stats, IoStats::default());
+}
+
+#[cfg(gfapi_stub)]
+#[test]
+fn io_stats_from_gfapi() {
+    use gfapi_sys::gluster::Gluster;
+
+    let dir = std::env::temp_dir().join(format!("gfapi-io-stats-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::env::set_var("GFAPI_STUB_RUN_DIR", &dir);
+    let cluster = Gluster::connect("statvol", "localhost", 24007).unwrap();
+
+    let stats = cluster.io_stats_in(&dir).unwrap();
+    let cumulative = stats.cumulative.unwrap();
+    assert_eq!(cumulative.fop("WRITE").unwrap().calls, 9);
+    assert_eq!(cumulative.block_sizes.len(), 4);
+    assert_eq!(stats.interval.unwrap().interval, Some(3));
+    // The dump is removed once read
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    // Where io-stats didn't write anything
+    let elsewhere = dir.join("elsewhere");
+    assert!(cluster.io_stats_in(&elsewhere).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,70 @@
+use gfapi_sys::fs::{GlusterFileOps, GlusterFs};
+use gfapi_sys::gfid::{Gfid, GFID_KEY};
+use gfapi_sys::localfs::{LocalFs, PATHINFO_KEY};
+use libc::{O_RDONLY, O_RDWR};
+
+use std::path::{Path, PathBuf};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gfapi-localfs-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn paths_stay_inside_the_root() {
+    let dir = scratch_dir("paths");
+    let fs = LocalFs::new(&dir).unwrap();
+    fs.mkdir(Path::new("/sub"), 0o755).unwrap();
+    fs.chdir(Path::new("sub")).unwrap();
+    assert_eq!(fs.getcwd().unwrap(), "/sub");
+
+    let file = fs.create(Path::new("../../../f"), O_RDWR, 0o644).unwrap();
+    file.write(b"hello", 0).unwrap();
+    drop(file);
+    assert_eq!(std::fs::read(dir.join("f")).unwrap(), b"hello");
+
+    let file = fs.open(Path::new("/f"), O_RDONLY).unwrap();
+    let mut buf = Vec::new();
+    file.pread(&mut buf, 16, 1, 0).unwrap();
+    assert_eq!(buf, b"ello");
+
+    let names: Vec<PathBuf> = fs
+        .opendir(Path::new("/"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path)
+        .collect();
+    assert!(names.contains(&PathBuf::from("sub")));
+    assert!(names.contains(&PathBuf::from("f")));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn virtual_xattrs_are_deterministic() {
+    let dir = scratch_dir("xattrs");
+    let fs = LocalFs::new(&dir).unwrap();
+    fs.create(Path::new("/f"), O_RDWR, 0o644).unwrap();
+
+    let root = fs.getxattr_bytes(Path::new("/"), GFID_KEY).unwrap();
+    assert!(Gfid::from_bytes(&root).unwrap().is_root());
+    let gfid = fs.getxattr_bytes(Path::new("/f"), GFID_KEY).unwrap();
+    assert_eq!(gfid.len(), 16);
+    assert_eq!(gfid, fs.getxattr_bytes(Path::new("/f"), GFID_KEY).unwrap());
+    assert_ne!(gfid, root);
+
+    let file = fs.open(Path::new("/f"), O_RDONLY).unwrap();
+    assert_eq!(
+        fs.getxattr(Path::new("/f"), PATHINFO_KEY).unwrap(),
+        file.fgetxattr(PATHINFO_KEY).unwrap()
+    );
+    assert!(fs
+        .getxattr(Path::new("/f"), PATHINFO_KEY)
+        .unwrap()
+        .ends_with(&format!(
+            "{}>)",
+            dir.canonicalize().unwrap().join("f").display()
+        )));
+    assert!(fs.setxattr(Path::new("/f"), GFID_KEY, &gfid, 0).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,243 @@
+//! Parsing gluster's logs and forwarding them to the log crate.  The last
+//! test runs against the stub library as described in tests/stub.rs.
+use gfapi_sys::gluster::GlusterLogLevel;
+use gfapi_sys::logging::{forward_log, LogEntry, LogReader, LogRecord};
+use log::{Level, Log, Metadata, Record};
+
+use std::sync::{Mutex, Once};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Keeps every message logged as (level, target, message)
+struct Captured(Mutex<Vec<(Level, String, String)>>);
+
+impl Log for Captured {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        self.0.lock().unwrap().push((
+            record.level(),
+            record.target().to_string(),
+            record.args().to_string(),
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+static CAPTURED: Captured = Captured(Mutex::new(Vec::new()));
+static INSTALL: Once = Once::new();
+
+fn install() {
+    INSTALL.call_once(|| {
+        log::set_logger(&CAPTURED).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+}
+
+/// What has been logged with targets under prefix
+fn captured(prefix: &str) -> Vec<(Level, String, String)> {
+    CAPTURED
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, target, _)| target.starts_with(prefix))
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn parse_log_lines() {
+    let record = LogRecord::parse_line(
+        "[2026-10-17 10:00:00.123456 +0000] E [MSGID: 114031] \
+         [client-rpc-fops.c:1234:client4_0_lookup_cbk] 0-vol-client-0: \
+         remote operation failed [Transport endpoint is not connected]",
+    )
+    .unwrap();
+    assert_eq!(
+        record.timestamp,
+        UNIX_EPOCH + Duration::new(1_792_231_200, 123_456_000)
+    );
+    assert_eq!(record.level, GlusterLogLevel::Error);
+    assert_eq!(record.log_level(), Level::Error);
+    assert_eq!(record.msgid, Some(114031));
+    assert_eq!(record.file, "client-rpc-fops.c");
+    assert_eq!(record.line, 1234);
+    assert_eq!(record.function, "client4_0_lookup_cbk");
+    assert_eq!(record.xlator, "0-vol-client-0");
+    assert_eq!(
+        record.message,
+        "remote operation failed [Transport endpoint is not connected]"
+    );
+
+    // Older releases have no message ids or time zone
+    let record = LogRecord::parse_line(
+        "[2017-01-02 03:04:05.000006] I [glfs.c:100:glfs_init] 0-gfapi: ready",
+    )
+    .unwrap();
+    assert_eq!(record.level, GlusterLogLevel::Info);
+    assert_eq!(record.msgid, None);
+    assert_eq!(record.xlator, "0-gfapi");
+    assert_eq!(
+        record.timestamp,
+        UNIX_EPOCH + Duration::new(1_483_326_245, 6_000)
+    );
+
+    let east = LogRecord::parse_line("[2026-10-17 12:00:00.123456 +0200] W [a.c:1:f] 0-x: shifted")
+        .unwrap();
+    assert_eq!(
+        east.timestamp,
+        UNIX_EPOCH + Duration::new(1_792_231_200, 123_456_000)
+    );
+
+    assert!(LogRecord::parse_line("").is_none());
+    assert!(LogRecord::parse_line("  continued from the line before").is_none());
+    assert!(LogRecord::parse_line("[2026-10-17 10:00:00.1] Q [a.c:1:f] 0-x: bad level").is_none());
+    // Five bytes but not five characters
+    assert!(
+        LogRecord::parse_line("[2026-10-17 10:00:00.1 \u{FFFD}00] E [a.c:1:f] 0-x: bad").is_none()
+    );
+    let garbled: &[u8] = b"[2026-10-17 10:00:00.1 \xff00] E [a.c:1:f] 0-x: garbled\n";
+    assert!(LogReader::new(garbled).next().is_none());
+}
+
+#[test]
+fn parse_repeated_summaries() {
+    let entry = LogEntry::parse_line(
+        "The message \"E [MSGID: 114031] [client-rpc-fops.c:1234:client4_0_lookup_cbk] \
+         0-vol-client-0: remote operation failed\" repeated 5 times between \
+         [2026-10-17 10:00:00.000001 +0000] and [2026-10-17 10:02:00.000002 +0000]",
+    )
+    .unwrap();
+    assert_eq!(entry.count(), 5);
+    assert_eq!(entry.record().level, GlusterLogLevel::Error);
+    assert_eq!(entry.record().msgid, Some(114031));
+    assert_eq!(entry.record().xlator, "0-vol-client-0");
+    assert_eq!(entry.record().message, "remote operation failed");
+    match entry {
+        LogEntry::Repeated { record, last, .. } => {
+            assert_eq!(
+                record.timestamp,
+                UNIX_EPOCH + Duration::new(1_792_231_200, 1_000)
+            );
+            assert_eq!(last, UNIX_EPOCH + Duration::new(1_792_231_320, 2_000));
+        }
+        other => panic!("not a summary: {:?}", other),
+    }
+
+    let entry = LogEntry::parse_line(
+        "[2017-01-02 03:04:05.000006] I [glfs.c:100:glfs_init] 0-gfapi: ready",
+    )
+    .unwrap();
+    assert_eq!(entry.count(), 1);
+    assert!(LogEntry::parse_line("The message \"E [a.c:1:f] 0-x: cut short").is_none());
+}
+
+#[test]
+fn read_multi_line_entries() {
+    let log = "\
+stray output from before the first record
+[2026-10-17 10:00:00.000001 +0000] W [MSGID: 1] [a.c:1:f] 0-vol-client-0: first
+[2026-10-17 10:00:00.000002 +0000] E [b.c:2:g] 0-vol-dht: second
+  which goes on here
+
+  and here
+The message \"I [c.c:3:h] 0-vol-client-0: split
+over two lines\" repeated 3 times between [2026-10-17 10:00:00.000003 +0000] and [2026-10-17 10:00:01.000003 +0000]
+The message \"I [d.c:4:i] 0-vol-client-0: never finished
+[2026-10-17 10:00:02.000004 +0000] T [e.c:5:j] 0-vol-client-0: last";
+    let entries = LogReader::new(log.as_bytes())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let messages: Vec<_> = entries
+        .iter()
+        .map(|entry| (entry.count(), entry.record().message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            (1, "first"),
+            (1, "second\n  which goes on here\n  and here"),
+            (
+                3,
+                "split\nover two lines\nThe message \"I [d.c:4:i] 0-vol-client-0: never finished"
+            ),
+            (1, "last"),
+        ]
+    );
+}
+
+#[test]
+fn forward_records_and_continuations() {
+    let log = "\
+[2026-10-17 10:00:00.000001 +0000] W [MSGID: 1] [a.c:1:f] 0-rdr-client-0: first
+[2026-10-17 10:00:00.000002 +0000] D [b.c:2:g] 0-rdr-dht: second
+  which goes on here
+
+[2026-10-17 10:00:00.000003 +0000] T [c.c:3:h] 0-rdr-client-0: third
+The message \"E [d.c:4:i] 0-rdr-dht: fourth\" repeated 2 times between [2026-10-17 10:00:00.000004 +0000] and [2026-10-17 10:00:00.000005 +0000]
+";
+    install();
+    forward_log(log.as_bytes());
+    assert_eq!(
+        captured("gfapi::0-rdr-"),
+        vec![
+            (
+                Level::Warn,
+                "gfapi::0-rdr-client-0".to_string(),
+                "[MSGID: 1] first".to_string()
+            ),
+            (
+                Level::Debug,
+                "gfapi::0-rdr-dht".to_string(),
+                "second".to_string()
+            ),
+            (
+                Level::Debug,
+                "gfapi::0-rdr-dht".to_string(),
+                "  which goes on here".to_string()
+            ),
+            (
+                Level::Trace,
+                "gfapi::0-rdr-client-0".to_string(),
+                "third".to_string()
+            ),
+            (
+                Level::Error,
+                "gfapi::0-rdr-dht".to_string(),
+                "fourth (repeated 2 times)".to_string()
+            ),
+        ]
+    );
+}
+
+#[cfg(gfapi_stub)]
+#[test]
+fn forward_from_gfapi() {
+    use gfapi_sys::gluster::Gluster;
+    use std::thread::sleep;
+
+    // The stub logs when the volume is unmounted
+    install();
+    let cluster = Gluster::connect("fwdstub", "localhost", 24007).unwrap();
+    cluster.forward_logging(GlusterLogLevel::Info).unwrap();
+    drop(cluster);
+
+    // The forwarding thread gets there in its own time
+    for _ in 0..100 {
+        if !captured("gfapi::0-fwdstub").is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        captured("gfapi::0-fwdstub"),
+        vec![(
+            Level::Info,
+            "gfapi::0-fwdstub-gfapi".to_string(),
+            "[MSGID: 101190] unmounting fwdstub".to_string()
+        )]
+    );
+}
@@ -0,0 +1,260 @@
+use errno::errno;
+use gfapi_sys::credentials::Credentials;
+use gfapi_sys::fs::{GlusterFileOps, GlusterFs};
+use gfapi_sys::gluster::{FallocMode, GlusterError, PosixLockCmd};
+use gfapi_sys::memfs::MemFs;
+use libc::{
+    flock, EACCES, EAGAIN, EEXIST, EFBIG, EINVAL, ELOOP, ENODATA, ENOENT, EOVERFLOW,
+    FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE, O_RDONLY, O_RDWR, SEEK_CUR, SEEK_SET, XATTR_CREATE,
+    XATTR_REPLACE,
+};
+
+use std::path::Path;
+
+/// Written against the trait the way application code would be
+fn write_file<F: GlusterFs>(fs: &F, path: &Path, data: &[u8]) -> Result<(), GlusterError> {
+    let file = fs.create(path, O_RDWR, 0o644)?;
+    file.write(data, 0)?;
+    Ok(())
+}
+
+fn read_file<F: GlusterFs>(fs: &F, path: &Path) -> Result<Vec<u8>, GlusterError> {
+    let file = fs.open(path, O_RDONLY)?;
+    let mut buf = Vec::new();
+    file.read(&mut buf, 1 << 20, 0)?;
+    Ok(buf)
+}
+
+fn fails_with<T>(result: Result<T, GlusterError>, code: i32) {
+    assert!(result.is_err());
+    assert_eq!(errno().0, code);
+}
+
+#[test]
+fn hard_links_share_an_inode() {
+    let fs = MemFs::new();
+    write_file(&fs, Path::new("/a"), b"hello").unwrap();
+    fs.link(Path::new("/a"), Path::new("/b")).unwrap();
+    assert_eq!(fs.stat(Path::new("/a")).unwrap().st_nlink, 2);
+    assert_eq!(
+        fs.stat(Path::new("/a")).unwrap().st_ino,
+        fs.stat(Path::new("/b")).unwrap().st_ino
+    );
+
+    // An open file outlives its last link
+    let file = fs.open(Path::new("/a"), O_RDONLY).unwrap();
+    fs.unlink(Path::new("/a")).unwrap();
+    fs.unlink(Path::new("/b")).unwrap();
+    assert!(!fs.exists(Path::new("/b")).unwrap());
+    let mut buf = Vec::new();
+    file.pread(&mut buf, 5, 0, 0).unwrap();
+    assert_eq!(buf, b"hello");
+    assert_eq!(file.fstat().unwrap().st_nlink, 0);
+}
+
+#[test]
+fn symlinks_resolve_relative_to_their_directory() {
+    let fs = MemFs::new();
+    fs.mkdir(Path::new("/dir"), 0o755).unwrap();
+    write_file(&fs, Path::new("/dir/target"), b"data").unwrap();
+    fs.symlink(Path::new("target"), Path::new("/dir/link"))
+        .unwrap();
+    assert_eq!(read_file(&fs, Path::new("/dir/link")).unwrap(), b"data");
+    assert_eq!(
+        fs.lsstat(Path::new("/dir/link")).unwrap().st_mode & libc::S_IFMT,
+        libc::S_IFLNK
+    );
+
+    let mut buf = [0; 16];
+    fs.readlink(Path::new("/dir/link"), &mut buf).unwrap();
+    assert_eq!(&buf[..6], b"target");
+
+    fs.symlink(Path::new("loop"), Path::new("/loop")).unwrap();
+    fails_with(fs.stat(Path::new("/loop")), ELOOP);
+
+    fs.chdir(Path::new("/dir")).unwrap();
+    assert_eq!(fs.getcwd().unwrap(), "/dir");
+    assert_eq!(read_file(&fs, Path::new("../dir/link")).unwrap(), b"data");
+}
+
+#[test]
+fn permissions_apply_to_unprivileged_users() {
+    let fs = MemFs::new();
+    fs.mkdir(Path::new("/private"), 0o700).unwrap();
+    write_file(&fs, Path::new("/shared"), b"").unwrap();
+
+    fs.set_credentials(Credentials::new(1000, 1000, &[]));
+    fails_with(fs.opendir(Path::new("/private")), EACCES);
+    fails_with(fs.open(Path::new("/shared"), O_RDWR), EACCES);
+    assert!(fs.open(Path::new("/shared"), O_RDONLY).is_ok());
+    fails_with(fs.mkdir(Path::new("/mine"), 0o755), EACCES);
+    fails_with(
+        fs.setxattr(Path::new("/shared"), "trusted.x", b"1", 0),
+        libc::EPERM,
+    );
+}
+
+#[test]
+fn xattr_flags() {
+    let fs = MemFs::new();
+    write_file(&fs, Path::new("/f"), b"").unwrap();
+    let path = Path::new("/f");
+    fails_with(fs.setxattr(path, "user.a", b"1", XATTR_REPLACE), ENODATA);
+    fs.setxattr(path, "user.a", b"1", XATTR_CREATE).unwrap();
+    fails_with(fs.setxattr(path, "user.a", b"2", XATTR_CREATE), EEXIST);
+    fs.setxattr(path, "user.b", b"2", 0).unwrap();
+    assert_eq!(fs.getxattr(path, "user.a").unwrap(), "1");
+    assert_eq!(fs.listxattr(path).unwrap(), "user.a\0user.b\0");
+    fs.removexattr(path, "user.a").unwrap();
+    fails_with(fs.getxattr(path, "user.a"), ENODATA);
+}
+
+#[test]
+fn conflicting_locks_fail() {
+    let fs = MemFs::new();
+    write_file(&fs, Path::new("/f"), b"0123456789").unwrap();
+    let first = fs.open(Path::new("/f"), O_RDWR).unwrap();
+    let second = fs.open(Path::new("/f"), O_RDWR).unwrap();
+    let mut range: flock = unsafe { std::mem::zeroed() };
+    range.l_whence = SEEK_SET as i16;
+    range.l_start = 0;
+    range.l_len = 5;
+
+    first.posixlock(PosixLockCmd::Shared, &mut range).unwrap();
+    second.posixlock(PosixLockCmd::Shared, &mut range).unwrap();
+    fails_with(
+        second.posixlock(PosixLockCmd::Exclusive, &mut range),
+        EAGAIN,
+    );
+
+    // Closing a file releases its locks
+    drop(first);
+    second
+        .posixlock(PosixLockCmd::Exclusive, &mut range)
+        .unwrap();
+}
+
+#[test]
+fn locks_relative_to_the_offset() {
+    let fs = MemFs::new();
+    write_file(&fs, Path::new("/f"), b"0123456789").unwrap();
+    let first = fs.open(Path::new("/f"), O_RDWR).unwrap();
+    let second = fs.open(Path::new("/f"), O_RDWR).unwrap();
+    first.lseek(5, SEEK_SET).unwrap();
+    let mut range: flock = unsafe { std::mem::zeroed() };
+    range.l_whence = SEEK_CUR as i16;
+    range.l_start = 0;
+    range.l_len = 5;
+    first
+        .posixlock(PosixLockCmd::Exclusive, &mut range)
+        .unwrap();
+
+    range.l_whence = SEEK_SET as i16;
+    range.l_len = 5;
+    second
+        .posixlock(PosixLockCmd::Exclusive, &mut range)
+        .unwrap();
+    range.l_start = 5;
+    fails_with(
+        second.posixlock(PosixLockCmd::Exclusive, &mut range),
+        EAGAIN,
+    );
+
+    range.l_start = i64::MAX;
+    fails_with(
+        second.posixlock(PosixLockCmd::Shared, &mut range),
+        EOVERFLOW,
+    );
+}
+
+#[test]
+fn dups_share_the_offset() {
+    let fs = MemFs::new();
+    write_file(&fs, Path::new("/f"), b"hello world").unwrap();
+    let file = fs.open(Path::new("/f"), O_RDONLY).unwrap();
+    let dup = file.dup().unwrap();
+    let mut buf = Vec::new();
+    file.read(&mut buf, 6, 0).unwrap();
+    dup.read(&mut buf, 5, 0).unwrap();
+    assert_eq!(buf, b"world");
+    assert_eq!(file.lseek(0, SEEK_CUR).unwrap(), 11);
+}
+
+#[test]
+fn files_stop_growing_at_the_limit() {
+    let fs = MemFs::new();
+    write_file(&fs, Path::new("/f"), b"hello").unwrap();
+    let file = fs.open(Path::new("/f"), O_RDWR).unwrap();
+    fails_with(file.pwrite(b"x", 1, 1 << 40, 0), EFBIG);
+    fails_with(file.pwrite(b"x", 1, i64::MAX, 0), EINVAL);
+    fails_with(file.ftruncate(1 << 40), EFBIG);
+    fails_with(file.allocate(0..1 << 40, FallocMode::Allocate), EFBIG);
+    fails_with(file.zerofill(i64::MAX, 1), EFBIG);
+    fails_with(file.discard(i64::MAX, 1), EFBIG);
+    file.lseek(1, SEEK_SET).unwrap();
+    fails_with(file.lseek(i64::MAX, SEEK_CUR), EOVERFLOW);
+    assert_eq!(file.fstat().unwrap().st_size, 5);
+
+    fs.set_max_file_size(8);
+    file.pwrite(b"abc", 3, 5, 0).unwrap();
+    fails_with(file.pwrite(b"d", 1, 8, 0), EFBIG);
+    assert_eq!(read_file(&fs, Path::new("/f")).unwrap(), b"helloabc");
+}
+
+#[test]
+fn rename_rules() {
+    let fs = MemFs::new();
+    fs.mkdir(Path::new("/a"), 0o755).unwrap();
+    fs.mkdir(Path::new("/a/b"), 0o755).unwrap();
+    fails_with(fs.rename(Path::new("/a"), Path::new("/a/b/c")), EINVAL);
+
+    write_file(&fs, Path::new("/a/b/f"), b"x").unwrap();
+    fs.rename(Path::new("/a"), Path::new("/z")).unwrap();
+    assert_eq!(read_file(&fs, Path::new("/z/b/f")).unwrap(), b"x");
+    fails_with(fs.stat(Path::new("/a")), ENOENT);
+
+    fs.remove_dir_all(Path::new("/z")).unwrap();
+    assert!(!fs.exists(Path::new("/z")).unwrap());
+    assert_eq!(fs.stat(Path::new("/")).unwrap().st_nlink, 2);
+}
+
+#[test]
+fn rmdir_refuses_dot() {
+    let fs = MemFs::new();
+    fs.mkdir(Path::new("/dir"), 0o755).unwrap();
+    fails_with(fs.rmdir(Path::new("/dir/.")), EINVAL);
+    fails_with(fs.rmdir(Path::new("/dir/./")), EINVAL);
+    fs.chdir(Path::new("/dir")).unwrap();
+    fails_with(fs.rmdir(Path::new(".")), EINVAL);
+    assert!(fs.exists(Path::new("/dir")).unwrap());
+
+    fs.chdir(Path::new("/")).unwrap();
+    fs.rmdir(Path::new("/dir")).unwrap();
+    assert!(!fs.exists(Path::new("/dir")).unwrap());
+}
+
+#[test]
+fn allocate_modes() {
+    let fs = MemFs::new();
+    write_file(&fs, Path::new("/f"), b"hello world").unwrap();
+    let file = fs.open(Path::new("/f"), O_RDWR).unwrap();
+    file.allocate(0..16, FallocMode::KeepSize).unwrap();
+    assert_eq!(file.fstat().unwrap().st_size, 11);
+    file.allocate(0..16, FallocMode::Allocate).unwrap();
+    assert_eq!(file.fstat().unwrap().st_size, 16);
+    file.allocate(0..5, FallocMode::PunchHole).unwrap();
+    file.allocate(14..20, FallocMode::ZeroRange).unwrap();
+    assert_eq!(
+        read_file(&fs, Path::new("/f")).unwrap(),
+        b"\0\0\0\0\0 world\0\0\0\0\0\0\0\0\0"
+    );
+
+    fails_with(file.allocate(5..5, FallocMode::Allocate), EINVAL);
+    fails_with(file.allocate(0..u64::MAX, FallocMode::ZeroRange), EINVAL);
+
+    assert_eq!(
+        FallocMode::from_flags(FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE),
+        Some(FallocMode::PunchHole)
+    );
+    assert_eq!(FallocMode::from_flags(FALLOC_FL_PUNCH_HOLE), None);
+}
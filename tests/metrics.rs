@@ -0,0 +1,117 @@
+//! Checks what the metrics feature records, against the stub library as
+//! described in tests/stub.rs.  The registry is shared by the tests in this
+//! file, so each looks at glfs functions the other doesn't call.
+#![cfg(all(feature = "metrics", gfapi_stub))]
+
+use gfapi_sys::copy::CopyMethod;
+use gfapi_sys::gluster::Gluster;
+use gfapi_sys::metrics::{metrics, Operation};
+use libc::{EEXIST, ENOENT, O_CREAT, O_RDWR};
+
+use std::path::Path;
+
+fn operation(name: &str) -> Operation {
+    metrics().operation(name).cloned().unwrap_or_default()
+}
+
+#[test]
+fn calls_errors_and_bytes() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let (opens, writes, reads) = (
+        operation("glfs_open"),
+        operation("glfs_pwrite"),
+        operation("glfs_pread"),
+    );
+    let before = metrics();
+
+    assert!(cluster.open(Path::new("/missing"), O_RDWR).is_err());
+    // Recording the call leaves errno for the caller
+    assert_eq!(errno::errno().0, ENOENT);
+    let file = cluster
+        .create(Path::new("/data"), O_CREAT | O_RDWR, 0o644)
+        .unwrap();
+    file.pwrite(b"hello world", 11, 0, 0).unwrap();
+    let mut buf = Vec::with_capacity(64);
+    file.pread(&mut buf, 64, 6, 0).unwrap();
+    assert_eq!(buf, b"world");
+
+    let after = metrics();
+    let open = after.operation("glfs_open").unwrap();
+    assert_eq!(open.calls, opens.calls + 1);
+    assert_eq!(
+        open.errors.get(&ENOENT),
+        Some(&(opens.errors.get(&ENOENT).unwrap_or(&0) + 1))
+    );
+    assert_eq!(open.latency.count(), open.calls);
+
+    let write = after.operation("glfs_pwrite").unwrap();
+    assert_eq!(write.calls, writes.calls + 1);
+    assert_eq!(write.bytes, writes.bytes + 11);
+    assert_eq!(write.error_count(), 0);
+    assert_eq!(
+        after.operation("glfs_pread").unwrap().bytes,
+        reads.bytes + 5
+    );
+    assert!(after.bytes_written >= before.bytes_written + 11);
+    assert!(after.bytes_read >= before.bytes_read + 5);
+}
+
+#[test]
+fn prometheus_exposition() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    cluster.mkdir(Path::new("/dir"), 0o755).unwrap();
+    assert!(cluster.mkdir(Path::new("/dir"), 0o755).is_err());
+
+    let snapshot = metrics();
+    let mkdir = snapshot.operation("glfs_mkdir").unwrap();
+    let text = snapshot.prometheus();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines
+        .contains(&format!("gluster_calls_total{{op=\"glfs_mkdir\"}} {}", mkdir.calls).as_str()));
+    assert!(lines.contains(&"# TYPE gluster_call_duration_seconds histogram"));
+    assert!(lines.contains(
+        &format!(
+            "gluster_call_duration_seconds_bucket{{op=\"glfs_mkdir\",le=\"+Inf\"}} {}",
+            mkdir.calls
+        )
+        .as_str()
+    ));
+    let errors = format!(
+        "gluster_errors_total{{op=\"glfs_mkdir\",errno=\"{}\"}}",
+        EEXIST
+    );
+    assert!(lines.iter().any(|line| line.starts_with(&errors)));
+    assert!(lines.iter().any(|line| line
+        .starts_with("gluster_call_duration_seconds_bucket{op=\"glfs_mkdir\",le=\"0.00005\"}")));
+    // Buckets are cumulative so they never decrease
+    let buckets: Vec<u64> = lines
+        .iter()
+        .filter(|line| line.starts_with("gluster_call_duration_seconds_bucket{op=\"glfs_mkdir\""))
+        .map(|line| line.rsplit(' ').next().unwrap().parse().unwrap())
+        .collect();
+    assert!(buckets.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[test]
+fn server_side_copies_are_counted_apart() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let file = cluster
+        .create(Path::new("/original"), O_CREAT | O_RDWR, 0o644)
+        .unwrap();
+    file.pwrite(&[7; 4096], 4096, 0, 0).unwrap();
+    let before = metrics();
+
+    let copy = cluster
+        .copy_file(Path::new("/original"), Path::new("/copy"))
+        .unwrap();
+    if copy.method != CopyMethod::ServerSide {
+        eprintln!("skipping, copy_file_range isn't supported here");
+        return;
+    }
+    let after = metrics();
+    assert!(after.bytes_copied >= before.bytes_copied + 4096);
+    assert!(after
+        .prometheus()
+        .lines()
+        .any(|line| line.starts_with("gluster_copied_bytes_total ")));
+}
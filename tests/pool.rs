@@ -0,0 +1,77 @@
+//! GlusterPool against the stub library as described in tests/stub.rs
+#![cfg(gfapi_stub)]
+
+mod common;
+
+use common::inject;
+use gfapi_sys::pool::{GlusterPool, PoolConfig, PoolKey};
+use libc::ENOTCONN;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SERVERS: &[(&str, u16)] = &[("localhost", 24007)];
+
+#[test]
+fn handles_are_shared_until_evicted() {
+    let pool = GlusterPool::default();
+    let first = pool.get("shared", SERVERS).unwrap();
+    let second = pool.get("shared", SERVERS).unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(pool.keys(), vec![PoolKey::new("shared", SERVERS)]);
+
+    pool.evict(&PoolKey::new("shared", SERVERS));
+    assert!(pool.keys().is_empty());
+    let third = pool.get("shared", SERVERS).unwrap();
+    assert!(!Arc::ptr_eq(&first, &third));
+}
+
+#[test]
+fn idle_handles_are_torn_down() {
+    let pool = GlusterPool::new(PoolConfig {
+        health_check_interval: Duration::from_secs(3600),
+        idle_timeout: Duration::from_secs(0),
+    });
+    let handle = pool.get("idle", SERVERS).unwrap();
+    // Still in use outside the pool
+    assert!(pool.maintain().is_empty());
+
+    drop(handle);
+    assert_eq!(pool.maintain(), vec![PoolKey::new("idle", SERVERS)]);
+    assert!(pool.keys().is_empty());
+}
+
+#[test]
+fn unhealthy_handles_are_replaced() {
+    let pool = GlusterPool::new(PoolConfig {
+        health_check_interval: Duration::from_secs(0),
+        idle_timeout: Duration::from_secs(3600),
+    });
+    let handle = pool.get("unhealthy", SERVERS).unwrap();
+    assert!(pool.maintain().is_empty());
+
+    inject("glfs_statvfs", ENOTCONN, 1);
+    assert_eq!(pool.maintain(), vec![PoolKey::new("unhealthy", SERVERS)]);
+    let replaced = pool.get("unhealthy", SERVERS).unwrap();
+    assert!(!Arc::ptr_eq(&handle, &replaced));
+}
+
+#[test]
+fn maintenance_runs_in_the_background() {
+    let pool = GlusterPool::new(PoolConfig {
+        health_check_interval: Duration::from_millis(10),
+        idle_timeout: Duration::from_secs(0),
+    });
+    drop(pool.get("background", SERVERS).unwrap());
+    let thread = pool.start_maintenance().unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !pool.keys().is_empty() {
+        assert!(Instant::now() < deadline, "idle handle was never torn down");
+        thread::sleep(Duration::from_millis(10));
+    }
+    // The thread stops with the last clone of the pool
+    drop(pool);
+    thread.join().unwrap();
+}
@@ -0,0 +1,53 @@
+use gfapi_sys::quota::*;
+
+fn encode(values: &[i64]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|v| v.to_be_bytes().to_vec())
+        .collect()
+}
+
+#[test]
+fn decode_quota_size() {
+    let usage = QuotaUsage::from_xattr(&encode(&[4096, 3, 2])).unwrap();
+    assert_eq!(
+        usage,
+        QuotaUsage {
+            size: 4096,
+            file_count: 3,
+            dir_count: 2,
+        }
+    );
+    assert_eq!(usage.objects(), 5);
+
+    // Volumes created before 3.7 only account the size
+    let legacy = QuotaUsage::from_xattr(&encode(&[4096])).unwrap();
+    assert_eq!(legacy.size, 4096);
+    assert_eq!(legacy.objects(), 0);
+
+    assert!(QuotaUsage::from_xattr(&[0; 12]).is_err());
+}
+
+#[test]
+fn decode_quota_limit() {
+    let limit = QuotaLimit::from_xattr(&encode(&[1 << 30, 90])).unwrap();
+    assert_eq!(limit.hard_limit, 1 << 30);
+    assert_eq!(limit.soft_limit_percent, Some(90));
+    assert_eq!(limit.soft_limit(DEFAULT_SOFT_LIMIT_PERCENT), 966_367_641);
+
+    // -1 means the volume's default-soft-limit applies
+    let inherited = QuotaLimit::from_xattr(&encode(&[1000, -1])).unwrap();
+    assert_eq!(inherited.soft_limit_percent, None);
+    assert_eq!(inherited.soft_limit(DEFAULT_SOFT_LIMIT_PERCENT), 800);
+
+    let usage = QuotaUsage {
+        size: 250,
+        file_count: 10,
+        dir_count: 0,
+    };
+    assert_eq!(usage.size_percent(&inherited), 25.0);
+    assert_eq!(usage.objects_percent(&inherited), 1.0);
+
+    assert!(QuotaLimit::from_xattr(&encode(&[1000, 150])).is_err());
+    assert!(QuotaLimit::from_xattr(&encode(&[1000])).is_err());
+}
@@ -0,0 +1,72 @@
+//! ResilientGluster against the stub library as described in tests/stub.rs.
+//! Every test in here shares one GFAPI_STUB_ROOT, so a reconnected handle
+//! sees the files the one before it wrote.
+#![cfg(gfapi_stub)]
+
+mod common;
+
+use common::inject;
+use errno::{errno, Errno};
+use gfapi_sys::resilient::ResilientGluster;
+use libc::{EBADF, ENOTCONN, ESTALE, O_CREAT, O_RDWR};
+
+use std::path::Path;
+use std::sync::{Arc, Once};
+
+fn connect() -> ResilientGluster {
+    static ROOT: Once = Once::new();
+    ROOT.call_once(|| {
+        let root = std::env::temp_dir().join(format!("gfapi-resilient-{}", std::process::id()));
+        std::env::set_var("GFAPI_STUB_ROOT", root);
+    });
+    ResilientGluster::connect("test", &[("localhost", 24007)]).unwrap()
+}
+
+#[test]
+fn reconnects_when_the_cluster_goes_away() {
+    let cluster = connect();
+    let file = cluster
+        .create(Path::new("enotconn"), O_RDWR | O_CREAT, 0o644)
+        .unwrap();
+    file.write(b"hello world").unwrap();
+    file.lseek(6, libc::SEEK_SET).unwrap();
+    let before = cluster.handle();
+
+    inject("glfs_pread", ENOTCONN, 1);
+    let mut buf = Vec::with_capacity(16);
+    assert_eq!(file.read(&mut buf, 16).unwrap(), 5);
+    assert_eq!(buf, b"world");
+    assert!(!Arc::ptr_eq(&before, &cluster.handle()));
+}
+
+#[test]
+fn stale_files_are_reopened_without_reconnecting() {
+    let cluster = connect();
+    let file = cluster
+        .create(Path::new("estale"), O_RDWR | O_CREAT, 0o644)
+        .unwrap();
+    file.write(b"hello").unwrap();
+    let before = cluster.handle();
+
+    inject("glfs_pread", ESTALE, 1);
+    let mut buf = Vec::with_capacity(16);
+    assert_eq!(file.pread(&mut buf, 16, 0).unwrap(), 5);
+    assert_eq!(buf, b"hello");
+    assert!(Arc::ptr_eq(&before, &cluster.handle()));
+}
+
+#[test]
+fn other_errors_are_returned_as_they_are() {
+    let cluster = connect();
+    let file = cluster
+        .create(Path::new("ebadf"), O_RDWR | O_CREAT, 0o644)
+        .unwrap();
+    let before = cluster.handle();
+
+    inject("glfs_pwrite", EBADF, 1);
+    assert!(file.pwrite(b"hello", 0).is_err());
+    assert_eq!(errno(), Errno(EBADF));
+    assert!(Arc::ptr_eq(&before, &cluster.handle()));
+    // Nothing was replayed
+    assert_eq!(file.fstat().unwrap().st_size, 0);
+}
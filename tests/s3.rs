@@ -0,0 +1,344 @@
+#![cfg(feature = "s3")]
+
+use gfapi_sys::fs::GlusterFs;
+use gfapi_sys::localfs::LocalFs;
+use gfapi_sys::memfs::MemFs;
+use gfapi_sys::s3::{S3Gateway, S3Response, ETAG_KEY};
+
+use std::path::Path;
+
+struct Reply {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Reply {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The text of every <name> element, in order
+    fn elements(&self, name: &str) -> Vec<String> {
+        let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+        self.body
+            .split(open.as_str())
+            .skip(1)
+            .map(|rest| rest[..rest.find(close.as_str()).unwrap()].to_string())
+            .collect()
+    }
+}
+
+fn request<F: GlusterFs>(
+    gateway: &S3Gateway<F>,
+    method: &str,
+    target: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Reply
+where
+    F::File: 'static,
+{
+    let (path, query) = match target.find('?') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (target, ""),
+    };
+    let headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    let mut body = body;
+    let response: S3Response = gateway.handle(gfapi_sys::s3::S3Request {
+        method,
+        path,
+        query,
+        headers: &headers,
+        body: &mut body,
+    });
+    let (status, headers) = (response.status, response.headers.clone());
+    let body = String::from_utf8(response.body_bytes().unwrap()).unwrap();
+    Reply {
+        status,
+        headers,
+        body,
+    }
+}
+
+fn memfs_gateway() -> S3Gateway<MemFs> {
+    let fs = MemFs::new();
+    fs.mkdir(Path::new("/buckets"), 0o755).unwrap();
+    S3Gateway::new(fs, Path::new("/buckets"))
+}
+
+fn objects_round_trip<F: GlusterFs>(gateway: &S3Gateway<F>)
+where
+    F::File: 'static,
+{
+    assert_eq!(request(gateway, "PUT", "/photos", &[], b"").status, 200);
+    assert_eq!(request(gateway, "PUT", "/photos", &[], b"").status, 409);
+    let put = request(
+        gateway,
+        "PUT",
+        "/photos/2020/beach%20day.jpg",
+        &[
+            ("Content-Type", "image/jpeg"),
+            ("X-Amz-Meta-Camera", "pinhole"),
+        ],
+        b"hello world",
+    );
+    assert_eq!(put.status, 200);
+    // The MD5 of the body
+    assert_eq!(
+        put.header("etag"),
+        Some("\"5eb63bbbe01eeed093cb22bb8f5acdc3\"")
+    );
+
+    let get = request(gateway, "GET", "/photos/2020/beach%20day.jpg", &[], b"");
+    assert_eq!(get.status, 200);
+    assert_eq!(get.body, "hello world");
+    assert_eq!(get.header("content-type"), Some("image/jpeg"));
+    assert_eq!(get.header("x-amz-meta-camera"), Some("pinhole"));
+    assert_eq!(get.header("etag"), put.header("etag"));
+
+    let head = request(gateway, "HEAD", "/photos/2020/beach%20day.jpg", &[], b"");
+    assert_eq!(head.status, 200);
+    assert_eq!(head.body, "");
+    assert_eq!(head.header("x-amz-meta-camera"), Some("pinhole"));
+
+    let buckets = request(gateway, "GET", "/", &[], b"");
+    assert_eq!(buckets.elements("Name"), ["photos"]);
+
+    assert_eq!(request(gateway, "DELETE", "/photos", &[], b"").status, 409);
+    assert_eq!(
+        request(gateway, "DELETE", "/photos/2020/beach%20day.jpg", &[], b"").status,
+        204
+    );
+    let missing = request(gateway, "GET", "/photos/2020/beach%20day.jpg", &[], b"");
+    assert_eq!(missing.status, 404);
+    assert_eq!(missing.elements("Code"), ["NoSuchKey"]);
+    // Emptied directories go with their last object
+    assert_eq!(request(gateway, "DELETE", "/photos", &[], b"").status, 204);
+    assert_eq!(request(gateway, "HEAD", "/photos", &[], b"").status, 404);
+}
+
+#[test]
+fn objects_round_trip_through_memfs() {
+    objects_round_trip(&memfs_gateway());
+}
+
+#[test]
+fn objects_round_trip_through_localfs() {
+    let dir = std::env::temp_dir().join(format!("gfapi-s3-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let fs = LocalFs::new(&dir).unwrap();
+    // Not every filesystem temp_dir can be on supports user xattrs
+    let probe = dir.join("probe");
+    std::fs::write(&probe, b"").unwrap();
+    if fs.setxattr(Path::new("/probe"), ETAG_KEY, b"x", 0).is_err() {
+        eprintln!("skipping, {} has no user xattrs", dir.display());
+        return;
+    }
+    std::fs::remove_file(&probe).unwrap();
+
+    objects_round_trip(&S3Gateway::new(fs, Path::new("/")));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ranges_are_served_partially() {
+    let gateway = memfs_gateway();
+    request(&gateway, "PUT", "/b", &[], b"");
+    request(&gateway, "PUT", "/b/digits", &[], b"0123456789");
+
+    let cases = [
+        ("bytes=2-4", "234", "bytes 2-4/10"),
+        ("bytes=7-", "789", "bytes 7-9/10"),
+        ("bytes=-3", "789", "bytes 7-9/10"),
+        ("bytes=8-100", "89", "bytes 8-9/10"),
+    ];
+    for (range, body, content_range) in &cases {
+        let get = request(&gateway, "GET", "/b/digits", &[("Range", range)], b"");
+        assert_eq!(get.status, 206, "{}", range);
+        assert_eq!(get.body, *body);
+        assert_eq!(get.header("content-range"), Some(*content_range));
+    }
+
+    let past = request(&gateway, "GET", "/b/digits", &[("Range", "bytes=10-")], b"");
+    assert_eq!(past.status, 416);
+    assert_eq!(past.header("content-range"), Some("bytes */10"));
+    // More than one range is answered with everything
+    let several = request(
+        &gateway,
+        "GET",
+        "/b/digits",
+        &[("Range", "bytes=0-1,4-5")],
+        b"",
+    );
+    assert_eq!(several.status, 200);
+    assert_eq!(several.body, "0123456789");
+}
+
+#[test]
+fn listings_page_and_group_by_delimiter() {
+    let gateway = memfs_gateway();
+    request(&gateway, "PUT", "/b", &[], b"");
+    for key in &["a-b", "a/1", "a/2", "a/c/3", "a0", "b/1", "c"] {
+        assert_eq!(
+            request(&gateway, "PUT", &format!("/b/{}", key), &[], b"x").status,
+            200
+        );
+    }
+
+    let all = request(&gateway, "GET", "/b?list-type=2", &[], b"");
+    assert_eq!(
+        all.elements("Key"),
+        ["a-b", "a/1", "a/2", "a/c/3", "a0", "b/1", "c"]
+    );
+    assert_eq!(all.elements("IsTruncated"), ["false"]);
+
+    let top = request(&gateway, "GET", "/b?list-type=2&delimiter=%2F", &[], b"");
+    assert_eq!(top.elements("Key"), ["a-b", "a0", "c"]);
+    assert_eq!(top.elements("Prefix")[1..], ["a/", "b/"]);
+
+    let under_a = request(
+        &gateway,
+        "GET",
+        "/b?list-type=2&prefix=a%2F&delimiter=%2F",
+        &[],
+        b"",
+    );
+    assert_eq!(under_a.elements("Key"), ["a/1", "a/2"]);
+    assert_eq!(under_a.elements("Prefix")[1..], ["a/c/"]);
+
+    let partial = request(&gateway, "GET", "/b?list-type=2&prefix=a%2F", &[], b"");
+    assert_eq!(partial.elements("Key"), ["a/1", "a/2", "a/c/3"]);
+
+    // Walk everything two at a time
+    let mut keys = Vec::new();
+    let mut token: Option<String> = None;
+    loop {
+        let target = match token {
+            Some(ref token) => format!("/b?list-type=2&max-keys=2&continuation-token={}", token),
+            None => "/b?list-type=2&max-keys=2".to_string(),
+        };
+        let page = request(&gateway, "GET", &target, &[], b"");
+        assert!(page.elements("Key").len() <= 2);
+        keys.extend(page.elements("Key"));
+        token = page.elements("NextContinuationToken").pop();
+        if page.elements("IsTruncated") == ["false"] {
+            assert!(token.is_none());
+            break;
+        }
+    }
+    assert_eq!(keys, all.elements("Key"));
+
+    // Common prefixes count towards max-keys and aren't repeated
+    let first = request(
+        &gateway,
+        "GET",
+        "/b?list-type=2&delimiter=%2F&max-keys=3",
+        &[],
+        b"",
+    );
+    assert_eq!(first.elements("Key"), ["a-b", "a0"]);
+    assert_eq!(first.elements("Prefix")[1..], ["a/"]);
+    let token = first.elements("NextContinuationToken").pop().unwrap();
+    let rest = request(
+        &gateway,
+        "GET",
+        &format!("/b?list-type=2&delimiter=%2F&continuation-token={}", token),
+        &[],
+        b"",
+    );
+    assert_eq!(rest.elements("Key"), ["c"]);
+    assert_eq!(rest.elements("Prefix")[1..], ["b/"]);
+}
+
+#[test]
+fn multipart_uploads_assemble_in_order() {
+    let gateway = memfs_gateway();
+    request(&gateway, "PUT", "/b", &[], b"");
+    let created = request(
+        &gateway,
+        "POST",
+        "/b/dir/big?uploads",
+        &[("x-amz-meta-origin", "test")],
+        b"",
+    );
+    assert_eq!(created.status, 200);
+    let id = created.elements("UploadId").pop().unwrap();
+
+    let mut etags = Vec::new();
+    for (number, data) in &[(1, "first "), (2, "second "), (3, "third")] {
+        let part = request(
+            &gateway,
+            "PUT",
+            &format!("/b/dir/big?partNumber={}&uploadId={}", number, id),
+            &[],
+            data.as_bytes(),
+        );
+        assert_eq!(part.status, 200);
+        etags.push(part.header("etag").unwrap().to_string());
+    }
+    // The upload in progress isn't an object
+    let listing = request(&gateway, "GET", "/b?list-type=2", &[], b"");
+    assert!(listing.elements("Key").is_empty());
+
+    let parts = |numbers: &[usize]| {
+        let mut xml = "<CompleteMultipartUpload>".to_string();
+        for number in numbers {
+            xml.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                number,
+                etags[number - 1]
+            ));
+        }
+        xml + "</CompleteMultipartUpload>"
+    };
+    let target = format!("/b/dir/big?uploadId={}", id);
+    let out_of_order = request(&gateway, "POST", &target, &[], parts(&[2, 1]).as_bytes());
+    assert_eq!(out_of_order.elements("Code"), ["InvalidPartOrder"]);
+
+    let done = request(&gateway, "POST", &target, &[], parts(&[1, 3]).as_bytes());
+    assert_eq!(done.status, 200);
+    let etag = done.elements("ETag").pop().unwrap();
+    assert!(etag.ends_with("-2&quot;"), "{}", etag);
+
+    let get = request(&gateway, "GET", "/b/dir/big", &[], b"");
+    assert_eq!(get.body, "first third");
+    assert_eq!(get.header("x-amz-meta-origin"), Some("test"));
+    assert!(get.header("etag").unwrap().ends_with("-2\""));
+    // Finishing removes the upload
+    assert_eq!(
+        request(&gateway, "POST", &target, &[], parts(&[1]).as_bytes()).status,
+        404
+    );
+
+    let aborted = request(&gateway, "POST", "/b/other?uploads", &[], b"");
+    let id = aborted.elements("UploadId").pop().unwrap();
+    let target = format!("/b/other?uploadId={}", id);
+    assert_eq!(request(&gateway, "DELETE", &target, &[], b"").status, 204);
+    assert_eq!(request(&gateway, "DELETE", &target, &[], b"").status, 404);
+}
+
+#[test]
+fn keys_that_arent_paths_are_refused() {
+    let gateway = memfs_gateway();
+    request(&gateway, "PUT", "/b", &[], b"");
+    for key in &["../escape", "a//b", "a/./b", ".s3-uploads/x"] {
+        let put = request(&gateway, "PUT", &format!("/b/{}", key), &[], b"x");
+        assert_eq!(put.status, 400, "{}", key);
+    }
+    // A key can't be both an object and a directory of others
+    request(&gateway, "PUT", "/b/a/b", &[], b"x");
+    assert_eq!(request(&gateway, "PUT", "/b/a", &[], b"x").status, 409);
+    assert_eq!(request(&gateway, "PUT", "/b/a/b/c", &[], b"x").status, 409);
+    // Folders made by consoles are plain directories
+    assert_eq!(request(&gateway, "PUT", "/b/folder/", &[], b"").status, 200);
+    let listing = request(&gateway, "GET", "/b?list-type=2&delimiter=%2F", &[], b"");
+    assert_eq!(listing.elements("Prefix")[1..], ["a/", "folder/"]);
+}
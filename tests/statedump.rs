@@ -0,0 +1,246 @@
+//! Parsing statedumps.  The last test runs against the stub library as
+//! described in tests/stub.rs.
+use gfapi_sys::gfid::Gfid;
+use gfapi_sys::statedump::StateDump;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+const DUMP: &str = "\
+DUMP-START-TIME: 2026-10-17 10:00:00.000001
+
+[mallinfo]
+mallinfo_arena=1052672
+
+[global.glusterfs - Memory usage]
+num_types=1
+
+[global.glusterfs - usage-type gf_common_mt_inode_ctx memusage]
+size=4096
+num_allocs=16
+max_size=8192
+max_num_allocs=32
+total_allocs=48
+
+[mempool]
+-----=-----
+pool-name=glusterfs:dict_t
+hot-count=3
+padded_sizeof=256
+-----=-----
+pool-name=glusterfs:data_t
+active-count=7
+padded-sizeof=128
+
+[protocol/client.vol-client-0 - Memory usage]
+num_types=2
+
+[protocol/client.vol-client-0 - usage-type gf_client_mt_clnt_conf_t memusage]
+size=1024
+num_allocs=1
+max_size=1024
+max_num_allocs=1
+total_allocs=1
+
+[protocol/client.vol-client-0 - usage-type gf_common_mt_strdup memusage]
+size=24
+num_allocs=2
+max_size=24
+max_num_allocs=2
+total_allocs=9
+
+[xlator.protocol.client.vol-client-0.priv]
+xlator.protocol.client.vol-client-0.priv.connected=1
+
+[xlator.api.vol.itable]
+xlator.api.vol.itable.lru_limit=131072
+xlator.api.vol.itable.active_size=1
+xlator.api.vol.itable.lru_size=1
+xlator.api.vol.itable.purge_size=0
+
+[xlator.api.vol.itable.active.1]
+gfid=00000000-0000-0000-0000-000000000001
+nlookup=0
+fd-count=0
+ref=1
+ia_type=2
+
+[xlator.api.vol.itable.active.1.vol-client-0]
+nlookup=0
+
+[xlator.api.vol.itable.lru.1]
+gfid=0a1b2c3d-4e5f-6071-8293-a4b5c6d7e8f9
+nlookup=1
+fd-count=0
+ref=0
+ia_type=1
+
+[xlator.api.vol.fdtable]
+xlator.api.vol.fdtable.refcount=1
+xlator.api.vol.fdtable.maxfds=128
+xlator.api.vol.fdtable.first_free=4
+
+[xlator.api.vol.fdtable.fdentry[3]]
+pid=-6
+refcount=2
+flags=32770
+
+[global.callpool]
+callpool_address=0x7f0000001000
+callpool.cnt=1
+
+[global.callpool.stack.1]
+uid=1000
+gid=1000
+pid=4242
+unique=42
+op=WRITE
+
+[global.callpool.stack.1.frame.1]
+translator=vol-client-0
+complete=0
+wind_from=syncop_writev
+wind_to=subvol->fops->writev
+
+[global.callpool.stack.1.frame.2]
+translator=vol
+complete=1
+unwind_to=syncop_writev_cbk
+
+DUMP-END-TIME: 2026-10-17 10:00:00.500001
+";
+
+#[test]
+fn parse_statedump() {
+    let dump = StateDump::parse(DUMP.as_bytes()).unwrap();
+    assert_eq!(dump.path, None);
+    assert_eq!(
+        dump.start,
+        Some(UNIX_EPOCH + Duration::new(1_792_231_200, 1_000))
+    );
+    assert_eq!(
+        dump.end,
+        Some(UNIX_EPOCH + Duration::new(1_792_231_200, 500_001_000))
+    );
+    assert_eq!(
+        dump.section("mallinfo")
+            .unwrap()
+            .parse::<u64>("mallinfo_arena"),
+        Some(1_052_672)
+    );
+
+    let xlators = dump.xlators();
+    let names: Vec<_> = xlators
+        .iter()
+        .map(|xl| (xl.kind.as_str(), xl.name.as_str(), xl.allocated()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("global", "glusterfs", 4096),
+            ("protocol/client", "vol-client-0", 1048)
+        ]
+    );
+    let client = &xlators[1];
+    assert_eq!(client.memory[1].type_name, "gf_common_mt_strdup");
+    assert_eq!(client.memory[1].total_allocs, 9);
+    assert_eq!(client.sections.len(), 1);
+    assert_eq!(client.sections[0].get("connected"), Some("1"));
+
+    let pools = dump.mempools();
+    assert_eq!(pools.len(), 2);
+    assert_eq!(pools[0].name, "glusterfs:dict_t");
+    assert_eq!(pools[0].in_use, Some(3));
+    assert_eq!(pools[0].padded_size, Some(256));
+    assert_eq!(pools[1].in_use, Some(7));
+    assert_eq!(pools[1].padded_size, Some(128));
+
+    let tables = dump.inode_tables();
+    assert_eq!(tables.len(), 1);
+    assert_eq!(tables[0].lru_limit, Some(131_072));
+    assert_eq!(tables[0].active_size, Some(1));
+    let inodes: Vec<_> = tables[0]
+        .inodes
+        .iter()
+        .map(|inode| (inode.list.as_str(), inode.gfid, inode.ref_count))
+        .collect();
+    assert_eq!(
+        inodes,
+        vec![
+            ("active", Some(Gfid::root()), Some(1)),
+            (
+                "lru",
+                Some("0a1b2c3d-4e5f-6071-8293-a4b5c6d7e8f9".parse().unwrap()),
+                Some(0)
+            ),
+        ]
+    );
+
+    let fds = dump.fd_tables();
+    assert_eq!(fds.len(), 1);
+    assert_eq!(fds[0].maxfds, Some(128));
+    assert_eq!(fds[0].first_free, Some(4));
+    assert_eq!(fds[0].fds.len(), 1);
+    assert_eq!(fds[0].fds[0].index, 3);
+    assert_eq!(fds[0].fds[0].pid, Some(-6));
+    assert_eq!(fds[0].fds[0].flags, Some(0o100002));
+
+    let pool = dump.call_pool().unwrap();
+    assert_eq!(pool.count, Some(1));
+    assert_eq!(pool.stacks.len(), 1);
+    let stack = &pool.stacks[0];
+    assert_eq!(stack.uid, Some(1000));
+    assert_eq!(stack.pid, Some(4242));
+    assert_eq!(stack.op.as_deref(), Some("WRITE"));
+    assert_eq!(stack.frames.len(), 2);
+    assert_eq!(stack.frames[0].translator.as_deref(), Some("vol-client-0"));
+    assert!(!stack.frames[0].complete);
+    assert_eq!(stack.frames[0].wind_from.as_deref(), Some("syncop_writev"));
+    assert!(stack.frames[1].complete);
+    assert_eq!(
+        stack.frames[1].unwind_to.as_deref(),
+        Some("syncop_writev_cbk")
+    );
+}
+
+#[test]
+fn parse_empty_statedump() {
+    let dump = StateDump::parse("".as_bytes()).unwrap();
+    assert!(dump.sections.is_empty());
+    assert!(dump.xlators().is_empty());
+    assert!(dump.mempools().is_empty());
+    assert!(dump.call_pool().is_none());
+}
+
+#[cfg(gfapi_stub)]
+#[test]
+fn statedump_from_gfapi() {
+    use gfapi_sys::gluster::Gluster;
+
+    let dir = std::env::temp_dir().join(format!("gfapi-statedump-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cluster = Gluster::connect("dumpvol", "localhost", 24007).unwrap();
+    // Left over from an earlier dump and newer than anything written now
+    let stale = dir.join(format!("glusterdump.{}.dump.1", std::process::id()));
+    let file = std::fs::File::create(&stale).unwrap();
+    file.set_modified(std::time::SystemTime::now() + Duration::from_secs(3600))
+        .unwrap();
+
+    let dump = cluster.statedump(&dir).unwrap();
+    let path = dump.path.clone().unwrap();
+    assert!(path.starts_with(&dir));
+    assert_ne!(path, stale);
+    assert!(dump.start.is_some());
+    assert!(dump
+        .xlators()
+        .iter()
+        .any(|xl| xl.kind == "protocol/client" && xl.name == "dumpvol-client-0"));
+    assert_eq!(dump.inode_tables()[0].inodes.len(), 3);
+    assert_eq!(dump.call_pool().unwrap().stacks[0].frames.len(), 2);
+
+    assert!(cluster
+        .statedump(&dir.join("missing"))
+        .unwrap_err()
+        .to_string()
+        .contains("No such file"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
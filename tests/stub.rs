@@ -0,0 +1,482 @@
+//! These run against the stub library in gfapi-stub rather than a real
+//! cluster.  Build it first and point GFAPI_STUB_DIR at it:
+//!   cargo build -p gfapi-stub
+//!   GFAPI_STUB_DIR=$PWD/target/debug cargo test --test stub
+#![cfg(gfapi_stub)]
+
+mod common;
+
+use common::{gfapi_stub_fs_ids, gfapi_stub_set_volfile, gfapi_stub_unknown_d_type, inject};
+use errno::errno;
+use gfapi_sys::copy::CopyMethod;
+use gfapi_sys::credentials::Credentials;
+use gfapi_sys::gluster::{
+    DirCookie, Extent, ExtentKind, FallocMode, FileType, Gluster, GlusterError,
+};
+use libc::{c_void, EINVAL, EIO, ENOENT, EOPNOTSUPP, EPERM, EXDEV, O_RDONLY, O_RDWR};
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// The uid, gid and groups this thread last handed gfapi
+fn fs_ids() -> Credentials {
+    let (mut uid, mut gid) = (0, 0);
+    let mut groups = vec![0; 64];
+    let count = unsafe { gfapi_stub_fs_ids(&mut uid, &mut gid, groups.as_mut_ptr(), groups.len()) };
+    groups.truncate(count);
+    Credentials { uid, gid, groups }
+}
+
+fn connect_with_volfile(volfile: &[u8]) -> Gluster {
+    unsafe { gfapi_stub_set_volfile(volfile.as_ptr() as *const c_void, volfile.len()) };
+    Gluster::connect("test", "localhost", 24007).unwrap()
+}
+
+#[test]
+fn volfile_of_any_size() {
+    let small = "volume test\n    type debug/io-stats\nend-volume\n";
+    let cluster = connect_with_volfile(small.as_bytes());
+    assert_eq!(cluster.get_volfile().unwrap(), small);
+
+    // Past the 1K first guess get_volfile has to ask again
+    let large = "# padding\n".repeat(500);
+    let cluster = connect_with_volfile(large.as_bytes());
+    assert_eq!(cluster.get_volfile().unwrap(), large);
+
+    assert!(connect_with_volfile(b"").get_volfile().is_err());
+}
+
+#[test]
+fn errors_carry_errno() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    assert!(cluster.open(Path::new("/missing"), O_RDONLY).is_err());
+    assert_eq!(errno().0, ENOENT);
+    assert!(!cluster.exists(Path::new("/missing")).unwrap());
+
+    cluster.mkdir(Path::new("/dir"), 0o755).unwrap();
+    inject("glfs_stat", EIO, 1);
+    assert!(cluster.exists(Path::new("/dir")).is_err());
+    assert_eq!(errno().0, EIO);
+    assert!(cluster.exists(Path::new("/dir")).unwrap());
+
+    inject("glfs_init", EIO, 1);
+    assert!(Gluster::connect("test", "localhost", 24007).is_err());
+}
+
+#[test]
+fn readdir_stops_at_the_end() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    cluster.mkdir(Path::new("/dir"), 0o755).unwrap();
+    for name in &["a", "b", "c"] {
+        cluster
+            .create(&Path::new("/dir").join(name), O_RDWR, 0o644)
+            .unwrap();
+    }
+
+    let mut names = cluster
+        .opendir(Path::new("/dir"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path)
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        [".", "..", "a", "b", "c"]
+            .iter()
+            .map(Path::new)
+            .collect::<Vec<_>>()
+    );
+
+    let entries = cluster
+        .opendir_plus(Path::new("/dir"))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(entries.len(), 5);
+    for entry in entries {
+        assert_eq!(entry.inode, entry.stat.st_ino);
+    }
+}
+
+#[test]
+fn listing_resumes_from_a_cookie() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    cluster.mkdir(Path::new("/pages"), 0o755).unwrap();
+    for i in 0..10 {
+        cluster
+            .create(&Path::new("/pages").join(i.to_string()), O_RDWR, 0o644)
+            .unwrap();
+    }
+
+    let mut dir = cluster.opendir(Path::new("/pages")).unwrap();
+    let first = dir.read_batch(5).unwrap();
+    assert_eq!(first.len(), 5);
+    // Cookies survive being sent somewhere as a string
+    let cookie: DirCookie = dir.tell().to_string().parse().unwrap();
+    drop(dir);
+
+    let mut dir = cluster.opendir_at(Path::new("/pages"), cookie).unwrap();
+    let second = dir.read_batch(100).unwrap();
+    assert_eq!(second.len(), 7);
+    assert!(dir.read_batch(100).unwrap().is_empty());
+
+    let mut names: Vec<_> = first
+        .iter()
+        .chain(&second)
+        .map(|e| e.path.clone())
+        .collect();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), 12);
+
+    // Seeking back within the same listing reads the same entries again
+    dir.seek(cookie);
+    let again = dir.read_batch(100).unwrap();
+    assert_eq!(
+        again.iter().map(|e| &e.path).collect::<Vec<_>>(),
+        second.iter().map(|e| &e.path).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn entries_keep_names_that_are_not_utf8() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let latin1 = Path::new(OsStr::from_bytes(b"caf\xe9"));
+    let dir = Path::new("/latin1").join(latin1);
+    cluster.mkdir(Path::new("/latin1"), 0o755).unwrap();
+    cluster.mkdir(&dir, 0o755).unwrap();
+    cluster.create(&dir.join(latin1), O_RDWR, 0o644).unwrap();
+
+    let entry = cluster
+        .opendir(Path::new("/latin1"))
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| entry.path == latin1)
+        .unwrap();
+    assert_eq!(entry.file_type, FileType::Directory);
+    let entry = cluster
+        .opendir_plus(&dir)
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| entry.path == latin1)
+        .unwrap();
+    assert_eq!(entry.file_type, FileType::Regular);
+    cluster.open(&dir.join(&entry.path), O_RDONLY).unwrap();
+
+    // remove_dir_all finds its way back to every entry it lists
+    cluster.remove_dir_all(Path::new("/latin1")).unwrap();
+    // and opendir reports a missing directory straight away
+    assert!(cluster.opendir(&dir).is_err());
+    assert_eq!(errno().0, ENOENT);
+}
+
+#[test]
+fn entries_without_a_d_type_are_statted() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    cluster.mkdir(Path::new("/untyped"), 0o755).unwrap();
+    cluster.mkdir(Path::new("/untyped/sub"), 0o755).unwrap();
+    cluster
+        .create(Path::new("/untyped/file"), O_RDWR, 0o644)
+        .unwrap();
+    cluster
+        .symlink(Path::new("file"), Path::new("/untyped/link"))
+        .unwrap();
+    unsafe { gfapi_stub_unknown_d_type(1) };
+
+    let mut types = cluster
+        .opendir(Path::new("/untyped"))
+        .unwrap()
+        .map(|entry| entry.map(|entry| (entry.path, entry.file_type)))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    types.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        types,
+        [
+            (".", FileType::Directory),
+            ("..", FileType::Directory),
+            ("file", FileType::Regular),
+            ("link", FileType::Symlink),
+            ("sub", FileType::Directory),
+        ]
+        .iter()
+        .map(|(name, file_type)| (Path::new(name).to_path_buf(), *file_type))
+        .collect::<Vec<_>>()
+    );
+
+    // Opened by handle there's no path to lstat the entries through
+    let untyped = cluster
+        .opendir_xplus(Path::new("/"))
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| entry.path == Path::new("untyped"))
+        .unwrap();
+    let entries = untyped
+        .opendir()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(entries.len(), 5);
+    assert!(entries
+        .iter()
+        .all(|entry| entry.file_type == FileType::Unknown));
+    unsafe { gfapi_stub_unknown_d_type(0) };
+}
+
+#[test]
+fn xreaddirplus_entries_open_by_handle() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    cluster.mkdir(Path::new("/xdir"), 0o755).unwrap();
+    cluster.mkdir(Path::new("/xdir/sub"), 0o755).unwrap();
+    let file = cluster
+        .create(Path::new("/xdir/file"), O_RDWR, 0o644)
+        .unwrap();
+    file.write(b"by handle", 0).unwrap();
+    drop(file);
+
+    let mut entries = cluster
+        .opendir_xplus(Path::new("/xdir"))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let names: Vec<_> = entries.iter().map(|entry| entry.path.as_path()).collect();
+    assert_eq!(
+        names,
+        [".", "..", "file", "sub"]
+            .iter()
+            .map(Path::new)
+            .collect::<Vec<_>>()
+    );
+    for entry in &entries {
+        assert_eq!(entry.inode, entry.stat.unwrap().st_ino);
+    }
+
+    // gfapi has no handles for . and ..
+    assert!(!entries[0].has_handle());
+    assert!(entries[0].open(O_RDONLY).is_err());
+    assert_eq!(errno().0, EINVAL);
+
+    let file = entries[2].open(O_RDONLY).unwrap();
+    let mut buf = Vec::with_capacity(16);
+    assert_eq!(file.pread(&mut buf, 16, 0, 0).unwrap(), 9);
+    assert_eq!(buf, b"by handle");
+    assert_eq!(entries[2].restat().unwrap().st_size, 9);
+
+    let sub = entries[3].opendir().unwrap();
+    assert_eq!(sub.count(), 2);
+    assert!(entries[3].open(O_RDWR).is_err());
+}
+
+#[test]
+fn written_data_reads_back() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let file = cluster.create(Path::new("/f"), O_RDWR, 0o644).unwrap();
+    file.write(b"hello world", 0).unwrap();
+
+    let mut buf = Vec::with_capacity(16);
+    assert_eq!(file.pread(&mut buf, 16, 6, 0).unwrap(), 5);
+    assert_eq!(buf, b"world");
+}
+
+#[test]
+fn files_copy_server_side_or_through_the_client() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let data = b"0123456789".repeat(1000);
+    let file = cluster
+        .create(Path::new("/original"), O_RDWR, 0o640)
+        .unwrap();
+    file.write(&data, 0).unwrap();
+
+    let dst = cluster.create(Path::new("/ranged"), O_RDWR, 0o644).unwrap();
+    assert_eq!(file.copy_range_to(&dst, 10, 0, 5).unwrap(), 5);
+    let mut buf = Vec::with_capacity(16);
+    dst.pread(&mut buf, 16, 0, 0).unwrap();
+    assert_eq!(buf, b"01234");
+
+    let copied = cluster
+        .copy_file(Path::new("/original"), Path::new("/server"))
+        .unwrap();
+    assert_eq!(copied.method, CopyMethod::ServerSide);
+    assert_eq!(copied.bytes, data.len() as u64);
+
+    // As when the two files are on different bricks
+    inject("glfs_copy_file_range", EXDEV, 1);
+    let copied = cluster
+        .copy_file(Path::new("/original"), Path::new("/buffered"))
+        .unwrap();
+    assert_eq!(copied.method, CopyMethod::Buffered);
+    assert_eq!(copied.bytes, data.len() as u64);
+
+    for path in &["/server", "/buffered"] {
+        let copy = cluster.open(Path::new(path), O_RDONLY).unwrap();
+        assert_eq!(copy.fstat().unwrap().st_mode & 0o777, 0o640);
+        let mut buf = Vec::with_capacity(data.len() + 1);
+        copy.pread(&mut buf, data.len() + 1, 0, 0).unwrap();
+        assert_eq!(buf, data);
+    }
+
+    // Other failures aren't worked around
+    inject("glfs_copy_file_range", EIO, 1);
+    assert!(cluster
+        .copy_file(Path::new("/original"), Path::new("/failed"))
+        .is_err());
+    assert_eq!(errno().0, EIO);
+
+    // Copying a file onto itself would empty it first
+    cluster
+        .link(Path::new("/original"), Path::new("/linked"))
+        .unwrap();
+    for dst in &["/original", "/linked"] {
+        assert!(cluster
+            .copy_file(Path::new("/original"), Path::new(dst))
+            .is_err());
+        assert_eq!(errno().0, EINVAL);
+        assert!(cluster
+            .copy_file_sparse(Path::new("/original"), Path::new(dst))
+            .is_err());
+        assert_eq!(errno().0, EINVAL);
+    }
+    assert_eq!(file.fstat().unwrap().st_size, data.len() as i64);
+}
+
+#[test]
+fn sparse_copies_keep_holes() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let block = vec![7u8; 4096];
+    let image = cluster.create(Path::new("/image"), O_RDWR, 0o600).unwrap();
+    image.pwrite(&block, block.len(), 0, 0).unwrap();
+    image.pwrite(&block, block.len(), 1 << 20, 0).unwrap();
+    image.ftruncate(2 << 20).unwrap();
+
+    let extents: Vec<_> = image.extents().unwrap().map(Result::unwrap).collect();
+    let extent = |kind, offset, len| Extent { kind, offset, len };
+    assert_eq!(
+        extents,
+        vec![
+            extent(ExtentKind::Data, 0, 4096),
+            extent(ExtentKind::Hole, 4096, (1 << 20) - 4096),
+            extent(ExtentKind::Data, 1 << 20, 4096),
+            extent(ExtentKind::Hole, (1 << 20) + 4096, (1 << 20) - 4096),
+        ]
+    );
+
+    // Over a copy that's full of data, which the holes punch out
+    let full = cluster.create(Path::new("/full"), O_RDWR, 0o600).unwrap();
+    full.pwrite(&vec![1u8; 3 << 20], 3 << 20, 0, 0).unwrap();
+    let copied = cluster
+        .copy_file_sparse(Path::new("/image"), Path::new("/full"))
+        .unwrap();
+    assert_eq!(copied.bytes, 8192);
+    assert_eq!(copied.holes, (2 << 20) - 8192);
+    let copy = cluster.open(Path::new("/full"), O_RDONLY).unwrap();
+    assert_eq!(
+        copy.extents()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>(),
+        extents
+    );
+    let mut buf = Vec::with_capacity(8192);
+    copy.pread(&mut buf, 8192, 4096, 0).unwrap();
+    assert_eq!(buf, vec![0u8; 8192]);
+
+    let local = std::env::temp_dir().join(format!("gfapi-sparse-{}", std::process::id()));
+    let file = std::fs::File::create(&local).unwrap();
+    std::os::unix::fs::FileExt::write_at(&file, &block, 1 << 20).unwrap();
+    file.set_len(2 << 20).unwrap();
+    let uploaded = cluster
+        .upload_sparse(&local, Path::new("/uploaded"))
+        .unwrap();
+    std::fs::remove_file(&local).unwrap();
+    assert_eq!(uploaded.bytes, 4096);
+    assert_eq!(uploaded.holes, (2 << 20) - 4096);
+    let copy = cluster.open(Path::new("/uploaded"), O_RDONLY).unwrap();
+    assert_eq!(copy.fstat().unwrap().st_size, 2 << 20);
+    assert_eq!(
+        copy.extents()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>(),
+        vec![extent(ExtentKind::Hole, 0, 1 << 20), extents[2], extents[3]]
+    );
+}
+
+#[test]
+fn unsupported_allocate_modes_are_told_apart() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let file = cluster
+        .create(Path::new("/prealloc"), O_RDWR, 0o644)
+        .unwrap();
+    file.allocate(0..8192, FallocMode::Allocate).unwrap();
+    assert_eq!(file.fstat().unwrap().st_size, 8192);
+
+    inject("glfs_discard", EOPNOTSUPP, 1);
+    match file.allocate(0..4096, FallocMode::PunchHole) {
+        Err(GlusterError::Unsupported(_)) => {}
+        other => panic!("{:?}", other),
+    }
+    inject("glfs_discard", EIO, 1);
+    match file.allocate(0..4096, FallocMode::PunchHole) {
+        Err(GlusterError::Error(_)) => assert_eq!(errno().0, EIO),
+        other => panic!("{:?}", other),
+    }
+    file.allocate(0..4096, FallocMode::PunchHole).unwrap();
+}
+
+#[test]
+fn impersonation_nests() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let process = Credentials::process().unwrap();
+    let outer = Credentials::new(1000, 1000, &[1000, 10]);
+    let inner = Credentials::new(2000, 2000, &[]);
+    cluster
+        .with_credentials(&outer, |cluster| {
+            assert_eq!(fs_ids(), outer);
+            {
+                let _guard = cluster.impersonate(2000, 2000, &[])?;
+                assert_eq!(fs_ids(), inner);
+                assert_eq!(cluster.current_credentials()?, inner);
+            }
+            assert_eq!(fs_ids(), outer);
+            assert_eq!(cluster.current_credentials()?, outer);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(fs_ids(), process);
+    assert_eq!(cluster.current_credentials().unwrap(), process);
+}
+
+#[test]
+fn impersonation_guards_dropped_out_of_order() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let process = Credentials::process().unwrap();
+    let outer = cluster.impersonate(1000, 1000, &[]).unwrap();
+    let inner = cluster.impersonate(2000, 2000, &[]).unwrap();
+
+    // The newer identity stays in force
+    drop(outer);
+    assert_eq!(fs_ids(), Credentials::new(2000, 2000, &[]));
+    assert_eq!(
+        cluster.current_credentials().unwrap(),
+        Credentials::new(2000, 2000, &[])
+    );
+    drop(inner);
+    assert_eq!(fs_ids(), process);
+    assert_eq!(cluster.current_credentials().unwrap(), process);
+}
+
+#[test]
+fn failed_impersonation_keeps_the_identity() {
+    let cluster = Gluster::connect("test", "localhost", 24007).unwrap();
+    let _guard = cluster.impersonate(1000, 1000, &[1000]).unwrap();
+    // The groups and gid are set by the time the uid fails
+    inject("glfs_setfsuid", EPERM, 1);
+    assert!(cluster.impersonate(2000, 2000, &[2000]).is_err());
+    assert_eq!(fs_ids(), Credentials::new(1000, 1000, &[1000]));
+    assert_eq!(
+        cluster.current_credentials().unwrap(),
+        Credentials::new(1000, 1000, &[1000])
+    );
+}
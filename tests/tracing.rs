@@ -0,0 +1,141 @@
+//! Checks the spans the tracing feature opens, against the stub library as
+//! described in tests/stub.rs.
+#![cfg(all(feature = "tracing", gfapi_stub))]
+
+use errno::{errno, set_errno, Errno};
+use gfapi_sys::gluster::Gluster;
+use libc::{EIO, ENOENT, O_CREAT, O_RDONLY, O_RDWR};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, Default)]
+struct Span {
+    name: &'static str,
+    fields: BTreeMap<&'static str, String>,
+}
+
+impl Visit for Span {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.fields.insert(field.name(), format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields.insert(field.name(), value.to_string());
+    }
+}
+
+/// Keeps every span with the fields recorded on it
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<Span>>>,
+    /// Set errno on leaving a span, as a subscriber making syscalls might
+    clobber_errno: bool,
+}
+
+impl Recorder {
+    fn named(&self, name: &str) -> Vec<Span> {
+        let spans = self.spans.lock().unwrap();
+        spans
+            .iter()
+            .filter(|span| span.name == name)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let mut span = Span {
+            name: attributes.metadata().name(),
+            ..Span::default()
+        };
+        attributes.record(&mut span);
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(span);
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, id: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut spans[id.into_u64() as usize - 1]);
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {
+        if self.clobber_errno {
+            set_errno(Errno(EIO));
+        }
+    }
+
+    fn try_close(&self, _: Id) -> bool {
+        if self.clobber_errno {
+            set_errno(Errno(EIO));
+        }
+        false
+    }
+}
+
+#[test]
+fn operations_open_spans() {
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let cluster = Gluster::connect("traced", "localhost", 24007).unwrap();
+        let file = cluster
+            .create(Path::new("/file"), O_CREAT | O_RDWR, 0o644)
+            .unwrap();
+        file.pwrite(b"0123456789", 10, 0, 0).unwrap();
+        let mut buf = Vec::with_capacity(8);
+        file.pread(&mut buf, 8, 4, 0).unwrap();
+        assert!(cluster.open(Path::new("/missing"), O_RDONLY).is_err());
+        for entry in cluster.opendir(Path::new("/")).unwrap() {
+            entry.unwrap();
+        }
+    });
+
+    let create = &recorder.named("create")[0];
+    assert_eq!(create.fields["volume"], "traced");
+    assert_eq!(create.fields["path"], "/file");
+
+    let pread = &recorder.named("pread")[0];
+    assert_eq!(pread.fields["volume"], "traced");
+    assert_eq!(pread.fields["offset"], "4");
+    assert_eq!(pread.fields["length"], "8");
+    assert_eq!(pread.fields["size"], "6");
+    assert!(!pread.fields.contains_key("errno"));
+    assert_eq!(recorder.named("pwrite")[0].fields["size"], "10");
+
+    let open = &recorder.named("open")[0];
+    assert_eq!(open.fields["path"], "/missing");
+    assert_eq!(open.fields["errno"], ENOENT.to_string());
+
+    // One span per entry read, plus the one finding the end
+    assert!(recorder.named("readdir").len() >= 4);
+}
+
+#[test]
+fn errno_survives_the_subscriber() {
+    let recorder = Recorder {
+        clobber_errno: true,
+        ..Recorder::default()
+    };
+    tracing::subscriber::with_default(recorder, || {
+        let cluster = Gluster::connect("clobbered", "localhost", 24007).unwrap();
+        assert!(cluster.open(Path::new("/missing"), O_RDONLY).is_err());
+        assert_eq!(errno(), Errno(ENOENT));
+    });
+}
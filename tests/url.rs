@@ -0,0 +1,55 @@
+use gfapi_sys::url::{GlusterUrl, DEFAULT_PORT};
+
+use std::path::{Path, PathBuf};
+
+#[test]
+fn parse_gluster_urls() {
+    let url: GlusterUrl = "gluster://server1/myvol/dir/file".parse().unwrap();
+    assert_eq!(
+        url,
+        GlusterUrl {
+            host: "server1".to_string(),
+            port: DEFAULT_PORT,
+            volume: "myvol".to_string(),
+            path: PathBuf::from("/dir/file"),
+        }
+    );
+    assert_eq!(url.to_string(), "gluster://server1/myvol/dir/file");
+
+    let root = GlusterUrl::parse("gluster://server1:24010/myvol").unwrap();
+    assert_eq!(root.port, 24010);
+    assert_eq!(root.path, Path::new("/"));
+    assert_eq!(root.to_string(), "gluster://server1:24010/myvol/");
+
+    let ipv6 = GlusterUrl::parse("gluster://[fe80::1]:24008/vol/a b").unwrap();
+    assert_eq!(ipv6.host, "fe80::1");
+    assert_eq!(ipv6.port, 24008);
+    assert_eq!(ipv6.path, Path::new("/a b"));
+    assert_eq!(ipv6.to_string(), "gluster://[fe80::1]:24008/vol/a b");
+
+    for bad in &[
+        "server1/myvol",
+        "gluster://",
+        "gluster:///myvol",
+        "gluster://server1",
+        "gluster://server1/",
+        "gluster://server1:port/myvol",
+        "gluster://[fe80::1/myvol",
+    ] {
+        assert!(GlusterUrl::parse(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn resolve_paths_on_the_same_volume() {
+    let url = GlusterUrl::parse("gluster://server1/myvol/dir/file").unwrap();
+    assert_eq!(url.resolve("other").unwrap(), Path::new("/other"));
+    assert_eq!(url.resolve("/a/b").unwrap(), Path::new("/a/b"));
+    assert_eq!(
+        url.resolve("gluster://server1/myvol/x").unwrap(),
+        Path::new("/x")
+    );
+    assert!(url.resolve("gluster://server1/othervol/x").is_err());
+    assert!(url.resolve("gluster://server2/myvol/x").is_err());
+    assert_eq!(url.with_path("y").to_string(), "gluster://server1/myvol/y");
+}
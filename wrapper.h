@@ -1 +1,2 @@
-#include <glfs.h>
\ No newline at end of file
+#include <glfs.h>
+#include <glfs-handles.h>